@@ -5,6 +5,7 @@ pub mod header;
 pub mod operation;
 pub mod request;
 pub mod response;
+pub mod value;
 
 pub(crate) const CQL_VERSION_KEY: &str = "CQL_VERSION";
 pub(crate) const CQL_VERSION_VALUE: &str = "3.0.0";
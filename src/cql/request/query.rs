@@ -48,11 +48,11 @@ bitflags! {
     struct QueryFlags: u8 {
         const VALUES = 0x01;
         const SKIP_METADATA = 0x02;
-        const PAGE_SIZE = 0x03;
-        const PAGING_STATE = 0x04;
-        const SERIAL_CONSISTENCY = 0x05;
-        const TIMESTAMPS = 0x06;
-        const NAMES_FOR_VALUES = 0x07;
+        const PAGE_SIZE = 0x04;
+        const PAGING_STATE = 0x08;
+        const SERIAL_CONSISTENCY = 0x10;
+        const TIMESTAMPS = 0x20;
+        const NAMES_FOR_VALUES = 0x40;
     }
 }
 
@@ -99,6 +99,12 @@ pub(crate) fn decode(src: &mut BytesMut) -> Operation {
     } else {
         None
     };
+
+    // Serial consistency only matters to lightweight transactions
+    if flags.contains(QueryFlags::SERIAL_CONSISTENCY) {
+        let _ = short!(src);
+    }
+
     let timestamp = if flags.contains(QueryFlags::TIMESTAMPS) {
         Some(long!(src))
     } else {
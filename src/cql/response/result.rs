@@ -1,8 +1,13 @@
-use crate::db::data::{ColumnType, Row, Value};
-use crate::serde::writer::{bool, bytes, int, string};
+use crate::cql::value::serialize;
+use crate::db::data::{ColumnType, Row};
+use crate::serde::writer::{bytes, int, string, unsigned_short};
 use bitflags::bitflags;
 use bytes::BytesMut;
 
+const VOID_KIND: i32 = 0x0001;
+const ROWS_KIND: i32 = 0x0002;
+const SET_KEYSPACE_KIND: i32 = 0x0003;
+
 #[derive(Debug, Clone)]
 pub(crate) enum Result {
     Void,
@@ -21,96 +26,111 @@ struct GlobalTableSpec {
 }
 
 #[derive(Debug, Clone)]
-struct ColumnSpec {
+pub struct ColumnSpec {
     keyspace: Option<String>,
     table: Option<String>,
     name: String,
     column_type: ColumnType,
 }
 
+impl ColumnSpec {
+    pub fn new(name: String, column_type: ColumnType) -> ColumnSpec {
+        ColumnSpec {
+            keyspace: None,
+            table: None,
+            name,
+            column_type,
+        }
+    }
+}
+
 bitflags! {
     /// Represents a set of flags.
     #[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
     pub struct Flags: i32 {
         const GLOBAL_TABLES_SPEC = 0x01;
         const HAS_PAGING_STATE = 0x02;
-        const NO_METADATA = 0x03;
+        const NO_METADATA = 0x04;
     }
 }
 
 #[derive(Debug, Clone)]
 pub struct Metadata {
     flags: Flags,
-    paging_state: Option<String>,
+    paging_state: Option<Vec<u8>>,
     column_count: i32,
     global_table_spec: Option<GlobalTableSpec>,
     column_specs: Vec<ColumnSpec>,
 }
 
 impl Metadata {
-    pub fn new(flags: Flags, column_count: i32) -> Metadata {
+    pub fn for_table(keyspace: String, table: String, column_specs: Vec<ColumnSpec>) -> Metadata {
         Metadata {
-            flags,
+            flags: Flags::GLOBAL_TABLES_SPEC,
             paging_state: None,
-            column_count,
-            global_table_spec: None,
-            column_specs: vec![],
+            column_count: column_specs.len() as i32,
+            global_table_spec: Some(GlobalTableSpec { keyspace, table }),
+            column_specs,
         }
     }
+
+    pub fn with_paging_state(mut self, paging_state: Option<Vec<u8>>) -> Metadata {
+        self.flags
+            .set(Flags::HAS_PAGING_STATE, paging_state.is_some());
+        self.paging_state = paging_state;
+        self
+    }
 }
 
 pub(crate) fn encode(src: Result, dst: &mut BytesMut) -> anyhow::Result<()> {
     match src {
-        Result::Void => int!(dst, 01),
-        Result::SetKeyspace(keyspace) => string!(dst, keyspace),
+        Result::Void => int!(dst, VOID_KIND),
+        Result::SetKeyspace(keyspace) => {
+            int!(dst, SET_KEYSPACE_KIND);
+            string!(dst, keyspace);
+        }
         Result::Rows {
             metadata,
-            row_count: _,
+            row_count,
             rows,
         } => {
-            let metadata = metadata;
             let flags = metadata.flags;
 
+            int!(dst, ROWS_KIND);
             int!(dst, flags.bits());
             int!(dst, metadata.column_count);
 
-            match metadata.global_table_spec {
-                Some(spec) => {
-                    string!(dst, spec.keyspace);
-                    string!(dst, spec.table);
-                }
-                None => {
-                    unimplemented!()
+            if let Some(paging_state) = metadata.paging_state {
+                bytes!(dst, paging_state.as_slice());
+            }
+
+            if let Some(spec) = metadata.global_table_spec {
+                string!(dst, spec.keyspace);
+                string!(dst, spec.table);
+            }
+
+            for column_spec in metadata.column_specs {
+                if !flags.contains(Flags::GLOBAL_TABLES_SPEC) {
+                    let keyspace = column_spec.keyspace.unwrap_or_default();
+                    let table = column_spec.table.unwrap_or_default();
+
+                    string!(dst, keyspace);
+                    string!(dst, table);
                 }
+
+                string!(dst, column_spec.name);
+                encode_type(&column_spec.column_type, dst);
             }
 
+            int!(dst, row_count);
+
             for row in rows {
                 for column in row.columns {
                     match column {
                         None => {
-                            int!(dst, 0);
+                            int!(dst, -1);
                         }
-                        Some(column) => match column {
-                            Value::Int(value) => bytes!(dst, value.to_be_bytes().as_slice()),
-                            Value::Ascii(value) => bytes!(dst, value.as_slice()),
-                            Value::Bigint(value) => bytes!(dst, value.to_be_bytes().as_slice()),
-                            Value::Blob(value) => bytes!(dst, value.as_slice()),
-                            Value::Boolean(value) => bytes!(dst, &[value as u8]),
-                            Value::Counter(_) => {}
-                            Value::Decimal(_) => {}
-                            Value::Double(value) => bytes!(dst, value.to_be_bytes().as_slice()),
-                            Value::Float(value) => bytes!(dst, value.to_be_bytes().as_slice()),
-                            Value::Timestamp(value) => bytes!(dst, value.to_be_bytes().as_slice()),
-                            Value::Uuid(uuid) => bytes!(dst, uuid.as_bytes().as_slice()),
-                            Value::Varchar(value) => bytes!(dst, value.as_bytes()),
-                            Value::Varint(_) => {}
-                            Value::Timeuuid(_) => {}
-                            Value::Inet(_) => {}
-                            Value::Date(value) => bytes!(dst, value.to_be_bytes().as_slice()),
-                            Value::Time(_) => {}
-                            Value::Smallint(value) => bytes!(dst, value.to_be_bytes().as_slice()),
-                            Value::Tinyint(value) => bytes!(dst, value.to_be_bytes().as_slice()),
-                        },
+                        Some(column) => bytes!(dst, serialize(&column).as_slice()),
                     }
                 }
             }
@@ -119,3 +139,187 @@ pub(crate) fn encode(src: Result, dst: &mut BytesMut) -> anyhow::Result<()> {
 
     Ok(())
 }
+
+// Writes a column type as an [option], recursing into collection elements
+// and user type or tuple fields. Frozen types are indistinguishable on the wire.
+fn encode_type(column_type: &ColumnType, dst: &mut BytesMut) {
+    if let ColumnType::Frozen(inner) = column_type {
+        return encode_type(inner, dst);
+    }
+
+    unsigned_short!(dst, column_type.type_identifier());
+
+    match column_type {
+        ColumnType::List(element) | ColumnType::Set(element) => encode_type(element, dst),
+        ColumnType::Map(key, value) => {
+            encode_type(key, dst);
+            encode_type(value, dst);
+        }
+        ColumnType::Tuple(element_types) => {
+            unsigned_short!(dst, element_types.len() as u16);
+
+            for element_type in element_types {
+                encode_type(element_type, dst);
+            }
+        }
+        ColumnType::Udt(user_type) => {
+            string!(dst, user_type.keyspace);
+            string!(dst, user_type.name);
+            unsigned_short!(dst, user_type.fields.len() as u16);
+
+            for (name, field_type) in &user_type.fields {
+                string!(dst, name);
+                encode_type(field_type, dst);
+            }
+        }
+        ColumnType::Vector(..) => string!(dst, marshal_class(column_type)),
+        _ => {}
+    }
+}
+
+// The Java class Cassandra implements a type with, which is how custom types
+// such as vectors are named on the wire
+fn marshal_class(column_type: &ColumnType) -> String {
+    let name = match column_type {
+        ColumnType::Ascii => "AsciiType",
+        ColumnType::Bigint => "LongType",
+        ColumnType::Blob => "BytesType",
+        ColumnType::Boolean => "BooleanType",
+        ColumnType::Decimal => "DecimalType",
+        ColumnType::Double => "DoubleType",
+        ColumnType::Duration => "DurationType",
+        ColumnType::Float => "FloatType",
+        ColumnType::Inet => "InetAddressType",
+        ColumnType::Int => "Int32Type",
+        ColumnType::Smallint => "ShortType",
+        ColumnType::Date => "SimpleDateType",
+        ColumnType::Time => "TimeType",
+        ColumnType::Timestamp => "TimestampType",
+        ColumnType::Timeuuid => "TimeUUIDType",
+        ColumnType::Tinyint => "ByteType",
+        ColumnType::Uuid => "UUIDType",
+        ColumnType::Varchar => "UTF8Type",
+        ColumnType::Varint => "IntegerType",
+        ColumnType::Vector(element, dimension) => {
+            return format!(
+                "org.apache.cassandra.db.marshal.VectorType({}, {})",
+                marshal_class(element),
+                dimension
+            )
+        }
+        // Vectors only hold the types above
+        _ => "BytesType",
+    };
+
+    format!("org.apache.cassandra.db.marshal.{}", name)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::db::data::Value;
+    use crate::db::schema::UserType;
+
+    #[test]
+    fn test_encode_rows() {
+        let mut bytes = BytesMut::new();
+        let result = Result::Rows {
+            metadata: Metadata::for_table(
+                "ks".to_string(),
+                "t".to_string(),
+                vec![ColumnSpec::new("c".to_string(), ColumnType::Date)],
+            ),
+            row_count: 2,
+            rows: vec![
+                Row {
+                    columns: vec![Some(Value::Date(1))],
+                },
+                Row {
+                    columns: vec![None],
+                },
+            ],
+        };
+
+        encode(result, &mut bytes).unwrap();
+
+        // Rows, global table spec, one column, ks.t, c date, two rows
+        assert_eq!(
+            bytes.as_ref(),
+            &[
+                0, 0, 0, 2, 0, 0, 0, 1, 0, 0, 0, 1, 0, 2, b'k', b's', 0, 1, b't', 0, 1, b'c', 0,
+                0x11, 0, 0, 0, 2, 0, 0, 0, 4, 0x80, 0, 0, 1, 0xFF, 0xFF, 0xFF, 0xFF
+            ]
+        );
+    }
+
+    #[test]
+    fn test_encode_collection_type_options() {
+        let mut bytes = BytesMut::new();
+        let column_type = ColumnType::Map(
+            Box::new(ColumnType::Varchar),
+            Box::new(ColumnType::List(Box::new(ColumnType::Int))),
+        );
+
+        encode_type(&column_type, &mut bytes);
+
+        assert_eq!(
+            bytes.as_ref(),
+            &[0x00, 0x21, 0x00, 0x0D, 0x00, 0x20, 0x00, 0x09]
+        );
+    }
+
+    #[test]
+    fn test_encode_user_type_option() {
+        let mut bytes = BytesMut::new();
+        let column_type = ColumnType::Frozen(Box::new(ColumnType::Udt(UserType {
+            keyspace: "ks".to_string(),
+            name: "address".to_string(),
+            fields: vec![("zip".to_string(), ColumnType::Int)],
+        })));
+
+        encode_type(&column_type, &mut bytes);
+
+        assert_eq!(
+            bytes.as_ref(),
+            &[
+                0x00, 0x30, 0x00, 0x02, b'k', b's', 0x00, 0x07, b'a', b'd', b'd', b'r', b'e', b's',
+                b's', 0x00, 0x01, 0x00, 0x03, b'z', b'i', b'p', 0x00, 0x09
+            ]
+        );
+        assert_eq!(
+            serialize(&Value::Udt(vec![Some(Value::Int(7)), None])),
+            vec![0, 0, 0, 4, 0, 0, 0, 7, 0xFF, 0xFF, 0xFF, 0xFF]
+        );
+    }
+
+    #[test]
+    fn test_encode_tuple_type_option() {
+        let mut bytes = BytesMut::new();
+        let column_type = ColumnType::Tuple(vec![ColumnType::Int, ColumnType::Varchar]);
+
+        encode_type(&column_type, &mut bytes);
+
+        assert_eq!(
+            bytes.as_ref(),
+            &[0x00, 0x31, 0x00, 0x02, 0x00, 0x09, 0x00, 0x0D]
+        );
+        assert_eq!(
+            serialize(&Value::Tuple(vec![None, Some(Value::Int(7))])),
+            vec![0xFF, 0xFF, 0xFF, 0xFF, 0, 0, 0, 4, 0, 0, 0, 7]
+        );
+    }
+
+    #[test]
+    fn test_encode_vector_type_option() {
+        let mut bytes = BytesMut::new();
+        let column_type = ColumnType::Vector(Box::new(ColumnType::Float), 3);
+        let class = "org.apache.cassandra.db.marshal.VectorType(\
+                     org.apache.cassandra.db.marshal.FloatType, 3)";
+
+        encode_type(&column_type, &mut bytes);
+
+        assert_eq!(&bytes[..2], &[0x00, 0x00]);
+        assert_eq!(&bytes[2..4], (class.len() as u16).to_be_bytes());
+        assert_eq!(&bytes[4..], class.as_bytes());
+    }
+}
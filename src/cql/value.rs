@@ -0,0 +1,523 @@
+use crate::db::data::{decimal_from_bytes, decimal_to_bytes, ColumnType, Value};
+use anyhow::{anyhow, bail};
+use num_bigint::BigInt;
+use uuid::Uuid;
+
+// Dates go over the wire as unsigned days with the epoch at 2^31
+const EPOCH_DAY: u32 = 1 << 31;
+
+// The protocol's encoding of a value, without the [bytes] length in front
+pub(crate) fn serialize(value: &Value) -> Vec<u8> {
+    match value {
+        Value::Int(value) => value.to_be_bytes().to_vec(),
+        Value::Ascii(value) => value.clone(),
+        Value::Bigint(value) => value.to_be_bytes().to_vec(),
+        Value::Blob(value) => value.clone(),
+        Value::Boolean(value) => vec![*value as u8],
+        Value::Counter(value) => value.to_be_bytes().to_vec(),
+        Value::Decimal(value) => decimal_to_bytes(value),
+        Value::Double(value) => value.to_be_bytes().to_vec(),
+        Value::Float(value) => value.to_be_bytes().to_vec(),
+        Value::Timestamp(value) => value.to_be_bytes().to_vec(),
+        Value::Uuid(uuid) => uuid.as_bytes().to_vec(),
+        Value::Varchar(value) => value.as_bytes().to_vec(),
+        Value::Varint(value) => value.to_signed_bytes_be(),
+        Value::Timeuuid(uuid) => uuid.as_bytes().to_vec(),
+        Value::Inet(value) => value.clone(),
+        Value::Date(value) => (*value as u32)
+            .wrapping_add(EPOCH_DAY)
+            .to_be_bytes()
+            .to_vec(),
+        Value::Time(value) => value.to_be_bytes().to_vec(),
+        Value::Smallint(value) => value.to_be_bytes().to_vec(),
+        Value::Tinyint(value) => value.to_be_bytes().to_vec(),
+        Value::Duration(months, days, nanos) => {
+            let mut bytes = vec![];
+
+            write_vint(*months as i64, &mut bytes);
+            write_vint(*days as i64, &mut bytes);
+            write_vint(*nanos, &mut bytes);
+            bytes
+        }
+        Value::List(values) | Value::Set(values) => {
+            serialize_collection(values.len(), values.iter().map(serialize))
+        }
+        Value::Map(entries) => serialize_collection(
+            entries.len(),
+            entries
+                .iter()
+                .flat_map(|(key, value)| [serialize(key), serialize(value)]),
+        ),
+        // User type and tuple values are their fields as [bytes], without a count
+        Value::Udt(fields) | Value::Tuple(fields) => {
+            let mut bytes = vec![];
+
+            for field in fields {
+                match field {
+                    Some(value) => {
+                        let value = serialize(value);
+
+                        bytes.extend((value.len() as i32).to_be_bytes());
+                        bytes.extend(value);
+                    }
+                    None => bytes.extend((-1i32).to_be_bytes()),
+                }
+            }
+
+            bytes
+        }
+        // Vector elements of a fixed length are simply concatenated, others
+        // each follow their length as an unsigned vint
+        Value::Vector(elements) => {
+            let mut bytes = vec![];
+
+            for element in elements {
+                let element_bytes = serialize(element);
+
+                if fixed_length(&element.column_type()).is_none() {
+                    write_unsigned_vint(element_bytes.len() as u64, &mut bytes);
+                }
+
+                bytes.extend(element_bytes);
+            }
+
+            bytes
+        }
+    }
+}
+
+// The length of every value of a type, for the types whose values all have
+// the same length
+fn fixed_length(column_type: &ColumnType) -> Option<usize> {
+    match column_type {
+        ColumnType::Boolean | ColumnType::Tinyint => Some(1),
+        ColumnType::Smallint => Some(2),
+        ColumnType::Int | ColumnType::Float | ColumnType::Date => Some(4),
+        ColumnType::Bigint | ColumnType::Double | ColumnType::Timestamp | ColumnType::Time => {
+            Some(8)
+        }
+        ColumnType::Uuid | ColumnType::Timeuuid => Some(16),
+        _ => None,
+    }
+}
+
+// Collections are an [int] element count followed by each element as [bytes]
+fn serialize_collection(count: usize, elements: impl Iterator<Item = Vec<u8>>) -> Vec<u8> {
+    let mut bytes = (count as i32).to_be_bytes().to_vec();
+
+    for element in elements {
+        bytes.extend((element.len() as i32).to_be_bytes());
+        bytes.extend(element);
+    }
+
+    bytes
+}
+
+// Reads a value bound by a client, the reverse of serialize
+pub(crate) fn deserialize(column_type: &ColumnType, bytes: &[u8]) -> anyhow::Result<Value> {
+    let fixed = |length: usize| {
+        if bytes.len() == length {
+            Ok(bytes)
+        } else {
+            Err(anyhow!(
+                "Expected {} bytes for a {:?} value, got {}",
+                length,
+                column_type,
+                bytes.len()
+            ))
+        }
+    };
+
+    let value = match column_type {
+        ColumnType::Frozen(inner) => return deserialize(inner, bytes),
+        ColumnType::Ascii if bytes.is_ascii() => Value::Ascii(bytes.to_vec()),
+        ColumnType::Ascii => bail!("Invalid ASCII bytes"),
+        ColumnType::Bigint => Value::Bigint(i64::from_be_bytes(fixed(8)?.try_into()?)),
+        ColumnType::Blob => Value::Blob(bytes.to_vec()),
+        ColumnType::Boolean => Value::Boolean(fixed(1)?[0] != 0),
+        ColumnType::Counter => Value::Counter(i64::from_be_bytes(fixed(8)?.try_into()?)),
+        ColumnType::Decimal => match decimal_from_bytes(bytes) {
+            Some(decimal) => Value::Decimal(decimal),
+            None => bail!("Invalid decimal of {} bytes", bytes.len()),
+        },
+        ColumnType::Double => Value::Double(f64::from_be_bytes(fixed(8)?.try_into()?)),
+        ColumnType::Float => Value::Float(f32::from_be_bytes(fixed(4)?.try_into()?)),
+        ColumnType::Int => Value::Int(i32::from_be_bytes(fixed(4)?.try_into()?)),
+        ColumnType::Timestamp => Value::Timestamp(i64::from_be_bytes(fixed(8)?.try_into()?)),
+        ColumnType::Uuid => Value::Uuid(Uuid::from_slice(fixed(16)?)?),
+        ColumnType::Varchar => Value::Varchar(String::from_utf8(bytes.to_vec())?),
+        ColumnType::Varint if !bytes.is_empty() => {
+            Value::Varint(BigInt::from_signed_bytes_be(bytes))
+        }
+        ColumnType::Varint => bail!("Invalid empty varint"),
+        ColumnType::Timeuuid => Value::Timeuuid(Uuid::from_slice(fixed(16)?)?),
+        ColumnType::Inet if bytes.len() == 4 || bytes.len() == 16 => Value::Inet(bytes.to_vec()),
+        ColumnType::Inet => bail!("Invalid inet address of {} bytes", bytes.len()),
+        ColumnType::Date => {
+            let days = u32::from_be_bytes(fixed(4)?.try_into()?);
+
+            Value::Date(days.wrapping_sub(EPOCH_DAY) as i32)
+        }
+        ColumnType::Time => Value::Time(i64::from_be_bytes(fixed(8)?.try_into()?)),
+        ColumnType::Smallint => Value::Smallint(i16::from_be_bytes(fixed(2)?.try_into()?)),
+        ColumnType::Tinyint => Value::Tinyint(i8::from_be_bytes(fixed(1)?.try_into()?)),
+        ColumnType::Duration => {
+            let mut rest = bytes;
+            let months = i32::try_from(read_vint(&mut rest)?)?;
+            let days = i32::try_from(read_vint(&mut rest)?)?;
+            let nanos = read_vint(&mut rest)?;
+
+            if !rest.is_empty() {
+                bail!("Unexpected {} bytes after duration", rest.len());
+            }
+
+            if !(months >= 0 && days >= 0 && nanos >= 0 || months <= 0 && days <= 0 && nanos <= 0) {
+                bail!("The duration months, days and nanoseconds must be all of the same sign");
+            }
+
+            Value::Duration(months, days, nanos)
+        }
+        ColumnType::List(element_type) => {
+            Value::List(deserialize_collection(bytes, &[element_type.as_ref()])?)
+        }
+        ColumnType::Set(element_type) => {
+            Value::set(deserialize_collection(bytes, &[element_type.as_ref()])?)
+        }
+        ColumnType::Map(key_type, value_type) => {
+            let mut elements =
+                deserialize_collection(bytes, &[key_type.as_ref(), value_type.as_ref()])?
+                    .into_iter();
+            let mut entries = vec![];
+
+            while let (Some(key), Some(value)) = (elements.next(), elements.next()) {
+                entries.push((key, value));
+            }
+
+            Value::map(entries)
+        }
+        ColumnType::Udt(user_type) => {
+            let field_types = user_type.fields.iter().map(|(_, field_type)| field_type);
+            let mut fields = deserialize_fields(bytes, field_types.collect())?;
+
+            // Clients may leave off trailing fields, e.g. ones added by ALTER TYPE
+            fields.resize(user_type.fields.len(), None);
+            Value::Udt(fields)
+        }
+        ColumnType::Tuple(element_types) => {
+            Value::Tuple(deserialize_fields(bytes, element_types.iter().collect())?)
+        }
+        ColumnType::Vector(element_type, dimension) => {
+            let mut rest = bytes;
+            let mut elements = vec![];
+
+            for _ in 0..*dimension {
+                let length = match fixed_length(element_type) {
+                    Some(length) => length,
+                    None => usize::try_from(read_unsigned_vint(&mut rest)?)?,
+                };
+
+                if rest.len() < length {
+                    bail!(
+                        "Expected {} elements for a {} value",
+                        dimension,
+                        column_type
+                    );
+                }
+
+                let (element, remaining) = rest.split_at(length);
+
+                elements.push(deserialize(element_type, element)?);
+                rest = remaining;
+            }
+
+            if !rest.is_empty() {
+                bail!("Unexpected {} bytes after vector", rest.len());
+            }
+
+            Value::Vector(elements)
+        }
+    };
+
+    Ok(value)
+}
+
+// Element types repeat, e.g. a map's elements alternate between key and value
+fn deserialize_collection(
+    mut bytes: &[u8],
+    element_types: &[&ColumnType],
+) -> anyhow::Result<Vec<Value>> {
+    let count = usize::try_from(read_int(&mut bytes)?)?;
+    let mut elements = vec![];
+
+    for element_type in element_types
+        .iter()
+        .cycle()
+        .take(count * element_types.len())
+    {
+        let element =
+            read_bytes(&mut bytes)?.ok_or_else(|| anyhow!("Invalid null collection element"))?;
+
+        elements.push(deserialize(element_type, element)?);
+    }
+
+    if !bytes.is_empty() {
+        bail!("Unexpected {} bytes after collection", bytes.len());
+    }
+
+    Ok(elements)
+}
+
+fn deserialize_fields(
+    mut bytes: &[u8],
+    field_types: Vec<&ColumnType>,
+) -> anyhow::Result<Vec<Option<Value>>> {
+    let mut fields = vec![];
+
+    for field_type in field_types {
+        if bytes.is_empty() {
+            break;
+        }
+
+        fields.push(
+            read_bytes(&mut bytes)?
+                .map(|field| deserialize(field_type, field))
+                .transpose()?,
+        );
+    }
+
+    if !bytes.is_empty() {
+        bail!("Unexpected {} bytes after last field", bytes.len());
+    }
+
+    Ok(fields)
+}
+
+// Vints are zigzag encoded so small negative numbers stay short, then written
+// big endian with a leading one bit in the first byte for every byte after it
+fn write_vint(value: i64, out: &mut Vec<u8>) {
+    write_unsigned_vint(((value << 1) ^ (value >> 63)) as u64, out)
+}
+
+fn write_unsigned_vint(value: u64, out: &mut Vec<u8>) {
+    let extra = (0..8)
+        .find(|extra| value < 1 << (7 * (extra + 1)))
+        .unwrap_or(8);
+
+    if extra == 8 {
+        out.push(0xFF);
+        out.extend(value.to_be_bytes());
+    } else {
+        let bytes = value.to_be_bytes();
+
+        out.push(bytes[7 - extra] | !(0xFF >> extra));
+        out.extend(&bytes[8 - extra..]);
+    }
+}
+
+fn read_vint(bytes: &mut &[u8]) -> anyhow::Result<i64> {
+    let value = read_unsigned_vint(bytes)?;
+
+    Ok((value >> 1) as i64 ^ -((value & 1) as i64))
+}
+
+fn read_unsigned_vint(bytes: &mut &[u8]) -> anyhow::Result<u64> {
+    let first = *bytes
+        .first()
+        .ok_or_else(|| anyhow!("Unexpected end of value"))?;
+    let extra = first.leading_ones() as usize;
+
+    if bytes.len() <= extra {
+        bail!("Unexpected end of value");
+    }
+
+    let mut value = (first & 0xFFu8.checked_shr(extra as u32).unwrap_or(0)) as u64;
+
+    for byte in &bytes[1..=extra] {
+        value = value << 8 | *byte as u64;
+    }
+
+    *bytes = &bytes[extra + 1..];
+    Ok(value)
+}
+
+fn read_int(bytes: &mut &[u8]) -> anyhow::Result<i32> {
+    if bytes.len() < 4 {
+        bail!("Unexpected end of value");
+    }
+
+    let (int, rest) = bytes.split_at(4);
+
+    *bytes = rest;
+    Ok(i32::from_be_bytes(int.try_into()?))
+}
+
+// A [bytes], where a negative length is null
+fn read_bytes<'a>(bytes: &mut &'a [u8]) -> anyhow::Result<Option<&'a [u8]>> {
+    let Ok(length) = usize::try_from(read_int(bytes)?) else {
+        return Ok(None);
+    };
+
+    if bytes.len() < length {
+        bail!("Unexpected end of value");
+    }
+
+    let (value, rest) = bytes.split_at(length);
+
+    *bytes = rest;
+    Ok(Some(value))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use bigdecimal::BigDecimal;
+
+    fn round_trip(column_type: &str, value: Value, bytes: &[u8]) {
+        let column_type = ColumnType::from_cql_type(column_type.to_string()).unwrap();
+
+        assert_eq!(serialize(&value), bytes);
+        assert_eq!(deserialize(&column_type, bytes).unwrap(), value);
+    }
+
+    #[test]
+    fn test_native_round_trips() {
+        round_trip("int", Value::Int(-2), &[0xFF, 0xFF, 0xFF, 0xFE]);
+        round_trip("counter", Value::Counter(1), &[0, 0, 0, 0, 0, 0, 0, 1]);
+        round_trip("ascii", Value::Ascii(b"ab".to_vec()), b"ab");
+        round_trip("boolean", Value::Boolean(true), &[1]);
+        round_trip(
+            "double",
+            Value::Double(1.5),
+            &[0x3F, 0xF8, 0, 0, 0, 0, 0, 0],
+        );
+        round_trip("float", Value::Float(1.5), &[0x3F, 0xC0, 0, 0]);
+        round_trip("smallint", Value::Smallint(-1), &[0xFF, 0xFF]);
+        round_trip("tinyint", Value::Tinyint(7), &[7]);
+        round_trip("text", Value::Varchar("é".to_string()), &[0xC3, 0xA9]);
+        // 1.50 is 150 at scale 2, 128 needs a sign byte
+        round_trip(
+            "decimal",
+            Value::Decimal(BigDecimal::new(BigInt::from(150), 2)),
+            &[0, 0, 0, 2, 0x00, 0x96],
+        );
+        round_trip("varint", Value::Varint(BigInt::from(128)), &[0x00, 0x80]);
+        round_trip("varint", Value::Varint(BigInt::from(-129)), &[0xFF, 0x7F]);
+        round_trip("varint", Value::Varint(BigInt::from(0)), &[0x00]);
+        round_trip("inet", Value::Inet(vec![127, 0, 0, 1]), &[127, 0, 0, 1]);
+        // 1970-01-01 is 2^31, 12:00:00 is 43200 seconds in nanoseconds
+        round_trip("date", Value::Date(0), &[0x80, 0, 0, 0]);
+        round_trip("date", Value::Date(-1), &[0x7F, 0xFF, 0xFF, 0xFF]);
+        round_trip(
+            "time",
+            Value::Time(43_200_000_000_000),
+            &[0x00, 0x00, 0x27, 0x4A, 0x48, 0xA7, 0x80, 0x00],
+        );
+        round_trip(
+            "timestamp",
+            Value::Timestamp(1_704_110_400_000),
+            &[0x00, 0x00, 0x01, 0x8C, 0xC4, 0xE5, 0x22, 0x00],
+        );
+
+        let uuid = Uuid::parse_str("123e4567-e89b-12d3-a456-426614174000").unwrap();
+
+        round_trip("timeuuid", Value::Timeuuid(uuid), uuid.as_bytes());
+    }
+
+    #[test]
+    fn test_duration_vints() {
+        // 1 month, 2 days and 1h zigzag to 2, 4 and 7.2e12, the last taking
+        // six bytes after its first
+        round_trip(
+            "duration",
+            Value::Duration(1, 2, 3_600_000_000_000),
+            &[0x02, 0x04, 0xFC, 0x06, 0x8C, 0x61, 0x71, 0x40, 0x00],
+        );
+        round_trip("duration", Value::Duration(0, -1, -1), &[0x00, 0x01, 0x01]);
+        round_trip(
+            "duration",
+            Value::Duration(0, 0, i64::MIN),
+            &[
+                0x00, 0x00, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF,
+            ],
+        );
+        round_trip(
+            "duration",
+            Value::Duration(0, 64, 0),
+            &[0x00, 0x80, 0x80, 0x00],
+        );
+
+        let column_type = ColumnType::Duration;
+
+        assert!(deserialize(&column_type, &[0x02, 0x01, 0x00]).is_err());
+        assert!(deserialize(&column_type, &[0x02, 0x02]).is_err());
+        assert!(deserialize(&column_type, &[0x02, 0x02, 0x02, 0x00]).is_err());
+    }
+
+    #[test]
+    fn test_serialize_collections() {
+        let list = Value::List(vec![Value::Int(1), Value::Int(2)]);
+
+        assert_eq!(
+            serialize(&list),
+            vec![0, 0, 0, 2, 0, 0, 0, 4, 0, 0, 0, 1, 0, 0, 0, 4, 0, 0, 0, 2]
+        );
+
+        let map = Value::Map(vec![(Value::Varchar("k".to_string()), Value::Int(1))]);
+
+        round_trip(
+            "map<text, int>",
+            map,
+            &[0, 0, 0, 1, 0, 0, 0, 1, b'k', 0, 0, 0, 4, 0, 0, 0, 1],
+        );
+        round_trip(
+            "set<int>",
+            Value::Set(vec![Value::Int(1)]),
+            &[0, 0, 0, 1, 0, 0, 0, 4, 0, 0, 0, 1],
+        );
+        round_trip(
+            "tuple<int, text>",
+            Value::Tuple(vec![Some(Value::Int(7)), None]),
+            &[0, 0, 0, 4, 0, 0, 0, 7, 0xFF, 0xFF, 0xFF, 0xFF],
+        );
+    }
+
+    #[test]
+    fn test_serialize_vectors() {
+        // Floats are concatenated, text elements each follow their length
+        round_trip(
+            "vector<float, 2>",
+            Value::Vector(vec![Value::Float(1.5), Value::Float(-2.0)]),
+            &[0x3F, 0xC0, 0, 0, 0xC0, 0, 0, 0],
+        );
+        round_trip(
+            "vector<text, 2>",
+            Value::Vector(vec![
+                Value::Varchar("ab".to_string()),
+                Value::Varchar(String::new()),
+            ]),
+            &[2, b'a', b'b', 0],
+        );
+
+        let column_type = ColumnType::from_cql_type("vector<float, 2>".to_string()).unwrap();
+
+        assert!(deserialize(&column_type, &[0x3F, 0xC0, 0, 0]).is_err());
+        assert!(deserialize(&column_type, &[0; 12]).is_err());
+    }
+
+    #[test]
+    fn test_deserialize_invalid() {
+        assert!(deserialize(&ColumnType::Int, &[0, 1]).is_err());
+        assert!(deserialize(&ColumnType::Varchar, &[0xFF]).is_err());
+        assert!(deserialize(&ColumnType::Inet, &[1, 2, 3]).is_err());
+        assert!(deserialize(
+            &ColumnType::List(Box::new(ColumnType::Int)),
+            &[0, 0, 0, 1, 0xFF, 0xFF, 0xFF, 0xFF]
+        )
+        .is_err());
+        assert!(deserialize(
+            &ColumnType::List(Box::new(ColumnType::Int)),
+            &[0, 0, 0, 2, 0, 0, 0, 4, 0, 0, 0, 1]
+        )
+        .is_err());
+    }
+}
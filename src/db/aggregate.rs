@@ -0,0 +1,340 @@
+use crate::db::builtins::{signature, ScalarFunction};
+use crate::db::data::{ColumnType, Value};
+use crate::db::error::DbError;
+use bigdecimal::{BigDecimal, RoundingMode};
+use num_bigint::BigInt;
+use std::sync::Arc;
+
+#[derive(Debug, Clone)]
+pub enum AggregateFunction {
+    Count,
+    Min,
+    Max,
+    Sum,
+    Avg,
+    User(Arc<UserAggregate>),
+}
+
+// CREATE AGGREGATE's state function folds each row's argument into the state,
+// which the final function, if any, turns into the result
+#[derive(Debug)]
+pub struct UserAggregate {
+    pub keyspace: String,
+    pub name: String,
+    pub argument_types: Vec<ColumnType>,
+    pub state_function: Arc<ScalarFunction>,
+    pub state_type: ColumnType,
+    pub final_function: Option<Arc<ScalarFunction>>,
+    pub initial_condition: Option<Value>,
+}
+
+impl UserAggregate {
+    pub fn handle(&self) -> String {
+        signature(&self.keyspace, &self.name, &self.argument_types)
+    }
+
+    pub fn return_type(&self) -> ColumnType {
+        match &self.final_function {
+            Some(final_function) => final_function.return_type.clone(),
+            None => self.state_type.clone(),
+        }
+    }
+}
+
+impl AggregateFunction {
+    pub fn from_name(name: &str) -> Option<AggregateFunction> {
+        match name.to_lowercase().as_str() {
+            "count" => Some(AggregateFunction::Count),
+            "min" => Some(AggregateFunction::Min),
+            "max" => Some(AggregateFunction::Max),
+            "sum" => Some(AggregateFunction::Sum),
+            "avg" => Some(AggregateFunction::Avg),
+            _ => None,
+        }
+    }
+
+    // As in Cassandra, counts are bigints, counters aggregate to bigints and
+    // everything else keeps the type of its argument
+    pub fn return_type(&self, argument_type: &ColumnType) -> Option<ColumnType> {
+        match (self, argument_type) {
+            (AggregateFunction::User(aggregate), _) => Some(aggregate.return_type()),
+            (AggregateFunction::Count, _) => Some(ColumnType::Bigint),
+            (_, ColumnType::Counter) => Some(ColumnType::Bigint),
+            (AggregateFunction::Min | AggregateFunction::Max, _) => Some(argument_type.clone()),
+            (AggregateFunction::Sum | AggregateFunction::Avg, _) if argument_type.is_numeric() => {
+                Some(argument_type.clone())
+            }
+            _ => None,
+        }
+    }
+}
+
+// The running state of an aggregate, fed the argument's value for each row.
+// Nulls are skipped, as they are by Cassandra.
+#[derive(Debug, Clone)]
+pub struct Aggregator {
+    function: AggregateFunction,
+    return_type: ColumnType,
+    count: i64,
+    extreme: Option<Value>,
+    total: Total,
+    state: Option<Value>,
+}
+
+// Sums are kept wider than the values they add up, then narrowed back to the
+// return type at the end
+#[derive(Debug, Clone)]
+enum Total {
+    Integer(i128),
+    Float(f64),
+    Varint(BigInt),
+    Decimal(BigDecimal),
+}
+
+impl Aggregator {
+    pub fn new(function: &AggregateFunction, return_type: &ColumnType) -> Aggregator {
+        let total = match return_type {
+            ColumnType::Float | ColumnType::Double => Total::Float(0.0),
+            ColumnType::Varint => Total::Varint(BigInt::from(0)),
+            ColumnType::Decimal => Total::Decimal(BigDecimal::from(0)),
+            _ => Total::Integer(0),
+        };
+
+        let state = match function {
+            AggregateFunction::User(aggregate) => aggregate.initial_condition.clone(),
+            _ => None,
+        };
+
+        Aggregator {
+            function: function.clone(),
+            return_type: return_type.clone(),
+            count: 0,
+            extreme: None,
+            total,
+            state,
+        }
+    }
+
+    pub fn add(&mut self, value: Option<Value>) -> Result<(), DbError> {
+        // State functions see nulls too, unless they return null on null input
+        if let AggregateFunction::User(aggregate) = &self.function {
+            let arguments = [self.state.take(), value];
+            let called_on_null_input = aggregate
+                .state_function
+                .definition
+                .as_ref()
+                .is_none_or(|definition| definition.called_on_null_input);
+
+            self.state = if called_on_null_input || arguments.iter().all(Option::is_some) {
+                aggregate.state_function.call(&arguments)?
+            } else {
+                arguments[0].clone()
+            };
+
+            return Ok(());
+        }
+
+        let Some(value) = value else {
+            return Ok(());
+        };
+
+        self.count += 1;
+
+        match &self.function {
+            AggregateFunction::User(_) | AggregateFunction::Count => {}
+            AggregateFunction::Min => {
+                if self.extreme.as_ref().is_none_or(|min| value < *min) {
+                    self.extreme = Some(value);
+                }
+            }
+            AggregateFunction::Max => {
+                if self.extreme.as_ref().is_none_or(|max| value > *max) {
+                    self.extreme = Some(value);
+                }
+            }
+            AggregateFunction::Sum | AggregateFunction::Avg => self.total.add(value),
+        }
+
+        Ok(())
+    }
+
+    pub fn result(&self) -> Result<Option<Value>, DbError> {
+        let result = match &self.function {
+            AggregateFunction::User(aggregate) => match &aggregate.final_function {
+                Some(final_function) => {
+                    return final_function.call(std::slice::from_ref(&self.state))
+                }
+                None => self.state.clone(),
+            },
+            AggregateFunction::Count => Some(Value::Bigint(self.count)),
+            AggregateFunction::Min | AggregateFunction::Max => match &self.extreme {
+                Some(Value::Counter(value)) => Some(Value::Bigint(*value)),
+                extreme => extreme.clone(),
+            },
+            AggregateFunction::Sum => Some(self.total.value(&self.return_type, 1)),
+            // The average of no values is zero rather than null
+            AggregateFunction::Avg => Some(self.total.value(&self.return_type, self.count.max(1))),
+        };
+
+        Ok(result)
+    }
+}
+
+impl Total {
+    fn add(&mut self, value: Value) {
+        match (self, value) {
+            (Total::Integer(total), Value::Tinyint(value)) => *total += value as i128,
+            (Total::Integer(total), Value::Smallint(value)) => *total += value as i128,
+            (Total::Integer(total), Value::Int(value)) => *total += value as i128,
+            (Total::Integer(total), Value::Bigint(value) | Value::Counter(value)) => {
+                *total += value as i128
+            }
+            (Total::Float(total), Value::Float(value)) => *total += value as f64,
+            (Total::Float(total), Value::Double(value)) => *total += value,
+            (Total::Varint(total), Value::Varint(value)) => *total += value,
+            (Total::Decimal(total), Value::Decimal(value)) => *total += value,
+            _ => {}
+        }
+    }
+
+    // Integer types wrap around on overflow like Cassandra's do, and integer
+    // averages are truncated
+    fn value(&self, return_type: &ColumnType, divisor: i64) -> Value {
+        match self {
+            Total::Integer(total) => {
+                let value = total / divisor as i128;
+
+                match return_type {
+                    ColumnType::Tinyint => Value::Tinyint(value as i8),
+                    ColumnType::Smallint => Value::Smallint(value as i16),
+                    ColumnType::Int => Value::Int(value as i32),
+                    _ => Value::Bigint(value as i64),
+                }
+            }
+            Total::Float(total) => match return_type {
+                ColumnType::Float => Value::Float((total / divisor as f64) as f32),
+                _ => Value::Double(total / divisor as f64),
+            },
+            Total::Varint(total) => Value::Varint(total / divisor),
+            // Averages keep the scale of the sum
+            Total::Decimal(total) if divisor == 1 => Value::Decimal(total.clone()),
+            Total::Decimal(total) => Value::Decimal(
+                (total / BigDecimal::from(divisor))
+                    .with_scale_round(total.fractional_digit_count(), RoundingMode::HalfEven),
+            ),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn aggregate(function: AggregateFunction, column_type: ColumnType, values: &[Value]) -> Value {
+        let return_type = function.return_type(&column_type).unwrap();
+        let mut aggregator = Aggregator::new(&function, &return_type);
+
+        for value in values {
+            aggregator.add(Some(value.clone())).unwrap();
+        }
+
+        aggregator.add(None).unwrap();
+        aggregator.result().unwrap().unwrap()
+    }
+
+    #[test]
+    fn test_aggregates() {
+        let ints = [Value::Int(3), Value::Int(-1), Value::Int(4)];
+
+        assert_eq!(
+            aggregate(AggregateFunction::Count, ColumnType::Int, &ints),
+            Value::Bigint(3)
+        );
+        assert_eq!(
+            aggregate(AggregateFunction::Min, ColumnType::Int, &ints),
+            Value::Int(-1)
+        );
+        assert_eq!(
+            aggregate(AggregateFunction::Max, ColumnType::Int, &ints),
+            Value::Int(4)
+        );
+        assert_eq!(
+            aggregate(AggregateFunction::Sum, ColumnType::Int, &ints),
+            Value::Int(6)
+        );
+        assert_eq!(
+            aggregate(AggregateFunction::Avg, ColumnType::Int, &ints),
+            Value::Int(2)
+        );
+        assert_eq!(
+            aggregate(AggregateFunction::Avg, ColumnType::Int, &[]),
+            Value::Int(0)
+        );
+        assert_eq!(
+            aggregate(
+                AggregateFunction::Sum,
+                ColumnType::Tinyint,
+                &[Value::Tinyint(100), Value::Tinyint(100)]
+            ),
+            Value::Tinyint(-56)
+        );
+        assert_eq!(
+            aggregate(
+                AggregateFunction::Avg,
+                ColumnType::Double,
+                &[Value::Double(1.0), Value::Double(2.0)]
+            ),
+            Value::Double(1.5)
+        );
+        assert_eq!(
+            aggregate(
+                AggregateFunction::Max,
+                ColumnType::Counter,
+                &[Value::Counter(2), Value::Counter(7)]
+            ),
+            Value::Bigint(7)
+        );
+        assert_eq!(
+            aggregate(
+                AggregateFunction::Min,
+                ColumnType::Varchar,
+                &[
+                    Value::Varchar("b".to_string()),
+                    Value::Varchar("a".to_string())
+                ]
+            ),
+            Value::Varchar("a".to_string())
+        );
+        assert!(AggregateFunction::Sum
+            .return_type(&ColumnType::Varchar)
+            .is_none());
+    }
+
+    #[test]
+    fn test_big_number_aggregates() {
+        let varints = ["99999999999999999999999999999", "1"]
+            .map(|varint| Value::Varint(varint.parse().unwrap()));
+
+        assert_eq!(
+            aggregate(AggregateFunction::Sum, ColumnType::Varint, &varints),
+            Value::Varint("100000000000000000000000000000".parse().unwrap())
+        );
+
+        let decimals =
+            ["10.25", "0.50", "1.00"].map(|decimal| Value::Decimal(decimal.parse().unwrap()));
+
+        assert_eq!(
+            aggregate(AggregateFunction::Sum, ColumnType::Decimal, &decimals),
+            Value::Decimal("11.75".parse().unwrap())
+        );
+
+        // 11.75 / 3 rounded half to even at the sum's scale
+        let Value::Decimal(average) =
+            aggregate(AggregateFunction::Avg, ColumnType::Decimal, &decimals)
+        else {
+            panic!("Expected a decimal average");
+        };
+
+        assert_eq!(average.to_string(), "3.92");
+    }
+}
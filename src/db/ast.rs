@@ -0,0 +1,533 @@
+use std::fmt::{self, Display, Formatter};
+
+pub enum Statement {
+    Select(Select),
+    Insert(Insert),
+    Update(Mutation),
+    Delete(Mutation),
+    Batch(Batch),
+    CreateTable(CreateTable),
+    // ALTER TABLE t WITH option AND option ...
+    AlterTable(Name, Vec<TableOption>),
+    CreateType(TypeDefinition),
+    AlterType(TypeAlteration),
+    DropType(Name, bool),
+    CreateFunction(FunctionDefinition),
+    CreateAggregate(AggregateDefinition),
+    CreateIndex(IndexDefinition),
+    DropIndex(Name, bool),
+    CreateView(ViewDefinition),
+    DropView(Name, bool),
+    Describe(DescribeKind, Option<Name>),
+}
+
+// An identifier, folded to lowercase unless it was double quoted
+#[derive(Debug, Clone, PartialEq)]
+pub struct Ident {
+    pub value: String,
+}
+
+// A name optionally qualified by a keyspace, e.g. `ks.t`
+#[derive(Debug, Clone, PartialEq)]
+pub struct Name {
+    pub keyspace: Option<Ident>,
+    pub name: Ident,
+}
+
+// SELECT [JSON] [DISTINCT] selection FROM t [WHERE ...] [GROUP BY ...]
+// [ORDER BY ...] [PER PARTITION LIMIT n] [LIMIT n] [ALLOW FILTERING]
+pub struct Select {
+    pub json: bool,
+    pub distinct: bool,
+    pub selection: Selection,
+    pub table: Name,
+    pub restriction: Option<Expr>,
+    pub group_by: Vec<Expr>,
+    pub order_by: Vec<Ordering>,
+    pub per_partition_limit: Option<Expr>,
+    pub limit: Option<Expr>,
+    pub allow_filtering: bool,
+}
+
+pub enum Selection {
+    Wildcard,
+    Selectors(Vec<Selector>),
+}
+
+pub struct Selector {
+    pub expr: Expr,
+    pub alias: Option<Ident>,
+}
+
+// `c [ASC | DESC]` or `v ANN OF [...]`
+pub struct Ordering {
+    pub column: Ident,
+    pub ann: Option<Expr>,
+    pub descending: bool,
+}
+
+// INSERT INTO t (c, ...) VALUES (v, ...) or INSERT INTO t JSON '{...}',
+// optionally IF NOT EXISTS and USING TTL or TIMESTAMP
+pub struct Insert {
+    pub table: Name,
+    pub values: InsertValues,
+    pub if_not_exists: bool,
+    pub using: Vec<(UsingOption, Expr)>,
+}
+
+pub enum InsertValues {
+    Columns(Vec<Ident>, Vec<Expr>),
+    // Columns missing from the document are written as null with DEFAULT NULL
+    // rather than left alone
+    Json { json: Expr, default_null: bool },
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum UsingOption {
+    Ttl,
+    Timestamp,
+}
+
+// UPDATE and DELETE can address single collection elements, e.g. `l[2] = 1`
+// or `DELETE m['k']`
+pub struct Mutation {
+    pub table: Name,
+    pub assignments: Vec<ElementAssignment>,
+    pub selection: Expr,
+    pub using: Vec<(UsingOption, Expr)>,
+    pub conditions: Option<Conditions>,
+}
+
+// IF EXISTS or IF c = v AND ..., the conditions of a lightweight transaction
+pub enum Conditions {
+    Exists,
+    Columns(Expr),
+}
+
+// `value` is absent for the columns and elements listed by a DELETE
+pub struct ElementAssignment {
+    pub column: Ident,
+    pub element: Option<Expr>,
+    pub field: Option<Ident>,
+    pub value: Option<Expr>,
+}
+
+// BEGIN [UNLOGGED | COUNTER] BATCH [USING TIMESTAMP t] ... APPLY BATCH
+pub struct Batch {
+    pub kind: BatchKind,
+    pub using: Vec<(UsingOption, Expr)>,
+    pub statements: Vec<Statement>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum BatchKind {
+    Logged,
+    Unlogged,
+    Counter,
+}
+
+pub struct CreateTable {
+    pub name: Name,
+    pub columns: Vec<ColumnDefinition>,
+    pub primary_key: Option<PrimaryKey>,
+    pub options: Vec<TableOption>,
+}
+
+pub struct ColumnDefinition {
+    pub name: Ident,
+    pub column_type: String,
+    pub is_static: bool,
+    // A column level PRIMARY KEY, making the column the whole key
+    pub primary_key: bool,
+}
+
+// `(k, c1, c2)` or `((k1, k2), c1, c2)`
+pub struct PrimaryKey {
+    pub partition_key: Vec<Ident>,
+    pub clustering_key: Vec<Ident>,
+}
+
+// WITH CLUSTERING ORDER BY (c DESC) AND COMPACT STORAGE AND comment = '...'
+pub enum TableOption {
+    ClusteringOrder(Vec<(Ident, bool)>),
+    CompactStorage,
+    Property(Ident, Expr),
+}
+
+pub struct TypeDefinition {
+    pub name: Name,
+    pub if_not_exists: bool,
+    pub fields: Vec<(Ident, String)>,
+}
+
+pub struct TypeAlteration {
+    pub name: Name,
+    pub change: FieldChange,
+}
+
+pub enum FieldChange {
+    Add(Ident, String),
+    Rename(Vec<(Ident, Ident)>),
+}
+
+pub struct FunctionDefinition {
+    pub name: Name,
+    pub or_replace: bool,
+    pub if_not_exists: bool,
+    pub arguments: Vec<(Ident, String)>,
+    pub called_on_null_input: bool,
+    pub return_type: String,
+    pub language: Ident,
+    pub body: String,
+}
+
+pub struct AggregateDefinition {
+    pub name: Name,
+    pub or_replace: bool,
+    pub if_not_exists: bool,
+    pub argument_types: Vec<String>,
+    pub state_function: Name,
+    pub state_type: String,
+    pub final_function: Option<Name>,
+    pub initial_condition: Option<Expr>,
+}
+
+// CREATE [CUSTOM] INDEX [IF NOT EXISTS] [name] ON t (c | keys(c) | values(c) | entries(c) | full(c))
+// [USING 'class' [WITH OPTIONS = {'option': 'value', ...}]]
+pub struct IndexDefinition {
+    pub name: Option<Ident>,
+    pub if_not_exists: bool,
+    pub table: Name,
+    pub column: Ident,
+    pub target: Option<Ident>,
+    pub class: Option<String>,
+    pub options: Option<Expr>,
+}
+
+// CREATE MATERIALIZED VIEW [IF NOT EXISTS] v AS SELECT ... FROM t WHERE ...
+// PRIMARY KEY (...) [WITH ...]
+pub struct ViewDefinition {
+    pub name: Name,
+    pub if_not_exists: bool,
+    pub query: Select,
+    pub primary_key: PrimaryKey,
+    pub options: Vec<TableOption>,
+}
+
+// DESCRIBE KEYSPACES | KEYSPACE [k] | SCHEMA | TABLES | TABLE t | TYPES | TYPE t
+// | FUNCTIONS | FUNCTION f | AGGREGATES | AGGREGATE a | INDEX i | MATERIALIZED VIEW v,
+// the plural forms listing the objects and the others giving the CQL creating them
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum DescribeKind {
+    Keyspaces,
+    Keyspace,
+    Schema,
+    Tables,
+    Table,
+    Types,
+    Type,
+    Functions,
+    Function,
+    Aggregates,
+    Aggregate,
+    Index,
+    View,
+}
+
+impl DescribeKind {
+    pub fn is_listing(&self) -> bool {
+        matches!(
+            self,
+            DescribeKind::Keyspaces
+                | DescribeKind::Tables
+                | DescribeKind::Types
+                | DescribeKind::Functions
+                | DescribeKind::Aggregates
+        )
+    }
+}
+
+// Terms, selectors and the relations of WHERE and IF clauses
+#[derive(Debug, Clone, PartialEq)]
+pub enum Expr {
+    Identifier(Ident),
+    // A field of a user type value, u.f
+    Field(Box<Expr>, Ident),
+    Literal(Literal),
+    // `?` markers are numbered in the order they appear, as ?1, ?2, ..., and
+    // named ones kept as :name
+    Marker(String),
+    Negate(Box<Expr>),
+    BinaryOp {
+        left: Box<Expr>,
+        op: Operator,
+        right: Box<Expr>,
+    },
+    IsNotNull(Box<Expr>),
+    // A list element or map value, l[i] or m[k]
+    Index(Box<Expr>, Box<Expr>),
+    Function(Function),
+    Cast(Box<Expr>, String),
+    List(Vec<Expr>),
+    Set(Vec<Expr>),
+    // Map literals, `{}` and user type literals such as {f: 1}
+    Map(Vec<(Expr, Expr)>),
+    Tuple(Vec<Expr>),
+    Nested(Box<Expr>),
+    // The * of count(*)
+    Wildcard,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Function {
+    pub name: Name,
+    pub args: Vec<Expr>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Literal {
+    String(String),
+    // Integers, decimals and the NaN and Infinity floats
+    Number(String),
+    Boolean(bool),
+    Null,
+    // The digits of a 0x blob
+    Hex(String),
+    // 1h30m, P1DT2H or P0001-02-03T04:05:06
+    Duration(String),
+    Uuid(String),
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Operator {
+    Eq,
+    NotEq,
+    Lt,
+    LtEq,
+    Gt,
+    GtEq,
+    Plus,
+    Minus,
+    Multiply,
+    Divide,
+    Modulo,
+    And,
+    Contains,
+    ContainsKey,
+    Like,
+    In,
+}
+
+impl Ident {
+    pub fn new(value: impl Into<String>) -> Ident {
+        Ident {
+            value: value.into(),
+        }
+    }
+}
+
+impl Display for Ident {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", quote_identifier(&self.value))
+    }
+}
+
+// Words that can only be used as identifiers when double quoted
+const RESERVED: [&str; 56] = [
+    "add",
+    "allow",
+    "alter",
+    "and",
+    "apply",
+    "asc",
+    "authorize",
+    "batch",
+    "begin",
+    "by",
+    "columnfamily",
+    "create",
+    "delete",
+    "desc",
+    "describe",
+    "drop",
+    "entries",
+    "execute",
+    "from",
+    "full",
+    "grant",
+    "if",
+    "in",
+    "index",
+    "infinity",
+    "insert",
+    "into",
+    "keyspace",
+    "limit",
+    "modify",
+    "nan",
+    "norecursive",
+    "not",
+    "null",
+    "of",
+    "on",
+    "or",
+    "order",
+    "primary",
+    "rename",
+    "replace",
+    "revoke",
+    "schema",
+    "select",
+    "set",
+    "table",
+    "to",
+    "token",
+    "truncate",
+    "unlogged",
+    "update",
+    "use",
+    "using",
+    "view",
+    "where",
+    "with",
+];
+
+pub fn is_reserved(word: &str) -> bool {
+    RESERVED.contains(&word.to_lowercase().as_str())
+}
+
+// Renders a name so that it reads back as itself, quoting it when it has
+// uppercase or other characters, or is a reserved word
+pub fn quote_identifier(name: &str) -> String {
+    let mut chars = name.chars();
+    let plain = chars.next().is_some_and(|first| first.is_ascii_lowercase())
+        && chars.all(|c| c.is_ascii_lowercase() || c.is_ascii_digit() || c == '_');
+
+    if plain && !is_reserved(name) {
+        name.to_string()
+    } else {
+        format!("\"{}\"", name.replace('"', "\"\""))
+    }
+}
+
+impl Display for Name {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        match &self.keyspace {
+            Some(keyspace) => write!(f, "{}.{}", keyspace, self.name),
+            None => write!(f, "{}", self.name),
+        }
+    }
+}
+
+impl Display for TableOption {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        match self {
+            TableOption::ClusteringOrder(order) => {
+                let order = order
+                    .iter()
+                    .map(|(column, descending)| {
+                        format!("{} {}", column, if *descending { "DESC" } else { "ASC" })
+                    })
+                    .collect::<Vec<_>>();
+
+                write!(f, "CLUSTERING ORDER BY ({})", order.join(", "))
+            }
+            TableOption::CompactStorage => write!(f, "COMPACT STORAGE"),
+            TableOption::Property(name, value) => write!(f, "{} = {}", name, value),
+        }
+    }
+}
+
+impl Display for Operator {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        let symbol = match self {
+            Operator::Eq => "=",
+            Operator::NotEq => "!=",
+            Operator::Lt => "<",
+            Operator::LtEq => "<=",
+            Operator::Gt => ">",
+            Operator::GtEq => ">=",
+            Operator::Plus => "+",
+            Operator::Minus => "-",
+            Operator::Multiply => "*",
+            Operator::Divide => "/",
+            Operator::Modulo => "%",
+            Operator::And => "AND",
+            Operator::Contains => "CONTAINS",
+            Operator::ContainsKey => "CONTAINS KEY",
+            Operator::Like => "LIKE",
+            Operator::In => "IN",
+        };
+
+        write!(f, "{}", symbol)
+    }
+}
+
+impl Display for Literal {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        match self {
+            Literal::String(text) => write!(f, "'{}'", text.replace('\'', "''")),
+            Literal::Number(number) => write!(f, "{}", number),
+            Literal::Boolean(boolean) => write!(f, "{}", boolean),
+            Literal::Null => write!(f, "null"),
+            Literal::Hex(hex) => write!(f, "0x{}", hex),
+            Literal::Duration(duration) => write!(f, "{}", duration),
+            Literal::Uuid(uuid) => write!(f, "{}", uuid),
+        }
+    }
+}
+
+impl Display for Function {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        // Function names may be reserved words such as token, so go unquoted
+        if let Some(keyspace) = &self.name.keyspace {
+            write!(f, "{}.", keyspace.value)?;
+        }
+
+        write!(
+            f,
+            "{}({})",
+            self.name.name.value,
+            comma_separated(&self.args)
+        )
+    }
+}
+
+impl Display for Expr {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        match self {
+            Expr::Identifier(ident) => write!(f, "{}", ident),
+            Expr::Field(expr, field) => write!(f, "{}.{}", expr, field),
+            Expr::Literal(literal) => write!(f, "{}", literal),
+            Expr::Marker(name) if name.starts_with('?') => write!(f, "?"),
+            Expr::Marker(name) => write!(f, "{}", name),
+            Expr::Negate(expr) => write!(f, "-{}", expr),
+            Expr::BinaryOp { left, op, right } => write!(f, "{} {} {}", left, op, right),
+            Expr::IsNotNull(expr) => write!(f, "{} IS NOT NULL", expr),
+            Expr::Index(expr, key) => write!(f, "{}[{}]", expr, key),
+            Expr::Function(function) => write!(f, "{}", function),
+            Expr::Cast(expr, data_type) => write!(f, "CAST({} AS {})", expr, data_type),
+            Expr::List(elements) => write!(f, "[{}]", comma_separated(elements)),
+            Expr::Set(elements) => write!(f, "{{{}}}", comma_separated(elements)),
+            Expr::Map(entries) => {
+                let entries = entries
+                    .iter()
+                    .map(|(key, value)| format!("{}: {}", key, value))
+                    .collect::<Vec<_>>();
+
+                write!(f, "{{{}}}", entries.join(", "))
+            }
+            Expr::Tuple(elements) => write!(f, "({})", comma_separated(elements)),
+            Expr::Nested(expr) => write!(f, "({})", expr),
+            Expr::Wildcard => write!(f, "*"),
+        }
+    }
+}
+
+pub fn comma_separated<T: Display>(items: &[T]) -> String {
+    items
+        .iter()
+        .map(ToString::to_string)
+        .collect::<Vec<_>>()
+        .join(", ")
+}
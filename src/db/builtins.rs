@@ -1,8 +1,25 @@
-use crate::db::data::Value;
+use crate::cql::value::{deserialize, serialize};
+use crate::db::aggregate::UserAggregate;
+use crate::db::data::{ColumnType, Value};
 use crate::db::error::{DbError, ErrorCode};
+use crate::db::json::{from_json, to_json, Json};
+use crate::db::literal::{format_date, format_duration, format_time, format_timestamp};
+use crate::db::schema::{TextAnalysis, UserFunction};
+use crate::db::vector::{floats, Similarity};
+use bigdecimal::{BigDecimal, RoundingMode};
+use indexmap::IndexMap;
+use num_bigint::BigInt;
+use num_traits::{FromPrimitive, ToPrimitive, Zero};
+use std::cmp::Ordering;
 use std::collections::HashMap;
+use std::fmt::{self, Debug, Formatter};
+use std::net::IpAddr;
+use std::num::NonZeroU64;
+use std::sync::{Arc, LazyLock};
+use std::time::{SystemTime, UNIX_EPOCH};
+use uuid::Uuid;
 
-pub type Function = fn(Vec<Option<Value>>) -> Value;
+pub type Function = Box<dyn Fn(&[Option<Value>]) -> Result<Option<Value>, DbError> + Send + Sync>;
 
 pub type FilterFunction = fn(Vec<Option<Value>>) -> Result<bool, DbError>;
 
@@ -26,10 +43,1226 @@ pub fn neq(args: Vec<Option<Value>>) -> Result<bool, DbError> {
     eq(args).map(|result| !result)
 }
 
+// Values of different types, or that have no order such as durations, never
+// compare as greater or less than one another
+fn compare(args: Vec<Option<Value>>, accept: fn(Ordering) -> bool) -> Result<bool, DbError> {
+    if args.len() != 2 {
+        return Err(DbError::new(
+            ErrorCode::Invalid,
+            "comparison function requires exactly 2 arguments".to_string(),
+        ));
+    }
+
+    match (args[0].as_ref(), args[1].as_ref()) {
+        (Some(a), Some(b)) => Ok(a.partial_cmp(b).is_some_and(accept)),
+        _ => Ok(false),
+    }
+}
+
+pub fn gt(args: Vec<Option<Value>>) -> Result<bool, DbError> {
+    compare(args, Ordering::is_gt)
+}
+
+pub fn gte(args: Vec<Option<Value>>) -> Result<bool, DbError> {
+    compare(args, Ordering::is_ge)
+}
+
+pub fn lt(args: Vec<Option<Value>>) -> Result<bool, DbError> {
+    compare(args, Ordering::is_lt)
+}
+
+pub fn lte(args: Vec<Option<Value>>) -> Result<bool, DbError> {
+    compare(args, Ordering::is_le)
+}
+
+// c CONTAINS v, on the elements of a list or set or the values of a map
+pub fn contains(args: Vec<Option<Value>>) -> Result<bool, DbError> {
+    match (&args[..], args.len()) {
+        ([Some(Value::List(elements) | Value::Set(elements)), Some(value)], 2) => {
+            Ok(elements.contains(value))
+        }
+        ([Some(Value::Map(entries)), Some(value)], 2) => {
+            Ok(entries.iter().any(|(_, entry)| entry == value))
+        }
+        (_, 2) => Ok(false),
+        _ => Err(DbError::new(
+            ErrorCode::Invalid,
+            "contains function requires exactly 2 arguments".to_string(),
+        )),
+    }
+}
+
+// m CONTAINS KEY k
+pub fn contains_key(args: Vec<Option<Value>>) -> Result<bool, DbError> {
+    match (&args[..], args.len()) {
+        ([Some(Value::Map(entries)), Some(key)], 2) => {
+            Ok(entries.iter().any(|(entry, _)| entry == key))
+        }
+        (_, 2) => Ok(false),
+        _ => Err(DbError::new(
+            ErrorCode::Invalid,
+            "contains key function requires exactly 2 arguments".to_string(),
+        )),
+    }
+}
+
+// m[k] = v
+pub fn entry_eq(args: Vec<Option<Value>>) -> Result<bool, DbError> {
+    match (&args[..], args.len()) {
+        ([Some(Value::Map(entries)), Some(key), Some(value)], 3) => Ok(entries
+            .iter()
+            .any(|entry| (&entry.0, &entry.1) == (key, value))),
+        (_, 3) => Ok(false),
+        _ => Err(DbError::new(
+            ErrorCode::Invalid,
+            "map entry equals function requires exactly 3 arguments".to_string(),
+        )),
+    }
+}
+
+// c LIKE 'p%', on text starting with a prefix
+pub fn like(args: Vec<Option<Value>>) -> Result<bool, DbError> {
+    match (&args[..], args.len()) {
+        ([Some(Value::Varchar(text)), Some(Value::Varchar(pattern))], 2) => {
+            Ok(text.starts_with(like_prefix(pattern)?))
+        }
+        ([Some(Value::Ascii(text)), Some(Value::Ascii(pattern))], 2) => {
+            let pattern = String::from_utf8_lossy(pattern);
+
+            Ok(text.starts_with(like_prefix(&pattern)?.as_bytes()))
+        }
+        (_, 2) => Ok(false),
+        _ => Err(DbError::new(
+            ErrorCode::Invalid,
+            "like function requires exactly 2 arguments".to_string(),
+        )),
+    }
+}
+
+// The prefix of a LIKE pattern, the only kind of pattern supported
+pub fn like_prefix(pattern: &str) -> Result<&str, DbError> {
+    match pattern.strip_suffix('%') {
+        Some(prefix) if !prefix.contains('%') => Ok(prefix),
+        _ => Err(DbError::new(
+            ErrorCode::Invalid,
+            format!(
+                "Unsupported LIKE pattern '{}', only prefix patterns such as 'abc%' are supported",
+                pattern
+            ),
+        )),
+    }
+}
+
 fn build_filters() -> HashMap<String, FilterFunction> {
     let mut filters: HashMap<String, FilterFunction> = HashMap::new();
 
     filters.insert("eq".to_string(), eq);
+    filters.insert("gt".to_string(), gt);
+    filters.insert("gte".to_string(), gte);
+    filters.insert("lt".to_string(), lt);
+    filters.insert("lte".to_string(), lte);
 
     filters
 }
+
+// A scalar function, one for each signature of an overloaded name
+pub struct ScalarFunction {
+    pub keyspace: String,
+    pub name: String,
+    pub argument_types: Vec<ColumnType>,
+    pub return_type: ColumnType,
+    // How a user-defined function was created, None for native ones
+    pub definition: Option<UserFunction>,
+    body: Function,
+}
+
+impl ScalarFunction {
+    pub fn new(
+        keyspace: &str,
+        name: &str,
+        argument_types: Vec<ColumnType>,
+        return_type: ColumnType,
+        definition: Option<UserFunction>,
+        body: impl Fn(&[Option<Value>]) -> Result<Option<Value>, DbError> + Send + Sync + 'static,
+    ) -> ScalarFunction {
+        ScalarFunction {
+            keyspace: keyspace.to_string(),
+            name: name.to_string(),
+            argument_types,
+            return_type,
+            definition,
+            body: Box::new(body),
+        }
+    }
+
+    // Identifies the function in parsed expressions, e.g. system.totimestamp(date)
+    pub fn handle(&self) -> String {
+        signature(&self.keyspace, &self.name, &self.argument_types)
+    }
+
+    pub fn call(&self, arguments: &[Option<Value>]) -> Result<Option<Value>, DbError> {
+        (self.body)(arguments)
+    }
+}
+
+impl Debug for ScalarFunction {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.handle())
+    }
+}
+
+// Functions of a keyspace the statement didn't name are known by their name
+// alone, keyspaces not being modelled yet
+pub fn signature(keyspace: &str, name: &str, argument_types: &[ColumnType]) -> String {
+    let argument_types = argument_types
+        .iter()
+        .map(|argument_type| argument_type.to_string())
+        .collect::<Vec<_>>();
+
+    match keyspace {
+        "" => format!("{}({})", name, argument_types.join(", ")),
+        keyspace => format!("{}.{}({})", keyspace, name, argument_types.join(", ")),
+    }
+}
+
+// The native functions along with the user-defined functions and aggregates
+// of a database
+#[derive(Clone)]
+pub struct FunctionCatalog {
+    functions: IndexMap<String, Arc<ScalarFunction>>,
+    aggregates: IndexMap<String, Arc<UserAggregate>>,
+}
+
+impl Default for FunctionCatalog {
+    fn default() -> FunctionCatalog {
+        catalog().clone()
+    }
+}
+
+impl FunctionCatalog {
+    pub fn get(&self, handle: &str) -> Option<&Arc<ScalarFunction>> {
+        self.functions.get(handle)
+    }
+
+    pub fn overloads<'a>(&'a self, name: &'a str) -> impl Iterator<Item = &'a Arc<ScalarFunction>> {
+        self.functions
+            .values()
+            .filter(move |function| function.name == name)
+    }
+
+    pub fn get_aggregate(&self, handle: &str) -> Option<&Arc<UserAggregate>> {
+        self.aggregates.get(handle)
+    }
+
+    pub fn aggregate_overloads<'a>(
+        &'a self,
+        name: &'a str,
+    ) -> impl Iterator<Item = &'a Arc<UserAggregate>> {
+        self.aggregates
+            .values()
+            .filter(move |aggregate| aggregate.name == name)
+    }
+
+    pub fn user_functions(&self) -> impl Iterator<Item = &Arc<ScalarFunction>> {
+        self.functions
+            .values()
+            .filter(|function| function.definition.is_some())
+    }
+
+    pub fn aggregates(&self) -> impl Iterator<Item = &Arc<UserAggregate>> {
+        self.aggregates.values()
+    }
+
+    // Replaces any function of the same signature. Functions and aggregates
+    // calling the one replaced keep calling it.
+    pub fn insert(&mut self, function: ScalarFunction) {
+        self.functions.insert(function.handle(), Arc::new(function));
+    }
+
+    pub fn insert_aggregate(&mut self, aggregate: UserAggregate) {
+        self.aggregates
+            .insert(aggregate.handle(), Arc::new(aggregate));
+    }
+
+    pub fn register(
+        &mut self,
+        name: &str,
+        argument_types: Vec<ColumnType>,
+        return_type: ColumnType,
+        body: impl Fn(&[Option<Value>]) -> Result<Option<Value>, DbError> + Send + Sync + 'static,
+    ) {
+        self.insert(ScalarFunction::new(
+            "system",
+            name,
+            argument_types,
+            return_type,
+            None,
+            body,
+        ));
+    }
+
+    // Most functions are null when any of their arguments is
+    fn register_strict(
+        &mut self,
+        name: &str,
+        argument_types: Vec<ColumnType>,
+        return_type: ColumnType,
+        body: impl Fn(&[Value]) -> Result<Value, DbError> + Send + Sync + 'static,
+    ) {
+        self.register(
+            name,
+            argument_types,
+            return_type,
+            move |arguments| match arguments.iter().cloned().collect::<Option<Vec<Value>>>() {
+                Some(arguments) => body(&arguments).map(Some),
+                None => Ok(None),
+            },
+        );
+    }
+}
+
+static NATIVE_FUNCTIONS: LazyLock<FunctionCatalog> = LazyLock::new(native_functions);
+
+pub fn catalog() -> &'static FunctionCatalog {
+    &NATIVE_FUNCTIONS
+}
+
+const NATIVE_TYPES: [ColumnType; 20] = [
+    ColumnType::Ascii,
+    ColumnType::Bigint,
+    ColumnType::Blob,
+    ColumnType::Boolean,
+    ColumnType::Counter,
+    ColumnType::Decimal,
+    ColumnType::Double,
+    ColumnType::Duration,
+    ColumnType::Float,
+    ColumnType::Inet,
+    ColumnType::Int,
+    ColumnType::Smallint,
+    ColumnType::Date,
+    ColumnType::Time,
+    ColumnType::Timestamp,
+    ColumnType::Timeuuid,
+    ColumnType::Tinyint,
+    ColumnType::Uuid,
+    ColumnType::Varchar,
+    ColumnType::Varint,
+];
+
+const NUMERIC_TYPES: [ColumnType; 8] = [
+    ColumnType::Tinyint,
+    ColumnType::Smallint,
+    ColumnType::Int,
+    ColumnType::Bigint,
+    ColumnType::Float,
+    ColumnType::Double,
+    ColumnType::Decimal,
+    ColumnType::Varint,
+];
+
+// The functions arithmetic operators call, named as Cassandra names them
+pub const OPERATORS: [(&str, &str); 5] = [
+    ("_add", "+"),
+    ("_subtract", "-"),
+    ("_multiply", "*"),
+    ("_divide", "/"),
+    ("_modulo", "%"),
+];
+
+const MILLIS_PER_DAY: i64 = 86_400_000;
+
+fn native_functions() -> FunctionCatalog {
+    let mut catalog = FunctionCatalog {
+        functions: IndexMap::new(),
+        aggregates: IndexMap::new(),
+    };
+
+    register_time_functions(&mut catalog);
+    register_blob_conversions(&mut catalog);
+    register_casts(&mut catalog);
+    register_operators(&mut catalog);
+
+    catalog.register("uuid", vec![], ColumnType::Uuid, |_| {
+        Ok(Some(Value::Uuid(Uuid::new_v4())))
+    });
+    // The token of a partition key, whichever columns make it up
+    catalog.register_strict("token", vec![], ColumnType::Bigint, |key| {
+        Ok(Value::Bigint(token(key)))
+    });
+
+    catalog
+}
+
+// toJson and fromJson convert from and to any type, so aren't in the catalog
+// but made for each call, with the type it's made for
+pub fn to_json_function(argument_type: ColumnType) -> ScalarFunction {
+    let column_type = argument_type.clone();
+
+    ScalarFunction::new(
+        "system",
+        "tojson",
+        vec![argument_type],
+        ColumnType::Varchar,
+        None,
+        move |arguments| {
+            let json = to_json(arguments[0].as_ref(), &column_type);
+
+            Ok(Some(Value::Varchar(json.to_string())))
+        },
+    )
+}
+
+pub fn from_json_function(return_type: ColumnType) -> ScalarFunction {
+    let column_type = return_type.clone();
+
+    ScalarFunction::new(
+        "system",
+        "fromjson",
+        vec![ColumnType::Varchar],
+        return_type,
+        None,
+        move |arguments| match &arguments[0] {
+            Some(Value::Varchar(text)) => from_json(&Json::parse(text)?, &column_type),
+            _ => Ok(None),
+        },
+    )
+}
+
+// Text as a storage-attached index analyzes it, for restrictions on what it
+// indexes to match the way it does
+pub fn analyze_function(analysis: TextAnalysis, argument_type: ColumnType) -> ScalarFunction {
+    ScalarFunction::new(
+        "system",
+        "analyze",
+        vec![argument_type.clone()],
+        argument_type,
+        None,
+        move |arguments| Ok(arguments[0].as_ref().map(|value| analysis.analyze(value))),
+    )
+}
+
+// similarity_cosine, similarity_dot_product and similarity_euclidean take
+// vectors of any dimension, so are made for each call like toJson
+pub fn similarity_function(similarity: Similarity, argument_type: ColumnType) -> ScalarFunction {
+    let name = format!("similarity_{}", similarity.name());
+    let message = format!("Function system.{} doesn't support all-zero vectors", name);
+
+    ScalarFunction::new(
+        "system",
+        &name,
+        vec![argument_type.clone(), argument_type],
+        ColumnType::Float,
+        None,
+        move |arguments| match arguments {
+            [Some(a), Some(b)] => {
+                let score = floats(a)
+                    .zip(floats(b))
+                    .and_then(|(a, b)| similarity.score(&a, &b))
+                    .ok_or_else(|| DbError::new(ErrorCode::Invalid, message.clone()))?;
+
+                Ok(Some(Value::Float(score)))
+            }
+            _ => Ok(None),
+        },
+    )
+}
+
+fn register_time_functions(catalog: &mut FunctionCatalog) {
+    for name in ["now", "currenttimeuuid"] {
+        catalog.register(name, vec![], ColumnType::Timeuuid, |_| {
+            Ok(Some(Value::Timeuuid(new_timeuuid())))
+        });
+    }
+
+    catalog.register("currenttimestamp", vec![], ColumnType::Timestamp, |_| {
+        Ok(Some(Value::Timestamp(current_millis())))
+    });
+    catalog.register("currentdate", vec![], ColumnType::Date, |_| {
+        Ok(Some(Value::Date(
+            current_millis().div_euclid(MILLIS_PER_DAY) as i32,
+        )))
+    });
+    catalog.register("currenttime", vec![], ColumnType::Time, |_| {
+        Ok(Some(Value::Time(
+            current_millis().rem_euclid(MILLIS_PER_DAY) * 1_000_000,
+        )))
+    });
+
+    for source in [ColumnType::Timeuuid, ColumnType::Date] {
+        catalog.register_strict(
+            "totimestamp",
+            vec![source],
+            ColumnType::Timestamp,
+            |arguments| Ok(Value::Timestamp(millis(&arguments[0]))),
+        );
+    }
+
+    for source in [ColumnType::Timeuuid, ColumnType::Timestamp] {
+        catalog.register_strict("todate", vec![source], ColumnType::Date, |arguments| {
+            Ok(Value::Date(
+                millis(&arguments[0]).div_euclid(MILLIS_PER_DAY) as i32,
+            ))
+        });
+    }
+
+    for source in [
+        ColumnType::Timeuuid,
+        ColumnType::Timestamp,
+        ColumnType::Date,
+    ] {
+        catalog.register_strict(
+            "tounixtimestamp",
+            vec![source],
+            ColumnType::Bigint,
+            |arguments| Ok(Value::Bigint(millis(&arguments[0]))),
+        );
+    }
+
+    // The smallest and largest timeuuids of a millisecond, for range queries
+    catalog.register_strict(
+        "mintimeuuid",
+        vec![ColumnType::Timestamp],
+        ColumnType::Timeuuid,
+        |arguments| {
+            let ticks = millis(&arguments[0]) * 10_000;
+
+            Ok(Value::Timeuuid(timeuuid(ticks, MIN_CLOCK_SEQ_AND_NODE)))
+        },
+    );
+    catalog.register_strict(
+        "maxtimeuuid",
+        vec![ColumnType::Timestamp],
+        ColumnType::Timeuuid,
+        |arguments| {
+            let ticks = millis(&arguments[0]) * 10_000 + 9_999;
+
+            Ok(Value::Timeuuid(timeuuid(ticks, MAX_CLOCK_SEQ_AND_NODE)))
+        },
+    );
+}
+
+// typeAsBlob and blobAsType for every native type, text also going by varchar
+fn register_blob_conversions(catalog: &mut FunctionCatalog) {
+    for native_type in NATIVE_TYPES {
+        if native_type == ColumnType::Blob {
+            continue;
+        }
+
+        let mut names = vec![native_type.to_string()];
+
+        if native_type == ColumnType::Varchar {
+            names.push("varchar".to_string());
+        }
+
+        for name in names {
+            catalog.register_strict(
+                &format!("{}asblob", name),
+                vec![native_type.clone()],
+                ColumnType::Blob,
+                |arguments| Ok(Value::Blob(serialize(&arguments[0]))),
+            );
+
+            let target = native_type.clone();
+
+            catalog.register_strict(
+                &format!("blobas{}", name),
+                vec![ColumnType::Blob],
+                native_type.clone(),
+                move |arguments| match &arguments[0] {
+                    Value::Blob(bytes) => deserialize(&target, bytes).map_err(|_| {
+                        DbError::new(
+                            ErrorCode::Invalid,
+                            format!("In call to function system.blobas{}, value 0x{} is not a valid binary representation for type {}", target, hex(bytes), target),
+                        )
+                    }),
+                    _ => Err(DbError::new(
+                        ErrorCode::Invalid,
+                        "Expected a blob".to_string(),
+                    )),
+                },
+            );
+        }
+    }
+}
+
+// CAST(x AS type) between numeric types, of times to dates and timestamps,
+// and of any native type to text
+fn register_casts(catalog: &mut FunctionCatalog) {
+    let numeric_sources = NUMERIC_TYPES.iter().chain([&ColumnType::Counter]);
+    let mut casts = numeric_sources
+        .flat_map(|source| NUMERIC_TYPES.map(|target| (source.clone(), target)))
+        .collect::<Vec<_>>();
+
+    casts.extend([
+        (ColumnType::Timeuuid, ColumnType::Timestamp),
+        (ColumnType::Timeuuid, ColumnType::Date),
+        (ColumnType::Timestamp, ColumnType::Date),
+        (ColumnType::Date, ColumnType::Timestamp),
+    ]);
+
+    for source in NATIVE_TYPES {
+        casts.push((source.clone(), ColumnType::Ascii));
+        casts.push((source, ColumnType::Varchar));
+    }
+
+    for (source, target) in casts {
+        let name = format!("castas{}", target);
+        let return_type = target.clone();
+
+        catalog.register_strict(&name, vec![source], return_type, move |arguments| {
+            cast(&arguments[0], &target)
+        });
+    }
+}
+
+fn cast(value: &Value, target: &ColumnType) -> Result<Value, DbError> {
+    let invalid = || {
+        DbError::new(
+            ErrorCode::Invalid,
+            format!("Cannot cast {:?} to {}", value, target),
+        )
+    };
+
+    let value = match target {
+        ColumnType::Ascii => Value::Ascii(text(value).into_bytes()),
+        ColumnType::Varchar => Value::Varchar(text(value)),
+        ColumnType::Timestamp => Value::Timestamp(millis(value)),
+        ColumnType::Date => Value::Date(millis(value).div_euclid(MILLIS_PER_DAY) as i32),
+        ColumnType::Float => Value::Float(float(value).ok_or_else(invalid)? as f32),
+        ColumnType::Double => Value::Double(float(value).ok_or_else(invalid)?),
+        ColumnType::Decimal => Value::Decimal(match value {
+            Value::Decimal(decimal) => decimal.clone(),
+            Value::Float(_) | Value::Double(_) => {
+                let float = float(value).ok_or_else(invalid)?;
+
+                format!("{:?}", float).parse().map_err(|_| invalid())?
+            }
+            _ => BigDecimal::from(integer(value).ok_or_else(invalid)?),
+        }),
+        ColumnType::Varint => Value::Varint(integer(value).ok_or_else(invalid)?),
+        // Integers narrow by keeping their low bits, while floating point
+        // values saturate first, as Java's conversions do
+        _ => {
+            let narrowed = match value {
+                Value::Float(_) | Value::Double(_) => {
+                    let float = float(value).ok_or_else(invalid)?;
+
+                    match target {
+                        ColumnType::Bigint => float as i64,
+                        _ => float as i32 as i64,
+                    }
+                }
+                _ => low_bits(&integer(value).ok_or_else(invalid)?),
+            };
+
+            match target {
+                ColumnType::Tinyint => Value::Tinyint(narrowed as i8),
+                ColumnType::Smallint => Value::Smallint(narrowed as i16),
+                ColumnType::Int => Value::Int(narrowed as i32),
+                ColumnType::Bigint => Value::Bigint(narrowed),
+                _ => return Err(invalid()),
+            }
+        }
+    };
+
+    Ok(value)
+}
+
+// Arithmetic between any two numeric types, negation, concatenation of text
+// and dates and timestamps moved by a duration
+fn register_operators(catalog: &mut FunctionCatalog) {
+    let numeric_types = NUMERIC_TYPES
+        .iter()
+        .chain([&ColumnType::Counter])
+        .cloned()
+        .collect::<Vec<_>>();
+
+    for left in &numeric_types {
+        for right in &numeric_types {
+            let return_type = promoted_type(left, right);
+
+            for (name, symbol) in OPERATORS {
+                let signature = format!("{} {} {}", left, symbol, right);
+                let target = return_type.clone();
+
+                catalog.register_strict(
+                    name,
+                    vec![left.clone(), right.clone()],
+                    return_type.clone(),
+                    move |arguments| {
+                        arithmetic(symbol, &arguments[0], &arguments[1], &target).map_err(|error| {
+                            DbError::new(
+                                ErrorCode::FunctionFailure,
+                                format!("the operation '{}' failed: {}", signature, error.message),
+                            )
+                        })
+                    },
+                );
+            }
+        }
+
+        let return_type = promoted_type(left, left);
+        let target = return_type.clone();
+
+        catalog.register_strict(
+            "_negate",
+            vec![left.clone()],
+            return_type,
+            move |arguments| arithmetic("-", &Value::Tinyint(0), &arguments[0], &target),
+        );
+    }
+
+    for left in [ColumnType::Ascii, ColumnType::Varchar] {
+        for right in [ColumnType::Ascii, ColumnType::Varchar] {
+            let return_type = match (&left, &right) {
+                (ColumnType::Ascii, ColumnType::Ascii) => ColumnType::Ascii,
+                _ => ColumnType::Varchar,
+            };
+
+            catalog.register_strict(
+                "_add",
+                vec![left.clone(), right],
+                return_type,
+                |arguments| {
+                    Ok(match (&arguments[0], &arguments[1]) {
+                        (Value::Ascii(left), Value::Ascii(right)) => {
+                            Value::Ascii([left.as_slice(), right].concat())
+                        }
+                        (left, right) => Value::Varchar(text(left) + &text(right)),
+                    })
+                },
+            );
+        }
+    }
+
+    for base in [ColumnType::Timestamp, ColumnType::Date] {
+        for (name, sign) in [("_add", 1), ("_subtract", -1)] {
+            catalog.register_strict(
+                name,
+                vec![base.clone(), ColumnType::Duration],
+                base.clone(),
+                move |arguments| match arguments[1] {
+                    Value::Duration(months, days, nanos) => arguments[0].clone().add_duration(
+                        sign * months as i64,
+                        sign * days as i64,
+                        sign * nanos,
+                    ),
+                    _ => Err(DbError::new(
+                        ErrorCode::Invalid,
+                        format!("Expected a duration, got {:?}", arguments[1]),
+                    )),
+                },
+            );
+        }
+    }
+
+    catalog.register_strict(
+        "_negate",
+        vec![ColumnType::Duration],
+        ColumnType::Duration,
+        |arguments| match arguments[0] {
+            Value::Duration(months, days, nanos) => Ok(Value::Duration(-months, -days, -nanos)),
+            _ => Err(DbError::new(
+                ErrorCode::Invalid,
+                format!("Expected a duration, got {:?}", arguments[0]),
+            )),
+        },
+    );
+}
+
+// As Cassandra promotes them, operations are on floating point values if
+// either side is one, as wide as the wider side. Varints and decimals are
+// wider than any other type.
+fn promoted_type(left: &ColumnType, right: &ColumnType) -> ColumnType {
+    let size = |column_type: &ColumnType| match column_type {
+        ColumnType::Tinyint => 1,
+        ColumnType::Smallint => 2,
+        ColumnType::Int | ColumnType::Float => 4,
+        ColumnType::Bigint | ColumnType::Counter | ColumnType::Double => 8,
+        _ => 16,
+    };
+    let floating_point = [left, right].into_iter().any(|column_type| {
+        matches!(
+            column_type,
+            ColumnType::Float | ColumnType::Double | ColumnType::Decimal
+        )
+    });
+
+    match (floating_point, size(left).max(size(right))) {
+        (true, 4) => ColumnType::Float,
+        (true, 8) => ColumnType::Double,
+        (true, _) => ColumnType::Decimal,
+        (false, 1) => ColumnType::Tinyint,
+        (false, 2) => ColumnType::Smallint,
+        (false, 4) => ColumnType::Int,
+        (false, 8) => ColumnType::Bigint,
+        (false, _) => ColumnType::Varint,
+    }
+}
+
+// Both sides are cast to the operation's type first. Integers wrap around on
+// overflow, and decimal quotients keep 34 significant digits, as Java's do.
+fn arithmetic(
+    operator: &str,
+    left: &Value,
+    right: &Value,
+    return_type: &ColumnType,
+) -> Result<Value, DbError> {
+    let by_zero = || DbError::new(ErrorCode::FunctionFailure, "/ by zero".to_string());
+    let invalid = || {
+        DbError::new(
+            ErrorCode::Invalid,
+            format!("Cannot compute {:?} {} {:?}", left, operator, right),
+        )
+    };
+
+    let value = match (cast(left, return_type)?, cast(right, return_type)?) {
+        (Value::Float(left), Value::Float(right)) => {
+            Value::Float(float_arithmetic(operator, left, right))
+        }
+        (Value::Double(left), Value::Double(right)) => {
+            Value::Double(float_arithmetic(operator, left, right))
+        }
+        (Value::Varint(left), Value::Varint(right)) => Value::Varint(match operator {
+            "+" => left + right,
+            "-" => left - right,
+            "*" => left * right,
+            _ if right.is_zero() => return Err(by_zero()),
+            "/" => left / right,
+            _ => left % right,
+        }),
+        (Value::Decimal(left), Value::Decimal(right)) => Value::Decimal(match operator {
+            "+" => left + right,
+            "-" => left - right,
+            "*" => left * right,
+            _ if right.is_zero() => return Err(by_zero()),
+            "/" => {
+                let quotient = left / right;
+
+                match NonZeroU64::new(34) {
+                    Some(precision) if quotient.digits() > precision.get() => {
+                        quotient.with_precision_round(precision, RoundingMode::HalfEven)
+                    }
+                    _ => quotient,
+                }
+            }
+            _ => left % right,
+        }),
+        (left, right) => {
+            let left = low_bits(&integer(&left).ok_or_else(invalid)?);
+            let right = low_bits(&integer(&right).ok_or_else(invalid)?);
+            let result = match operator {
+                "+" => left.wrapping_add(right),
+                "-" => left.wrapping_sub(right),
+                "*" => left.wrapping_mul(right),
+                _ if right == 0 => return Err(by_zero()),
+                "/" => left.wrapping_div(right),
+                _ => left.wrapping_rem(right),
+            };
+
+            cast(&Value::Bigint(result), return_type)?
+        }
+    };
+
+    Ok(value)
+}
+
+fn float_arithmetic<F: num_traits::Float>(operator: &str, left: F, right: F) -> F {
+    match operator {
+        "+" => left + right,
+        "-" => left - right,
+        "*" => left * right,
+        "/" => left / right,
+        _ => left % right,
+    }
+}
+
+// Decimals and floating point values are truncated
+fn integer(value: &Value) -> Option<BigInt> {
+    match value {
+        Value::Tinyint(value) => Some(BigInt::from(*value)),
+        Value::Smallint(value) => Some(BigInt::from(*value)),
+        Value::Int(value) => Some(BigInt::from(*value)),
+        Value::Bigint(value) | Value::Counter(value) => Some(BigInt::from(*value)),
+        Value::Varint(value) => Some(value.clone()),
+        Value::Float(value) => BigInt::from_f32(value.trunc()),
+        Value::Double(value) => BigInt::from_f64(value.trunc()),
+        Value::Decimal(value) => Some(
+            value
+                .with_scale_round(0, RoundingMode::Down)
+                .into_bigint_and_exponent()
+                .0,
+        ),
+        _ => None,
+    }
+}
+
+fn float(value: &Value) -> Option<f64> {
+    match value {
+        Value::Float(value) => Some(*value as f64),
+        Value::Double(value) => Some(*value),
+        Value::Decimal(value) => value.to_f64(),
+        _ => integer(value)?.to_f64(),
+    }
+}
+
+// The two's complement low 64 bits
+fn low_bits(integer: &BigInt) -> i64 {
+    let mut bytes = integer.to_signed_bytes_le();
+    let fill = if integer.sign() == num_bigint::Sign::Minus {
+        0xFF
+    } else {
+        0x00
+    };
+
+    bytes.resize(8.max(bytes.len()), fill);
+    i64::from_le_bytes(bytes[..8].try_into().unwrap())
+}
+
+// A native value as Cassandra renders it as text
+pub fn text(value: &Value) -> String {
+    match value {
+        Value::Ascii(bytes) => String::from_utf8_lossy(bytes).to_string(),
+        Value::Bigint(value) | Value::Counter(value) => value.to_string(),
+        Value::Blob(bytes) => format!("0x{}", hex(bytes)),
+        Value::Boolean(value) => value.to_string(),
+        Value::Decimal(value) => value.to_string(),
+        Value::Double(value) => format!("{:?}", value),
+        Value::Float(value) => format!("{:?}", value),
+        Value::Int(value) => value.to_string(),
+        Value::Timestamp(millis) => format_timestamp(*millis),
+        Value::Uuid(uuid) | Value::Timeuuid(uuid) => uuid.to_string(),
+        Value::Varchar(value) => value.clone(),
+        Value::Varint(value) => value.to_string(),
+        Value::Inet(bytes) => match bytes.len() {
+            4 => IpAddr::from(<[u8; 4]>::try_from(&bytes[..]).unwrap()).to_string(),
+            16 => IpAddr::from(<[u8; 16]>::try_from(&bytes[..]).unwrap()).to_string(),
+            _ => format!("0x{}", hex(bytes)),
+        },
+        Value::Date(days) => format_date(*days),
+        Value::Time(nanos) => format_time(*nanos),
+        Value::Smallint(value) => value.to_string(),
+        Value::Tinyint(value) => value.to_string(),
+        Value::Duration(months, days, nanos) => format_duration(*months, *days, *nanos),
+        _ => format!("{:?}", value),
+    }
+}
+
+fn hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|byte| format!("{:02x}", byte)).collect()
+}
+
+// Timeuuids count 100ns intervals from 1582-10-15, the start of the Gregorian calendar
+const UUID_EPOCH_TICKS: i64 = 0x01B2_1DD2_1381_4000;
+
+// As in Cassandra, the clock sequence and node of the smallest and largest
+// timeuuids of a given time
+const MIN_CLOCK_SEQ_AND_NODE: u64 = 0x8080_8080_8080_8080;
+const MAX_CLOCK_SEQ_AND_NODE: u64 = 0x7F7F_7F7F_7F7F_7F7F;
+
+fn current_millis() -> i64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map_or(0, |elapsed| elapsed.as_millis() as i64)
+}
+
+// Timestamps, dates and the time of timeuuids as milliseconds since the epoch
+fn millis(value: &Value) -> i64 {
+    match value {
+        Value::Timestamp(millis) => *millis,
+        Value::Date(days) => *days as i64 * MILLIS_PER_DAY,
+        Value::Timeuuid(uuid) => {
+            let (msb, _) = uuid.as_u64_pair();
+            let ticks = (msb >> 32) | ((msb >> 16) & 0xFFFF) << 32 | (msb & 0x0FFF) << 48;
+
+            (ticks as i64 - UUID_EPOCH_TICKS).div_euclid(10_000)
+        }
+        _ => 0,
+    }
+}
+
+// A version 1 uuid from 100ns intervals since the epoch
+fn timeuuid(ticks: i64, clock_seq_and_node: u64) -> Uuid {
+    let ticks = (ticks + UUID_EPOCH_TICKS) as u64;
+    let msb = (ticks & 0xFFFF_FFFF) << 32 | ((ticks >> 32) & 0xFFFF) << 16 | 0x1000 | (ticks >> 48);
+
+    Uuid::from_u64_pair(msb, clock_seq_and_node)
+}
+
+// A random clock sequence and node keep timeuuids of the same instant apart
+fn new_timeuuid() -> Uuid {
+    let ticks = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map_or(0, |elapsed| (elapsed.as_nanos() / 100) as i64);
+    let (_, random) = Uuid::new_v4().as_u64_pair();
+
+    timeuuid(
+        ticks,
+        random & 0x3FFF_FFFF_FFFF_FFFF | 0x8000_0000_0000_0000,
+    )
+}
+
+// The Murmur3 partitioner's token of a partition key. Composite keys hash
+// each component prefixed by its length and followed by a zero byte.
+fn token(key: &[Value]) -> i64 {
+    let bytes = match key {
+        [value] => serialize(value),
+        components => components
+            .iter()
+            .flat_map(|component| {
+                let bytes = serialize(component);
+
+                [(bytes.len() as u16).to_be_bytes().to_vec(), bytes, vec![0]].concat()
+            })
+            .collect(),
+    };
+
+    match murmur3(&bytes) {
+        i64::MIN => i64::MAX,
+        token => token,
+    }
+}
+
+// The first half of Cassandra's x64 128 bit Murmur3, which sign extends the
+// trailing bytes unlike the reference implementation
+fn murmur3(bytes: &[u8]) -> i64 {
+    const C1: u64 = 0x87C3_7B91_1142_53D5;
+    const C2: u64 = 0x4CF5_AD43_2745_937F;
+
+    let mix_k1 = |k1: u64| k1.wrapping_mul(C1).rotate_left(31).wrapping_mul(C2);
+    let mix_k2 = |k2: u64| k2.wrapping_mul(C2).rotate_left(33).wrapping_mul(C1);
+    let fmix = |mut k: u64| {
+        k ^= k >> 33;
+        k = k.wrapping_mul(0xFF51_AFD7_ED55_8CCD);
+        k ^= k >> 33;
+        k = k.wrapping_mul(0xC4CE_B9FE_1A85_EC53);
+        k ^ (k >> 33)
+    };
+    let (mut h1, mut h2) = (0u64, 0u64);
+    let blocks = bytes.chunks_exact(16);
+    let tail = blocks.remainder();
+
+    for block in blocks {
+        let k1 = u64::from_le_bytes(block[..8].try_into().unwrap());
+        let k2 = u64::from_le_bytes(block[8..].try_into().unwrap());
+
+        h1 ^= mix_k1(k1);
+        h1 = h1
+            .rotate_left(27)
+            .wrapping_add(h2)
+            .wrapping_mul(5)
+            .wrapping_add(0x52DC_E729);
+        h2 ^= mix_k2(k2);
+        h2 = h2
+            .rotate_left(31)
+            .wrapping_add(h1)
+            .wrapping_mul(5)
+            .wrapping_add(0x3849_5AB5);
+    }
+
+    let (mut k1, mut k2) = (0u64, 0u64);
+
+    for (i, byte) in tail.iter().enumerate() {
+        let byte = *byte as i8 as i64 as u64;
+
+        if i < 8 {
+            k1 ^= byte << (8 * i);
+        } else {
+            k2 ^= byte << (8 * (i - 8));
+        }
+    }
+
+    if tail.len() > 8 {
+        h2 ^= mix_k2(k2);
+    }
+
+    if !tail.is_empty() {
+        h1 ^= mix_k1(k1);
+    }
+
+    h1 ^= bytes.len() as u64;
+    h2 ^= bytes.len() as u64;
+    h1 = h1.wrapping_add(h2);
+    h2 = h2.wrapping_add(h1);
+    h1 = fmix(h1);
+    h2 = fmix(h2);
+
+    h1.wrapping_add(h2) as i64
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn call(handle: &str, arguments: &[Value]) -> Option<Value> {
+        let arguments = arguments.iter().cloned().map(Some).collect::<Vec<_>>();
+
+        catalog().get(handle).unwrap().call(&arguments).unwrap()
+    }
+
+    #[test]
+    fn test_time_functions() {
+        let time = 1_704_110_400_250;
+        let min = call("system.mintimeuuid(timestamp)", &[Value::Timestamp(time)]).unwrap();
+        let max = call("system.maxtimeuuid(timestamp)", &[Value::Timestamp(time)]).unwrap();
+
+        for uuid in [min, max] {
+            assert_eq!(
+                call("system.totimestamp(timeuuid)", &[uuid]),
+                Some(Value::Timestamp(time))
+            );
+        }
+
+        assert_eq!(
+            call("system.todate(timestamp)", &[Value::Timestamp(time)]),
+            Some(Value::Date(19723))
+        );
+        assert_eq!(
+            call("system.tounixtimestamp(date)", &[Value::Date(1)]),
+            Some(Value::Bigint(86_400_000))
+        );
+
+        let Some(Value::Timeuuid(now)) = call("system.now()", &[]) else {
+            panic!("Expected a timeuuid");
+        };
+
+        assert_eq!(now.get_version_num(), 1);
+        assert!((millis(&Value::Timeuuid(now)) - current_millis()).abs() < 60_000);
+        assert_eq!(
+            catalog()
+                .get("system.totimestamp(date)")
+                .unwrap()
+                .call(&[None])
+                .unwrap(),
+            None
+        );
+    }
+
+    #[test]
+    fn test_blob_conversions_and_casts() {
+        assert_eq!(
+            call("system.intasblob(int)", &[Value::Int(1)]),
+            Some(Value::Blob(vec![0, 0, 0, 1]))
+        );
+        assert_eq!(
+            call(
+                "system.blobasvarchar(blob)",
+                &[Value::Blob(b"abc".to_vec())]
+            ),
+            Some(Value::Varchar("abc".to_string()))
+        );
+        assert!(catalog()
+            .get("system.blobasint(blob)")
+            .unwrap()
+            .call(&[Some(Value::Blob(vec![1]))])
+            .is_err());
+        assert_eq!(
+            call("system.castasint(double)", &[Value::Double(-2.7)]),
+            Some(Value::Int(-2))
+        );
+        assert_eq!(
+            call("system.castastinyint(int)", &[Value::Int(300)]),
+            Some(Value::Tinyint(44))
+        );
+        assert_eq!(
+            call("system.castasdecimal(double)", &[Value::Double(0.1)]),
+            Some(Value::Decimal("0.1".parse().unwrap()))
+        );
+        assert_eq!(
+            call("system.castastext(timestamp)", &[Value::Timestamp(0)]),
+            Some(Value::Varchar("1970-01-01T00:00:00.000Z".to_string()))
+        );
+        assert_eq!(
+            call("system.castastext(float)", &[Value::Float(1.0)]),
+            Some(Value::Varchar("1.0".to_string()))
+        );
+    }
+
+    #[test]
+    fn test_operators() {
+        assert_eq!(
+            call(
+                "system._add(int, bigint)",
+                &[Value::Int(1), Value::Bigint(2)]
+            ),
+            Some(Value::Bigint(3))
+        );
+        assert_eq!(
+            call(
+                "system._multiply(tinyint, tinyint)",
+                &[Value::Tinyint(100), Value::Tinyint(2)]
+            ),
+            Some(Value::Tinyint(-56))
+        );
+        assert_eq!(
+            call(
+                "system._divide(int, float)",
+                &[Value::Int(3), Value::Float(2.0)]
+            ),
+            Some(Value::Float(1.5))
+        );
+        assert_eq!(
+            call(
+                "system._modulo(bigint, int)",
+                &[Value::Bigint(-7), Value::Int(3)]
+            ),
+            Some(Value::Bigint(-1))
+        );
+        assert_eq!(
+            call(
+                "system._subtract(varint, double)",
+                &[Value::Varint(BigInt::from(1)), Value::Double(0.25)]
+            ),
+            Some(Value::Decimal("0.75".parse().unwrap()))
+        );
+        assert_eq!(
+            call(
+                "system._divide(decimal, decimal)",
+                &[
+                    Value::Decimal(BigDecimal::from(1)),
+                    Value::Decimal(BigDecimal::from(3))
+                ]
+            ),
+            Some(Value::Decimal(
+                "0.3333333333333333333333333333333333".parse().unwrap()
+            ))
+        );
+        assert_eq!(
+            call("system._negate(counter)", &[Value::Counter(5)]),
+            Some(Value::Bigint(-5))
+        );
+        assert_eq!(
+            call(
+                "system._add(ascii, text)",
+                &[Value::Ascii(b"a".to_vec()), Value::Varchar("b".to_string())]
+            ),
+            Some(Value::Varchar("ab".to_string()))
+        );
+        assert_eq!(
+            call(
+                "system._subtract(date, duration)",
+                &[Value::Date(31), Value::Duration(1, 0, 0)]
+            ),
+            Some(Value::Date(0))
+        );
+
+        let error = catalog()
+            .get("system._divide(int, int)")
+            .unwrap()
+            .call(&[Some(Value::Int(1)), Some(Value::Int(0))])
+            .unwrap_err();
+
+        assert!(matches!(error.code, ErrorCode::FunctionFailure));
+        assert_eq!(error.message, "the operation 'int / int' failed: / by zero");
+    }
+
+    #[test]
+    fn test_token() {
+        // Tokens of the same keys as given by Cassandra
+        assert_eq!(
+            call("system.token()", &[Value::Int(1)]),
+            Some(Value::Bigint(-4069959284402364209))
+        );
+    }
+}
@@ -1,9 +1,15 @@
+use crate::db::ast::{quote_identifier, Expr, Literal, Operator};
+use crate::db::error::{DbError, ErrorCode};
+use crate::db::literal;
+use crate::db::schema::{Types, UserType};
+use bigdecimal::BigDecimal;
 use fjall::Slice;
-use sqlparser::ast::Value as SqlValue;
-use std::fmt::Debug;
+use num_bigint::BigInt;
+use std::cmp::Ordering;
+use std::fmt::{self, Debug, Display, Formatter};
 use uuid::Uuid;
 
-#[derive(Debug, Copy, Clone, PartialEq)]
+#[derive(Debug, Clone, PartialEq)]
 pub(crate) enum ColumnType {
     Ascii,
     Bigint,
@@ -24,10 +30,66 @@ pub(crate) enum ColumnType {
     Time,
     Smallint,
     Tinyint,
+    Duration,
+    List(Box<ColumnType>),
+    Set(Box<ColumnType>),
+    Map(Box<ColumnType>, Box<ColumnType>),
+    Udt(UserType),
+    Tuple(Vec<ColumnType>),
+    Frozen(Box<ColumnType>),
+    // A fixed number of elements of a native type, e.g. vector<float, 3>
+    Vector(Box<ColumnType>, usize),
 }
 
 impl ColumnType {
     pub fn from_cql_type(type_str: String) -> Option<ColumnType> {
+        ColumnType::resolve(&type_str, &Types::new())
+    }
+
+    // User types are looked up by name, ignoring any keyspace qualifier
+    pub fn resolve(type_str: &str, types: &Types) -> Option<ColumnType> {
+        let type_str = type_str.trim();
+
+        if let Some((name, arguments)) = type_str.split_once('<') {
+            let name = name.trim().to_lowercase();
+
+            // A vector's dimension is a number rather than a type
+            if name == "vector" {
+                let [element, dimension] = split_type_arguments(arguments.strip_suffix('>')?)[..]
+                else {
+                    return None;
+                };
+                let element = ColumnType::resolve(element, types)?;
+                let dimension = dimension.parse().ok().filter(|dimension| *dimension > 0)?;
+
+                return element
+                    .is_vector_element()
+                    .then(|| ColumnType::Vector(Box::new(element), dimension));
+            }
+
+            let arguments = split_type_arguments(arguments.strip_suffix('>')?)
+                .into_iter()
+                .map(|argument| ColumnType::resolve(argument, types))
+                .collect::<Option<Vec<ColumnType>>>()?;
+
+            return match (name.as_str(), arguments.as_slice()) {
+                ("list", [element]) => Some(ColumnType::List(Box::new(element.clone()))),
+                ("set", [element]) => Some(ColumnType::Set(Box::new(element.clone()))),
+                ("map", [key, value]) => Some(ColumnType::Map(
+                    Box::new(key.clone()),
+                    Box::new(value.clone()),
+                )),
+                ("frozen", [inner]) => Some(ColumnType::Frozen(Box::new(inner.clone()))),
+                ("tuple", elements) => Some(ColumnType::Tuple(elements.to_vec())),
+                _ => None,
+            };
+        }
+
+        // A quoted name is always a user type, matched case sensitively
+        if let Some(name) = type_str.rsplit('.').next().and_then(unquote) {
+            return types.get(&name).cloned().map(ColumnType::Udt);
+        }
+
         match type_str.to_lowercase().as_str() {
             "ascii" => Some(ColumnType::Ascii),
             "bigint" => Some(ColumnType::Bigint),
@@ -48,11 +110,92 @@ impl ColumnType {
             "time" => Some(ColumnType::Time),
             "smallint" => Some(ColumnType::Smallint),
             "tinyint" => Some(ColumnType::Tinyint),
-            _ => None,
+            "duration" => Some(ColumnType::Duration),
+            name => {
+                let name = name.rsplit('.').next().unwrap_or(name);
+
+                types.get(name).cloned().map(ColumnType::Udt)
+            }
         }
     }
 }
 
+// The type as written in CQL, e.g. map<int, frozen<list<text>>>
+impl Display for ColumnType {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        let name = match self {
+            ColumnType::Ascii => "ascii",
+            ColumnType::Bigint => "bigint",
+            ColumnType::Blob => "blob",
+            ColumnType::Boolean => "boolean",
+            ColumnType::Counter => "counter",
+            ColumnType::Decimal => "decimal",
+            ColumnType::Double => "double",
+            ColumnType::Float => "float",
+            ColumnType::Int => "int",
+            ColumnType::Timestamp => "timestamp",
+            ColumnType::Uuid => "uuid",
+            ColumnType::Varchar => "text",
+            ColumnType::Varint => "varint",
+            ColumnType::Timeuuid => "timeuuid",
+            ColumnType::Inet => "inet",
+            ColumnType::Date => "date",
+            ColumnType::Time => "time",
+            ColumnType::Smallint => "smallint",
+            ColumnType::Tinyint => "tinyint",
+            ColumnType::Duration => "duration",
+            ColumnType::List(element) => return write!(f, "list<{}>", element),
+            ColumnType::Set(element) => return write!(f, "set<{}>", element),
+            ColumnType::Map(key, value) => return write!(f, "map<{}, {}>", key, value),
+            ColumnType::Udt(user_type) => {
+                return write!(f, "{}", quote_identifier(&user_type.name))
+            }
+            ColumnType::Tuple(elements) => {
+                let elements = elements
+                    .iter()
+                    .map(|element| element.to_string())
+                    .collect::<Vec<_>>();
+
+                return write!(f, "tuple<{}>", elements.join(", "));
+            }
+            ColumnType::Frozen(inner) => return write!(f, "frozen<{}>", inner),
+            ColumnType::Vector(element, dimension) => {
+                return write!(f, "vector<{}, {}>", element, dimension)
+            }
+        };
+
+        f.write_str(name)
+    }
+}
+
+fn unquote(name: &str) -> Option<String> {
+    name.strip_prefix('"')
+        .and_then(|name| name.strip_suffix('"'))
+        .map(|name| name.replace("\"\"", "\""))
+}
+
+// Splits `text, map<int, text>` into its top level arguments
+fn split_type_arguments(arguments: &str) -> Vec<&str> {
+    let mut depth = 0;
+    let mut start = 0;
+    let mut split = vec![];
+
+    for (i, ch) in arguments.char_indices() {
+        match ch {
+            '<' => depth += 1,
+            '>' => depth -= 1,
+            ',' if depth == 0 => {
+                split.push(arguments[start..i].trim());
+                start = i + 1;
+            }
+            _ => {}
+        }
+    }
+
+    split.push(arguments[start..].trim());
+    split
+}
+
 const ASCII_TYPE_ID: u16 = 0x0001;
 const BIGINT_TYPE_ID: u16 = 0x0002;
 const BLOB_TYPE_ID: u16 = 0x0003;
@@ -72,6 +215,14 @@ const DATE_TYPE_ID: u16 = 0x0011;
 const TIME_TYPE_ID: u16 = 0x0012;
 const SMALLINT_TYPE_ID: u16 = 0x0013;
 const TINYINT_TYPE_ID: u16 = 0x0014;
+const DURATION_TYPE_ID: u16 = 0x0015;
+const LIST_TYPE_ID: u16 = 0x0020;
+const MAP_TYPE_ID: u16 = 0x0021;
+const SET_TYPE_ID: u16 = 0x0022;
+const UDT_TYPE_ID: u16 = 0x0030;
+const TUPLE_TYPE_ID: u16 = 0x0031;
+// Vectors go over the wire as a custom type, named by their Java class
+pub const CUSTOM_TYPE_ID: u16 = 0x0000;
 
 impl ColumnType {
     pub fn type_identifier(&self) -> u16 {
@@ -95,8 +246,94 @@ impl ColumnType {
             ColumnType::Time => TIME_TYPE_ID,
             ColumnType::Smallint => SMALLINT_TYPE_ID,
             ColumnType::Tinyint => TINYINT_TYPE_ID,
+            ColumnType::Duration => DURATION_TYPE_ID,
+            ColumnType::List(_) => LIST_TYPE_ID,
+            ColumnType::Map(_, _) => MAP_TYPE_ID,
+            ColumnType::Set(_) => SET_TYPE_ID,
+            ColumnType::Udt(_) => UDT_TYPE_ID,
+            ColumnType::Tuple(_) => TUPLE_TYPE_ID,
+            ColumnType::Frozen(inner) => inner.type_identifier(),
+            ColumnType::Vector(..) => CUSTOM_TYPE_ID,
+        }
+    }
+
+    // Vectors hold native types other than counters
+    pub fn is_vector_element(&self) -> bool {
+        !matches!(
+            self,
+            ColumnType::Counter
+                | ColumnType::List(_)
+                | ColumnType::Set(_)
+                | ColumnType::Map(..)
+                | ColumnType::Udt(_)
+                | ColumnType::Tuple(_)
+                | ColumnType::Frozen(_)
+                | ColumnType::Vector(..)
+        )
+    }
+
+    pub fn is_collection(&self) -> bool {
+        matches!(
+            self,
+            ColumnType::List(_) | ColumnType::Set(_) | ColumnType::Map(_, _)
+        )
+    }
+
+    pub fn user_type(&self) -> Option<&UserType> {
+        match self {
+            ColumnType::Udt(user_type) => Some(user_type),
+            ColumnType::Frozen(inner) => inner.user_type(),
+            _ => None,
+        }
+    }
+
+    // The type itself, or what a frozen type freezes
+    pub fn unfrozen(&self) -> &ColumnType {
+        match self {
+            ColumnType::Frozen(inner) => inner.unfrozen(),
+            _ => self,
         }
     }
+
+    pub fn is_numeric(&self) -> bool {
+        matches!(
+            self,
+            ColumnType::Tinyint
+                | ColumnType::Smallint
+                | ColumnType::Int
+                | ColumnType::Bigint
+                | ColumnType::Counter
+                | ColumnType::Float
+                | ColumnType::Double
+                | ColumnType::Varint
+                | ColumnType::Decimal
+        )
+    }
+
+    pub fn references_duration(&self) -> bool {
+        match self {
+            ColumnType::Duration => true,
+            ColumnType::List(inner)
+            | ColumnType::Set(inner)
+            | ColumnType::Frozen(inner)
+            | ColumnType::Vector(inner, _) => inner.references_duration(),
+            ColumnType::Map(key, value) => key.references_duration() || value.references_duration(),
+            ColumnType::Udt(user_type) => user_type
+                .fields
+                .iter()
+                .any(|(_, field_type)| field_type.references_duration()),
+            ColumnType::Tuple(element_types) => element_types
+                .iter()
+                .any(|element_type| element_type.references_duration()),
+            _ => false,
+        }
+    }
+
+    // Non-frozen collections and user types are stored one cell per element,
+    // counters one cell per increment
+    pub fn is_multi_cell(&self) -> bool {
+        self.is_collection() || matches!(self, ColumnType::Udt(_) | ColumnType::Counter)
+    }
 }
 
 #[derive(Debug, Clone)]
@@ -106,40 +343,285 @@ pub enum Value {
     Blob(Vec<u8>),
     Boolean(bool),
     Counter(i64),
-    Decimal(Vec<u8>), // Typically represented as a byte array
+    Decimal(BigDecimal),
     Double(f64),
     Float(f32),
     Int(i32),
     Timestamp(i64), // Typically represented as a Unix timestamp
     Uuid(Uuid),
     Varchar(String),
-    Varint(Vec<u8>), // Typically represented as a byte array
+    Varint(BigInt),
     Timeuuid(Uuid),
     Inet(Vec<u8>), // Typically represented as a byte array
     Date(i32),     // Typically represented as the number of days since the epoch
     Time(i64),     // Typically represented as the number of nanoseconds since midnight
     Smallint(i16),
     Tinyint(i8),
+    Duration(i32, i32, i64), // Months, days and nanoseconds
+    List(Vec<Value>),
+    Set(Vec<Value>),          // Kept sorted and free of duplicates
+    Map(Vec<(Value, Value)>), // Kept sorted by key and free of duplicate keys
+    Udt(Vec<Option<Value>>),  // Fields in the order the type declares them
+    Tuple(Vec<Option<Value>>),
+    Vector(Vec<Value>),
 }
 
+const MILLIS_PER_DAY: i64 = 86_400_000;
+
 impl Value {
-    pub fn from_sql_value(column_type: ColumnType, value: &SqlValue) -> Option<Value> {
-        match value {
-            SqlValue::Number(num, _) => match column_type {
-                ColumnType::Bigint => Some(Value::Bigint(num.parse::<i64>().unwrap())),
-                ColumnType::Int => Some(Value::Int(num.parse::<i32>().unwrap())),
-                ColumnType::Smallint => Some(Value::Smallint(num.parse::<i16>().unwrap())),
-                ColumnType::Tinyint => Some(Value::Tinyint(num.parse::<i8>().unwrap())),
-                _ => unimplemented!(),
+    pub fn from_literal(
+        column_type: &ColumnType,
+        value: &Literal,
+    ) -> Result<Option<Value>, DbError> {
+        let parsed = match (column_type, value) {
+            (_, Literal::Null) => return Ok(None),
+            (ColumnType::Frozen(inner), _) => return Value::from_literal(inner, value),
+            (ColumnType::Bigint, Literal::Number(num)) => num.parse().ok().map(Value::Bigint),
+            (ColumnType::Counter, Literal::Number(num)) => num.parse().ok().map(Value::Counter),
+            (ColumnType::Int, Literal::Number(num)) => num.parse().ok().map(Value::Int),
+            (ColumnType::Smallint, Literal::Number(num)) => num.parse().ok().map(Value::Smallint),
+            (ColumnType::Tinyint, Literal::Number(num)) => num.parse().ok().map(Value::Tinyint),
+            (ColumnType::Float, Literal::Number(num)) => {
+                literal::parse_float(num).map(|float| Value::Float(float as f32))
+            }
+            (ColumnType::Double, Literal::Number(num)) => {
+                literal::parse_float(num).map(Value::Double)
+            }
+            (ColumnType::Varint, Literal::Number(num)) => {
+                literal::parse_varint(num).map(Value::Varint)
+            }
+            (ColumnType::Decimal, Literal::Number(num)) => {
+                literal::parse_decimal(num).map(Value::Decimal)
+            }
+            (ColumnType::Timestamp, Literal::Number(num)) => num.parse().ok().map(Value::Timestamp),
+            (ColumnType::Timestamp, Literal::String(s)) => {
+                literal::parse_timestamp(s).map(Value::Timestamp)
+            }
+            (ColumnType::Date, Literal::Number(num)) => {
+                literal::parse_date_number(num).map(Value::Date)
+            }
+            (ColumnType::Date, Literal::String(s)) => literal::parse_date(s).map(Value::Date),
+            (ColumnType::Time, Literal::Number(num)) => {
+                literal::parse_time_number(num).map(Value::Time)
+            }
+            (ColumnType::Time, Literal::String(s)) => literal::parse_time(s).map(Value::Time),
+            (ColumnType::Uuid, Literal::String(s) | Literal::Uuid(s)) => {
+                Uuid::parse_str(s).ok().map(Value::Uuid)
+            }
+            (ColumnType::Timeuuid, Literal::String(s) | Literal::Uuid(s)) => Uuid::parse_str(s)
+                .ok()
+                .filter(|uuid| uuid.get_version_num() == 1)
+                .map(Value::Timeuuid),
+            (ColumnType::Inet, Literal::String(s)) => literal::parse_inet(s).map(Value::Inet),
+            (ColumnType::Blob, Literal::Hex(hex)) => literal::parse_hex(hex).map(Value::Blob),
+            (ColumnType::Ascii, Literal::String(s)) if s.is_ascii() => {
+                Some(Value::Ascii(s.as_bytes().to_vec()))
+            }
+            (ColumnType::Varchar, Literal::String(s)) => Some(Value::Varchar(s.clone())),
+            (ColumnType::Boolean, Literal::Boolean(b)) => Some(Value::Boolean(*b)),
+            (ColumnType::Duration, Literal::Duration(s)) => return duration(s).map(Some),
+            _ => None,
+        };
+
+        parsed.map(Some).ok_or_else(|| {
+            DbError::new(
+                ErrorCode::Invalid,
+                format!("Invalid literal {} for type {:?}", value, column_type),
+            )
+        })
+    }
+
+    pub fn from_expr(column_type: &ColumnType, expr: &Expr) -> Result<Option<Value>, DbError> {
+        match (column_type, expr) {
+            (ColumnType::Frozen(inner), _) => Value::from_expr(inner, expr),
+            (_, Expr::Literal(value)) => Value::from_literal(column_type, value),
+            // Dates and timestamps can be moved by a duration, e.g. '2024-01-01' - 2h
+            (
+                ColumnType::Timestamp | ColumnType::Date,
+                Expr::BinaryOp {
+                    left,
+                    op: op @ (Operator::Plus | Operator::Minus),
+                    right,
+                },
+            ) => {
+                let base = Value::from_expr(column_type, left)?;
+                let duration = Value::from_expr(&ColumnType::Duration, right)?;
+                let sign = if *op == Operator::Minus { -1 } else { 1 };
+
+                match (base, duration) {
+                    (Some(base), Some(Value::Duration(months, days, nanos))) => base
+                        .add_duration(sign * months as i64, sign * days as i64, sign * nanos)
+                        .map(Some),
+                    _ => Ok(None),
+                }
+            }
+            (_, Expr::Negate(operand)) => match operand.as_ref() {
+                Expr::Literal(Literal::Number(num)) => {
+                    Value::from_literal(column_type, &Literal::Number(format!("-{}", num)))
+                }
+                Expr::Literal(Literal::Duration(text)) if *column_type == ColumnType::Duration => {
+                    duration(&format!("-{}", text)).map(Some)
+                }
+                _ => Err(DbError::new(
+                    ErrorCode::Invalid,
+                    format!("Invalid literal -{} for type {:?}", operand, column_type),
+                )),
             },
-            SqlValue::SingleQuotedString(s) => Some(Value::Varchar(s.clone())),
-            SqlValue::Boolean(b) => Some(Value::Boolean(*b)),
-            SqlValue::Null => None,
-            _ => unimplemented!(),
+            (ColumnType::List(element_type), Expr::List(elements)) => Ok(Some(Value::List(
+                collection_elements(element_type, elements)?,
+            ))),
+            (ColumnType::Vector(element_type, dimension), Expr::List(elements)) => {
+                if elements.len() != *dimension {
+                    return Err(DbError::new(
+                        ErrorCode::Invalid,
+                        format!(
+                            "Invalid vector literal for {}: expected {} elements, got {}",
+                            column_type,
+                            dimension,
+                            elements.len()
+                        ),
+                    ));
+                }
+
+                Ok(Some(Value::Vector(collection_elements(
+                    element_type,
+                    elements,
+                )?)))
+            }
+            (ColumnType::Set(element_type), Expr::Set(elements)) => Ok(Some(Value::set(
+                collection_elements(element_type, elements)?,
+            ))),
+            // `{}` reads as an empty map, and is an empty set where one is expected
+            (ColumnType::Set(_), Expr::Map(entries)) if entries.is_empty() => {
+                Ok(Some(Value::Set(vec![])))
+            }
+            (ColumnType::Map(key_type, value_type), Expr::Map(entries)) => {
+                let keys = entries
+                    .iter()
+                    .map(|(key, _)| key.clone())
+                    .collect::<Vec<Expr>>();
+                let values = entries
+                    .iter()
+                    .map(|(_, value)| value.clone())
+                    .collect::<Vec<Expr>>();
+
+                Ok(Some(Value::map(
+                    collection_elements(key_type, &keys)?
+                        .into_iter()
+                        .zip(collection_elements(value_type, &values)?)
+                        .collect(),
+                )))
+            }
+            (ColumnType::Udt(user_type), Expr::Map(entries)) => {
+                let mut fields = vec![None; user_type.fields.len()];
+
+                for (key, value) in entries {
+                    let Expr::Identifier(name) = key else {
+                        return Err(DbError::new(
+                            ErrorCode::Invalid,
+                            format!("Invalid field name {}", key),
+                        ));
+                    };
+                    let (position, field_type) = user_type.field(&name.value).ok_or_else(|| {
+                        DbError::new(
+                            ErrorCode::Invalid,
+                            format!(
+                                "Unknown field '{}' in value of user defined type {}",
+                                name, user_type.name
+                            ),
+                        )
+                    })?;
+
+                    fields[position] = Value::from_expr(field_type, value)?;
+                }
+
+                Ok(Some(Value::Udt(fields)))
+            }
+            (ColumnType::Tuple(element_types), Expr::Tuple(elements))
+                if element_types.len() == elements.len() =>
+            {
+                Ok(Some(Value::Tuple(
+                    element_types
+                        .iter()
+                        .zip(elements)
+                        .map(|(element_type, element)| Value::from_expr(element_type, element))
+                        .collect::<Result<Vec<_>, DbError>>()?,
+                )))
+            }
+            // A parenthesized single value is only a tuple where one is expected
+            (ColumnType::Tuple(element_types), Expr::Nested(element))
+                if element_types.len() == 1 =>
+            {
+                Ok(Some(Value::Tuple(vec![Value::from_expr(
+                    &element_types[0],
+                    element,
+                )?])))
+            }
+            _ => Err(DbError::new(
+                ErrorCode::Invalid,
+                format!("Invalid literal {} for type {:?}", expr, column_type),
+            )),
         }
     }
 
-    fn column_type(&self) -> ColumnType {
+    // Moves a date or timestamp by a duration, with months and days following
+    // the calendar
+    pub fn add_duration(self, months: i64, days: i64, nanos: i64) -> Result<Value, DbError> {
+        let out_of_range = || {
+            DbError::new(
+                ErrorCode::Invalid,
+                format!("{:?} is out of range once moved by the duration", self),
+            )
+        };
+        let shift = |day: i64| literal::add_months(day, months)?.checked_add(days);
+
+        match self {
+            Value::Timestamp(millis) => shift(millis.div_euclid(MILLIS_PER_DAY))
+                .and_then(|day| day.checked_mul(MILLIS_PER_DAY))
+                .and_then(|millis_of_day| {
+                    millis_of_day.checked_add(millis.rem_euclid(MILLIS_PER_DAY) + nanos / 1_000_000)
+                })
+                .map(Value::Timestamp)
+                .ok_or_else(out_of_range),
+            Value::Date(day) if nanos == 0 => shift(day as i64)
+                .and_then(|day| i32::try_from(day).ok())
+                .map(Value::Date)
+                .ok_or_else(out_of_range),
+            Value::Date(_) => Err(DbError::new(
+                ErrorCode::Invalid,
+                "The duration must have a day precision to be added to a date".to_string(),
+            )),
+            _ => Err(DbError::new(
+                ErrorCode::Invalid,
+                format!("Cannot add a duration to {:?}", self),
+            )),
+        }
+    }
+
+    pub fn set(mut elements: Vec<Value>) -> Value {
+        elements.sort_by(|a, b| a.partial_cmp(b).unwrap_or(Ordering::Equal));
+        elements.dedup();
+
+        Value::Set(elements)
+    }
+
+    pub fn map(entries: Vec<(Value, Value)>) -> Value {
+        let mut sorted: Vec<(Value, Value)> = Vec::with_capacity(entries.len());
+
+        // Later entries win, as they would for repeated keys in a CQL map literal
+        for (key, value) in entries {
+            match sorted.binary_search_by(|(existing, _)| {
+                existing.partial_cmp(&key).unwrap_or(Ordering::Equal)
+            }) {
+                Ok(index) => sorted[index] = (key, value),
+                Err(index) => sorted.insert(index, (key, value)),
+            }
+        }
+
+        Value::Map(sorted)
+    }
+
+    pub fn column_type(&self) -> ColumnType {
         match self {
             Value::Ascii(_) => ColumnType::Ascii,
             Value::Bigint(_) => ColumnType::Bigint,
@@ -160,9 +642,116 @@ impl Value {
             Value::Time(_) => ColumnType::Time,
             Value::Smallint(_) => ColumnType::Smallint,
             Value::Tinyint(_) => ColumnType::Tinyint,
+            Value::Duration(..) => ColumnType::Duration,
+            Value::List(values) => ColumnType::List(Box::new(element_type(values.first()))),
+            Value::Set(values) => ColumnType::Set(Box::new(element_type(values.first()))),
+            Value::Map(entries) => {
+                let first = entries.first();
+
+                ColumnType::Map(
+                    Box::new(element_type(first.map(|(key, _)| key))),
+                    Box::new(element_type(first.map(|(_, value)| value))),
+                )
+            }
+            // Values don't know which user type they belong to
+            Value::Udt(_) => ColumnType::Udt(UserType::default()),
+            Value::Tuple(elements) => ColumnType::Tuple(
+                elements
+                    .iter()
+                    .map(|element| element_type(element.as_ref()))
+                    .collect(),
+            ),
+            Value::Vector(elements) => {
+                ColumnType::Vector(Box::new(element_type(elements.first())), elements.len())
+            }
         }
     }
 }
+
+// Collections don't carry their element type, so empty ones report blobs
+fn element_type(value: Option<&Value>) -> ColumnType {
+    value.map_or(ColumnType::Blob, Value::column_type)
+}
+
+fn collection_elements(element_type: &ColumnType, exprs: &[Expr]) -> Result<Vec<Value>, DbError> {
+    exprs
+        .iter()
+        .map(|expr| {
+            Value::from_expr(element_type, expr)?.ok_or_else(|| {
+                DbError::new(
+                    ErrorCode::Invalid,
+                    "null is not supported inside collections".to_string(),
+                )
+            })
+        })
+        .collect()
+}
+
+fn duration(literal: &str) -> Result<Value, DbError> {
+    literal::parse_duration(literal)
+        .map(|(months, days, nanos)| Value::Duration(months, days, nanos))
+        .ok_or_else(|| {
+            DbError::new(
+                ErrorCode::Invalid,
+                format!(
+                    "Invalid literal {} for type {:?}",
+                    literal,
+                    ColumnType::Duration
+                ),
+            )
+        })
+}
+
+// A decimal is its scale as an int followed by the unscaled value as a varint
+pub(crate) fn decimal_to_bytes(decimal: &BigDecimal) -> Vec<u8> {
+    let (unscaled, scale) = decimal.as_bigint_and_exponent();
+
+    [
+        (scale as i32).to_be_bytes().to_vec(),
+        unscaled.to_signed_bytes_be(),
+    ]
+    .concat()
+}
+
+pub(crate) fn decimal_from_bytes(bytes: &[u8]) -> Option<BigDecimal> {
+    let (scale, unscaled) = bytes.split_first_chunk::<4>()?;
+
+    if unscaled.is_empty() {
+        return None;
+    }
+
+    let scale = i32::from_be_bytes(*scale) as i64;
+
+    Some(BigDecimal::new(
+        BigInt::from_signed_bytes_be(unscaled),
+        scale,
+    ))
+}
+
+// Timeuuids sort by their time, then by their clock sequence and node as
+// signed bytes, as in Cassandra. The key puts the time first and flips the
+// sign bits so that it sorts bytewise.
+pub(crate) fn timeuuid_key(uuid: &Uuid) -> [u8; 16] {
+    let (msb, lsb) = uuid.as_u64_pair();
+    let ticks = (msb & 0x0FFF) << 48 | ((msb >> 16) & 0xFFFF) << 32 | (msb >> 32);
+    let version = (msb >> 12) & 0x0F;
+    let mut key = [0; 16];
+
+    key[..8].copy_from_slice(&(ticks << 4 | version).to_be_bytes());
+    key[8..].copy_from_slice(&(lsb ^ 0x8080_8080_8080_8080).to_be_bytes());
+    key
+}
+
+pub(crate) fn timeuuid_from_key(key: [u8; 16]) -> Uuid {
+    let high = u64::from_be_bytes(key[..8].try_into().unwrap());
+    let lsb = u64::from_be_bytes(key[8..].try_into().unwrap()) ^ 0x8080_8080_8080_8080;
+    let (ticks, version) = (high >> 4, high & 0x0F);
+    let msb =
+        (ticks & 0xFFFF_FFFF) << 32 | ((ticks >> 32) & 0xFFFF) << 16 | version << 12 | ticks >> 48;
+
+    Uuid::from_u64_pair(msb, lsb)
+}
+
 impl PartialEq for Value {
     fn eq(&self, other: &Self) -> bool {
         match (self, other) {
@@ -185,36 +774,56 @@ impl PartialEq for Value {
             (Value::Time(a), Value::Time(b)) => a == b,
             (Value::Smallint(a), Value::Smallint(b)) => a == b,
             (Value::Tinyint(a), Value::Tinyint(b)) => a == b,
+            (Value::Duration(a, b, c), Value::Duration(x, y, z)) => (a, b, c) == (x, y, z),
+            (Value::List(a), Value::List(b)) => a == b,
+            (Value::Set(a), Value::Set(b)) => a == b,
+            (Value::Map(a), Value::Map(b)) => a == b,
+            (Value::Udt(a), Value::Udt(b)) => a == b,
+            (Value::Tuple(a), Value::Tuple(b)) => a == b,
+            (Value::Vector(a), Value::Vector(b)) => a == b,
             _ => false,
         }
     }
 }
 
-#[derive(Debug, Clone)]
-pub struct Row {
-    pub columns: Vec<Option<Value>>,
-}
-
-macro_rules! row {
-    ($($x:expr),+ $(,)?) => {
-        Row::from_values(vec![$($x),+])
-    };
-}
-
-pub(crate) use row;
-
-impl Row {
-    fn new() -> Self {
-        Row {
-            columns: Vec::new(),
+impl PartialOrd for Value {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        match (self, other) {
+            (Value::Ascii(a), Value::Ascii(b)) => a.partial_cmp(b),
+            (Value::Bigint(a), Value::Bigint(b)) => a.partial_cmp(b),
+            (Value::Blob(a), Value::Blob(b)) => a.partial_cmp(b),
+            (Value::Boolean(a), Value::Boolean(b)) => a.partial_cmp(b),
+            (Value::Counter(a), Value::Counter(b)) => a.partial_cmp(b),
+            (Value::Decimal(a), Value::Decimal(b)) => a.partial_cmp(b),
+            (Value::Double(a), Value::Double(b)) => a.partial_cmp(b),
+            (Value::Float(a), Value::Float(b)) => a.partial_cmp(b),
+            (Value::Int(a), Value::Int(b)) => a.partial_cmp(b),
+            (Value::Timestamp(a), Value::Timestamp(b)) => a.partial_cmp(b),
+            (Value::Uuid(a), Value::Uuid(b)) => a.partial_cmp(b),
+            (Value::Varchar(a), Value::Varchar(b)) => a.partial_cmp(b),
+            (Value::Varint(a), Value::Varint(b)) => a.partial_cmp(b),
+            (Value::Timeuuid(a), Value::Timeuuid(b)) => {
+                timeuuid_key(a).partial_cmp(&timeuuid_key(b))
+            }
+            (Value::Inet(a), Value::Inet(b)) => a.partial_cmp(b),
+            (Value::Date(a), Value::Date(b)) => a.partial_cmp(b),
+            (Value::Time(a), Value::Time(b)) => a.partial_cmp(b),
+            (Value::Smallint(a), Value::Smallint(b)) => a.partial_cmp(b),
+            (Value::Tinyint(a), Value::Tinyint(b)) => a.partial_cmp(b),
+            (Value::List(a), Value::List(b)) => a.partial_cmp(b),
+            (Value::Set(a), Value::Set(b)) => a.partial_cmp(b),
+            (Value::Map(a), Value::Map(b)) => a.partial_cmp(b),
+            (Value::Udt(a), Value::Udt(b)) => a.partial_cmp(b),
+            (Value::Tuple(a), Value::Tuple(b)) => a.partial_cmp(b),
+            (Value::Vector(a), Value::Vector(b)) => a.partial_cmp(b),
+            _ => None,
         }
     }
+}
 
-    pub fn from_values(values: Vec<Value>) -> Self {
-        Row {
-            columns: values.into_iter().map(Some).collect(),
-        }
-    }
+#[derive(Debug, Clone)]
+pub struct Row {
+    pub columns: Vec<Option<Value>>,
 }
 
 impl Into<Vec<u8>> for Value {
@@ -233,7 +842,8 @@ impl Into<Vec<u8>> for Value {
             }
             Value::Boolean(b) => vec![b as u8],
             Value::Counter(i) => i.to_be_bytes().to_vec(),
-            Value::Decimal(v) => {
+            Value::Decimal(d) => {
+                let v = decimal_to_bytes(&d);
                 let size = (v.len() as u32).to_be_bytes().to_vec();
                 [size, v].concat()
             }
@@ -247,7 +857,8 @@ impl Into<Vec<u8>> for Value {
                 let size = (bytes.len() as u32).to_be_bytes().to_vec();
                 [size, bytes].concat()
             }
-            Value::Varint(v) => {
+            Value::Varint(i) => {
+                let v = i.to_signed_bytes_be();
                 let size = (v.len() as u32).to_be_bytes().to_vec();
                 [size, v].concat()
             }
@@ -260,6 +871,51 @@ impl Into<Vec<u8>> for Value {
             Value::Time(t) => t.to_be_bytes().to_vec(),
             Value::Smallint(i) => i.to_be_bytes().to_vec(),
             Value::Tinyint(i) => i.to_be_bytes().to_vec(),
+            Value::Duration(months, days, nanos) => [
+                months.to_be_bytes().to_vec(),
+                days.to_be_bytes().to_vec(),
+                nanos.to_be_bytes().to_vec(),
+            ]
+            .concat(),
+            Value::List(values) | Value::Set(values) | Value::Vector(values) => {
+                let mut bytes = (values.len() as u32).to_be_bytes().to_vec();
+
+                for value in values {
+                    let value_bytes: Vec<u8> = value.into();
+                    bytes.extend(value_bytes);
+                }
+
+                bytes
+            }
+            Value::Map(entries) => {
+                let mut bytes = (entries.len() as u32).to_be_bytes().to_vec();
+
+                for (key, value) in entries {
+                    let key_bytes: Vec<u8> = key.into();
+                    let value_bytes: Vec<u8> = value.into();
+                    bytes.extend(key_bytes);
+                    bytes.extend(value_bytes);
+                }
+
+                bytes
+            }
+            Value::Udt(fields) | Value::Tuple(fields) => {
+                let mut bytes = (fields.len() as u32).to_be_bytes().to_vec();
+
+                for field in fields {
+                    match field {
+                        Some(value) => {
+                            let value_bytes: Vec<u8> = value.into();
+
+                            bytes.push(NOT_NULL);
+                            bytes.extend(value_bytes);
+                        }
+                        None => bytes.push(NULL),
+                    }
+                }
+
+                bytes
+            }
         };
 
         let mut result = type_.to_vec();
@@ -282,8 +938,6 @@ impl Into<Slice> for Row {
     fn into(self) -> Slice {
         let mut bytes = Vec::new();
 
-        println!("Writing to slice: {:?}", self);
-
         for value in self.columns {
             match value {
                 Some(v) => {
@@ -302,13 +956,15 @@ impl Into<Slice> for Row {
     }
 }
 
-impl From<Slice> for Row {
-    fn from(value: Slice) -> Self {
+impl TryFrom<Slice> for Row {
+    type Error = DbError;
+
+    fn try_from(value: Slice) -> Result<Self, DbError> {
         let mut columns: Vec<Option<Value>> = Vec::new();
         let mut remaining = value.as_ref();
 
         while !remaining.is_empty() {
-            let (present_bytes, rest) = remaining.split_at(1);
+            let (present_bytes, rest) = split(remaining, 1)?;
 
             if present_bytes[0] == NULL {
                 columns.push(None);
@@ -317,88 +973,183 @@ impl From<Slice> for Row {
                 continue;
             }
 
-            let (type_bytes, rest) = rest.split_at(2);
-            let type_id = u16::from_be_bytes(type_bytes.try_into().unwrap());
+            let (column, rest) = read_value(rest)?;
+
+            columns.push(Some(column));
+            remaining = rest;
+        }
+
+        Ok(Row { columns })
+    }
+}
+
+impl TryFrom<Slice> for Value {
+    type Error = DbError;
+
+    fn try_from(value: Slice) -> Result<Self, DbError> {
+        Ok(read_value(value.as_ref())?.0)
+    }
+}
+
+// Stored bytes that don't decode are corrupt, which fails the read rather
+// than the server
+fn corrupt(message: String) -> DbError {
+    DbError::new(ErrorCode::ReadFailure, message)
+}
+
+fn split(bytes: &[u8], at: usize) -> Result<(&[u8], &[u8]), DbError> {
+    bytes.split_at_checked(at).ok_or_else(|| {
+        corrupt(format!(
+            "Expected {} bytes of a stored value, found {}",
+            at,
+            bytes.len()
+        ))
+    })
+}
+
+fn read_count(bytes: &[u8]) -> Result<(usize, &[u8]), DbError> {
+    let (count_bytes, rest) = split(bytes, 4)?;
+
+    Ok((
+        u32::from_be_bytes(count_bytes.try_into().unwrap()) as usize,
+        rest,
+    ))
+}
+
+fn read_value(bytes: &[u8]) -> Result<(Value, &[u8]), DbError> {
+    let (type_bytes, rest) = split(bytes, 2)?;
+    let type_id = u16::from_be_bytes(type_bytes.try_into().unwrap());
+
+    match type_id {
+        UDT_TYPE_ID | TUPLE_TYPE_ID => {
+            let (count, mut rest) = read_count(rest)?;
+            let mut fields = Vec::with_capacity(count);
+
+            for _ in 0..count {
+                let (present_bytes, remaining) = split(rest, 1)?;
+
+                if present_bytes[0] == NULL {
+                    fields.push(None);
+                    rest = remaining;
+                } else {
+                    let (field, remaining) = read_value(remaining)?;
 
-            let (column_bytes, rest) = match type_id {
-                ASCII_TYPE_ID | BLOB_TYPE_ID | DECIMAL_TYPE_ID | VARCHAR_TYPE_ID
-                | VARINT_TYPE_ID | INET_TYPE_ID => {
-                    let (size_bytes, rest) = rest.split_at(4);
-                    let size = u32::from_be_bytes(size_bytes.try_into().unwrap()) as usize;
-                    println!("  Size: {}", size.to_string());
-                    rest.split_at(size)
+                    fields.push(Some(field));
+                    rest = remaining;
                 }
-                BIGINT_TYPE_ID => rest.split_at(8),
-                BOOLEAN_TYPE_ID => rest.split_at(1),
-                COUNTER_TYPE_ID => rest.split_at(8),
-                DOUBLE_TYPE_ID => rest.split_at(8),
-                FLOAT_TYPE_ID => rest.split_at(4),
-                INT_TYPE_ID => rest.split_at(4),
-                TIMESTAMP_TYPE_ID => rest.split_at(8),
-                UUID_TYPE_ID => rest.split_at(16),
-                TIMEUUID_TYPE_ID => rest.split_at(16),
-                DATE_TYPE_ID => rest.split_at(4),
-                TIME_TYPE_ID => rest.split_at(8),
-                SMALLINT_TYPE_ID => rest.split_at(2),
-                TINYINT_TYPE_ID => rest.split_at(1),
-                _ => unimplemented!(),
-            };
+            }
 
-            let column = {
-                match type_id {
-                    ASCII_TYPE_ID => Value::Ascii(column_bytes.to_vec()),
-                    BIGINT_TYPE_ID => {
-                        Value::Bigint(i64::from_be_bytes(column_bytes.try_into().unwrap()))
-                    }
-                    BLOB_TYPE_ID => Value::Blob(column_bytes.to_vec()),
-                    BOOLEAN_TYPE_ID => Value::Boolean(column_bytes[0] != 0),
-                    COUNTER_TYPE_ID => {
-                        Value::Counter(i64::from_be_bytes(column_bytes.try_into().unwrap()))
-                    }
-                    DECIMAL_TYPE_ID => Value::Decimal(column_bytes.to_vec()),
-                    DOUBLE_TYPE_ID => {
-                        Value::Double(f64::from_be_bytes(column_bytes.try_into().unwrap()))
-                    }
-                    FLOAT_TYPE_ID => {
-                        Value::Float(f32::from_be_bytes(column_bytes.try_into().unwrap()))
-                    }
-                    INT_TYPE_ID => Value::Int(i32::from_be_bytes(column_bytes.try_into().unwrap())),
-                    TIMESTAMP_TYPE_ID => {
-                        Value::Timestamp(i64::from_be_bytes(column_bytes.try_into().unwrap()))
-                    }
-                    UUID_TYPE_ID => Value::Uuid(Uuid::from_slice(column_bytes).unwrap()),
-                    VARCHAR_TYPE_ID => {
-                        Value::Varchar(String::from_utf8(column_bytes.to_vec()).unwrap())
-                    }
-                    VARINT_TYPE_ID => Value::Varint(column_bytes.to_vec()),
-                    TIMEUUID_TYPE_ID => Value::Timeuuid(Uuid::from_slice(column_bytes).unwrap()),
-                    INET_TYPE_ID => Value::Inet(column_bytes.to_vec()),
-                    DATE_TYPE_ID => {
-                        Value::Date(i32::from_be_bytes(column_bytes.try_into().unwrap()))
-                    }
-                    TIME_TYPE_ID => {
-                        Value::Time(i64::from_be_bytes(column_bytes.try_into().unwrap()))
-                    }
-                    SMALLINT_TYPE_ID => {
-                        Value::Smallint(i16::from_be_bytes(column_bytes.try_into().unwrap()))
-                    }
-                    TINYINT_TYPE_ID => {
-                        Value::Tinyint(i8::from_be_bytes(column_bytes.try_into().unwrap()))
-                    }
-                    _ => unimplemented!(),
+            if type_id == TUPLE_TYPE_ID {
+                return Ok((Value::Tuple(fields), rest));
+            }
+
+            return Ok((Value::Udt(fields), rest));
+        }
+        LIST_TYPE_ID | SET_TYPE_ID | MAP_TYPE_ID | CUSTOM_TYPE_ID => {
+            let (count, mut rest) = read_count(rest)?;
+            let mut elements = Vec::with_capacity(count);
+            let mut entries = Vec::with_capacity(count);
+
+            for _ in 0..count {
+                let (element, remaining) = read_value(rest)?;
+
+                if type_id == MAP_TYPE_ID {
+                    let (value, remaining) = read_value(remaining)?;
+
+                    entries.push((element, value));
+                    rest = remaining;
+                } else {
+                    elements.push(element);
+                    rest = remaining;
                 }
+            }
+
+            let collection = match type_id {
+                LIST_TYPE_ID => Value::List(elements),
+                SET_TYPE_ID => Value::Set(elements),
+                CUSTOM_TYPE_ID => Value::Vector(elements),
+                _ => Value::Map(entries),
             };
 
-            columns.push(Some(column));
-            remaining = rest;
+            return Ok((collection, rest));
         }
+        _ => {}
+    }
 
-        let row = Row { columns };
+    let (column_bytes, rest) = match type_id {
+        ASCII_TYPE_ID | BLOB_TYPE_ID | DECIMAL_TYPE_ID | VARCHAR_TYPE_ID | VARINT_TYPE_ID
+        | INET_TYPE_ID => {
+            let (size, rest) = read_count(rest)?;
 
-        println!("Reading from slice: {:?}", row);
+            split(rest, size)?
+        }
+        BIGINT_TYPE_ID => split(rest, 8)?,
+        BOOLEAN_TYPE_ID => split(rest, 1)?,
+        COUNTER_TYPE_ID => split(rest, 8)?,
+        DOUBLE_TYPE_ID => split(rest, 8)?,
+        FLOAT_TYPE_ID => split(rest, 4)?,
+        INT_TYPE_ID => split(rest, 4)?,
+        TIMESTAMP_TYPE_ID => split(rest, 8)?,
+        UUID_TYPE_ID => split(rest, 16)?,
+        TIMEUUID_TYPE_ID => split(rest, 16)?,
+        DATE_TYPE_ID => split(rest, 4)?,
+        TIME_TYPE_ID => split(rest, 8)?,
+        SMALLINT_TYPE_ID => split(rest, 2)?,
+        TINYINT_TYPE_ID => split(rest, 1)?,
+        DURATION_TYPE_ID => split(rest, 16)?,
+        _ => {
+            return Err(corrupt(format!(
+                "Unknown type id 0x{:04X} in a stored value",
+                type_id
+            )))
+        }
+    };
 
-        row
-    }
+    let column = {
+        match type_id {
+            ASCII_TYPE_ID => Value::Ascii(column_bytes.to_vec()),
+            BIGINT_TYPE_ID => Value::Bigint(i64::from_be_bytes(column_bytes.try_into().unwrap())),
+            BLOB_TYPE_ID => Value::Blob(column_bytes.to_vec()),
+            BOOLEAN_TYPE_ID => Value::Boolean(column_bytes[0] != 0),
+            COUNTER_TYPE_ID => Value::Counter(i64::from_be_bytes(column_bytes.try_into().unwrap())),
+            DECIMAL_TYPE_ID => Value::Decimal(
+                decimal_from_bytes(column_bytes)
+                    .ok_or_else(|| corrupt("Invalid stored decimal".to_string()))?,
+            ),
+            DOUBLE_TYPE_ID => Value::Double(f64::from_be_bytes(column_bytes.try_into().unwrap())),
+            FLOAT_TYPE_ID => Value::Float(f32::from_be_bytes(column_bytes.try_into().unwrap())),
+            INT_TYPE_ID => Value::Int(i32::from_be_bytes(column_bytes.try_into().unwrap())),
+            TIMESTAMP_TYPE_ID => {
+                Value::Timestamp(i64::from_be_bytes(column_bytes.try_into().unwrap()))
+            }
+            UUID_TYPE_ID => Value::Uuid(Uuid::from_slice(column_bytes).unwrap()),
+            VARCHAR_TYPE_ID => Value::Varchar(
+                String::from_utf8(column_bytes.to_vec())
+                    .map_err(|err| corrupt(format!("Invalid stored text: {}", err)))?,
+            ),
+            VARINT_TYPE_ID => Value::Varint(BigInt::from_signed_bytes_be(column_bytes)),
+            TIMEUUID_TYPE_ID => Value::Timeuuid(Uuid::from_slice(column_bytes).unwrap()),
+            INET_TYPE_ID => Value::Inet(column_bytes.to_vec()),
+            DATE_TYPE_ID => Value::Date(i32::from_be_bytes(column_bytes.try_into().unwrap())),
+            TIME_TYPE_ID => Value::Time(i64::from_be_bytes(column_bytes.try_into().unwrap())),
+            SMALLINT_TYPE_ID => {
+                Value::Smallint(i16::from_be_bytes(column_bytes.try_into().unwrap()))
+            }
+            TINYINT_TYPE_ID => Value::Tinyint(i8::from_be_bytes(column_bytes.try_into().unwrap())),
+            _ => {
+                let (months, rest) = column_bytes.split_at(4);
+                let (days, nanos) = rest.split_at(4);
+
+                Value::Duration(
+                    i32::from_be_bytes(months.try_into().unwrap()),
+                    i32::from_be_bytes(days.try_into().unwrap()),
+                    i64::from_be_bytes(nanos.try_into().unwrap()),
+                )
+            }
+        }
+    };
+
+    Ok((column, rest))
 }
 
 #[cfg(test)]
@@ -407,6 +1158,12 @@ mod tests {
     use fjall::Slice;
     use uuid::Uuid;
 
+    fn row(values: Vec<Value>) -> Row {
+        Row {
+            columns: values.into_iter().map(Some).collect(),
+        }
+    }
+
     #[test]
     fn test_value_column_type() {
         assert_eq!(
@@ -418,7 +1175,7 @@ mod tests {
         assert_eq!(Value::Boolean(true).column_type(), ColumnType::Boolean);
         assert_eq!(Value::Counter(987654321).column_type(), ColumnType::Counter);
         assert_eq!(
-            Value::Decimal(vec![1, 2, 3, 4]).column_type(),
+            Value::Decimal(BigDecimal::from(1)).column_type(),
             ColumnType::Decimal
         );
         assert_eq!(
@@ -440,7 +1197,7 @@ mod tests {
             ColumnType::Varchar
         );
         assert_eq!(
-            Value::Varint(vec![1, 2, 3, 4]).column_type(),
+            Value::Varint(BigInt::from(1)).column_type(),
             ColumnType::Varint
         );
         assert_eq!(
@@ -459,8 +1216,8 @@ mod tests {
 
     #[test]
     fn test_row_equality() {
-        let row1 = Row::from_values(vec![Value::Ascii(b"Hello".to_vec()), Value::Int(42)]);
-        let row2 = Row::from_values(vec![Value::Ascii(b"Hello".to_vec()), Value::Int(42)]);
+        let row1 = row(vec![Value::Ascii(b"Hello".to_vec()), Value::Int(42)]);
+        let row2 = row(vec![Value::Ascii(b"Hello".to_vec()), Value::Int(42)]);
 
         assert_eq!(row1, row2);
     }
@@ -481,8 +1238,8 @@ mod tests {
 
     #[test]
     fn test_row_inequality() {
-        let row1 = Row::from_values(vec![Value::Ascii(b"Hello".to_vec()), Value::Int(42)]);
-        let row2 = Row::from_values(vec![Value::Ascii(b"Hello".to_vec()), Value::Int(43)]);
+        let row1 = row(vec![Value::Ascii(b"Hello".to_vec()), Value::Int(42)]);
+        let row2 = row(vec![Value::Ascii(b"Hello".to_vec()), Value::Int(43)]);
 
         assert_ne!(row1, row2);
     }
@@ -512,6 +1269,145 @@ mod tests {
         assert_eq!(ColumnType::from_cql_type("invalid".to_string()), None);
     }
 
+    #[test]
+    fn test_from_cql_collection_type() {
+        assert_eq!(
+            ColumnType::from_cql_type("list<int>".to_string()),
+            Some(ColumnType::List(Box::new(ColumnType::Int)))
+        );
+        assert_eq!(
+            ColumnType::from_cql_type("SET<text>".to_string()),
+            Some(ColumnType::Set(Box::new(ColumnType::Varchar)))
+        );
+        assert_eq!(
+            ColumnType::from_cql_type("map<text, list<bigint>>".to_string()),
+            Some(ColumnType::Map(
+                Box::new(ColumnType::Varchar),
+                Box::new(ColumnType::List(Box::new(ColumnType::Bigint)))
+            ))
+        );
+        assert_eq!(
+            ColumnType::from_cql_type("list<int, int>".to_string()),
+            None
+        );
+        assert_eq!(
+            ColumnType::from_cql_type("tuple<int, frozen<list<text>>>".to_string()),
+            Some(ColumnType::Tuple(vec![
+                ColumnType::Int,
+                ColumnType::Frozen(Box::new(ColumnType::List(Box::new(ColumnType::Varchar))))
+            ]))
+        );
+        assert_eq!(ColumnType::from_cql_type("map<int>".to_string()), None);
+        assert_eq!(ColumnType::from_cql_type("list<invalid>".to_string()), None);
+    }
+
+    #[test]
+    fn test_collection_literals() {
+        let parse = |column_type: &str, literal: &str| {
+            let expr = crate::db::grammar::parse_term(literal).unwrap();
+
+            Value::from_expr(
+                &ColumnType::from_cql_type(column_type.to_string()).unwrap(),
+                &expr,
+            )
+        };
+
+        assert_eq!(
+            parse("list<int>", "[3, 1, 3]").unwrap(),
+            Some(Value::List(vec![
+                Value::Int(3),
+                Value::Int(1),
+                Value::Int(3)
+            ]))
+        );
+        assert_eq!(
+            parse("set<text>", "{'b', 'a', 'b'}").unwrap(),
+            Some(Value::Set(vec![
+                Value::Varchar("a".to_string()),
+                Value::Varchar("b".to_string())
+            ]))
+        );
+        assert_eq!(
+            parse("map<text, int>", "{'k': 1, 'a': 2, 'k': 3}").unwrap(),
+            Some(Value::Map(vec![
+                (Value::Varchar("a".to_string()), Value::Int(2)),
+                (Value::Varchar("k".to_string()), Value::Int(3)),
+            ]))
+        );
+        assert_eq!(parse("set<int>", "{}").unwrap(), Some(Value::Set(vec![])));
+        assert_eq!(
+            parse("tuple<int, text>", "(1, null)").unwrap(),
+            Some(Value::Tuple(vec![Some(Value::Int(1)), None]))
+        );
+        assert!(parse("tuple<int, text>", "(1, 'a', 2)").is_err());
+        assert!(parse("list<int>", "{1, 2}").is_err());
+        assert!(parse("list<int>", "[1, null]").is_err());
+    }
+
+    #[test]
+    fn test_native_literals() {
+        let parse = |column_type: &str, literal: &str| {
+            let expr = crate::db::grammar::parse_term(literal).unwrap();
+
+            Value::from_expr(
+                &ColumnType::from_cql_type(column_type.to_string()).unwrap(),
+                &expr,
+            )
+        };
+
+        assert_eq!(
+            parse("ascii", "'abc'").unwrap(),
+            Some(Value::Ascii(b"abc".to_vec()))
+        );
+        assert_eq!(parse("int", "-42").unwrap(), Some(Value::Int(-42)));
+        assert_eq!(
+            parse("blob", "0xCAFE").unwrap(),
+            Some(Value::Blob(vec![0xCA, 0xFE]))
+        );
+        assert_eq!(
+            parse("timestamp", "'2024-01-01 12:00:00+0000'").unwrap(),
+            Some(Value::Timestamp(1_704_110_400_000))
+        );
+        assert_eq!(
+            parse("timestamp", "1704110400000").unwrap(),
+            Some(Value::Timestamp(1_704_110_400_000))
+        );
+        assert_eq!(parse("date", "'1970-01-02'").unwrap(), Some(Value::Date(1)));
+        assert_eq!(
+            parse("time", "'00:00:01'").unwrap(),
+            Some(Value::Time(1_000_000_000))
+        );
+        assert_eq!(
+            parse("inet", "'10.0.0.1'").unwrap(),
+            Some(Value::Inet(vec![10, 0, 0, 1]))
+        );
+        assert_eq!(
+            parse("double", "-Infinity").unwrap(),
+            Some(Value::Double(f64::NEG_INFINITY))
+        );
+        assert_eq!(parse("float", "1.5").unwrap(), Some(Value::Float(1.5)));
+        assert!(matches!(
+            parse("float", "NaN").unwrap(),
+            Some(Value::Float(float)) if float.is_nan()
+        ));
+        assert_eq!(
+            parse("uuid", "'123e4567-e89b-12d3-a456-426614174000'").unwrap(),
+            Some(Value::Uuid(
+                Uuid::parse_str("123e4567-e89b-12d3-a456-426614174000").unwrap()
+            ))
+        );
+        assert_eq!(
+            parse("varint", "-129").unwrap(),
+            Some(Value::Varint(BigInt::from(-129)))
+        );
+        assert_eq!(parse("int", "null").unwrap(), None);
+        assert!(parse("int", "3000000000").is_err());
+        assert!(parse("int", "'abc'").is_err());
+        assert!(parse("ascii", "'café'").is_err());
+        assert!(parse("timeuuid", "'123e4567-e89b-42d3-a456-426614174000'").is_err());
+        assert!(parse("text", "1").is_err());
+    }
+
     #[test]
     fn test_slice_to_row() {
         let values = vec![
@@ -520,14 +1416,14 @@ mod tests {
             Value::Blob(vec![1, 2, 3]),
             Value::Boolean(true),
             Value::Counter(987654321),
-            Value::Decimal(vec![1, 2, 3, 4]),
+            Value::Decimal(BigDecimal::new(BigInt::from(-12345), 3)),
             Value::Double(std::f64::consts::PI),
             Value::Float(std::f32::consts::E),
             Value::Int(42),
             Value::Timestamp(1627846261),
             Value::Uuid(Uuid::new_v4()),
             Value::Varchar("test".to_string()),
-            Value::Varint(vec![1, 2, 3, 4]),
+            Value::Varint("123456789012345678901234567890".parse().unwrap()),
             Value::Timeuuid(Uuid::new_v4()),
             Value::Inet(vec![192, 168, 1, 1]),
             Value::Date(18628),
@@ -536,12 +1432,32 @@ mod tests {
             Value::Tinyint(12),
         ];
 
-        let row = Row::from_values(values.clone());
+        let row = row(values.clone());
         let slice: Slice = row.clone().into();
 
         println!("{:?}", slice);
 
-        let row2: Row = slice.into();
+        let row2: Row = slice.try_into().unwrap();
+
+        assert_eq!(row, row2);
+    }
+
+    #[test]
+    fn test_slice_to_row_with_collections() {
+        let values = vec![
+            Value::List(vec![Value::Int(1), Value::Int(2)]),
+            Value::Set(vec![Value::Varchar("a".to_string())]),
+            Value::Map(vec![(
+                Value::Varchar("k".to_string()),
+                Value::List(vec![Value::Bigint(7)]),
+            )]),
+            Value::List(vec![]),
+            Value::Int(42),
+        ];
+
+        let row = row(values);
+        let slice: Slice = row.clone().into();
+        let row2: Row = slice.try_into().unwrap();
 
         assert_eq!(row, row2);
     }
@@ -563,8 +1479,21 @@ mod tests {
         };
         let slice: Slice = row.clone().into();
 
-        let row2: Row = slice.into();
+        let row2: Row = slice.try_into().unwrap();
 
         assert_eq!(row, row2);
     }
+
+    #[test]
+    fn test_corrupt_value_is_an_error() {
+        for bytes in [
+            vec![0x00, 0x7F, 1, 2],
+            vec![0x00, 0x09, 1],
+            vec![0x00, 0x0D, 0, 0, 0, 9],
+        ] {
+            let error = Value::try_from(Slice::from(bytes)).err().unwrap();
+
+            assert!(matches!(error.code, ErrorCode::ReadFailure));
+        }
+    }
 }
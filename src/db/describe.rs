@@ -0,0 +1,710 @@
+use crate::db::aggregate::UserAggregate;
+use crate::db::ast::{quote_identifier, DescribeKind};
+use crate::db::builtins::{signature, text};
+use crate::db::data::{ColumnType, Value};
+use crate::db::error::{DbError, ErrorCode};
+use crate::db::execution::references_user_type;
+use crate::db::schema::{
+    ColumnMetadata, Functions, IndexKind, IndexMetadata, IndexTarget, Kind, TableMetadata,
+    TableOptions, Tables, Types, UserFunction, UserType,
+};
+use crate::db::vector::Similarity;
+use std::collections::BTreeMap;
+
+// A schema object as DESCRIBE returns it, along with the CQL creating it
+struct Described {
+    kind: &'static str,
+    name: String,
+    statement: String,
+}
+
+pub fn describe_columns(kind: DescribeKind) -> Vec<(String, ColumnType)> {
+    let names = match kind.is_listing() {
+        true => &["keyspace_name", "type", "name"][..],
+        false => &["keyspace_name", "type", "name", "create_statement"][..],
+    };
+
+    names
+        .iter()
+        .map(|name| (name.to_string(), ColumnType::Varchar))
+        .collect()
+}
+
+pub fn describe(
+    keyspace: &str,
+    kind: DescribeKind,
+    qualifier: Option<&str>,
+    name: Option<&str>,
+    tables: &Tables,
+    types: &Types,
+    functions: &Functions,
+) -> Result<Vec<Vec<Option<Value>>>, DbError> {
+    let not_found = |message: String| Err(DbError::new(ErrorCode::Invalid, message));
+
+    // Keyspaces aren't modelled, the only one there is being the database's
+    if let Some(qualifier) = qualifier.filter(|qualifier| !qualifier.eq_ignore_ascii_case(keyspace))
+    {
+        return not_found(format!("'{}' not found in keyspaces", qualifier));
+    }
+
+    let name = name.unwrap_or_default();
+    let described = match kind {
+        DescribeKind::Keyspaces => vec![Described {
+            kind: "keyspace",
+            name: keyspace.to_string(),
+            statement: create_keyspace(keyspace),
+        }],
+        DescribeKind::Keyspace | DescribeKind::Schema => {
+            let mut described = vec![Described {
+                kind: "keyspace",
+                name: keyspace.to_string(),
+                statement: create_keyspace(keyspace),
+            }];
+
+            described.extend(describe_types(keyspace, types));
+            described.extend(describe_functions(keyspace, functions, None));
+            described.extend(describe_aggregates(keyspace, functions, None));
+
+            for table in base_tables(tables) {
+                described.extend(describe_table(keyspace, table));
+            }
+
+            described
+        }
+        DescribeKind::Tables => base_tables(tables)
+            .into_iter()
+            .flat_map(|table| describe_table(keyspace, table).into_iter().take(1))
+            .collect(),
+        DescribeKind::Table => match tables.get(name) {
+            Some(table) if table.base_table.is_none() => describe_table(keyspace, table),
+            _ => return not_found(format!("'{}' not found in keyspace '{}'", name, keyspace)),
+        },
+        DescribeKind::Types => describe_types(keyspace, types),
+        DescribeKind::Type => match types.get(name) {
+            Some(user_type) => vec![describe_type(keyspace, user_type)],
+            None => {
+                return not_found(format!(
+                    "User defined type '{}' not found in '{}'",
+                    name, keyspace
+                ))
+            }
+        },
+        DescribeKind::Functions => describe_functions(keyspace, functions, None),
+        DescribeKind::Function => match describe_functions(keyspace, functions, Some(name)) {
+            described if described.is_empty() => {
+                return not_found(format!(
+                    "User defined function '{}' not found in '{}'",
+                    name, keyspace
+                ))
+            }
+            described => described,
+        },
+        DescribeKind::Aggregates => describe_aggregates(keyspace, functions, None),
+        DescribeKind::Aggregate => match describe_aggregates(keyspace, functions, Some(name)) {
+            described if described.is_empty() => {
+                return not_found(format!(
+                    "User defined aggregate '{}' not found in '{}'",
+                    name, keyspace
+                ))
+            }
+            described => described,
+        },
+        DescribeKind::Index => {
+            let found = tables.values().find_map(|table| {
+                table
+                    .indexes
+                    .iter()
+                    .find(|index| index.name == name)
+                    .map(|index| (table, index))
+            });
+
+            match found {
+                Some((table, index)) => vec![describe_index(keyspace, table, index)],
+                None => return not_found(format!("Index '{}' not found in '{}'", name, keyspace)),
+            }
+        }
+        DescribeKind::View => match tables.get(name) {
+            Some(
+                view @ TableMetadata {
+                    base_table: Some(base_table),
+                    ..
+                },
+            ) => vec![describe_view(keyspace, base_table, view)],
+            _ => {
+                return not_found(format!(
+                    "Materialized view '{}' not found in '{}'",
+                    name, keyspace
+                ))
+            }
+        },
+    };
+
+    Ok(described
+        .into_iter()
+        .map(|described| {
+            let mut row = vec![
+                Some(Value::Varchar(keyspace.to_string())),
+                Some(Value::Varchar(described.kind.to_string())),
+                Some(Value::Varchar(described.name)),
+            ];
+
+            if !kind.is_listing() {
+                row.push(Some(Value::Varchar(described.statement)));
+            }
+
+            row
+        })
+        .collect())
+}
+
+fn base_tables(tables: &Tables) -> Vec<&TableMetadata> {
+    let mut base_tables = tables
+        .values()
+        .filter(|table| table.base_table.is_none())
+        .collect::<Vec<_>>();
+
+    base_tables.sort_by(|a, b| a.name.cmp(&b.name));
+    base_tables
+}
+
+// There being a single node, the keyspace is as replicated as it can be
+fn create_keyspace(keyspace: &str) -> String {
+    format!(
+        "CREATE KEYSPACE {} WITH replication = {{'class': 'SimpleStrategy', 'replication_factor': '1'}} AND durable_writes = true;",
+        quote_identifier(keyspace)
+    )
+}
+
+// A table followed by its indexes and materialized views
+fn describe_table(keyspace: &str, table: &TableMetadata) -> Vec<Described> {
+    let single_key = table.partition_key.len() == 1 && table.cluster_key.is_empty();
+    let mut lines = ordered_columns(table)
+        .iter()
+        .map(|column| {
+            let mut line = format!(
+                "    {} {}",
+                quote_identifier(&column.name),
+                column.column_type
+            );
+
+            if column.kind == Kind::Static {
+                line.push_str(" static");
+            }
+
+            if single_key && column.kind == Kind::PartitionKey {
+                line.push_str(" PRIMARY KEY");
+            }
+
+            line
+        })
+        .collect::<Vec<_>>();
+
+    if !single_key {
+        lines.push(format!("    {}", primary_key(table)));
+    }
+
+    let mut described = vec![Described {
+        kind: "table",
+        name: table.name.clone(),
+        statement: format!(
+            "CREATE TABLE {}.{} (\n{}\n){};",
+            quote_identifier(keyspace),
+            quote_identifier(&table.name),
+            lines.join(",\n"),
+            table_options(table)
+        ),
+    }];
+    let mut indexes = table.indexes.iter().collect::<Vec<_>>();
+    let mut views = table.views.iter().collect::<Vec<_>>();
+
+    indexes.sort_by(|a, b| a.name.cmp(&b.name));
+    views.sort_by(|a, b| a.name.cmp(&b.name));
+    described.extend(
+        indexes
+            .into_iter()
+            .map(|index| describe_index(keyspace, table, index)),
+    );
+    described.extend(
+        views
+            .into_iter()
+            .map(|view| describe_view(keyspace, &table.name, view)),
+    );
+    described
+}
+
+// The key columns in key order, then the others by name, as Cassandra lists them
+fn ordered_columns(table: &TableMetadata) -> Vec<&ColumnMetadata> {
+    let mut others = table
+        .columns
+        .values()
+        .filter(|column| !column.kind.is_primary_key())
+        .collect::<Vec<_>>();
+
+    others.sort_by(|a, b| a.name.cmp(&b.name));
+    table
+        .partition_key
+        .iter()
+        .chain(&table.cluster_key)
+        .filter_map(|name| table.columns.get(name))
+        .chain(others)
+        .collect()
+}
+
+// The clustering order and, as for indexes, only the options differing from
+// their defaults
+fn table_options(table: &TableMetadata) -> String {
+    let options = &table.options;
+    let defaults = TableOptions::default();
+    let quoted = |text: &str| format!("'{}'", text.replace('\'', "''"));
+    let map = |map: &BTreeMap<String, String>| {
+        let entries = map
+            .iter()
+            .map(|(key, value)| format!("{}: {}", quoted(key), quoted(value)))
+            .collect::<Vec<_>>();
+
+        format!("{{{}}}", entries.join(", "))
+    };
+    let mut clauses = vec![];
+
+    if !options.descending.is_empty() {
+        let order = table
+            .cluster_key
+            .iter()
+            .map(|column| match table.is_descending(column) {
+                true => format!("{} DESC", quote_identifier(column)),
+                false => format!("{} ASC", quote_identifier(column)),
+            })
+            .collect::<Vec<_>>();
+
+        clauses.push(format!("CLUSTERING ORDER BY ({})", order.join(", ")));
+    }
+
+    for (name, value, default) in [
+        (
+            "bloom_filter_fp_chance",
+            options.bloom_filter_fp_chance.to_string(),
+            defaults.bloom_filter_fp_chance.to_string(),
+        ),
+        ("caching", map(&options.caching), map(&defaults.caching)),
+        (
+            "comment",
+            quoted(&options.comment),
+            quoted(&defaults.comment),
+        ),
+        (
+            "compaction",
+            map(&options.compaction),
+            map(&defaults.compaction),
+        ),
+        (
+            "compression",
+            map(&options.compression),
+            map(&defaults.compression),
+        ),
+        (
+            "default_time_to_live",
+            options.default_time_to_live.to_string(),
+            defaults.default_time_to_live.to_string(),
+        ),
+        (
+            "gc_grace_seconds",
+            options.gc_grace_seconds.to_string(),
+            defaults.gc_grace_seconds.to_string(),
+        ),
+    ] {
+        if value != default {
+            clauses.push(format!("{} = {}", name, value));
+        }
+    }
+
+    match clauses.is_empty() {
+        true => String::new(),
+        false => format!(" WITH {}", clauses.join("\n    AND ")),
+    }
+}
+
+fn primary_key(table: &TableMetadata) -> String {
+    let partition_key = match table.partition_key.as_slice() {
+        [column] => quote_identifier(column),
+        columns => format!("({})", quoted_list(columns)),
+    };
+
+    format!(
+        "PRIMARY KEY ({})",
+        std::iter::once(partition_key)
+            .chain(
+                table
+                    .cluster_key
+                    .iter()
+                    .map(|column| quote_identifier(column))
+            )
+            .collect::<Vec<_>>()
+            .join(", ")
+    )
+}
+
+fn quoted_list(names: &[String]) -> String {
+    names
+        .iter()
+        .map(|name| quote_identifier(name))
+        .collect::<Vec<_>>()
+        .join(", ")
+}
+
+fn describe_index(keyspace: &str, table: &TableMetadata, index: &IndexMetadata) -> Described {
+    let column = quote_identifier(&index.column);
+    let target = match index.target {
+        IndexTarget::Simple => column,
+        IndexTarget::Full => format!("full({})", column),
+        IndexTarget::Keys => format!("keys({})", column),
+        IndexTarget::Values => format!("values({})", column),
+        IndexTarget::Entries => format!("entries({})", column),
+    };
+    let (name, keyspace, table) = (
+        quote_identifier(&index.name),
+        quote_identifier(keyspace),
+        quote_identifier(&table.name),
+    );
+    // Only the options differing from their defaults are given
+    let options = match index.kind {
+        IndexKind::Secondary => None,
+        IndexKind::StorageAttached(analysis) => Some(
+            [
+                (analysis.ascii, "'ascii': 'true'"),
+                (analysis.case_insensitive, "'case_sensitive': 'false'"),
+                (analysis.normalize, "'normalize': 'true'"),
+            ]
+            .into_iter()
+            .filter(|(set, _)| *set)
+            .map(|(_, option)| option.to_string())
+            .collect::<Vec<_>>(),
+        ),
+        IndexKind::Vector(Similarity::Cosine) => Some(vec![]),
+        IndexKind::Vector(similarity) => Some(vec![format!(
+            "'similarity_function': '{}'",
+            similarity.name()
+        )]),
+    };
+    let statement = match options {
+        None => format!(
+            "CREATE INDEX {} ON {}.{} ({});",
+            name, keyspace, table, target
+        ),
+        Some(options) if options.is_empty() => format!(
+            "CREATE CUSTOM INDEX {} ON {}.{} ({}) USING 'StorageAttachedIndex';",
+            name, keyspace, table, target
+        ),
+        Some(options) => format!(
+            "CREATE CUSTOM INDEX {} ON {}.{} ({}) USING 'StorageAttachedIndex' WITH OPTIONS = {{{}}};",
+            name,
+            keyspace,
+            table,
+            target,
+            options.join(", ")
+        ),
+    };
+
+    Described {
+        kind: "index",
+        name: index.name.clone(),
+        statement,
+    }
+}
+
+fn describe_view(keyspace: &str, base_table: &str, view: &TableMetadata) -> Described {
+    let key = view
+        .partition_key
+        .iter()
+        .chain(&view.cluster_key)
+        .map(|column| format!("{} IS NOT NULL", quote_identifier(column)))
+        .collect::<Vec<_>>();
+
+    Described {
+        kind: "materialized_view",
+        name: view.name.clone(),
+        statement: format!(
+            "CREATE MATERIALIZED VIEW {}.{} AS\n    SELECT {}\n    FROM {}.{}\n    WHERE {}\n    {};",
+            quote_identifier(keyspace),
+            quote_identifier(&view.name),
+            quoted_list(&view.ordered_column_names()),
+            quote_identifier(keyspace),
+            quote_identifier(base_table),
+            key.join(" AND "),
+            primary_key(view)
+        ),
+    }
+}
+
+// Types come after the types their fields are made of, so that each can be
+// created in turn
+fn describe_types(keyspace: &str, types: &Types) -> Vec<Described> {
+    let mut remaining = types.values().collect::<Vec<_>>();
+    let mut described = vec![];
+
+    remaining.sort_by(|a, b| a.name.cmp(&b.name));
+
+    while !remaining.is_empty() {
+        let position = remaining
+            .iter()
+            .position(|user_type| {
+                !remaining.iter().any(|other| {
+                    other.name != user_type.name
+                        && user_type
+                            .fields
+                            .iter()
+                            .any(|(_, field_type)| references_user_type(field_type, &other.name))
+                })
+            })
+            .unwrap_or(0);
+
+        described.push(describe_type(keyspace, remaining.remove(position)));
+    }
+
+    described
+}
+
+fn describe_type(keyspace: &str, user_type: &UserType) -> Described {
+    let fields = user_type
+        .fields
+        .iter()
+        .map(|(name, field_type)| format!("    {} {}", quote_identifier(name), field_type))
+        .collect::<Vec<_>>();
+
+    Described {
+        kind: "type",
+        name: user_type.name.clone(),
+        statement: format!(
+            "CREATE TYPE {}.{} (\n{}\n);",
+            quote_identifier(keyspace),
+            quote_identifier(&user_type.name),
+            fields.join(",\n")
+        ),
+    }
+}
+
+// Every overload of a function is described, by name and then arguments
+fn describe_functions(keyspace: &str, functions: &Functions, name: Option<&str>) -> Vec<Described> {
+    let mut definitions = functions
+        .user_functions()
+        .filter_map(|function| function.definition.as_ref())
+        .filter(|function| name.is_none_or(|name| function.name == name))
+        .collect::<Vec<_>>();
+
+    definitions.sort_by_key(|function| function_signature(function));
+    definitions
+        .into_iter()
+        .map(|function| describe_function(keyspace, function))
+        .collect()
+}
+
+fn function_signature(function: &UserFunction) -> String {
+    let argument_types = function
+        .arguments
+        .iter()
+        .map(|(_, argument_type)| argument_type.clone())
+        .collect::<Vec<_>>();
+
+    signature("", &function.name, &argument_types)
+}
+
+fn describe_function(keyspace: &str, function: &UserFunction) -> Described {
+    let arguments = function
+        .arguments
+        .iter()
+        .map(|(name, argument_type)| format!("{} {}", quote_identifier(name), argument_type))
+        .collect::<Vec<_>>();
+    let on_null_input = match function.called_on_null_input {
+        true => "CALLED ON NULL INPUT",
+        false => "RETURNS NULL ON NULL INPUT",
+    };
+    let body = match function.body.contains("$$") {
+        true => format!("'{}'", function.body.replace('\'', "''")),
+        false => format!("$${}$$", function.body),
+    };
+
+    Described {
+        kind: "function",
+        name: function_signature(function),
+        statement: format!(
+            "CREATE FUNCTION {}.{}({})\n    {}\n    RETURNS {}\n    LANGUAGE {}\n    AS {};",
+            quote_identifier(keyspace),
+            quote_identifier(&function.name),
+            arguments.join(", "),
+            on_null_input,
+            function.return_type,
+            function.language,
+            body
+        ),
+    }
+}
+
+fn describe_aggregates(
+    keyspace: &str,
+    functions: &Functions,
+    name: Option<&str>,
+) -> Vec<Described> {
+    let mut aggregates = functions
+        .aggregates()
+        .filter(|aggregate| name.is_none_or(|name| aggregate.name == name))
+        .collect::<Vec<_>>();
+
+    aggregates.sort_by_key(|aggregate| signature("", &aggregate.name, &aggregate.argument_types));
+    aggregates
+        .into_iter()
+        .map(|aggregate| describe_aggregate(keyspace, aggregate))
+        .collect()
+}
+
+fn describe_aggregate(keyspace: &str, aggregate: &UserAggregate) -> Described {
+    let argument_types = aggregate
+        .argument_types
+        .iter()
+        .map(|argument_type| argument_type.to_string())
+        .collect::<Vec<_>>();
+    let mut statement = format!(
+        "CREATE AGGREGATE {}.{}({})\n    SFUNC {}\n    STYPE {}",
+        quote_identifier(keyspace),
+        quote_identifier(&aggregate.name),
+        argument_types.join(", "),
+        quote_identifier(&aggregate.state_function.name),
+        aggregate.state_type
+    );
+
+    if let Some(final_function) = &aggregate.final_function {
+        statement.push_str(&format!(
+            "\n    FINALFUNC {}",
+            quote_identifier(&final_function.name)
+        ));
+    }
+
+    if let Some(initial_condition) = &aggregate.initial_condition {
+        statement.push_str(&format!(
+            "\n    INITCOND {}",
+            literal(Some(initial_condition), &aggregate.state_type)
+        ));
+    }
+
+    statement.push(';');
+
+    Described {
+        kind: "aggregate",
+        name: signature("", &aggregate.name, &aggregate.argument_types),
+        statement,
+    }
+}
+
+// A value as a CQL literal of its type
+fn literal(value: Option<&Value>, column_type: &ColumnType) -> String {
+    let Some(value) = value else {
+        return "null".to_string();
+    };
+    let quoted = |text: String| format!("'{}'", text.replace('\'', "''"));
+    let elements = |elements: &[Value], element_type: &ColumnType| {
+        elements
+            .iter()
+            .map(|element| literal(Some(element), element_type))
+            .collect::<Vec<_>>()
+            .join(", ")
+    };
+
+    match (value, column_type) {
+        (_, ColumnType::Frozen(inner)) => literal(Some(value), inner),
+        (
+            Value::Ascii(_)
+            | Value::Varchar(_)
+            | Value::Timestamp(_)
+            | Value::Date(_)
+            | Value::Time(_)
+            | Value::Inet(_),
+            _,
+        ) => quoted(text(value)),
+        (Value::Float(float), _) if !float.is_finite() => non_finite(*float as f64),
+        (Value::Double(double), _) if !double.is_finite() => non_finite(*double),
+        (Value::List(values), ColumnType::List(element_type))
+        | (Value::Vector(values), ColumnType::Vector(element_type, _)) => {
+            format!("[{}]", elements(values, element_type))
+        }
+        (Value::Set(values), ColumnType::Set(element_type)) => {
+            format!("{{{}}}", elements(values, element_type))
+        }
+        (Value::Map(entries), ColumnType::Map(key_type, value_type)) => {
+            let entries = entries
+                .iter()
+                .map(|(key, value)| {
+                    format!(
+                        "{}: {}",
+                        literal(Some(key), key_type),
+                        literal(Some(value), value_type)
+                    )
+                })
+                .collect::<Vec<_>>();
+
+            format!("{{{}}}", entries.join(", "))
+        }
+        (Value::Tuple(values), ColumnType::Tuple(element_types)) => {
+            let values = values
+                .iter()
+                .zip(element_types)
+                .map(|(value, element_type)| literal(value.as_ref(), element_type))
+                .collect::<Vec<_>>();
+
+            format!("({})", values.join(", "))
+        }
+        (Value::Udt(values), ColumnType::Udt(user_type)) => {
+            let fields = values
+                .iter()
+                .zip(&user_type.fields)
+                .map(|(value, (name, field_type))| {
+                    format!("{}: {}", name, literal(value.as_ref(), field_type))
+                })
+                .collect::<Vec<_>>();
+
+            format!("{{{}}}", fields.join(", "))
+        }
+        _ => text(value),
+    }
+}
+
+fn non_finite(value: f64) -> String {
+    match value {
+        value if value.is_nan() => "NaN".to_string(),
+        value if value > 0.0 => "Infinity".to_string(),
+        _ => "-Infinity".to_string(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_literal() {
+        let tuple_type = ColumnType::Tuple(vec![ColumnType::Varchar, ColumnType::Int]);
+        let map_type = ColumnType::Map(Box::new(ColumnType::Varchar), Box::new(ColumnType::Double));
+
+        assert_eq!(
+            literal(
+                Some(&Value::Tuple(vec![
+                    Some(Value::Varchar("it's".to_string())),
+                    None
+                ])),
+                &tuple_type
+            ),
+            "('it''s', null)"
+        );
+        assert_eq!(
+            literal(
+                Some(&Value::Map(vec![
+                    (Value::Varchar("a".to_string()), Value::Double(1.5)),
+                    (Value::Varchar("b".to_string()), Value::Double(f64::NAN)),
+                ])),
+                &map_type
+            ),
+            "{'a': 1.5, 'b': NaN}"
+        );
+        assert_eq!(
+            literal(Some(&Value::Date(0)), &ColumnType::Date),
+            "'1970-01-01'"
+        );
+    }
+}
@@ -1,133 +1,2230 @@
-use crate::db::builtins::Function;
-use crate::db::data::{Row, Value};
+use crate::db::aggregate::{Aggregator, UserAggregate};
+use crate::db::ast::DescribeKind;
+use crate::db::builtins::{like_prefix, signature, ScalarFunction};
+use crate::db::data::{ColumnType, Value};
+use crate::db::describe::describe;
 use crate::db::error::{DbError, ErrorCode};
-use crate::db::parse::{ParsedExpr, ParsedInsert, ParsedQuery};
-use crate::db::schema::{TableMetadata, Tables};
-use fjall::{Keyspace, KvPair, PartitionCreateOptions};
-use std::collections::HashMap;
-use std::iter::empty;
-use std::ops::Not;
-use std::sync::Arc;
+use crate::db::json::{to_json, Json};
+use crate::db::parse::{
+    AnnOrdering, IndexLookup, IndexRestriction, Operation, ParsedDelete, ParsedExpr, ParsedFilter,
+    ParsedInsert, ParsedOperation, ParsedQuery, ParsedStatement, ParsedUpdate,
+};
+use crate::db::schema::{
+    ColumnMetadata, Functions, IndexMetadata, Kind, TableMetadata, TableOptions, Tables, Types,
+    UserFunction, UserType,
+};
+use crate::db::storage::{
+    cell_key, column_prefix, decode_cell, element_path, field_path, index_partition, index_row_key,
+    index_term, index_term_prefix, index_terms, list_element_path, partition_options,
+    partition_prefix, prefix_end, reversed_rows, row_prefix, static_prefix, table_partition, Rows,
+    ROW_MARKER,
+};
+use crate::db::system::{system_table, tables_row, SYSTEM_TABLES};
+use crate::db::vector::floats;
+use fjall::{Batch, Keyspace, KvPair, PartitionCreateOptions, PartitionHandle};
+use std::cell::Cell;
+use std::collections::hash_map::DefaultHasher;
+use std::collections::{HashMap, HashSet};
+use std::hash::{Hash, Hasher};
+use std::iter::{empty, from_fn, once};
+use std::ops::{Bound, Not};
+use std::sync::{Arc, Mutex, MutexGuard, PoisonError};
+use std::time::{Duration, Instant};
 use tokio::sync::RwLock;
 
-pub fn execute_insert<'a>(
+pub fn execute_insert(
     keyspace: &Keyspace,
     parsed_insert: ParsedInsert,
 ) -> Result<impl Iterator<Item = Vec<Option<Value>>>, DbError> {
+    let mut batch = keyspace.batch();
+
+    write_insert(keyspace, &mut batch, &parsed_insert)?;
+    commit(batch)
+}
+
+// Counter writes read the totals they fold deltas into, so the writes to a
+// partition's counters are made one at a time, from the read through to the
+// commit. Partitions share a fixed set of locks between them.
+static COUNTER_WRITES: [Mutex<()>; 64] = [const { Mutex::new(()) }; 64];
+
+fn counter_writes<'a>(
+    updates: impl Iterator<Item = &'a ParsedUpdate>,
+) -> Result<Vec<MutexGuard<'static, ()>>, DbError> {
+    let mut locks = updates
+        .filter(|update| update.table.is_counter_table())
+        .map(|update| {
+            let mut hasher = DefaultHasher::new();
+
+            update.table.name.hash(&mut hasher);
+            resolve_row_prefix(&update.table, &update.partition_key, &[])?.hash(&mut hasher);
+            Ok(hasher.finish() as usize % COUNTER_WRITES.len())
+        })
+        .collect::<Result<Vec<_>, DbError>>()?;
+
+    // Taken in order, so that batches over the same partitions can't deadlock
+    locks.sort_unstable();
+    locks.dedup();
+
+    Ok(locks
+        .into_iter()
+        .map(|lock| {
+            COUNTER_WRITES[lock]
+                .lock()
+                .unwrap_or_else(PoisonError::into_inner)
+        })
+        .collect())
+}
+
+pub fn execute_update(
+    keyspace: &Keyspace,
+    parsed_update: ParsedUpdate,
+) -> Result<impl Iterator<Item = Vec<Option<Value>>>, DbError> {
+    let _counter_writes = counter_writes(once(&parsed_update))?;
+    let mut batch = keyspace.batch();
+
+    write_update(keyspace, &mut batch, &parsed_update)?;
+    commit(batch)
+}
+
+pub fn execute_delete(
+    keyspace: &Keyspace,
+    parsed_delete: ParsedDelete,
+) -> Result<impl Iterator<Item = Vec<Option<Value>>>, DbError> {
+    let mut batch = keyspace.batch();
+
+    write_delete(keyspace, &mut batch, &parsed_delete)?;
+    commit(batch)
+}
+
+// The statements of a BATCH are committed together, or not at all
+pub fn execute_batch(
+    keyspace: &Keyspace,
+    statements: Vec<ParsedStatement>,
+) -> Result<impl Iterator<Item = Vec<Option<Value>>>, DbError> {
+    let _counter_writes =
+        counter_writes(statements.iter().filter_map(|statement| match statement {
+            ParsedStatement::Update(update) => Some(update),
+            _ => None,
+        }))?;
+    let mut batch = keyspace.batch();
+
+    for statement in &statements {
+        match statement {
+            ParsedStatement::Insert(insert) => write_insert(keyspace, &mut batch, insert)?,
+            ParsedStatement::InsertJson(insert) => {
+                write_insert(keyspace, &mut batch, &insert.to_insert()?)?
+            }
+            ParsedStatement::Update(update) => write_update(keyspace, &mut batch, update)?,
+            ParsedStatement::Delete(delete) => write_delete(keyspace, &mut batch, delete)?,
+            _ => {}
+        }
+    }
+
+    commit(batch)
+}
+
+fn write_insert(
+    keyspace: &Keyspace,
+    batch: &mut Batch,
+    parsed_insert: &ParsedInsert,
+) -> Result<(), DbError> {
     let table = &parsed_insert.table;
-    let partition = keyspace
-        .open_partition(&table.name, PartitionCreateOptions::default())
+    let partition = open_partition(keyspace, table, ErrorCode::WriteFailure)?;
+    let prefix = resolve_row_prefix(
+        table,
+        &parsed_insert.partition_key,
+        &parsed_insert.clustering_key,
+    )?;
+    let statics = static_prefix(&resolve_row_prefix(
+        table,
+        &parsed_insert.partition_key,
+        &[],
+    )?);
+
+    // Writing static columns alone doesn't create a row
+    if parsed_insert.clustering_key.len() == table.cluster_key.len() {
+        batch.insert(&partition, cell_key(&prefix, ROW_MARKER, &[]), Vec::new());
+
+        let key = key_row(
+            table,
+            &parsed_insert.partition_key,
+            &parsed_insert.clustering_key,
+        )?;
+
+        update_views(keyspace, batch, table, &prefix, |row| {
+            let mut row = row.unwrap_or(key);
+
+            for (column_name, value) in parsed_insert.columns.iter().zip(&parsed_insert.values) {
+                if table.columns[column_name].kind != Kind::Regular {
+                    continue;
+                }
+
+                match resolve(value)? {
+                    Some(value) => row.insert(column_name.clone(), value),
+                    None => row.remove(column_name),
+                };
+            }
+
+            Ok(Some(row))
+        })?;
+    }
+
+    for (column_name, value) in parsed_insert.columns.iter().zip(&parsed_insert.values) {
+        let column = &table.columns[column_name];
+        let prefix = match column.kind {
+            Kind::Regular => &prefix,
+            Kind::Static => &statics,
+            _ => continue,
+        };
+        let value = resolve(value)?;
+
+        update_indexes(
+            keyspace,
+            batch,
+            table,
+            prefix,
+            column,
+            |_| Ok(value.clone()),
+        )?;
+        write_column(batch, &partition, prefix, column, value)?;
+    }
+
+    Ok(())
+}
+
+fn write_update(
+    keyspace: &Keyspace,
+    batch: &mut Batch,
+    parsed_update: &ParsedUpdate,
+) -> Result<(), DbError> {
+    let table = &parsed_update.table;
+    let partition = open_partition(keyspace, table, ErrorCode::WriteFailure)?;
+    let prefix = resolve_row_prefix(
+        table,
+        &parsed_update.partition_key,
+        &parsed_update.clustering_key,
+    )?;
+    let statics = static_prefix(&resolve_row_prefix(
+        table,
+        &parsed_update.partition_key,
+        &[],
+    )?);
+
+    if parsed_update.clustering_key.len() == table.cluster_key.len() {
+        let key = key_row(
+            table,
+            &parsed_update.partition_key,
+            &parsed_update.clustering_key,
+        )?;
+
+        update_views(keyspace, batch, table, &prefix, |row| {
+            updated_row(row, key, &parsed_update.operations)
+        })?;
+    }
+
+    for operation in &parsed_update.operations {
+        let prefix = match operation.column.kind {
+            Kind::Static => &statics,
+            _ => &prefix,
+        };
+
+        update_indexes(keyspace, batch, table, prefix, &operation.column, |value| {
+            updated_value(value, &operation.operation)
+        })?;
+        apply_operation(batch, &partition, prefix, operation)?;
+    }
+
+    Ok(())
+}
+
+fn write_delete(
+    keyspace: &Keyspace,
+    batch: &mut Batch,
+    parsed_delete: &ParsedDelete,
+) -> Result<(), DbError> {
+    let table = &parsed_delete.table;
+    let partition = open_partition(keyspace, table, ErrorCode::WriteFailure)?;
+    let prefix = resolve_row_prefix(
+        table,
+        &parsed_delete.partition_key,
+        &parsed_delete.clustering_key,
+    )?;
+
+    if parsed_delete.operations.is_empty() {
+        // The index entries and view rows of every row deleted go with it
+        for row in Rows::new(table.clone(), partition.prefix(&prefix)) {
+            let row = row?;
+            let key = row_key(table, &row);
+
+            update_view_rows(keyspace, batch, table, Some(&row), None)?;
+
+            for index in &table.indexes {
+                let Some(value) = row.get(&index.column) else {
+                    continue;
+                };
+                let index_partition = open_index(keyspace, table, index, ErrorCode::WriteFailure)?;
+
+                for term in index_terms(index, value) {
+                    batch.remove(&index_partition, [term, key.clone()].concat());
+                }
+
+                if let Some(mut graph) = index.graph() {
+                    graph.remove(&key);
+                }
+            }
+        }
+
+        for cell in partition.prefix(&prefix) {
+            let (key, _) =
+                cell.map_err(|err| DbError::new(ErrorCode::ReadFailure, err.to_string()))?;
+
+            batch.remove(&partition, key);
+        }
+    }
+
+    let statics = static_prefix(&resolve_row_prefix(
+        table,
+        &parsed_delete.partition_key,
+        &[],
+    )?);
+
+    if !parsed_delete.operations.is_empty()
+        && parsed_delete.clustering_key.len() == table.cluster_key.len()
+    {
+        let key = key_row(
+            table,
+            &parsed_delete.partition_key,
+            &parsed_delete.clustering_key,
+        )?;
+
+        update_views(keyspace, batch, table, &prefix, |row| {
+            updated_row(row, key, &parsed_delete.operations)
+        })?;
+    }
+
+    for operation in &parsed_delete.operations {
+        let prefix = match operation.column.kind {
+            Kind::Static => &statics,
+            _ => &prefix,
+        };
+
+        update_indexes(keyspace, batch, table, prefix, &operation.column, |value| {
+            updated_value(value, &operation.operation)
+        })?;
+        apply_operation(batch, &partition, prefix, operation)?;
+    }
+
+    Ok(())
+}
+
+// Keeps the indexes on a column in step with a write to it, given what the
+// write makes of the column's current value
+fn update_indexes(
+    keyspace: &Keyspace,
+    batch: &mut Batch,
+    table: &TableMetadata,
+    row_prefix: &[u8],
+    column: &ColumnMetadata,
+    update: impl FnOnce(Option<Value>) -> Result<Option<Value>, DbError>,
+) -> Result<(), DbError> {
+    let indexes = table
+        .indexes
+        .iter()
+        .filter(|index| index.column == column.name)
+        .collect::<Vec<_>>();
+
+    if indexes.is_empty() {
+        return Ok(());
+    }
+
+    let partition = open_partition(keyspace, table, ErrorCode::WriteFailure)?;
+    let cells = partition.prefix(column_prefix(row_prefix, &column.name));
+    let old = Rows::new(table.clone(), cells)
+        .next()
+        .transpose()?
+        .and_then(|mut row| row.remove(&column.name));
+    let new = update(old.clone())?;
+
+    for index in indexes {
+        let index_partition = open_index(keyspace, table, index, ErrorCode::WriteFailure)?;
+        let old_terms = old
+            .as_ref()
+            .map(|value| index_terms(index, value))
+            .unwrap_or_default();
+        let new_terms = new
+            .as_ref()
+            .map(|value| index_terms(index, value))
+            .unwrap_or_default();
+
+        for term in old_terms.iter().filter(|term| !new_terms.contains(term)) {
+            batch.remove(&index_partition, [term, row_prefix].concat());
+        }
+
+        for term in new_terms {
+            batch.insert(&index_partition, [&term[..], row_prefix].concat(), vec![]);
+        }
+
+        if let Some(mut graph) = index.graph() {
+            match new.as_ref().and_then(floats) {
+                Some(vector) => graph.insert(row_prefix.to_vec(), vector),
+                None => graph.remove(row_prefix),
+            }
+        }
+    }
+
+    Ok(())
+}
+
+// Keeps the views of a table in step with a write to one of its rows, given
+// what the write makes of the row
+fn update_views(
+    keyspace: &Keyspace,
+    batch: &mut Batch,
+    table: &TableMetadata,
+    row_prefix: &[u8],
+    update: impl FnOnce(
+        Option<HashMap<String, Value>>,
+    ) -> Result<Option<HashMap<String, Value>>, DbError>,
+) -> Result<(), DbError> {
+    if table.views.is_empty() {
+        return Ok(());
+    }
+
+    let partition = open_partition(keyspace, table, ErrorCode::WriteFailure)?;
+    let old = Rows::new(table.clone(), partition.prefix(row_prefix))
+        .next()
+        .transpose()?;
+    let new = update(old.clone())?;
+
+    update_view_rows(keyspace, batch, table, old.as_ref(), new.as_ref())
+}
+
+// Replaces what a base row made of each view with what it now makes, a view
+// row moving when a column of the view's key changes
+fn update_view_rows(
+    keyspace: &Keyspace,
+    batch: &mut Batch,
+    table: &TableMetadata,
+    old: Option<&HashMap<String, Value>>,
+    new: Option<&HashMap<String, Value>>,
+) -> Result<(), DbError> {
+    for view in &table.views {
+        let partition = open_partition(keyspace, view, ErrorCode::WriteFailure)?;
+        let old_key = old.and_then(|row| view_key(view, row));
+        let new_key = new.and_then(|row| view_key(view, row));
+
+        if let Some(old_key) = old_key.filter(|key| Some(key) != new_key.as_ref()) {
+            for cell in partition.prefix(&old_key) {
+                let (key, _) =
+                    cell.map_err(|err| DbError::new(ErrorCode::ReadFailure, err.to_string()))?;
+
+                batch.remove(&partition, key);
+            }
+        }
+
+        let (Some(row), Some(key)) = (new, new_key) else {
+            continue;
+        };
+
+        batch.insert(&partition, cell_key(&key, ROW_MARKER, &[]), Vec::new());
+
+        for column in view.columns.values() {
+            if column.kind == Kind::Regular {
+                write_column(
+                    batch,
+                    &partition,
+                    &key,
+                    column,
+                    row.get(&column.name).cloned(),
+                )?;
+            }
+        }
+    }
+
+    Ok(())
+}
+
+// Where a base row goes in a view, None when a column of the view's key is null
+fn view_key(view: &TableMetadata, row: &HashMap<String, Value>) -> Option<Vec<u8>> {
+    let values = |columns: &[String]| {
+        columns
+            .iter()
+            .map(|column| row.get(column).cloned())
+            .collect::<Option<Vec<_>>>()
+    };
+
+    Some(row_prefix(
+        view,
+        &values(&view.partition_key)?,
+        &values(&view.cluster_key)?,
+    ))
+}
+
+// The key columns of the row a statement writes
+fn key_row(
+    table: &TableMetadata,
+    partition_key: &[ParsedExpr],
+    clustering_key: &[ParsedExpr],
+) -> Result<HashMap<String, Value>, DbError> {
+    let mut row = HashMap::new();
+
+    for (column, expr) in table
+        .partition_key
+        .iter()
+        .chain(&table.cluster_key)
+        .zip(partition_key.iter().chain(clustering_key))
+    {
+        if let Some(value) = resolve(expr)? {
+            row.insert(column.clone(), value);
+        }
+    }
+
+    Ok(row)
+}
+
+// A row once an update or delete is applied to it, None if it's yet to exist
+// and isn't given a value
+fn updated_row(
+    row: Option<HashMap<String, Value>>,
+    key: HashMap<String, Value>,
+    operations: &[ParsedOperation],
+) -> Result<Option<HashMap<String, Value>>, DbError> {
+    let existed = row.is_some();
+    let mut row = row.unwrap_or(key);
+    let mut written = false;
+
+    for operation in operations {
+        let name = &operation.column.name;
+
+        if operation.column.kind != Kind::Regular {
+            continue;
+        }
+
+        if let Some(value) = updated_value(row.remove(name), &operation.operation)? {
+            row.insert(name.clone(), value);
+            written = true;
+        }
+    }
+
+    Ok((existed || written).then_some(row))
+}
+
+// A column's value once an operation is applied to it
+fn updated_value(value: Option<Value>, operation: &Operation) -> Result<Option<Value>, DbError> {
+    let updated = match (operation, value) {
+        (Operation::Set(value), _) => resolve(value)?,
+        (Operation::Append(added), value) => match (value, resolve(added)?) {
+            (Some(Value::List(elements)), Some(Value::List(added))) => {
+                Some(Value::List([elements, added].concat()))
+            }
+            (Some(Value::Set(elements)), Some(Value::Set(added))) => {
+                Some(Value::set([elements, added].concat()))
+            }
+            (Some(Value::Map(entries)), Some(Value::Map(added))) => {
+                Some(Value::map([entries, added].concat()))
+            }
+            (value, added) => added.or(value),
+        },
+        (Operation::Prepend(added), value) => match (value, resolve(added)?) {
+            (Some(Value::List(elements)), Some(Value::List(added))) => {
+                Some(Value::List([added, elements].concat()))
+            }
+            (value, added) => added.or(value),
+        },
+        (Operation::Remove(removed), value) => match (value, resolve(removed)?) {
+            (Some(Value::List(mut elements)), Some(Value::List(removed))) => {
+                elements.retain(|element| !removed.contains(element));
+                Some(Value::List(elements))
+            }
+            (Some(Value::Set(mut elements)), Some(Value::Set(removed))) => {
+                elements.retain(|element| !removed.contains(element));
+                Some(Value::Set(elements))
+            }
+            (Some(Value::Map(mut entries)), Some(Value::Set(removed))) => {
+                entries.retain(|(key, _)| !removed.contains(key));
+                Some(Value::Map(entries))
+            }
+            (value, _) => value,
+        },
+        (Operation::SetElement(element, value), old) => {
+            match (old, resolve(element)?, resolve(value)?) {
+                (Some(Value::List(mut elements)), Some(Value::Int(index)), value) => {
+                    if let Ok(index) = usize::try_from(index) {
+                        match value {
+                            Some(value) if index < elements.len() => elements[index] = value,
+                            None if index < elements.len() => {
+                                elements.remove(index);
+                            }
+                            _ => {}
+                        }
+                    }
+
+                    Some(Value::List(elements))
+                }
+                (Some(Value::Map(mut entries)), Some(key), value) => {
+                    entries.retain(|(existing, _)| *existing != key);
+                    entries.extend(value.map(|value| (key, value)));
+                    Some(Value::map(entries))
+                }
+                (None, Some(key), Some(value)) => Some(Value::Map(vec![(key, value)])),
+                (old, _, _) => old,
+            }
+        }
+        (Operation::SetField(position, value), Some(Value::Udt(mut fields))) => {
+            if fields.len() <= *position {
+                fields.resize(*position + 1, None);
+            }
+
+            fields[*position] = resolve(value)?;
+            Some(Value::Udt(fields))
+        }
+        // Counters can't be indexed or viewed
+        (_, value) => value,
+    };
+
+    Ok(updated)
+}
+
+fn commit(batch: Batch) -> Result<impl Iterator<Item = Vec<Option<Value>>>, DbError> {
+    batch
+        .commit()
         .map_err(|err| DbError::new(ErrorCode::WriteFailure, err.to_string()))?;
-    let prefix = [
-        &parsed_insert.partition_key[..],
-        &parsed_insert.clustering_key[..],
-    ]
-    .concat()
-    .join("");
-
-    println!("[INSERT] prefix: {}", prefix);
-
-    let row = Row {
-        columns: parsed_insert
-            .values
+
+    Ok(empty())
+}
+
+// Rows are selected as they're read, an error evaluating a function of any of
+// them failing the query from that row on
+pub fn execute_select(
+    keyspace: &Keyspace,
+    parsed_query: ParsedQuery,
+) -> Result<SelectedRows, DbError> {
+    let limit = limit(&parsed_query)?.unwrap_or(usize::MAX);
+    let results = select(keyspace, parsed_query, None)?
+        .take(limit)
+        .map(|result| result.map(|(row, _)| row));
+
+    Ok(Box::new(results))
+}
+
+type SelectedRows = Box<dyn Iterator<Item = Result<Vec<Option<Value>>, DbError>>>;
+type Page = (Vec<Vec<Option<Value>>>, Option<Vec<u8>>);
+type Selected = Box<dyn Iterator<Item = Result<(Vec<Option<Value>>, Vec<u8>), DbError>>>;
+// A row along with its key
+type KeyedRow = (HashMap<String, Value>, Vec<u8>);
+
+// Selects up to a page of results, along with the paging state to resume
+// from when there may be more. A page always ends on a group boundary, the
+// paging state being the key of the last row it covered, led by the number
+// of results left to return under a LIMIT.
+pub fn execute_select_page(
+    keyspace: &Keyspace,
+    parsed_query: ParsedQuery,
+    page_size: usize,
+    paging_state: Option<&[u8]>,
+) -> Result<Page, DbError> {
+    let limit = limit(&parsed_query)?;
+    let (remaining, paging_state) = match (limit, paging_state) {
+        (Some(_), Some(paging_state)) => {
+            let (remaining, last_key) = paging_state.split_first_chunk().ok_or_else(|| {
+                DbError::new(ErrorCode::ProtocolError, "Invalid paging state".to_string())
+            })?;
+
+            (u32::from_be_bytes(*remaining) as usize, Some(last_key))
+        }
+        (limit, paging_state) => (limit.unwrap_or(usize::MAX), paging_state),
+    };
+    let mut results = select(keyspace, parsed_query, paging_state)?
+        .take(remaining)
+        .peekable();
+    let mut page = vec![];
+    let mut last_key = None;
+
+    while page.len() < page_size {
+        let Some((row, key)) = results.next().transpose()? else {
+            break;
+        };
+
+        page.push(row);
+        last_key = Some(key);
+    }
+
+    let paging_state = results.peek().and(last_key).map(|key| match limit {
+        Some(_) => [&((remaining - page.len()) as u32).to_be_bytes()[..], &key].concat(),
+        None => key,
+    });
+
+    Ok((page, paging_state))
+}
+
+// LIMIT n, once a marker given for n is bound
+fn limit(parsed_query: &ParsedQuery) -> Result<Option<usize>, DbError> {
+    let Some(limit) = &parsed_query.limit else {
+        return Ok(None);
+    };
+
+    match resolve(limit)? {
+        Some(Value::Int(limit)) if limit > 0 => Ok(Some(limit as usize)),
+        _ => Err(DbError::new(
+            ErrorCode::Invalid,
+            "LIMIT must be strictly positive".to_string(),
+        )),
+    }
+}
+
+// Each result comes with the key of the last row it was selected from
+fn select(
+    keyspace: &Keyspace,
+    parsed_query: ParsedQuery,
+    paging_state: Option<&[u8]>,
+) -> Result<Selected, DbError> {
+    if !parsed_query.json {
+        return select_rows(keyspace, parsed_query, paging_state);
+    }
+
+    // SELECT JSON turns each row into an object keyed by the selected names
+    let columns = parsed_query
+        .projection
+        .iter()
+        .map(|expr| expr.column_spec())
+        .collect::<Vec<_>>();
+    let results = select_rows(keyspace, parsed_query, paging_state)?.map(move |result| {
+        let (row, key) = result?;
+        let json = Json::Object(
+            columns
+                .iter()
+                .zip(&row)
+                .map(|((name, column_type), value)| {
+                    // Case sensitive names are quoted, so they read back as themselves
+                    let key = match name.chars().any(|c| c.is_ascii_uppercase()) {
+                        true => format!("\"{}\"", name),
+                        false => name.clone(),
+                    };
+
+                    (key, to_json(value.as_ref(), column_type))
+                })
+                .collect(),
+        );
+
+        Ok((vec![Some(Value::Varchar(json.to_string()))], key))
+    });
+
+    Ok(Box::new(results))
+}
+
+fn select_rows(
+    keyspace: &Keyspace,
+    parsed_query: ParsedQuery,
+    paging_state: Option<&[u8]>,
+) -> Result<Selected, DbError> {
+    let partition = open_partition(keyspace, &parsed_query.table, ErrorCode::ReadFailure)?;
+    let prefix = resolve_row_prefix(
+        &parsed_query.table,
+        &parsed_query.partition_key,
+        &parsed_query.clustering_key,
+    )?;
+    // Static cells sort ahead of the partition's rows, so are read separately
+    // for rows selected from the middle of a partition
+    let statics = |key: &[u8]| {
+        let partition_prefix = partition_prefix(&parsed_query.table, key);
+
+        (partition_prefix.len() < key.len())
+            .then(|| partition.prefix(static_prefix(partition_prefix)))
+            .into_iter()
+            .flatten()
+    };
+    // ANN results are ranked rather than in key order, so resume by rank
+    let ann = parsed_query.ann.clone();
+    let scan_state = paging_state.filter(|_| ann.is_none());
+    let iterator: Box<dyn Iterator<Item = fjall::Result<KvPair>>> =
+        match (scan_state, &parsed_query.index[..]) {
+            _ if parsed_query.distinct => {
+                let start = paging_state.map_or_else(|| Some(prefix.clone()), prefix_end);
+
+                Box::new(partition_heads(
+                    partition,
+                    parsed_query.table.clone(),
+                    prefix,
+                    start,
+                ))
+            }
+            _ if ann.as_ref().is_some_and(|ann| ann.index.is_some()) => {
+                let keys = nearest_keys(ann.as_ref().unwrap())?;
+
+                keyed_rows(partition, &parsed_query.table, keys.into_iter().map(Ok))
+            }
+            (_, [_, ..]) => indexed_rows(
+                keyspace,
+                partition,
+                &parsed_query.table,
+                &parsed_query.index,
+                paging_state,
+            )?,
+            // Resuming before the last row returned when reading backwards
+            (scan_state, []) if parsed_query.reversed => {
+                let end = match scan_state {
+                    Some(last_key) => Bound::Excluded(last_key.to_vec()),
+                    None => prefix_end(&prefix).map_or(Bound::Unbounded, Bound::Excluded),
+                };
+                let statics = partition.prefix(static_prefix(partition_prefix(
+                    &parsed_query.table,
+                    &prefix,
+                )));
+                let rows = partition.range((Bound::Included(prefix), end));
+
+                Box::new(statics.chain(reversed_rows(parsed_query.table.clone(), rows)))
+            }
+            (Some(last_key), []) => match prefix_end(last_key) {
+                Some(start) => Box::new(statics(last_key).chain(
+                    partition.range(start..).take_while(move |item| {
+                        item.as_ref()
+                            .map_or(true, |(key, _)| key.starts_with(&prefix))
+                    }),
+                )),
+                None => Box::new(empty()),
+            },
+            (None, []) if prefix.is_empty().not() => {
+                Box::new(statics(&prefix).chain(partition.prefix(prefix)))
+            }
+            (None, []) => Box::new(partition.iter()),
+        };
+    let ParsedQuery {
+        table,
+        projection,
+        filters,
+        group_by,
+        distinct,
+        ..
+    } = parsed_query;
+    let key_columns = table
+        .partition_key
+        .iter()
+        .chain(&table.cluster_key)
+        .cloned()
+        .collect::<Vec<_>>();
+    let rows = Rows::new(table.clone(), iterator)
+        .resume(scan_state)
+        .filter_map(move |row| {
+            let mut row = match row {
+                Ok(row) => row,
+                Err(error) => return Some(Err(error)),
+            };
+
+            // A distinct partition is keyed by its partition key alone, so paging
+            // resumes from the next partition
+            if distinct {
+                row.retain(|column, _| {
+                    table.partition_key.contains(column)
+                        || table.columns[column].kind == Kind::Static
+                });
+            }
+
+            match filters.iter().try_fold(true, |matched, filter| {
+                Ok::<_, DbError>(matched && filter.matches(&row)?)
+            }) {
+                Ok(true) => {
+                    let key = row_key(&table, &row);
+
+                    Some(Ok((row, key)))
+                }
+                Ok(false) => None,
+                Err(error) => Some(Err(error)),
+            }
+        });
+    let rows: Box<dyn Iterator<Item = Result<KeyedRow, DbError>>> = match ann {
+        Some(ann) => Box::new(nearest_rows(rows, &ann, paging_state)?.into_iter().map(Ok)),
+        None => Box::new(rows),
+    };
+
+    let aggregates = projection
+        .iter()
+        .any(|expr| matches!(expr, ParsedExpr::Aggregate(_)));
+
+    if aggregates && group_by.is_empty() {
+        let mut last_key = vec![];
+        let row = aggregate(
+            rows.map(|result| {
+                result.map(|(row, key)| {
+                    last_key = key;
+                    row
+                })
+            }),
+            &projection,
+        );
+
+        return Ok(Box::new(once(row.map(|row| (row, last_key)))));
+    }
+
+    if group_by.is_empty() {
+        let results = rows.map(move |result| {
+            let (row, key) = result?;
+            let values = projection
+                .iter()
+                .map(|expr| expr.resolve(&row))
+                .collect::<Result<Vec<_>, DbError>>()?;
+
+            Ok((values, key))
+        });
+
+        return Ok(Box::new(results));
+    }
+
+    // Rows come in primary key order, so the rows of a group are adjacent
+    // and each group can be folded as it streams by
+    let group_of = move |row: &HashMap<String, Value>| {
+        key_columns[..group_by.len()]
             .iter()
-            .map(|value| value.resolve(HashMap::new(), &HashMap::new()))
-            .collect(),
+            .map(|column| row.get(column).cloned())
+            .collect::<Vec<_>>()
+    };
+    let mut rows = rows.peekable();
+    let groups = from_fn(move || {
+        let (first, mut last_key) = match rows.next()? {
+            Ok(first) => first,
+            Err(error) => return Some(Err(error)),
+        };
+        let group = group_of(&first);
+        // An error ends the group, to be returned in place of the next one
+        let rest = from_fn(|| {
+            rows.next_if(|result| result.as_ref().is_ok_and(|(row, _)| group_of(row) == group))?
+                .ok()
+        });
+        let row = aggregate(
+            once(Ok(first)).chain(rest.map(|(row, key)| {
+                last_key = key;
+                Ok(row)
+            })),
+            &projection,
+        );
+
+        Some(row.map(|row| (row, last_key)))
+    });
+
+    Ok(Box::new(groups))
+}
+
+// The first cell of each partition, or all of its static cells, seeking past
+// the rest of every partition rather than reading through its rows
+fn partition_heads(
+    partition: PartitionHandle,
+    table: TableMetadata,
+    prefix: Vec<u8>,
+    mut start: Option<Vec<u8>>,
+) -> impl Iterator<Item = fjall::Result<KvPair>> {
+    from_fn(move || {
+        let (key, value) = match partition.range(start.take()?..).next()? {
+            Ok(cell) => cell,
+            Err(error) => return Some(vec![Err(error)]),
+        };
+
+        if !key.starts_with(&prefix) {
+            return None;
+        }
+
+        let cell = decode_cell(&table, &key, value.clone());
+        let partition_prefix = row_prefix(&table, &cell.partition_key, &[]);
+
+        start = prefix_end(&partition_prefix);
+
+        if cell.is_static {
+            Some(partition.prefix(static_prefix(&partition_prefix)).collect())
+        } else {
+            Some(vec![Ok((key, value))])
+        }
+    })
+    .flatten()
+}
+
+// The rows the indexes hold entries for, in the order of the first's entries,
+// each along with its partition's static row
+fn indexed_rows(
+    keyspace: &Keyspace,
+    partition: PartitionHandle,
+    table: &TableMetadata,
+    restrictions: &[IndexRestriction],
+    paging_state: Option<&[u8]>,
+) -> Result<Box<dyn Iterator<Item = fjall::Result<KvPair>>>, DbError> {
+    let [first, others @ ..] = restrictions else {
+        return Ok(Box::new(empty()));
+    };
+    // Every other index narrows the rows down to those it holds entries for too
+    let others = others
+        .iter()
+        .map(|restriction| {
+            index_entries(keyspace, &partition, table, restriction, None)?
+                .collect::<fjall::Result<HashSet<_>>>()
+                .map_err(|error| DbError::new(ErrorCode::ReadFailure, error.to_string()))
+        })
+        .collect::<Result<Vec<_>, DbError>>()?;
+    let entries = index_entries(keyspace, &partition, table, first, paging_state)?;
+    let keys = entries.filter(move |key| {
+        key.as_ref()
+            .map_or(true, |key| others.iter().all(|keys| keys.contains(key)))
+    });
+
+    Ok(keyed_rows(partition, table, keys))
+}
+
+// The cells of the rows of the given keys, in that order, each along with its
+// partition's static row
+fn keyed_rows(
+    partition: PartitionHandle,
+    table: &TableMetadata,
+    keys: impl Iterator<Item = fjall::Result<Vec<u8>>> + 'static,
+) -> Box<dyn Iterator<Item = fjall::Result<KvPair>>> {
+    let table = table.clone();
+    let rows = keys.flat_map(
+        move |key| -> Box<dyn Iterator<Item = fjall::Result<KvPair>>> {
+            let key = match key {
+                Ok(key) => key,
+                Err(error) => return Box::new(once(Err(error))),
+            };
+            let partition_prefix = partition_prefix(&table, &key);
+            let statics = (partition_prefix.len() < key.len())
+                .then(|| partition.prefix(static_prefix(partition_prefix)))
+                .into_iter()
+                .flatten();
+
+            Box::new(statics.chain(partition.prefix(key)))
+        },
+    );
+
+    Box::new(rows)
+}
+
+// The vector given by ANN OF, as floats
+fn ann_vector(ann: &AnnOrdering) -> Result<Vec<f32>, DbError> {
+    resolve(&ann.vector)?
+        .as_ref()
+        .and_then(floats)
+        .ok_or_else(|| {
+            DbError::new(
+                ErrorCode::Invalid,
+                "Invalid null value for the vector of ANN OF".to_string(),
+            )
+        })
+}
+
+// The fewest candidates a search of a vector index weighs up
+const ANN_CANDIDATES: usize = 100;
+
+// The keys of the rows the index's graph finds the nearest vectors for, best
+// first, each to be scored again against what's stored. Twice as many rows
+// as asked for are looked up, as the graph may hold vectors a failed write
+// never stored.
+fn nearest_keys(ann: &AnnOrdering) -> Result<Vec<Vec<u8>>, DbError> {
+    let query = ann_vector(ann)?;
+    let Some(graph) = ann.index.as_ref().and_then(IndexMetadata::graph) else {
+        return Ok(vec![]);
+    };
+    let limit = 2 * ann.limit;
+
+    Ok(graph
+        .search(&query, limit, ANN_CANDIDATES.max(limit))
+        .into_iter()
+        .map(|(key, _)| key)
+        .collect())
+}
+
+// The rows with the vectors most like the one of ANN OF, most alike first and
+// then in key order, resuming past the given row. Rows without a vector that
+// can be scored are left out.
+fn nearest_rows(
+    rows: impl Iterator<Item = Result<KeyedRow, DbError>>,
+    ann: &AnnOrdering,
+    paging_state: Option<&[u8]>,
+) -> Result<Vec<KeyedRow>, DbError> {
+    let query = ann_vector(ann)?;
+    let mut scored = vec![];
+
+    for result in rows {
+        let (row, key) = result?;
+        let score = row
+            .get(&ann.column)
+            .and_then(floats)
+            .and_then(|vector| ann.similarity.score(&query, &vector));
+
+        if let Some(score) = score {
+            scored.push((score, row, key));
+        }
+    }
+
+    scored.sort_by(|(a, _, a_key), (b, _, b_key)| b.total_cmp(a).then_with(|| a_key.cmp(b_key)));
+    scored.truncate(ann.limit);
+
+    // A row gone since the last page ends the results rather than have them
+    // start over
+    let start = match paging_state {
+        Some(last_key) => scored
+            .iter()
+            .position(|(_, _, key)| key == last_key)
+            .map_or(scored.len(), |position| position + 1),
+        None => 0,
+    };
+
+    Ok(scored
+        .into_iter()
+        .skip(start)
+        .map(|(_, row, key)| (row, key))
+        .collect())
+}
+
+// The keys of the rows an index holds entries for the restriction's terms
+// for, in the order of the entries, resuming past the given row
+fn index_entries(
+    keyspace: &Keyspace,
+    partition: &PartitionHandle,
+    table: &TableMetadata,
+    restriction: &IndexRestriction,
+    paging_state: Option<&[u8]>,
+) -> Result<Box<dyn Iterator<Item = fjall::Result<Vec<u8>>>>, DbError> {
+    let index = &restriction.index;
+    let index_partition = open_index(keyspace, table, index, ErrorCode::ReadFailure)?;
+    let analysis = index.kind.analysis();
+    // A term as the index analyzes and encodes it, None when a value is null
+    let term = |exprs: &[&ParsedExpr]| -> Result<Option<Vec<u8>>, DbError> {
+        let values = exprs
+            .iter()
+            .map(|expr| resolve(expr))
+            .collect::<Result<Option<Vec<_>>, DbError>>()?;
+
+        Ok(values.map(|values| {
+            index_term(
+                &values
+                    .iter()
+                    .map(|value| analysis.analyze(value))
+                    .collect::<Vec<_>>(),
+            )
+        }))
+    };
+    // The first entry of the lookup and the one past its last, None when
+    // nothing can match
+    let bounds = match &restriction.lookup {
+        IndexLookup::Term(terms) => term(&terms.iter().collect::<Vec<_>>())?.map(|term| {
+            let end = prefix_end(&term);
+
+            (term, end)
+        }),
+        IndexLookup::Range(lower, upper) => {
+            let start = match lower {
+                Bound::Included(expr) => term(&[expr])?,
+                Bound::Excluded(expr) => term(&[expr])?.and_then(|term| prefix_end(&term)),
+                Bound::Unbounded => Some(vec![]),
+            };
+            let end = match upper {
+                Bound::Included(expr) => term(&[expr])?.map(|term| prefix_end(&term)),
+                Bound::Excluded(expr) => term(&[expr])?.map(Some),
+                Bound::Unbounded => Some(None),
+            };
+
+            start.zip(end)
+        }
+        IndexLookup::Prefix(pattern) => {
+            let prefix = match resolve(pattern)? {
+                Some(Value::Varchar(pattern)) => {
+                    Some(Value::Varchar(like_prefix(&pattern)?.to_string()))
+                }
+                Some(Value::Ascii(pattern)) => {
+                    let pattern = String::from_utf8_lossy(&pattern);
+
+                    Some(Value::Ascii(like_prefix(&pattern)?.as_bytes().to_vec()))
+                }
+                _ => None,
+            };
+
+            prefix.map(|prefix| {
+                let prefix = index_term_prefix(&analysis.analyze(&prefix));
+                let end = prefix_end(&prefix);
+
+                (prefix, end)
+            })
+        }
+    };
+    let Some((start, end)) = bounds else {
+        return Ok(Box::new(empty()));
+    };
+    let in_bounds = move |key: &[u8]| end.as_ref().is_none_or(|end| key < &end[..]);
+    // Entries are ordered by term ahead of row, so a lookup resumes from the
+    // last row's entry for the term it was found by, and starts over should
+    // the row be gone
+    let start = match paging_state {
+        Some(last_key) => {
+            let last_term = match &restriction.lookup {
+                IndexLookup::Term(_) => Some(start.clone()),
+                _ => Rows::new(table.clone(), partition.prefix(last_key))
+                    .next()
+                    .transpose()?
+                    .and_then(|row| {
+                        index_terms(index, row.get(&index.column)?)
+                            .into_iter()
+                            .find(|term| term >= &start && in_bounds(term))
+                    }),
+            };
+
+            match (last_term, prefix_end(last_key)) {
+                (Some(term), Some(next)) => [term, next].concat(),
+                (Some(_), None) => return Ok(Box::new(empty())),
+                (None, _) => start,
+            }
+        }
+        None => start,
+    };
+    let term_types = index
+        .target
+        .term_types(&table.columns[&index.column].column_type);
+    let entries = index_partition
+        .range(start..)
+        .take_while(move |entry| entry.as_ref().map_or(true, |(key, _)| in_bounds(key)))
+        .map(move |entry| entry.map(|(key, _)| index_row_key(&term_types, &key).to_vec()));
+
+    Ok(Box::new(entries))
+}
+
+fn row_key(table: &TableMetadata, row: &HashMap<String, Value>) -> Vec<u8> {
+    let key = |columns: &[String]| {
+        columns
+            .iter()
+            .filter_map(|column| row.get(column).cloned())
+            .collect::<Vec<_>>()
+    };
+
+    row_prefix(table, &key(&table.partition_key), &key(&table.cluster_key))
+}
+
+// Folds the rows into a single one as they stream by. As in Cassandra,
+// selectors that aren't aggregates take their value from the first row.
+fn aggregate(
+    rows: impl Iterator<Item = Result<HashMap<String, Value>, DbError>>,
+    projection: &[ParsedExpr],
+) -> Result<Vec<Option<Value>>, DbError> {
+    let mut aggregators = projection
+        .iter()
+        .map(|expr| match expr {
+            ParsedExpr::Aggregate(aggregate) => {
+                Some(Aggregator::new(&aggregate.function, &aggregate.return_type))
+            }
+            _ => None,
+        })
+        .collect::<Vec<_>>();
+    let mut first_row = None;
+
+    for row in rows {
+        let row = row?;
+
+        for (expr, aggregator) in projection.iter().zip(&mut aggregators) {
+            if let (ParsedExpr::Aggregate(aggregate), Some(aggregator)) = (expr, aggregator) {
+                aggregator.add(aggregate.argument.resolve(&row)?)?;
+            }
+        }
+
+        first_row.get_or_insert(row);
+    }
+
+    projection
+        .iter()
+        .zip(aggregators)
+        .map(|(expr, aggregator)| match (aggregator, &first_row) {
+            (Some(aggregator), _) => aggregator.result(),
+            (None, Some(row)) => expr.resolve(row),
+            (None, None) => Ok(None),
+        })
+        .collect()
+}
+
+// The table's partition is created straight away, fixing the options it maps onto
+pub async fn execute_create_table(
+    keyspace_name: &str,
+    table_metadata: &TableMetadata,
+    tables: &Arc<RwLock<Tables>>,
+    keyspace: &Keyspace,
+) -> Result<impl Iterator<Item = Vec<Option<Value>>>, DbError> {
+    open_partition(keyspace, table_metadata, ErrorCode::WriteFailure)?;
+    tables
+        .write()
+        .await
+        .insert(table_metadata.name.clone(), table_metadata.clone());
+
+    write_system_tables(keyspace, keyspace_name, table_metadata)
+}
+
+// Options such as compression are fixed when a partition is created, so the
+// table's cells move to a partition created with the new ones when they change
+pub async fn execute_alter_table(
+    keyspace_name: &str,
+    table_name: &str,
+    mut options: TableOptions,
+    tables: &Arc<RwLock<Tables>>,
+    keyspace: &Keyspace,
+) -> Result<impl Iterator<Item = Vec<Option<Value>>>, DbError> {
+    let mut tables = tables.write().await;
+    let Some(table) = tables.get_mut(table_name) else {
+        return Err(DbError::new(
+            ErrorCode::Invalid,
+            "Table not found".to_string(),
+        ));
+    };
+
+    if options.bloom_filter_fp_chance != table.options.bloom_filter_fp_chance
+        || options.compaction != table.options.compaction
+        || options.compression != table.options.compression
+    {
+        options.generation += 1;
+        move_cells(
+            keyspace,
+            table,
+            &TableMetadata {
+                options: options.clone(),
+                ..table.clone()
+            },
+        )?;
+    }
+
+    table.options = options;
+    write_system_tables(keyspace, keyspace_name, table)
+}
+
+const MOVED_CELLS_PER_BATCH: usize = 1000;
+
+// Copies a table's cells into the partition of its altered options, then drops
+// the partition they were in
+fn move_cells(
+    keyspace: &Keyspace,
+    table: &TableMetadata,
+    altered: &TableMetadata,
+) -> Result<(), DbError> {
+    let from = open_partition(keyspace, table, ErrorCode::WriteFailure)?;
+    let to = open_partition(keyspace, altered, ErrorCode::WriteFailure)?;
+    let mut batch = keyspace.batch();
+
+    for (moved, cell) in from.iter().enumerate() {
+        let (key, value) =
+            cell.map_err(|err| DbError::new(ErrorCode::ReadFailure, err.to_string()))?;
+
+        if moved > 0 && moved % MOVED_CELLS_PER_BATCH == 0 {
+            std::mem::replace(&mut batch, keyspace.batch())
+                .commit()
+                .map_err(|err| DbError::new(ErrorCode::WriteFailure, err.to_string()))?;
+        }
+
+        batch.insert(&to, key, value);
+    }
+
+    batch
+        .commit()
+        .map_err(|err| DbError::new(ErrorCode::WriteFailure, err.to_string()))?;
+    keyspace
+        .delete_partition(from)
+        .map_err(|err| DbError::new(ErrorCode::WriteFailure, err.to_string()))
+}
+
+fn write_system_tables(
+    keyspace: &Keyspace,
+    keyspace_name: &str,
+    table: &TableMetadata,
+) -> Result<impl Iterator<Item = Vec<Option<Value>>>, DbError> {
+    let system = system_table(SYSTEM_TABLES).unwrap();
+    let partition = open_partition(keyspace, &system, ErrorCode::WriteFailure)?;
+    let prefix = row_prefix(
+        &system,
+        &[Value::Varchar(keyspace_name.to_string())],
+        &[Value::Varchar(table.name.clone())],
+    );
+    let mut batch = keyspace.batch();
+
+    batch.insert(&partition, cell_key(&prefix, ROW_MARKER, &[]), Vec::new());
+
+    for (column, value) in tables_row(keyspace_name, table) {
+        let column = &system.columns[&column];
+
+        if !column.kind.is_primary_key() {
+            write_column(&mut batch, &partition, &prefix, column, Some(value))?;
+        }
+    }
+
+    commit(batch)
+}
+
+// The index is added to the table straight away so that writes keep it up to
+// date, while the rows already written are indexed in the background
+pub async fn execute_create_index(
+    table_name: &str,
+    mut index: IndexMetadata,
+    default_name: bool,
+    if_not_exists: bool,
+    tables: &Arc<RwLock<Tables>>,
+    keyspace: &Keyspace,
+) -> Result<impl Iterator<Item = Vec<Option<Value>>>, DbError> {
+    let mut tables = tables.write().await;
+    let names = tables
+        .values()
+        .flat_map(|table| &table.indexes)
+        .map(|existing| existing.name.clone())
+        .collect::<HashSet<_>>();
+    let table = tables.get_mut(table_name).ok_or_else(|| {
+        DbError::new(
+            ErrorCode::Invalid,
+            format!("Table {} not found", table_name),
+        )
+    })?;
+    // Only names are unique across the keyspace
+    let duplicate = table
+        .indexes
+        .iter()
+        .find(|existing| existing.column == index.column && existing.target == index.target);
+
+    if let Some(duplicate) = duplicate {
+        return if if_not_exists {
+            Ok(empty())
+        } else {
+            Err(DbError::new(
+                ErrorCode::Invalid,
+                format!(
+                    "Index {} is a duplicate of existing index {}",
+                    index.name, duplicate.name
+                ),
+            ))
+        };
+    }
+
+    if !default_name && names.contains(&index.name) {
+        return if if_not_exists {
+            Ok(empty())
+        } else {
+            Err(DbError::new(
+                ErrorCode::AlreadyExists,
+                format!("Index {} already exists", index.name),
+            ))
+        };
+    }
+
+    // Default names that are taken are numbered, as p_m_idx_1
+    if default_name {
+        index.name = once(index.name.clone())
+            .chain((1..).map(|n| format!("{}_{}", index.name, n)))
+            .find(|name| !names.contains(name))
+            .unwrap();
+    }
+
+    table.indexes.push(index.clone());
+
+    let table = table.clone();
+    let keyspace = keyspace.clone();
+
+    tokio::task::spawn_blocking(move || match build_index(&keyspace, &table, &index) {
+        Ok(()) => index.set_built(),
+        Err(error) => index.set_failed(error.message),
+    });
+
+    Ok(empty())
+}
+
+fn build_index(
+    keyspace: &Keyspace,
+    table: &TableMetadata,
+    index: &IndexMetadata,
+) -> Result<(), DbError> {
+    let partition = open_partition(keyspace, table, ErrorCode::WriteFailure)?;
+    let index_partition = open_index(keyspace, table, index, ErrorCode::WriteFailure)?;
+    let mut batch = keyspace.batch();
+
+    for row in Rows::new(table.clone(), partition.iter()) {
+        let row = row?;
+        let Some(value) = row.get(&index.column) else {
+            continue;
+        };
+        let key = row_key(table, &row);
+
+        for term in index_terms(index, value) {
+            batch.insert(&index_partition, [term, key.clone()].concat(), vec![]);
+        }
+
+        if let (Some(mut graph), Some(vector)) = (index.graph(), floats(value)) {
+            graph.insert(key, vector);
+        }
+    }
+
+    commit(batch).map(|_| ())
+}
+
+pub async fn execute_drop_index(
+    name: &str,
+    if_exists: bool,
+    tables: &Arc<RwLock<Tables>>,
+    keyspace: &Keyspace,
+) -> Result<impl Iterator<Item = Vec<Option<Value>>>, DbError> {
+    let mut tables = tables.write().await;
+    let table = tables
+        .values_mut()
+        .find(|table| table.indexes.iter().any(|index| index.name == name));
+    let Some(table) = table else {
+        return if if_exists {
+            Ok(empty())
+        } else {
+            Err(DbError::new(
+                ErrorCode::Invalid,
+                format!("Index '{}' could not be found in any of the tables", name),
+            ))
+        };
     };
+    let position = table
+        .indexes
+        .iter()
+        .position(|index| index.name == name)
+        .unwrap();
+    let index = table.indexes.remove(position);
+    let index_partition = open_index(keyspace, table, &index, ErrorCode::WriteFailure)?;
 
-    partition
-        .insert(prefix, row)
+    keyspace
+        .delete_partition(index_partition)
         .map_err(|err| DbError::new(ErrorCode::WriteFailure, err.to_string()))?;
 
     Ok(empty())
 }
 
-pub fn execute_select<'a>(
+// The view is added to its base table straight away so that writes keep it
+// up to date, and the base table's rows are copied into it before the
+// statement completes
+pub async fn execute_create_view(
+    view: TableMetadata,
+    if_not_exists: bool,
+    tables: &Arc<RwLock<Tables>>,
     keyspace: &Keyspace,
-    parsed_query: ParsedQuery,
 ) -> Result<impl Iterator<Item = Vec<Option<Value>>>, DbError> {
-    let table = &parsed_query.table;
-    let partition = keyspace
-        .open_partition(&table.name, PartitionCreateOptions::default())
-        .map_err(|err| DbError::new(ErrorCode::ReadFailure, err.to_string()))?;
-    let prefix = [
-        &parsed_query.partition_key[..],
-        &parsed_query.clustering_key[..],
-    ]
-    .concat()
-    .join("");
-
-    fn unwrap_values(row: &HashMap<String, Option<Value>>) -> HashMap<String, Value> {
-        row.iter()
-            .filter(|(_, v)| v.is_some())
-            .map(|(k, v)| (k.clone(), v.clone().unwrap()))
-            .collect()
-    }
-
-    let iterator: Box<dyn DoubleEndedIterator<Item = fjall::Result<KvPair>>> =
-        if prefix.is_empty().not() {
-            Box::new(partition.prefix(prefix))
+    let mut tables = tables.write().await;
+
+    if tables.contains_key(&view.name) {
+        return if if_not_exists {
+            Ok(empty())
+        } else {
+            Err(DbError::new(
+                ErrorCode::AlreadyExists,
+                format!("Cannot add already existing table \"{}\"", view.name),
+            ))
+        };
+    }
+
+    let base_name = view.base_table.clone().unwrap_or_default();
+    let base = tables.get_mut(&base_name).ok_or_else(|| {
+        DbError::new(ErrorCode::Invalid, format!("Table {} not found", base_name))
+    })?;
+
+    base.views.push(view.clone());
+
+    // Only the new view is built
+    let base = TableMetadata {
+        views: vec![view.clone()],
+        ..base.clone()
+    };
+    let keyspace = keyspace.clone();
+
+    tables.insert(view.name.clone(), view);
+    tokio::task::spawn_blocking(move || build_view(&keyspace, &base))
+        .await
+        .map_err(|error| DbError::new(ErrorCode::ServerError, error.to_string()))??;
+
+    Ok(empty())
+}
+
+fn build_view(keyspace: &Keyspace, base: &TableMetadata) -> Result<(), DbError> {
+    let partition = open_partition(keyspace, base, ErrorCode::WriteFailure)?;
+    let mut batch = keyspace.batch();
+
+    for row in Rows::new(base.clone(), partition.iter()) {
+        update_view_rows(keyspace, &mut batch, base, None, Some(&row?))?;
+    }
+
+    commit(batch).map(|_| ())
+}
+
+pub async fn execute_drop_view(
+    name: &str,
+    if_exists: bool,
+    tables: &Arc<RwLock<Tables>>,
+    keyspace: &Keyspace,
+) -> Result<impl Iterator<Item = Vec<Option<Value>>>, DbError> {
+    let mut tables = tables.write().await;
+    let base_name = match tables.get(name) {
+        Some(TableMetadata {
+            base_table: Some(base_name),
+            ..
+        }) => base_name.clone(),
+        Some(_) => {
+            return Err(DbError::new(
+                ErrorCode::Invalid,
+                format!("Cannot use DROP MATERIALIZED VIEW on table {}", name),
+            ))
+        }
+        None if if_exists => return Ok(empty()),
+        None => {
+            return Err(DbError::new(
+                ErrorCode::Invalid,
+                format!("Materialized view '{}' could not be found", name),
+            ))
+        }
+    };
+    let view = tables.remove(name).unwrap();
+
+    if let Some(base) = tables.get_mut(&base_name) {
+        base.views.retain(|view| view.name != name);
+    }
+
+    let partition = open_partition(keyspace, &view, ErrorCode::WriteFailure)?;
+
+    keyspace
+        .delete_partition(partition)
+        .map_err(|err| DbError::new(ErrorCode::WriteFailure, err.to_string()))?;
+
+    Ok(empty())
+}
+
+pub async fn execute_describe(
+    keyspace: &str,
+    kind: DescribeKind,
+    qualifier: Option<&str>,
+    name: Option<&str>,
+    tables: &Arc<RwLock<Tables>>,
+    types: &Arc<RwLock<Types>>,
+    functions: &Arc<RwLock<Functions>>,
+) -> Result<impl Iterator<Item = Vec<Option<Value>>>, DbError> {
+    let rows = describe(
+        keyspace,
+        kind,
+        qualifier,
+        name,
+        &*tables.read().await,
+        &*types.read().await,
+        &*functions.read().await,
+    )?;
+
+    Ok(rows.into_iter())
+}
+
+pub async fn execute_create_type(
+    user_type: &UserType,
+    if_not_exists: bool,
+    types: &Arc<RwLock<Types>>,
+) -> Result<impl Iterator<Item = Vec<Option<Value>>>, DbError> {
+    let mut types = types.write().await;
+
+    if types.contains_key(&user_type.name) {
+        return if if_not_exists {
+            Ok(empty())
         } else {
-            Box::new(partition.iter())
+            Err(DbError::new(
+                ErrorCode::AlreadyExists,
+                format!("A user type with name '{}' already exists", user_type.name),
+            ))
         };
-    let ordered_columns = table.ordered_column_names();
-    let results = iterator
-        .map(|raw_row| Row::from(raw_row.unwrap().1))
-        .map(move |row| {
-            let mut columns: HashMap<String, Option<Value>> = HashMap::new();
+    }
+
+    types.insert(user_type.name.clone(), user_type.clone());
+
+    Ok(empty())
+}
+
+const USER_FUNCTION_TIMEOUT: Duration = Duration::from_millis(1500);
+
+thread_local! {
+    // When the user functions being evaluated on this thread run out of time,
+    // checked before each function call within their bodies
+    static USER_FUNCTION_DEADLINE: Cell<Option<Instant>> = const { Cell::new(None) };
+}
+
+pub async fn execute_create_function(
+    function: &UserFunction,
+    body: ParsedExpr,
+    or_replace: bool,
+    if_not_exists: bool,
+    functions: &Arc<RwLock<Functions>>,
+) -> Result<impl Iterator<Item = Vec<Option<Value>>>, DbError> {
+    let mut functions = functions.write().await;
+    let function = user_function(function, body, USER_FUNCTION_TIMEOUT);
+
+    if let Some(existing) = functions.get(&function.handle()) {
+        if if_not_exists {
+            return Ok(empty());
+        }
+
+        if !or_replace {
+            return Err(DbError::new(
+                ErrorCode::AlreadyExists,
+                format!("Function {} already exists", function.handle()),
+            ));
+        }
+
+        if existing.return_type != function.return_type {
+            return Err(DbError::new(
+                ErrorCode::Invalid,
+                format!(
+                    "Cannot replace function {}, the new return type {} is not compatible with the return type {} of existing function",
+                    function.handle(),
+                    function.return_type,
+                    existing.return_type
+                ),
+            ));
+        }
+    }
+
+    functions.insert(function);
+
+    Ok(empty())
+}
+
+pub async fn execute_create_aggregate(
+    aggregate: UserAggregate,
+    or_replace: bool,
+    if_not_exists: bool,
+    functions: &Arc<RwLock<Functions>>,
+) -> Result<impl Iterator<Item = Vec<Option<Value>>>, DbError> {
+    let mut functions = functions.write().await;
+
+    if let Some(existing) = functions.get_aggregate(&aggregate.handle()) {
+        if if_not_exists {
+            return Ok(empty());
+        }
+
+        if !or_replace {
+            return Err(DbError::new(
+                ErrorCode::AlreadyExists,
+                format!("Aggregate {} already exists", aggregate.handle()),
+            ));
+        }
+
+        if existing.return_type() != aggregate.return_type() {
+            return Err(DbError::new(
+                ErrorCode::Invalid,
+                format!(
+                    "Cannot replace aggregate {}, the new return type {} is not compatible with the return type {} of existing aggregate",
+                    aggregate.handle(),
+                    aggregate.return_type(),
+                    existing.return_type()
+                ),
+            ));
+        }
+    }
+
+    functions.insert_aggregate(aggregate);
+
+    Ok(empty())
+}
+
+// The body is evaluated over a row of the arguments, named as the parameters
+fn user_function(definition: &UserFunction, body: ParsedExpr, timeout: Duration) -> ScalarFunction {
+    let names = definition
+        .arguments
+        .iter()
+        .map(|(name, _)| name.clone())
+        .collect::<Vec<_>>();
+    let argument_types = definition
+        .arguments
+        .iter()
+        .map(|(_, argument_type)| argument_type.clone())
+        .collect::<Vec<_>>();
+    let handle = signature(&definition.keyspace, &definition.name, &argument_types);
+    let called_on_null_input = definition.called_on_null_input;
 
-            for n in 0..row.columns.len() {
-                let column_name = ordered_columns[n].clone();
-                columns.insert(column_name, row.columns[n].clone());
+    ScalarFunction::new(
+        &definition.keyspace,
+        &definition.name,
+        argument_types,
+        definition.return_type.clone(),
+        Some(definition.clone()),
+        move |arguments| {
+            if !called_on_null_input && arguments.iter().any(Option::is_none) {
+                return Ok(None);
             }
 
-            columns
-        })
-        .map(move |row| {
-            parsed_query
-                .projection
+            let row = names
                 .iter()
-                .map(|expr| expr.resolve(unwrap_values(&row), &HashMap::new()))
-                .collect::<Vec<_>>()
-        });
+                .zip(arguments)
+                .filter_map(|(name, value)| Some((name.clone(), value.clone()?)))
+                .collect::<HashMap<_, _>>();
+            let start = Instant::now();
+            // A function called from another runs within the caller's time too
+            let previous = USER_FUNCTION_DEADLINE.get();
+            let deadline =
+                previous.map_or(start + timeout, |previous| previous.min(start + timeout));
+
+            USER_FUNCTION_DEADLINE.set(Some(deadline));
+
+            let result = body.resolve(&row);
+
+            USER_FUNCTION_DEADLINE.set(previous);
 
-    Ok(results)
+            if start.elapsed() >= timeout {
+                return Err(DbError::new(
+                    ErrorCode::FunctionFailure,
+                    format!(
+                        "User defined function {} ran longer than {}ms",
+                        handle,
+                        timeout.as_millis()
+                    ),
+                ));
+            }
+
+            result.map_err(|error| {
+                DbError::new(
+                    ErrorCode::FunctionFailure,
+                    format!("execution of '{}' failed: {}", handle, error.message),
+                )
+            })
+        },
+    )
 }
 
-pub async fn execute_create_table(
-    table_metadata: &TableMetadata,
+// Column types embed the definition of the user types they use, so every
+// table and user type referring to the altered type is updated with it
+pub async fn execute_alter_type(
+    user_type: &UserType,
+    types: &Arc<RwLock<Types>>,
     tables: &Arc<RwLock<Tables>>,
 ) -> Result<impl Iterator<Item = Vec<Option<Value>>>, DbError> {
-    tables
-        .write()
-        .await
-        .insert(table_metadata.name.clone(), table_metadata.clone());
+    let mut types = types.write().await;
+
+    for existing in types.values_mut() {
+        for (_, field_type) in existing.fields.iter_mut() {
+            replace_user_type(field_type, user_type);
+        }
+    }
+
+    types.insert(user_type.name.clone(), user_type.clone());
+
+    for table in tables.write().await.values_mut() {
+        for column in table.columns.values_mut() {
+            replace_user_type(&mut column.column_type, user_type);
+        }
+    }
+
+    Ok(empty())
+}
+
+pub async fn execute_drop_type(
+    name: &str,
+    if_exists: bool,
+    types: &Arc<RwLock<Types>>,
+    tables: &Arc<RwLock<Tables>>,
+) -> Result<impl Iterator<Item = Vec<Option<Value>>>, DbError> {
+    let mut types = types.write().await;
+
+    if !types.contains_key(name) {
+        return if if_exists {
+            Ok(empty())
+        } else {
+            Err(DbError::new(
+                ErrorCode::Invalid,
+                format!("No user type named {} exists.", name),
+            ))
+        };
+    }
+
+    let used_by_type = types.values().find(|user_type| {
+        user_type
+            .fields
+            .iter()
+            .any(|(_, field_type)| references_user_type(field_type, name))
+    });
+    let tables = tables.read().await;
+    let used_by_table = tables.values().find(|table| {
+        table
+            .columns
+            .values()
+            .any(|column| references_user_type(&column.column_type, name))
+    });
+    let user = used_by_type
+        .map(|user_type| format!("user type {}", user_type.name))
+        .or(used_by_table.map(|table| format!("table {}", table.name)));
+
+    if let Some(user) = user {
+        return Err(DbError::new(
+            ErrorCode::Invalid,
+            format!(
+                "Cannot drop user type {} as it is still used by {}",
+                name, user
+            ),
+        ));
+    }
+
+    types.remove(name);
 
     Ok(empty())
 }
 
+fn replace_user_type(column_type: &mut ColumnType, user_type: &UserType) {
+    match column_type {
+        ColumnType::Udt(existing) if existing.name == user_type.name => {
+            *existing = user_type.clone()
+        }
+        ColumnType::Udt(existing) => {
+            for (_, field_type) in existing.fields.iter_mut() {
+                replace_user_type(field_type, user_type);
+            }
+        }
+        ColumnType::List(inner) | ColumnType::Set(inner) | ColumnType::Frozen(inner) => {
+            replace_user_type(inner, user_type)
+        }
+        ColumnType::Map(key, value) => {
+            replace_user_type(key, user_type);
+            replace_user_type(value, user_type);
+        }
+        _ => {}
+    }
+}
+
+pub fn references_user_type(column_type: &ColumnType, name: &str) -> bool {
+    match column_type {
+        ColumnType::Udt(user_type) => {
+            user_type.name == name
+                || user_type
+                    .fields
+                    .iter()
+                    .any(|(_, field_type)| references_user_type(field_type, name))
+        }
+        ColumnType::List(inner) | ColumnType::Set(inner) | ColumnType::Frozen(inner) => {
+            references_user_type(inner, name)
+        }
+        ColumnType::Map(key, value) => {
+            references_user_type(key, name) || references_user_type(value, name)
+        }
+        _ => false,
+    }
+}
+
+fn open_partition(
+    keyspace: &Keyspace,
+    table: &TableMetadata,
+    code: ErrorCode,
+) -> Result<PartitionHandle, DbError> {
+    // The options only take effect when the partition is first created
+    keyspace
+        .open_partition(&table_partition(table), partition_options(&table.options))
+        .map_err(|err| DbError::new(code, err.to_string()))
+}
+
+fn open_index(
+    keyspace: &Keyspace,
+    table: &TableMetadata,
+    index: &IndexMetadata,
+    code: ErrorCode,
+) -> Result<PartitionHandle, DbError> {
+    keyspace
+        .open_partition(
+            &index_partition(table, index),
+            PartitionCreateOptions::default(),
+        )
+        .map_err(|err| DbError::new(code, err.to_string()))
+}
+
+fn resolve(expr: &ParsedExpr) -> Result<Option<Value>, DbError> {
+    expr.resolve(&HashMap::new())
+}
+
+fn resolve_row_prefix(
+    table: &TableMetadata,
+    partition_key: &[ParsedExpr],
+    clustering_key: &[ParsedExpr],
+) -> Result<Vec<u8>, DbError> {
+    let resolve_key = |key: &[ParsedExpr]| {
+        key.iter()
+            .map(|expr| {
+                resolve(expr)?.ok_or_else(|| {
+                    DbError::new(
+                        ErrorCode::Invalid,
+                        "Invalid null value for primary key part".to_string(),
+                    )
+                })
+            })
+            .collect::<Result<Vec<Value>, DbError>>()
+    };
+
+    Ok(row_prefix(
+        table,
+        &resolve_key(partition_key)?,
+        &resolve_key(clustering_key)?,
+    ))
+}
+
+// Replaces a column's value, including every cell of a non-frozen collection
+// or user type
+fn write_column(
+    batch: &mut Batch,
+    partition: &PartitionHandle,
+    row_prefix: &[u8],
+    column: &ColumnMetadata,
+    value: Option<Value>,
+) -> Result<(), DbError> {
+    if !column.column_type.is_multi_cell() {
+        let key = cell_key(row_prefix, &column.name, &[]);
+
+        match value {
+            Some(value) => batch.insert(partition, key, Into::<Vec<u8>>::into(value)),
+            None => batch.remove(partition, key),
+        }
+
+        return Ok(());
+    }
+
+    let prefix = column_prefix(row_prefix, &column.name);
+    let cells = value
+        .map(|value| element_cells(&prefix, value, false))
+        .unwrap_or_default();
+
+    // Cells that are about to be rewritten are left alone, as a removal and an
+    // insert of the same key within one batch don't have a defined order
+    for cell in partition.prefix(&prefix) {
+        let (key, _) = cell.map_err(|err| DbError::new(ErrorCode::ReadFailure, err.to_string()))?;
+
+        if !cells.iter().any(|(cell_key, _)| cell_key[..] == key[..]) {
+            batch.remove(partition, key);
+        }
+    }
+
+    for (key, value) in cells {
+        batch.insert(partition, key, value);
+    }
+
+    Ok(())
+}
+
+fn apply_operation(
+    batch: &mut Batch,
+    partition: &PartitionHandle,
+    row_prefix: &[u8],
+    parsed_operation: &ParsedOperation,
+) -> Result<(), DbError> {
+    let column = &parsed_operation.column;
+    let prefix = column_prefix(row_prefix, &column.name);
+
+    match &parsed_operation.operation {
+        Operation::Set(value) => {
+            write_column(batch, partition, row_prefix, column, resolve(value)?)?
+        }
+        Operation::Append(value) | Operation::Prepend(value) => {
+            let prepend = matches!(parsed_operation.operation, Operation::Prepend(_));
+
+            if let Some(value) = resolve(value)? {
+                for (key, value) in element_cells(&prefix, value, prepend) {
+                    batch.insert(partition, key, value);
+                }
+            }
+        }
+        Operation::Remove(value) => match resolve(value)? {
+            // List cells are keyed by position, so removing by value means reading them
+            Some(Value::List(removed)) => {
+                for cell in partition.prefix(&prefix) {
+                    let (key, value) =
+                        cell.map_err(|err| DbError::new(ErrorCode::ReadFailure, err.to_string()))?;
+
+                    if removed.contains(&Value::try_from(value)?) {
+                        batch.remove(partition, key);
+                    }
+                }
+            }
+            Some(Value::Set(removed)) => {
+                for element in removed {
+                    batch.remove(partition, [&prefix[..], &element_path(&element)].concat());
+                }
+            }
+            _ => {}
+        },
+        Operation::SetElement(element, value) => {
+            let key = match (&column.column_type, resolve(element)?) {
+                (ColumnType::List(_), Some(Value::Int(index))) => {
+                    list_element_key(partition, &prefix, index)?
+                }
+                (_, Some(element)) => [&prefix[..], &element_path(&element)].concat(),
+                (_, None) => {
+                    return Err(DbError::new(
+                        ErrorCode::Invalid,
+                        format!("Invalid null value for {} element", column.name),
+                    ))
+                }
+            };
+
+            match resolve(value)? {
+                Some(value) => batch.insert(partition, key, Into::<Vec<u8>>::into(value)),
+                None => batch.remove(partition, key),
+            }
+        }
+        // The column's own cell holds the total, which each increment folds
+        // the deltas already written into. The delta itself is a cell keyed
+        // like a list append, so increments within one batch all add up.
+        Operation::Increment(delta) | Operation::Decrement(delta) => {
+            let delta = match (resolve(delta)?, &parsed_operation.operation) {
+                (Some(Value::Counter(delta)), Operation::Decrement(_)) => delta.wrapping_neg(),
+                (Some(Value::Counter(delta)), _) => delta,
+                _ => {
+                    return Err(DbError::new(
+                        ErrorCode::Invalid,
+                        format!(
+                            "Invalid null value for counter increment on {}",
+                            column.name
+                        ),
+                    ))
+                }
+            };
+            let mut total = 0i64;
+
+            for cell in partition.prefix(&prefix) {
+                let (key, value) =
+                    cell.map_err(|err| DbError::new(ErrorCode::ReadFailure, err.to_string()))?;
+
+                if let Value::Counter(count) = Value::try_from(value)? {
+                    total = total.wrapping_add(count);
+                }
+
+                if key[..] != prefix[..] {
+                    batch.remove(partition, key);
+                }
+            }
+
+            let key = [&prefix[..], &list_element_path(false)].concat();
+
+            batch.insert(
+                partition,
+                prefix,
+                Into::<Vec<u8>>::into(Value::Counter(total)),
+            );
+            batch.insert(partition, key, Into::<Vec<u8>>::into(Value::Counter(delta)));
+        }
+        Operation::SetField(position, value) => {
+            let key = [&prefix[..], &field_path(*position)].concat();
+
+            match resolve(value)? {
+                Some(value) => batch.insert(partition, key, Into::<Vec<u8>>::into(value)),
+                None => batch.remove(partition, key),
+            }
+        }
+    }
+
+    Ok(())
+}
+
+// The cells making up a collection or user type value. Prepended list elements are keyed
+// last to first so that they keep their order ahead of the existing ones.
+fn element_cells(column_prefix: &[u8], value: Value, prepend: bool) -> Vec<(Vec<u8>, Vec<u8>)> {
+    let key = |path: Vec<u8>| [column_prefix, &path].concat();
+
+    match value {
+        Value::List(values) if prepend => values
+            .into_iter()
+            .rev()
+            .map(|value| (key(list_element_path(true)), value.into()))
+            .collect(),
+        Value::List(values) => values
+            .into_iter()
+            .map(|value| (key(list_element_path(false)), value.into()))
+            .collect(),
+        Value::Set(values) => values
+            .iter()
+            .map(|value| (key(element_path(value)), vec![]))
+            .collect(),
+        Value::Map(entries) => entries
+            .into_iter()
+            .map(|(element, value)| (key(element_path(&element)), value.into()))
+            .collect(),
+        Value::Udt(fields) => fields
+            .into_iter()
+            .enumerate()
+            .filter_map(|(position, value)| Some((key(field_path(position)), value?.into())))
+            .collect(),
+        _ => vec![],
+    }
+}
+
+fn list_element_key(
+    partition: &PartitionHandle,
+    column_prefix: &[u8],
+    index: i32,
+) -> Result<Vec<u8>, DbError> {
+    let cell = usize::try_from(index)
+        .ok()
+        .and_then(|index| partition.prefix(column_prefix).nth(index))
+        .transpose()
+        .map_err(|err| DbError::new(ErrorCode::ReadFailure, err.to_string()))?;
+
+    match cell {
+        Some((key, _)) => Ok(key.to_vec()),
+        None => Err(DbError::new(
+            ErrorCode::Invalid,
+            format!(
+                "List index {} out of bound, list has size {}",
+                index,
+                partition.prefix(column_prefix).count()
+            ),
+        )),
+    }
+}
+
+impl ParsedFilter {
+    fn matches(&self, row: &HashMap<String, Value>) -> Result<bool, DbError> {
+        let args = self
+            .args
+            .iter()
+            .map(|arg| arg.resolve(row))
+            .collect::<Result<Vec<_>, DbError>>()?;
+
+        Ok((self.filter)(args).unwrap_or(false))
+    }
+}
+
 impl ParsedExpr {
-    fn resolve(
-        &self,
-        row: HashMap<String, Value>,
-        catalog: &HashMap<String, Function>,
-    ) -> Option<Value> {
+    fn resolve(&self, row: &HashMap<String, Value>) -> Result<Option<Value>, DbError> {
         match self {
             ParsedExpr::Column(column) => {
                 let column_name = &column.target_column;
 
-                row.get(column_name).map(|value| value.to_owned())
+                Ok(row.get(column_name).map(|value| value.to_owned()))
+            }
+            ParsedExpr::Field(expr, field) => match expr.resolve(row)? {
+                Some(Value::Udt(fields)) => Ok(fields.get(field.position).cloned().flatten()),
+                _ => Ok(None),
+            },
+            ParsedExpr::Function(function) => {
+                let arguments = function
+                    .arguments
+                    .iter()
+                    .map(|expr| expr.resolve(row))
+                    .collect::<Result<Vec<_>, DbError>>()?;
+
+                if USER_FUNCTION_DEADLINE
+                    .get()
+                    .is_some_and(|deadline| Instant::now() >= deadline)
+                {
+                    return Err(DbError::new(
+                        ErrorCode::FunctionFailure,
+                        "User defined function ran out of time".to_string(),
+                    ));
+                }
+
+                function.function.call(&arguments)
             }
-            ParsedExpr::Function(function_handle, parameters) => {
-                let function = catalog.get(function_handle).unwrap();
-                let values: Vec<Option<Value>> = parameters
+            ParsedExpr::Literal(value) => Ok(value.clone()),
+            // Aggregates are folded over the rows by execute_select
+            ParsedExpr::Aggregate(_) => Ok(None),
+            ParsedExpr::Marker(..) => Ok(None),
+            ParsedExpr::Collection(column_type, elements) => {
+                let mut elements = elements
                     .iter()
-                    .map(|expr| expr.resolve(row.clone(), &catalog))
-                    .collect();
+                    .map(|expr| {
+                        expr.resolve(row)?.ok_or_else(|| {
+                            DbError::new(
+                                ErrorCode::Invalid,
+                                "null is not supported inside collections".to_string(),
+                            )
+                        })
+                    })
+                    .collect::<Result<Vec<_>, DbError>>()?
+                    .into_iter();
 
-                Some(function(values))
+                Ok(Some(match column_type.unfrozen() {
+                    ColumnType::Set(_) => Value::set(elements.collect()),
+                    ColumnType::Map(..) => {
+                        Value::map(from_fn(|| Some((elements.next()?, elements.next()?))).collect())
+                    }
+                    ColumnType::Vector(..) => Value::Vector(elements.collect()),
+                    _ => Value::List(elements.collect()),
+                }))
             }
-            ParsedExpr::Literal(value) => value.clone(),
         }
     }
 }
@@ -135,11 +2232,11 @@ impl ParsedExpr {
 #[cfg(test)]
 mod tests {
     use super::*;
-    use crate::db::data::{row, ColumnType, Row, Value};
-    use crate::db::parse::ProjectedColumn;
+    use crate::db::data::{ColumnType, Value};
+    use crate::db::parse::{ParsedFunction, ProjectedColumn};
     use crate::db::schema::{ColumnMetadata, Keyspace, Kind, TableMetadata, Tables};
+    use fjall::Config;
     use fjall::Keyspace as FjallKeyspace;
-    use fjall::{Config, PartitionCreateOptions};
     use indexmap::IndexMap;
 
     use crate::db::Database;
@@ -172,13 +2269,18 @@ mod tests {
             columns,
             partition_key: vec!["id".to_string()],
             cluster_key: vec![],
+            indexes: vec![],
+            views: vec![],
+            base_table: None,
+            options: TableOptions::default(),
         };
 
         let tables = Arc::new(RwLock::new(Tables::new()));
-        let fjall = FjallKeyspace::open(Config::new("/tmp/x")).unwrap();
+        let directory = tempfile::tempdir().unwrap();
+        let fjall = FjallKeyspace::open(Config::new(directory.path())).unwrap();
 
         // Step 2: Execute create table
-        let create_result = execute_create_table(&table_metadata, &tables).await;
+        let create_result = execute_create_table("test_db", &table_metadata, &tables, &fjall).await;
 
         assert!(create_result.is_ok());
         assert!(tables.read().await.contains_key("users"));
@@ -186,8 +2288,9 @@ mod tests {
         // Step 3: Prepare and execute an insert
         let parsed_insert = ParsedInsert {
             table: table_metadata.clone(),
-            partition_key: vec!["1".to_string()],
+            partition_key: vec![ParsedExpr::Literal(Some(Value::Int(1)))],
             clustering_key: vec![],
+            columns: vec!["id".to_string(), "name".to_string()],
             values: vec![
                 ParsedExpr::Literal(Some(Value::Int(1))),
                 ParsedExpr::Literal(Some(Value::Varchar("John Doe".to_string()))),
@@ -200,7 +2303,7 @@ mod tests {
         // Step 4: Prepare and execute a select
         let parsed_query = ParsedQuery {
             table: table_metadata.clone(),
-            partition_key: vec!["1".to_string()],
+            partition_key: vec![ParsedExpr::Literal(Some(Value::Int(1)))],
             clustering_key: vec![],
             projection: vec![
                 ParsedExpr::Column(ProjectedColumn {
@@ -214,7 +2317,14 @@ mod tests {
                     column_metadata: table_metadata.columns.get("name").unwrap().clone(),
                 }),
             ],
-            filters: HashMap::new(),
+            filters: vec![],
+            group_by: vec![],
+            distinct: false,
+            json: false,
+            index: vec![],
+            ann: None,
+            limit: None,
+            reversed: false,
             column_count: 2,
         };
 
@@ -222,7 +2332,7 @@ mod tests {
         assert!(select_result.is_ok());
 
         let mut result_iter = select_result.unwrap();
-        let row = result_iter.next().unwrap();
+        let row = result_iter.next().unwrap().unwrap();
 
         // Step 5: Verify the selected data
         assert_eq!(row.len(), 2);
@@ -257,6 +2367,10 @@ mod tests {
             columns,
             partition_key: vec!["id".to_string()],
             cluster_key: vec![],
+            indexes: vec![],
+            views: vec![],
+            base_table: None,
+            options: TableOptions::default(),
         };
         let mut tables = &mut Tables::new();
 
@@ -267,19 +2381,31 @@ mod tests {
             tables: &mut tables,
         };
 
-        let ks = FjallKeyspace::open(Config::new("/tmp/x")).unwrap();
-        let row_a = row![Value::Smallint(1), Value::Varchar("row1".to_string())];
-        let row_b = row![Value::Smallint(2), Value::Varchar("row2".to_string())];
-        let partition = ks
-            .open_partition(&table.name, PartitionCreateOptions::default())
-            .unwrap();
+        let directory = tempfile::tempdir().unwrap();
+        let ks = FjallKeyspace::open(Config::new(directory.path())).unwrap();
+
+        for (id, name) in [(1, "row1"), (2, "row2")] {
+            let key = ParsedExpr::Literal(Some(Value::Smallint(id)));
+            let insert_result = execute_insert(
+                &ks,
+                ParsedInsert {
+                    table: table.clone(),
+                    partition_key: vec![key.clone()],
+                    clustering_key: vec![],
+                    columns: vec!["id".to_string(), "name".to_string()],
+                    values: vec![
+                        key,
+                        ParsedExpr::Literal(Some(Value::Varchar(name.to_string()))),
+                    ],
+                },
+            );
 
-        partition.insert("1", row_a).unwrap();
-        partition.insert("2", row_b).unwrap();
+            assert!(insert_result.is_ok());
+        }
 
         // Define a simple ParsedQuery
         let parsed_query = ParsedQuery {
-            partition_key: vec![1u16.to_string()],
+            partition_key: vec![ParsedExpr::Literal(Some(Value::Smallint(1)))],
             clustering_key: vec![],
             projection: vec![
                 ParsedExpr::Column(ProjectedColumn {
@@ -293,7 +2419,14 @@ mod tests {
                     column_metadata: table.columns.get("name").unwrap().clone(),
                 }),
             ],
-            filters: HashMap::new(),
+            filters: vec![],
+            group_by: vec![],
+            distinct: false,
+            json: false,
+            index: vec![],
+            ann: None,
+            limit: None,
+            reversed: false,
             table,
             column_count: 2,
         };
@@ -305,7 +2438,7 @@ mod tests {
         assert!(result.is_ok());
 
         let mut result_iter = result.unwrap();
-        let row = result_iter.next().unwrap();
+        let row = result_iter.next().unwrap().unwrap();
 
         assert_eq!(row.len(), 2);
         assert_eq!(row[0], Some(Value::Smallint(1)));
@@ -339,20 +2472,27 @@ mod tests {
                 columns,
                 partition_key: vec!["id".to_string()],
                 cluster_key: vec![],
+                indexes: vec![],
+                views: vec![],
+                base_table: None,
+                options: TableOptions::default(),
             };
 
             let tables = Tables::new();
             let binding = Arc::new(RwLock::new(tables));
-            let fjall = FjallKeyspace::open(Config::new("/tmp/x")).unwrap();
+            let types = Arc::new(RwLock::new(Types::new()));
+            let functions = Arc::new(RwLock::new(Functions::default()));
+            let directory = tempfile::tempdir().unwrap();
+            let fjall = FjallKeyspace::open(Config::new(directory.path())).unwrap();
             let database = Arc::new(RwLock::new(Database {
                 name: "test_db",
                 tables: &binding,
+                types: &types,
+                functions: &functions,
                 fjall: &fjall,
             }));
 
-            let result =
-                execute_create_table(&table_metadata, Arc::clone(&database).read().await.tables)
-                    .await;
+            let result = execute_create_table("test_db", &table_metadata, &binding, &fjall).await;
 
             assert!(result.is_ok());
 
@@ -370,4 +2510,74 @@ mod tests {
             assert_eq!(created_table.columns.get("name").unwrap().name, "name");
         })
     }
+
+    #[test]
+    fn test_user_function_timeout() {
+        let definition = UserFunction {
+            keyspace: String::new(),
+            name: "one".to_string(),
+            arguments: vec![("x".to_string(), ColumnType::Int)],
+            return_type: ColumnType::Int,
+            called_on_null_input: true,
+            language: "cql".to_string(),
+            body: "1".to_string(),
+        };
+        let body = ParsedExpr::Literal(Some(Value::Int(1)));
+        let function = user_function(&definition, body.clone(), Duration::from_secs(60));
+
+        assert_eq!(function.call(&[None]).unwrap(), Some(Value::Int(1)));
+
+        let function = user_function(&definition, body, Duration::ZERO);
+        let error = function.call(&[Some(Value::Int(2))]).unwrap_err();
+
+        assert!(matches!(error.code, ErrorCode::FunctionFailure));
+        assert_eq!(
+            error.message,
+            "User defined function one(int) ran longer than 0ms"
+        );
+    }
+
+    #[test]
+    fn test_user_function_interrupted() {
+        let definition = |name: String| UserFunction {
+            keyspace: String::new(),
+            name,
+            arguments: vec![("x".to_string(), ColumnType::Int)],
+            return_type: ColumnType::Int,
+            called_on_null_input: true,
+            language: "cql".to_string(),
+            body: String::new(),
+        };
+        let call = |function: &Arc<ScalarFunction>, argument: ParsedExpr| {
+            ParsedExpr::Function(ParsedFunction {
+                function: function.clone(),
+                arguments: vec![argument],
+                resolved_name: function.name.clone(),
+                return_type: ColumnType::Int,
+            })
+        };
+        let timeout = Duration::from_millis(100);
+        let mut function = Arc::new(user_function(
+            &definition("f0".to_string()),
+            ParsedExpr::Literal(Some(Value::Int(1))),
+            timeout,
+        ));
+
+        // Each calls the one before twice, so the last makes 2^40 calls
+        for n in 1..=40 {
+            let body = call(&function, call(&function, ParsedExpr::Literal(None)));
+
+            function = Arc::new(user_function(&definition(format!("f{}", n)), body, timeout));
+        }
+
+        let start = Instant::now();
+        let error = function.call(&[None]).unwrap_err();
+
+        assert!(start.elapsed() < Duration::from_secs(5));
+        assert!(matches!(error.code, ErrorCode::FunctionFailure));
+        assert_eq!(
+            error.message,
+            "User defined function f40(int) ran longer than 100ms"
+        );
+    }
 }
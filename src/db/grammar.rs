@@ -0,0 +1,1373 @@
+use crate::db::ast::{
+    is_reserved, AggregateDefinition, Batch, BatchKind, ColumnDefinition, Conditions, CreateTable,
+    DescribeKind, ElementAssignment, Expr, FieldChange, Function, FunctionDefinition, Ident,
+    IndexDefinition, Insert, InsertValues, Literal, Mutation, Name, Operator, Ordering, PrimaryKey,
+    Select, Selection, Selector, Statement, TableOption, TypeAlteration, TypeDefinition,
+    UsingOption, ViewDefinition,
+};
+use crate::db::lexer::{tokenize, Located, SyntaxError, Token};
+
+type ParseResult<T> = Result<T, SyntaxError>;
+
+// Binding powers of the infix operators, from AND up to the arithmetic ones
+const AND: u8 = 1;
+const RELATION: u8 = 2;
+const ADDITIVE: u8 = 3;
+const MULTIPLICATIVE: u8 = 4;
+
+struct Parser {
+    tokens: Vec<Located>,
+    position: usize,
+    // Anonymous bind markers are numbered in the order they appear
+    markers: usize,
+}
+
+// A single statement, optionally ending with a semicolon
+pub fn parse_statement(cql: &str) -> ParseResult<Statement> {
+    let mut parser = Parser::new(cql)?;
+    let statement = parser.statement()?;
+
+    parser.consume(&Token::SemiColon);
+    parser.expect_end()?;
+
+    Ok(statement)
+}
+
+// The body of a user-defined function, a single CQL term
+pub fn parse_term(cql: &str) -> ParseResult<Expr> {
+    let mut parser = Parser::new(cql)?;
+    let expr = parser.expr()?;
+
+    parser.expect_end()?;
+
+    Ok(expr)
+}
+
+impl Parser {
+    fn new(cql: &str) -> ParseResult<Parser> {
+        Ok(Parser {
+            tokens: tokenize(cql)?,
+            position: 0,
+            markers: 0,
+        })
+    }
+
+    fn peek(&self) -> &Token {
+        self.peek_nth(0)
+    }
+
+    // The tokens end with Eof, which is returned past the end
+    fn peek_nth(&self, n: usize) -> &Token {
+        let last = self.tokens.len() - 1;
+
+        &self.tokens[(self.position + n).min(last)].token
+    }
+
+    fn next(&mut self) -> Token {
+        let token = self.peek().clone();
+
+        if self.position < self.tokens.len() - 1 {
+            self.position += 1;
+        }
+
+        token
+    }
+
+    fn consume(&mut self, token: &Token) -> bool {
+        if self.peek() == token {
+            self.next();
+            true
+        } else {
+            false
+        }
+    }
+
+    fn expect(&mut self, token: &Token) -> ParseResult<()> {
+        if self.consume(token) {
+            Ok(())
+        } else {
+            self.expected(&token.to_string())
+        }
+    }
+
+    fn expect_end(&mut self) -> ParseResult<()> {
+        if *self.peek() == Token::Eof {
+            Ok(())
+        } else {
+            self.expected("end of input")
+        }
+    }
+
+    fn expected<T>(&self, expected: &str) -> ParseResult<T> {
+        let located = &self.tokens[self.position];
+
+        Err(SyntaxError {
+            line: located.line,
+            column: located.column,
+            message: format!(
+                "mismatched input '{}' expecting {}",
+                located.token, expected
+            ),
+        })
+    }
+
+    fn is_keyword_at(&self, n: usize, keyword: &str) -> bool {
+        matches!(self.peek_nth(n), Token::Word(word) if word.eq_ignore_ascii_case(keyword))
+    }
+
+    fn is_keyword(&self, keyword: &str) -> bool {
+        self.is_keyword_at(0, keyword)
+    }
+
+    fn keyword(&mut self, keyword: &str) -> bool {
+        if self.is_keyword(keyword) {
+            self.next();
+            true
+        } else {
+            false
+        }
+    }
+
+    // All of the keywords in order, or none of them
+    fn keywords(&mut self, keywords: &[&str]) -> bool {
+        let matched = keywords
+            .iter()
+            .enumerate()
+            .all(|(n, keyword)| self.is_keyword_at(n, keyword));
+
+        if matched {
+            self.position += keywords.len();
+        }
+
+        matched
+    }
+
+    fn expect_keyword(&mut self, keyword: &str) -> ParseResult<()> {
+        if self.keyword(keyword) {
+            Ok(())
+        } else {
+            self.expected(keyword)
+        }
+    }
+
+    fn expect_keywords(&mut self, keywords: &[&str]) -> ParseResult<()> {
+        for keyword in keywords {
+            self.expect_keyword(keyword)?;
+        }
+
+        Ok(())
+    }
+
+    fn if_not_exists(&mut self) -> bool {
+        self.keywords(&["IF", "NOT", "EXISTS"])
+    }
+
+    fn if_exists(&mut self) -> bool {
+        self.keywords(&["IF", "EXISTS"])
+    }
+
+    fn comma_separated<T>(
+        &mut self,
+        mut parse: impl FnMut(&mut Parser) -> ParseResult<T>,
+    ) -> ParseResult<Vec<T>> {
+        let mut items = vec![parse(self)?];
+
+        while self.consume(&Token::Comma) {
+            items.push(parse(self)?);
+        }
+
+        Ok(items)
+    }
+
+    // `(item, ...)`, possibly empty
+    fn parenthesized<T>(
+        &mut self,
+        parse: impl FnMut(&mut Parser) -> ParseResult<T>,
+    ) -> ParseResult<Vec<T>> {
+        self.expect(&Token::LParen)?;
+
+        if self.consume(&Token::RParen) {
+            return Ok(vec![]);
+        }
+
+        let items = self.comma_separated(parse)?;
+
+        self.expect(&Token::RParen)?;
+        Ok(items)
+    }
+
+    // Unquoted identifiers are case insensitive, and can't be reserved words
+    fn ident(&mut self) -> ParseResult<Ident> {
+        match self.peek() {
+            Token::Word(word) if is_reserved(word) => self.expected("an identifier"),
+            _ => self.any_ident(),
+        }
+    }
+
+    // An identifier that may be a reserved word, as function and type names can
+    fn any_ident(&mut self) -> ParseResult<Ident> {
+        match self.peek().clone() {
+            Token::Word(value) => {
+                self.next();
+                Ok(Ident::new(value.to_lowercase()))
+            }
+            Token::QuotedName(value) => {
+                self.next();
+                Ok(Ident::new(value))
+            }
+            _ => self.expected("an identifier"),
+        }
+    }
+
+    fn name(&mut self) -> ParseResult<Name> {
+        let name = self.ident()?;
+
+        if self.consume(&Token::Period) {
+            Ok(Name {
+                keyspace: Some(name),
+                name: self.ident()?,
+            })
+        } else {
+            Ok(Name {
+                keyspace: None,
+                name,
+            })
+        }
+    }
+
+    fn string(&mut self) -> ParseResult<String> {
+        match self.next() {
+            Token::String(text) => Ok(text),
+            _ => {
+                self.position -= 1;
+                self.expected("a string")
+            }
+        }
+    }
+
+    // A type such as int, ks.address, frozen<map<text, list<int>>> or
+    // vector<float, 3>, rendered as its text
+    fn cql_type(&mut self) -> ParseResult<String> {
+        // Type names are case insensitive, and may be reserved words like set
+        let mut name = self.type_ident()?;
+
+        if self.consume(&Token::Period) {
+            name = format!("{}.{}", name, self.type_ident()?);
+        }
+
+        if !self.consume(&Token::Lt) {
+            return Ok(name);
+        }
+
+        let arguments = self.comma_separated(|parser| match parser.peek().clone() {
+            // The dimension of a vector
+            Token::Number(dimension) => {
+                parser.next();
+                Ok(dimension)
+            }
+            _ => parser.cql_type(),
+        })?;
+
+        self.expect(&Token::Gt)?;
+
+        Ok(format!("{}<{}>", name, arguments.join(", ")))
+    }
+
+    // Quoted user type names keep their quotes so they resolve case sensitively
+    fn type_ident(&mut self) -> ParseResult<String> {
+        match self.peek().clone() {
+            Token::QuotedName(value) => {
+                self.next();
+                Ok(format!("\"{}\"", value.replace('"', "\"\"")))
+            }
+            _ => self.any_ident().map(|ident| ident.value),
+        }
+    }
+
+    fn statement(&mut self) -> ParseResult<Statement> {
+        match self.peek() {
+            _ if self.is_keyword("SELECT") => self.select().map(Statement::Select),
+            _ if self.is_keyword("INSERT") => self.insert().map(Statement::Insert),
+            _ if self.is_keyword("UPDATE") => self.update().map(Statement::Update),
+            _ if self.is_keyword("DELETE") => self.delete().map(Statement::Delete),
+            _ if self.is_keyword("BEGIN") => self.batch().map(Statement::Batch),
+            _ if self.keyword("CREATE") => self.create(),
+            _ if self.keywords(&["ALTER", "TABLE"]) => self.alter_table(),
+            _ if self.keywords(&["ALTER", "TYPE"]) => self.alter_type().map(Statement::AlterType),
+            _ if self.keyword("DROP") => self.drop(),
+            _ if self.keyword("DESCRIBE") || self.keyword("DESC") => self.describe(),
+            _ => self.expected("a statement"),
+        }
+    }
+
+    fn create(&mut self) -> ParseResult<Statement> {
+        let or_replace = self.keywords(&["OR", "REPLACE"]);
+
+        if or_replace || self.is_keyword("FUNCTION") || self.is_keyword("AGGREGATE") {
+            if self.keyword("FUNCTION") {
+                return self
+                    .function_definition(or_replace)
+                    .map(Statement::CreateFunction);
+            }
+
+            self.expect_keyword("AGGREGATE")?;
+
+            return self
+                .aggregate_definition(or_replace)
+                .map(Statement::CreateAggregate);
+        }
+
+        if self.keyword("TABLE") || self.keyword("COLUMNFAMILY") {
+            self.create_table().map(Statement::CreateTable)
+        } else if self.keyword("TYPE") {
+            self.create_type().map(Statement::CreateType)
+        } else if self.is_keyword("INDEX") || self.is_keyword("CUSTOM") {
+            self.create_index().map(Statement::CreateIndex)
+        } else if self.keywords(&["MATERIALIZED", "VIEW"]) {
+            self.create_view().map(Statement::CreateView)
+        } else {
+            self.expected("TABLE, TYPE, FUNCTION, AGGREGATE, INDEX or MATERIALIZED VIEW")
+        }
+    }
+
+    fn drop(&mut self) -> ParseResult<Statement> {
+        let statement: fn(Name, bool) -> Statement = if self.keyword("TYPE") {
+            Statement::DropType
+        } else if self.keyword("INDEX") {
+            Statement::DropIndex
+        } else if self.keywords(&["MATERIALIZED", "VIEW"]) {
+            Statement::DropView
+        } else {
+            return self.expected("TYPE, INDEX or MATERIALIZED VIEW");
+        };
+        let if_exists = self.if_exists();
+
+        Ok(statement(self.name()?, if_exists))
+    }
+
+    fn describe(&mut self) -> ParseResult<Statement> {
+        let Token::Word(word) = self.peek().clone() else {
+            return self.expected("the object to describe");
+        };
+        let kind = match word.to_uppercase().as_str() {
+            "KEYSPACES" => DescribeKind::Keyspaces,
+            "KEYSPACE" => DescribeKind::Keyspace,
+            "SCHEMA" => DescribeKind::Schema,
+            "TABLES" => DescribeKind::Tables,
+            "TABLE" | "COLUMNFAMILY" => DescribeKind::Table,
+            "TYPES" => DescribeKind::Types,
+            "TYPE" => DescribeKind::Type,
+            "FUNCTIONS" => DescribeKind::Functions,
+            "FUNCTION" => DescribeKind::Function,
+            "AGGREGATES" => DescribeKind::Aggregates,
+            "AGGREGATE" => DescribeKind::Aggregate,
+            "INDEX" => DescribeKind::Index,
+            "MATERIALIZED" => {
+                self.next();
+                self.expect_keyword("VIEW")?;
+
+                return Ok(Statement::Describe(DescribeKind::View, Some(self.name()?)));
+            }
+            _ => return self.expected("the object to describe"),
+        };
+
+        self.next();
+
+        let name = match kind {
+            _ if kind.is_listing() || kind == DescribeKind::Schema => None,
+            // The current keyspace when none is named
+            DescribeKind::Keyspace if *self.peek() == Token::Eof => None,
+            DescribeKind::Keyspace if *self.peek() == Token::SemiColon => None,
+            _ => Some(self.name()?),
+        };
+
+        Ok(Statement::Describe(kind, name))
+    }
+
+    fn select(&mut self) -> ParseResult<Select> {
+        self.expect_keyword("SELECT")?;
+
+        // Unless json is the name of a selected column
+        let json = self.is_keyword("JSON")
+            && *self.peek_nth(1) != Token::Comma
+            && !self.is_keyword_at(1, "FROM")
+            && !self.is_keyword_at(1, "AS")
+            && self.keyword("JSON");
+        let distinct = self.is_keyword("DISTINCT")
+            && *self.peek_nth(1) != Token::Comma
+            && !self.is_keyword_at(1, "FROM")
+            && !self.is_keyword_at(1, "AS")
+            && self.keyword("DISTINCT");
+        let selection = if self.consume(&Token::Star) {
+            Selection::Wildcard
+        } else {
+            Selection::Selectors(self.comma_separated(|parser| {
+                let expr = parser.expr()?;
+                let alias = if parser.keyword("AS") {
+                    Some(parser.ident()?)
+                } else {
+                    None
+                };
+
+                Ok(Selector { expr, alias })
+            })?)
+        };
+
+        self.expect_keyword("FROM")?;
+
+        let table = self.name()?;
+        let restriction = if self.keyword("WHERE") {
+            Some(self.expr()?)
+        } else {
+            None
+        };
+        let group_by = if self.keywords(&["GROUP", "BY"]) {
+            self.comma_separated(Parser::expr)?
+        } else {
+            vec![]
+        };
+        let order_by = if self.keywords(&["ORDER", "BY"]) {
+            self.comma_separated(Parser::ordering)?
+        } else {
+            vec![]
+        };
+        let per_partition_limit = if self.keywords(&["PER", "PARTITION", "LIMIT"]) {
+            Some(self.term()?)
+        } else {
+            None
+        };
+        let limit = if self.keyword("LIMIT") {
+            Some(self.term()?)
+        } else {
+            None
+        };
+        let allow_filtering = self.keywords(&["ALLOW", "FILTERING"]);
+
+        Ok(Select {
+            json,
+            distinct,
+            selection,
+            table,
+            restriction,
+            group_by,
+            order_by,
+            per_partition_limit,
+            limit,
+            allow_filtering,
+        })
+    }
+
+    // c [ASC | DESC] or v ANN OF [...]
+    fn ordering(&mut self) -> ParseResult<Ordering> {
+        let column = self.ident()?;
+        let ann = if self.keywords(&["ANN", "OF"]) {
+            Some(self.term()?)
+        } else {
+            None
+        };
+        let descending = if self.keyword("DESC") {
+            true
+        } else {
+            self.keyword("ASC");
+            false
+        };
+
+        Ok(Ordering {
+            column,
+            ann,
+            descending,
+        })
+    }
+
+    fn insert(&mut self) -> ParseResult<Insert> {
+        self.expect_keywords(&["INSERT", "INTO"])?;
+
+        let table = self.name()?;
+        let values = if self.keyword("JSON") {
+            let json = self.term()?;
+            // Columns left out of the document are null unless DEFAULT UNSET
+            let default_null = if self.keyword("DEFAULT") {
+                if self.keyword("NULL") {
+                    true
+                } else {
+                    self.expect_keyword("UNSET")?;
+                    false
+                }
+            } else {
+                true
+            };
+
+            InsertValues::Json { json, default_null }
+        } else {
+            let columns = self.parenthesized(Parser::ident)?;
+
+            self.expect_keyword("VALUES")?;
+
+            InsertValues::Columns(columns, self.parenthesized(Parser::term)?)
+        };
+        let if_not_exists = self.if_not_exists();
+        let using = self.using()?;
+
+        Ok(Insert {
+            table,
+            values,
+            if_not_exists,
+            using,
+        })
+    }
+
+    // USING TTL n AND TIMESTAMP t
+    fn using(&mut self) -> ParseResult<Vec<(UsingOption, Expr)>> {
+        let mut options = vec![];
+
+        if !self.keyword("USING") {
+            return Ok(options);
+        }
+
+        loop {
+            let option = if self.keyword("TTL") {
+                UsingOption::Ttl
+            } else if self.keyword("TIMESTAMP") {
+                UsingOption::Timestamp
+            } else {
+                return self.expected("TTL or TIMESTAMP");
+            };
+
+            options.push((option, self.term()?));
+
+            if !self.keyword("AND") {
+                return Ok(options);
+            }
+        }
+    }
+
+    // IF EXISTS or IF c = v AND ...
+    fn conditions(&mut self) -> ParseResult<Option<Conditions>> {
+        if self.if_exists() {
+            Ok(Some(Conditions::Exists))
+        } else if self.keyword("IF") {
+            Ok(Some(Conditions::Columns(self.expr()?)))
+        } else {
+            Ok(None)
+        }
+    }
+
+    fn update(&mut self) -> ParseResult<Mutation> {
+        self.expect_keyword("UPDATE")?;
+
+        let table = self.name()?;
+        let using = self.using()?;
+
+        self.expect_keyword("SET")?;
+
+        let assignments = self.comma_separated(|parser| {
+            let (column, element, field) = parser.element_selector()?;
+
+            parser.expect(&Token::Eq)?;
+
+            Ok(ElementAssignment {
+                column,
+                element,
+                field,
+                value: Some(parser.expr_above(AND)?),
+            })
+        })?;
+
+        self.expect_keyword("WHERE")?;
+
+        let selection = self.expr()?;
+
+        Ok(Mutation {
+            table,
+            assignments,
+            selection,
+            using,
+            conditions: self.conditions()?,
+        })
+    }
+
+    fn delete(&mut self) -> ParseResult<Mutation> {
+        self.expect_keyword("DELETE")?;
+
+        let assignments = if self.is_keyword("FROM") {
+            vec![]
+        } else {
+            self.comma_separated(|parser| {
+                let (column, element, field) = parser.element_selector()?;
+
+                Ok(ElementAssignment {
+                    column,
+                    element,
+                    field,
+                    value: None,
+                })
+            })?
+        };
+
+        self.expect_keyword("FROM")?;
+
+        let table = self.name()?;
+        let using = self.using()?;
+
+        self.expect_keyword("WHERE")?;
+
+        let selection = self.expr()?;
+
+        Ok(Mutation {
+            table,
+            assignments,
+            selection,
+            using,
+            conditions: self.conditions()?,
+        })
+    }
+
+    // A column, optionally followed by a list index or map key in brackets or
+    // by a user type field
+    fn element_selector(&mut self) -> ParseResult<(Ident, Option<Expr>, Option<Ident>)> {
+        let column = self.ident()?;
+
+        if self.consume(&Token::LBracket) {
+            let element = self.term()?;
+
+            self.expect(&Token::RBracket)?;
+
+            Ok((column, Some(element), None))
+        } else if self.consume(&Token::Period) {
+            Ok((column, None, Some(self.ident()?)))
+        } else {
+            Ok((column, None, None))
+        }
+    }
+
+    // BEGIN [UNLOGGED | COUNTER] BATCH [USING TIMESTAMP t] <statement>; ... APPLY BATCH
+    fn batch(&mut self) -> ParseResult<Batch> {
+        self.expect_keyword("BEGIN")?;
+
+        let kind = if self.keyword("UNLOGGED") {
+            BatchKind::Unlogged
+        } else if self.keyword("COUNTER") {
+            BatchKind::Counter
+        } else {
+            BatchKind::Logged
+        };
+
+        self.expect_keyword("BATCH")?;
+
+        let using = self.using()?;
+        let mut statements = vec![];
+
+        while !self.keywords(&["APPLY", "BATCH"]) {
+            let statement = if self.is_keyword("INSERT") {
+                Statement::Insert(self.insert()?)
+            } else if self.is_keyword("UPDATE") {
+                Statement::Update(self.update()?)
+            } else if self.is_keyword("DELETE") {
+                Statement::Delete(self.delete()?)
+            } else {
+                return self.expected("INSERT, UPDATE, DELETE or APPLY BATCH");
+            };
+
+            statements.push(statement);
+            self.consume(&Token::SemiColon);
+        }
+
+        Ok(Batch {
+            kind,
+            using,
+            statements,
+        })
+    }
+
+    // IF NOT EXISTS is read but changes nothing, tables being replaced as ever
+    fn create_table(&mut self) -> ParseResult<CreateTable> {
+        self.if_not_exists();
+
+        let name = self.name()?;
+        let mut columns = vec![];
+        let mut primary_key = None;
+
+        self.expect(&Token::LParen)?;
+
+        loop {
+            if self.keywords(&["PRIMARY", "KEY"]) {
+                if primary_key.is_some() {
+                    return self.expected("a single PRIMARY KEY definition");
+                }
+
+                primary_key = Some(self.primary_key()?);
+            } else {
+                let name = self.ident()?;
+                let column_type = self.cql_type()?;
+                let is_static = self.keyword("STATIC");
+
+                columns.push(ColumnDefinition {
+                    name,
+                    column_type,
+                    is_static,
+                    primary_key: self.keywords(&["PRIMARY", "KEY"]),
+                });
+            }
+
+            // A trailing comma is allowed before the closing parenthesis
+            if !self.consume(&Token::Comma) || *self.peek() == Token::RParen {
+                break;
+            }
+        }
+
+        self.expect(&Token::RParen)?;
+
+        Ok(CreateTable {
+            name,
+            columns,
+            primary_key,
+            options: self.table_options()?,
+        })
+    }
+
+    // `(k, c1, c2)` or `((k1, k2), c1, c2)`
+    fn primary_key(&mut self) -> ParseResult<PrimaryKey> {
+        self.expect(&Token::LParen)?;
+
+        let partition_key = if *self.peek() == Token::LParen {
+            self.parenthesized(Parser::ident)?
+        } else {
+            vec![self.ident()?]
+        };
+        let mut clustering_key = vec![];
+
+        while self.consume(&Token::Comma) {
+            clustering_key.push(self.ident()?);
+        }
+
+        self.expect(&Token::RParen)?;
+
+        Ok(PrimaryKey {
+            partition_key,
+            clustering_key,
+        })
+    }
+
+    // WITH option AND option ..., each of CLUSTERING ORDER BY (c ASC, ...),
+    // COMPACT STORAGE or name = value
+    fn table_options(&mut self) -> ParseResult<Vec<TableOption>> {
+        let mut options = vec![];
+
+        if !self.keyword("WITH") {
+            return Ok(options);
+        }
+
+        loop {
+            if self.keywords(&["CLUSTERING", "ORDER", "BY"]) {
+                let order = self.parenthesized(|parser| {
+                    let column = parser.ident()?;
+                    let descending = if parser.keyword("DESC") {
+                        true
+                    } else {
+                        parser.expect_keyword("ASC")?;
+                        false
+                    };
+
+                    Ok((column, descending))
+                })?;
+
+                options.push(TableOption::ClusteringOrder(order));
+            } else if self.keywords(&["COMPACT", "STORAGE"]) {
+                options.push(TableOption::CompactStorage);
+            } else {
+                let name = self.ident()?;
+
+                self.expect(&Token::Eq)?;
+                options.push(TableOption::Property(name, self.term()?));
+            }
+
+            if !self.keyword("AND") {
+                return Ok(options);
+            }
+        }
+    }
+
+    // Only the options of a table can be altered
+    fn alter_table(&mut self) -> ParseResult<Statement> {
+        let name = self.name()?;
+
+        if !self.is_keyword("WITH") {
+            return self.expected("WITH");
+        }
+
+        Ok(Statement::AlterTable(name, self.table_options()?))
+    }
+
+    fn create_type(&mut self) -> ParseResult<TypeDefinition> {
+        let if_not_exists = self.if_not_exists();
+        let name = self.name()?;
+        let fields = self.parenthesized(|parser| Ok((parser.ident()?, parser.cql_type()?)))?;
+
+        Ok(TypeDefinition {
+            name,
+            if_not_exists,
+            fields,
+        })
+    }
+
+    // ALTER TYPE t ADD f type | ALTER TYPE t RENAME f TO g [AND h TO i ...]
+    fn alter_type(&mut self) -> ParseResult<TypeAlteration> {
+        let name = self.name()?;
+        let change = if self.keyword("ADD") {
+            FieldChange::Add(self.ident()?, self.cql_type()?)
+        } else {
+            self.expect_keyword("RENAME")?;
+
+            let mut renames = vec![];
+
+            loop {
+                let from = self.ident()?;
+
+                self.expect_keyword("TO")?;
+                renames.push((from, self.ident()?));
+
+                if !self.keyword("AND") {
+                    break;
+                }
+            }
+
+            FieldChange::Rename(renames)
+        };
+
+        Ok(TypeAlteration { name, change })
+    }
+
+    fn create_index(&mut self) -> ParseResult<IndexDefinition> {
+        let custom = self.keyword("CUSTOM");
+
+        self.expect_keyword("INDEX")?;
+
+        let if_not_exists = self.if_not_exists();
+        let name = if self.keyword("ON") {
+            None
+        } else {
+            let name = self.ident()?;
+
+            self.expect_keyword("ON")?;
+            Some(name)
+        };
+        let table = self.name()?;
+
+        self.expect(&Token::LParen)?;
+
+        let (column, target) = if *self.peek_nth(1) == Token::LParen {
+            let target = self.any_ident()?;
+
+            self.expect(&Token::LParen)?;
+
+            let column = self.ident()?;
+
+            self.expect(&Token::RParen)?;
+            (column, Some(target))
+        } else {
+            (self.ident()?, None)
+        };
+
+        self.expect(&Token::RParen)?;
+
+        let class = if custom {
+            self.expect_keyword("USING")?;
+
+            Some(self.string()?)
+        } else {
+            None
+        };
+        let options = if self.keyword("WITH") {
+            self.expect_keyword("OPTIONS")?;
+            self.expect(&Token::Eq)?;
+
+            if *self.peek() != Token::LBrace {
+                return self.expected("a map of options");
+            }
+
+            Some(self.term()?)
+        } else {
+            None
+        };
+
+        Ok(IndexDefinition {
+            name,
+            if_not_exists,
+            table,
+            column,
+            target,
+            class,
+            options,
+        })
+    }
+
+    fn create_view(&mut self) -> ParseResult<ViewDefinition> {
+        let if_not_exists = self.if_not_exists();
+        let name = self.name()?;
+
+        self.expect_keyword("AS")?;
+
+        // The query ends where the PRIMARY KEY clause starts
+        let query = self.select()?;
+
+        self.expect_keywords(&["PRIMARY", "KEY"])?;
+
+        let primary_key = self.primary_key()?;
+
+        Ok(ViewDefinition {
+            name,
+            if_not_exists,
+            query,
+            primary_key,
+            options: self.table_options()?,
+        })
+    }
+
+    // CREATE FUNCTION f (a type, ...) {CALLED | RETURNS NULL} ON NULL INPUT
+    // RETURNS type LANGUAGE l AS 'body'
+    fn function_definition(&mut self, or_replace: bool) -> ParseResult<FunctionDefinition> {
+        let if_not_exists = self.if_not_exists();
+        let name = self.name()?;
+        let arguments = self.parenthesized(|parser| Ok((parser.ident()?, parser.cql_type()?)))?;
+        let called_on_null_input = if self.keyword("CALLED") {
+            true
+        } else {
+            self.expect_keywords(&["RETURNS", "NULL"])?;
+            false
+        };
+
+        self.expect_keywords(&["ON", "NULL", "INPUT", "RETURNS"])?;
+
+        let return_type = self.cql_type()?;
+
+        self.expect_keyword("LANGUAGE")?;
+
+        let language = self.ident()?;
+
+        self.expect_keyword("AS")?;
+
+        // The body is quoted, with single quotes or $$
+        let body = self.string()?;
+
+        Ok(FunctionDefinition {
+            name,
+            or_replace,
+            if_not_exists,
+            arguments,
+            called_on_null_input,
+            return_type,
+            language,
+            body,
+        })
+    }
+
+    // CREATE AGGREGATE a (type) SFUNC f STYPE type [FINALFUNC g] [INITCOND term]
+    fn aggregate_definition(&mut self, or_replace: bool) -> ParseResult<AggregateDefinition> {
+        let if_not_exists = self.if_not_exists();
+        let name = self.name()?;
+        let argument_types = self.parenthesized(Parser::cql_type)?;
+
+        self.expect_keyword("SFUNC")?;
+
+        let state_function = self.name()?;
+
+        self.expect_keyword("STYPE")?;
+
+        let state_type = self.cql_type()?;
+        let final_function = if self.keyword("FINALFUNC") {
+            Some(self.name()?)
+        } else {
+            None
+        };
+        let initial_condition = if self.keyword("INITCOND") {
+            Some(self.term()?)
+        } else {
+            None
+        };
+
+        Ok(AggregateDefinition {
+            name,
+            or_replace,
+            if_not_exists,
+            argument_types,
+            state_function,
+            state_type,
+            final_function,
+            initial_condition,
+        })
+    }
+
+    // Relations joined by AND, or a single term
+    fn expr(&mut self) -> ParseResult<Expr> {
+        self.expr_above(0)
+    }
+
+    // A term, without relations
+    fn term(&mut self) -> ParseResult<Expr> {
+        self.expr_above(RELATION)
+    }
+
+    // The operators binding tighter than the given power, by precedence climbing
+    fn expr_above(&mut self, power: u8) -> ParseResult<Expr> {
+        let mut expr = self.unary()?;
+
+        loop {
+            let (op, op_power, length) = match self.peek() {
+                Token::Word(word) if word.eq_ignore_ascii_case("AND") => (Operator::And, AND, 1),
+                Token::Word(word) if word.eq_ignore_ascii_case("CONTAINS") => {
+                    if self.is_keyword_at(1, "KEY") {
+                        (Operator::ContainsKey, RELATION, 2)
+                    } else {
+                        (Operator::Contains, RELATION, 1)
+                    }
+                }
+                Token::Word(word) if word.eq_ignore_ascii_case("LIKE") => {
+                    (Operator::Like, RELATION, 1)
+                }
+                Token::Word(word) if word.eq_ignore_ascii_case("IN") => (Operator::In, RELATION, 1),
+                Token::Word(word) if word.eq_ignore_ascii_case("IS") && power < RELATION => {
+                    self.next();
+                    self.expect_keywords(&["NOT", "NULL"])?;
+                    expr = Expr::IsNotNull(Box::new(expr));
+                    continue;
+                }
+                Token::Eq => (Operator::Eq, RELATION, 1),
+                Token::NotEq => (Operator::NotEq, RELATION, 1),
+                Token::Lt => (Operator::Lt, RELATION, 1),
+                Token::LtEq => (Operator::LtEq, RELATION, 1),
+                Token::Gt => (Operator::Gt, RELATION, 1),
+                Token::GtEq => (Operator::GtEq, RELATION, 1),
+                Token::Plus => (Operator::Plus, ADDITIVE, 1),
+                Token::Minus => (Operator::Minus, ADDITIVE, 1),
+                Token::Star => (Operator::Multiply, MULTIPLICATIVE, 1),
+                Token::Slash => (Operator::Divide, MULTIPLICATIVE, 1),
+                Token::Percent => (Operator::Modulo, MULTIPLICATIVE, 1),
+                _ => return Ok(expr),
+            };
+
+            if op_power <= power {
+                return Ok(expr);
+            }
+
+            self.position += length;
+
+            let right = self.expr_above(op_power)?;
+
+            expr = Expr::BinaryOp {
+                left: Box::new(expr),
+                op,
+                right: Box::new(right),
+            };
+        }
+    }
+
+    fn unary(&mut self) -> ParseResult<Expr> {
+        if self.consume(&Token::Minus) {
+            return Ok(Expr::Negate(Box::new(self.unary()?)));
+        }
+
+        let mut expr = self.primary()?;
+
+        // Elements and fields, e.g. m['k'] or address.city
+        loop {
+            if self.consume(&Token::LBracket) {
+                let key = self.term()?;
+
+                self.expect(&Token::RBracket)?;
+                expr = Expr::Index(Box::new(expr), Box::new(key));
+            } else if self.consume(&Token::Period) {
+                expr = Expr::Field(Box::new(expr), self.ident()?);
+            } else {
+                return Ok(expr);
+            }
+        }
+    }
+
+    fn primary(&mut self) -> ParseResult<Expr> {
+        let literal = match self.peek().clone() {
+            Token::String(text) => Some(Literal::String(text)),
+            Token::Number(number) => Some(Literal::Number(number)),
+            Token::Hex(hex) => Some(Literal::Hex(hex)),
+            Token::Duration(duration) => Some(Literal::Duration(duration)),
+            Token::Uuid(uuid) => Some(Literal::Uuid(uuid)),
+            _ => None,
+        };
+
+        if let Some(literal) = literal {
+            self.next();
+            return Ok(Expr::Literal(literal));
+        }
+
+        match self.peek().clone() {
+            Token::Question => {
+                self.next();
+                self.markers += 1;
+
+                Ok(Expr::Marker(format!("?{}", self.markers)))
+            }
+            Token::Colon => {
+                self.next();
+
+                Ok(Expr::Marker(format!(":{}", self.ident()?.value)))
+            }
+            Token::LBracket => {
+                self.next();
+
+                let elements = if *self.peek() == Token::RBracket {
+                    vec![]
+                } else {
+                    self.comma_separated(Parser::term)?
+                };
+
+                self.expect(&Token::RBracket)?;
+                Ok(Expr::List(elements))
+            }
+            Token::LBrace => self.brace_literal(),
+            Token::LParen => {
+                self.next();
+
+                let mut elements = self.comma_separated(Parser::expr)?;
+
+                self.expect(&Token::RParen)?;
+
+                match elements.len() {
+                    1 => Ok(Expr::Nested(Box::new(elements.remove(0)))),
+                    _ => Ok(Expr::Tuple(elements)),
+                }
+            }
+            Token::Word(word) if *self.peek_nth(1) != Token::LParen && !self.qualified_call() => {
+                // Case matters to the float literals, as it does in Cassandra
+                let term = match word.to_lowercase().as_str() {
+                    "true" => Expr::Literal(Literal::Boolean(true)),
+                    "false" => Expr::Literal(Literal::Boolean(false)),
+                    "null" => Expr::Literal(Literal::Null),
+                    _ if word == "NaN" || word == "Infinity" => {
+                        Expr::Literal(Literal::Number(word))
+                    }
+                    _ => Expr::Identifier(self.ident()?),
+                };
+
+                if !matches!(term, Expr::Identifier(_)) {
+                    self.next();
+                }
+
+                Ok(term)
+            }
+            Token::Word(word) if word.eq_ignore_ascii_case("CAST") => {
+                self.next();
+                self.expect(&Token::LParen)?;
+
+                let expr = self.term()?;
+
+                self.expect_keyword("AS")?;
+
+                let data_type = self.cql_type()?;
+
+                self.expect(&Token::RParen)?;
+
+                Ok(Expr::Cast(Box::new(expr), data_type))
+            }
+            Token::Word(_) | Token::QuotedName(_) => {
+                let qualified = self.qualified_call();
+                let ident = self.any_ident()?;
+
+                // A function of a keyspace, ks.f(...)
+                if qualified {
+                    self.next();
+
+                    let name = Name {
+                        keyspace: Some(ident),
+                        name: self.any_ident()?,
+                    };
+
+                    return self.function(name);
+                }
+
+                if *self.peek() == Token::LParen {
+                    return self.function(Name {
+                        keyspace: None,
+                        name: ident,
+                    });
+                }
+
+                Ok(Expr::Identifier(ident))
+            }
+            _ => self.expected("a term"),
+        }
+    }
+
+    fn qualified_call(&self) -> bool {
+        *self.peek_nth(1) == Token::Period
+            && matches!(self.peek_nth(2), Token::Word(_) | Token::QuotedName(_))
+            && *self.peek_nth(3) == Token::LParen
+    }
+
+    // The arguments of a call, `*` standing for the whole row in count(*)
+    fn function(&mut self, name: Name) -> ParseResult<Expr> {
+        let args = self.parenthesized(|parser| {
+            if parser.consume(&Token::Star) {
+                Ok(Expr::Wildcard)
+            } else {
+                parser.term()
+            }
+        })?;
+
+        Ok(Expr::Function(Function { name, args }))
+    }
+
+    // `{}` and map literals come back as maps, as do user type literals such
+    // as {f: 1}, set literals as sets
+    fn brace_literal(&mut self) -> ParseResult<Expr> {
+        self.expect(&Token::LBrace)?;
+
+        if self.consume(&Token::RBrace) {
+            return Ok(Expr::Map(vec![]));
+        }
+
+        let first = self.term()?;
+        let literal = if self.consume(&Token::Colon) {
+            let mut entries = vec![(first, self.term()?)];
+
+            while self.consume(&Token::Comma) {
+                let key = self.term()?;
+
+                self.expect(&Token::Colon)?;
+                entries.push((key, self.term()?));
+            }
+
+            Expr::Map(entries)
+        } else {
+            let mut elements = vec![first];
+
+            while self.consume(&Token::Comma) {
+                elements.push(self.term()?);
+            }
+
+            Expr::Set(elements)
+        };
+
+        self.expect(&Token::RBrace)?;
+
+        Ok(literal)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_statements() {
+        let Ok(Statement::CreateTable(table)) = parse_statement(
+            "CREATE TABLE IF NOT EXISTS ks.events (
+                 id uuid, -- the partition
+                 at timestamp, // the clustering
+                 \"Tags\" frozen<map<text, list<int>>> STATIC,
+                 PRIMARY KEY ((id), at)
+             ) WITH CLUSTERING ORDER BY (at DESC) AND comment = 'recent first';",
+        ) else {
+            panic!("Expected CREATE TABLE");
+        };
+
+        assert_eq!(table.name.to_string(), "ks.events");
+        assert_eq!(table.columns[2].name, Ident::new("Tags"));
+        assert_eq!(table.columns[2].column_type, "frozen<map<text, list<int>>>");
+        assert!(table.columns[2].is_static);
+
+        let primary_key = table.primary_key.unwrap();
+
+        assert_eq!(primary_key.partition_key, vec![Ident::new("id")]);
+        assert_eq!(primary_key.clustering_key, vec![Ident::new("at")]);
+        assert!(matches!(
+            &table.options[..],
+            [TableOption::ClusteringOrder(order), TableOption::Property(name, _)]
+                if order == &[(Ident::new("at"), true)] && name.value == "comment"
+        ));
+
+        let Ok(Statement::AlterTable(name, options)) =
+            parse_statement("ALTER TABLE ks.events WITH gc_grace_seconds = 0")
+        else {
+            panic!("Expected ALTER TABLE");
+        };
+
+        assert_eq!(name.to_string(), "ks.events");
+        assert!(matches!(
+            &options[..],
+            [TableOption::Property(name, Expr::Literal(Literal::Number(seconds)))]
+                if name.value == "gc_grace_seconds" && seconds == "0"
+        ));
+
+        let Ok(Statement::Insert(insert)) = parse_statement(
+            "INSERT INTO t (k, s, m) VALUES (?, {1, 2}, {'a': [1]}) \
+             IF NOT EXISTS USING TTL 86400 AND TIMESTAMP 1",
+        ) else {
+            panic!("Expected INSERT");
+        };
+
+        assert!(insert.if_not_exists);
+        assert_eq!(
+            insert
+                .using
+                .iter()
+                .map(|(option, _)| *option)
+                .collect::<Vec<_>>(),
+            [UsingOption::Ttl, UsingOption::Timestamp]
+        );
+        assert!(matches!(
+            &insert.values,
+            InsertValues::Columns(_, values)
+                if values[0] == Expr::Marker("?1".to_string())
+                    && matches!(values[1], Expr::Set(_))
+                    && matches!(values[2], Expr::Map(_))
+        ));
+
+        let Ok(Statement::Select(select)) = parse_statement(
+            "SELECT token(k), ks.f(v) AS x FROM t WHERE token(k) > -10 \
+             PER PARTITION LIMIT 2 LIMIT 5 ALLOW FILTERING",
+        ) else {
+            panic!("Expected SELECT");
+        };
+
+        assert!(select.per_partition_limit.is_some());
+        assert!(select.allow_filtering);
+
+        let Selection::Selectors(selectors) = &select.selection else {
+            panic!("Expected selectors");
+        };
+
+        assert_eq!(selectors[0].expr.to_string(), "token(k)");
+        assert_eq!(selectors[1].expr.to_string(), "ks.f(v)");
+        assert_eq!(selectors[1].alias, Some(Ident::new("x")));
+        assert_eq!(
+            select
+                .restriction
+                .map(|restriction| restriction.to_string()),
+            Some("token(k) > -10".to_string())
+        );
+    }
+
+    #[test]
+    fn test_syntax_errors() {
+        let error = |cql: &str| parse_statement(cql).err().unwrap().to_string();
+
+        assert_eq!(
+            error("SELECT * FORM t"),
+            "line 1:9 mismatched input 'FORM' expecting FROM"
+        );
+        assert_eq!(
+            error("INSERT INTO t (k) VALUES (1)\nUSING TLL 1"),
+            "line 2:6 mismatched input 'TLL' expecting TTL or TIMESTAMP"
+        );
+        assert_eq!(
+            error("SELECT * FROM t WHERE k = 1 AND"),
+            "line 1:31 mismatched input '<EOF>' expecting a term"
+        );
+        assert_eq!(
+            error("ALTER TABLE t ADD v int"),
+            "line 1:14 mismatched input 'ADD' expecting WITH"
+        );
+        assert!(
+            parse_statement("CREATE TABLE t (k int, PRIMARY KEY (k), PRIMARY KEY (k))").is_err()
+        );
+    }
+}
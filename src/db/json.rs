@@ -0,0 +1,553 @@
+use crate::db::builtins::text;
+use crate::db::data::{ColumnType, Value};
+use crate::db::error::{DbError, ErrorCode};
+use crate::db::literal::{
+    format_timestamp, parse_date, parse_date_number, parse_decimal, parse_duration, parse_float,
+    parse_hex, parse_inet, parse_time, parse_time_number, parse_timestamp, parse_varint,
+};
+use std::fmt::{self, Display, Formatter};
+use std::iter::Peekable;
+use std::str::Chars;
+use uuid::Uuid;
+
+// A JSON document. Numbers keep their text so that varints and decimals
+// don't lose precision, and object members keep their order.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Json {
+    Null,
+    Boolean(bool),
+    Number(String),
+    String(String),
+    Array(Vec<Json>),
+    Object(Vec<(String, Json)>),
+}
+
+impl Json {
+    pub fn parse(text: &str) -> Result<Json, DbError> {
+        let mut chars = text.chars().peekable();
+        let json = parse_value(&mut chars);
+
+        skip_whitespace(&mut chars);
+
+        match (json, chars.next()) {
+            (Some(json), None) => Ok(json),
+            _ => Err(DbError::new(
+                ErrorCode::Invalid,
+                format!("Could not decode JSON string '{}'", text),
+            )),
+        }
+    }
+}
+
+// Separated as Cassandra does, e.g. {"id": 1, "tags": ["a", "b"]}
+impl Display for Json {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        match self {
+            Json::Null => write!(f, "null"),
+            Json::Boolean(boolean) => write!(f, "{}", boolean),
+            Json::Number(number) => write!(f, "{}", number),
+            Json::String(string) => write_string(f, string),
+            Json::Array(elements) => {
+                write!(f, "[")?;
+
+                for (i, element) in elements.iter().enumerate() {
+                    if i > 0 {
+                        write!(f, ", ")?;
+                    }
+
+                    write!(f, "{}", element)?;
+                }
+
+                write!(f, "]")
+            }
+            Json::Object(members) => {
+                write!(f, "{{")?;
+
+                for (i, (name, value)) in members.iter().enumerate() {
+                    if i > 0 {
+                        write!(f, ", ")?;
+                    }
+
+                    write_string(f, name)?;
+                    write!(f, ": {}", value)?;
+                }
+
+                write!(f, "}}")
+            }
+        }
+    }
+}
+
+fn write_string(f: &mut Formatter<'_>, string: &str) -> fmt::Result {
+    write!(f, "\"")?;
+
+    for c in string.chars() {
+        match c {
+            '"' => write!(f, "\\\"")?,
+            '\\' => write!(f, "\\\\")?,
+            '\n' => write!(f, "\\n")?,
+            '\r' => write!(f, "\\r")?,
+            '\t' => write!(f, "\\t")?,
+            c if c.is_control() => write!(f, "\\u{:04x}", c as u32)?,
+            c => write!(f, "{}", c)?,
+        }
+    }
+
+    write!(f, "\"")
+}
+
+fn skip_whitespace(chars: &mut Peekable<Chars>) {
+    while chars.next_if(|c| c.is_ascii_whitespace()).is_some() {}
+}
+
+fn parse_value(chars: &mut Peekable<Chars>) -> Option<Json> {
+    skip_whitespace(chars);
+
+    match chars.peek()? {
+        '{' => {
+            chars.next();
+
+            let mut members = vec![];
+
+            skip_whitespace(chars);
+
+            if chars.next_if_eq(&'}').is_some() {
+                return Some(Json::Object(members));
+            }
+
+            loop {
+                skip_whitespace(chars);
+
+                let Json::String(name) = parse_value(chars)? else {
+                    return None;
+                };
+
+                skip_whitespace(chars);
+                chars.next_if_eq(&':')?;
+                members.push((name, parse_value(chars)?));
+                skip_whitespace(chars);
+
+                match chars.next()? {
+                    ',' => continue,
+                    '}' => return Some(Json::Object(members)),
+                    _ => return None,
+                }
+            }
+        }
+        '[' => {
+            chars.next();
+
+            let mut elements = vec![];
+
+            skip_whitespace(chars);
+
+            if chars.next_if_eq(&']').is_some() {
+                return Some(Json::Array(elements));
+            }
+
+            loop {
+                elements.push(parse_value(chars)?);
+                skip_whitespace(chars);
+
+                match chars.next()? {
+                    ',' => continue,
+                    ']' => return Some(Json::Array(elements)),
+                    _ => return None,
+                }
+            }
+        }
+        '"' => {
+            chars.next();
+            parse_string(chars).map(Json::String)
+        }
+        c if *c == '-' || c.is_ascii_digit() => {
+            let mut number = String::new();
+
+            while let Some(c) =
+                chars.next_if(|c| c.is_ascii_digit() || matches!(c, '-' | '+' | '.' | 'e' | 'E'))
+            {
+                number.push(c);
+            }
+
+            parse_float(&number).map(|_| Json::Number(number))
+        }
+        _ => {
+            let mut word = String::new();
+
+            while let Some(c) = chars.next_if(char::is_ascii_alphabetic) {
+                word.push(c);
+            }
+
+            match word.as_str() {
+                "null" => Some(Json::Null),
+                "true" => Some(Json::Boolean(true)),
+                "false" => Some(Json::Boolean(false)),
+                _ => None,
+            }
+        }
+    }
+}
+
+fn parse_string(chars: &mut Peekable<Chars>) -> Option<String> {
+    let mut string = String::new();
+
+    loop {
+        match chars.next()? {
+            '"' => return Some(string),
+            '\\' => match chars.next()? {
+                'n' => string.push('\n'),
+                'r' => string.push('\r'),
+                't' => string.push('\t'),
+                'b' => string.push('\u{8}'),
+                'f' => string.push('\u{c}'),
+                'u' => {
+                    let high = parse_code_unit(chars)?;
+                    let code = if (0xD800..0xDC00).contains(&high) {
+                        chars.next_if_eq(&'\\')?;
+                        chars.next_if_eq(&'u')?;
+
+                        let low = parse_code_unit(chars)?;
+
+                        0x10000 + ((high - 0xD800) << 10) + (low.checked_sub(0xDC00)?)
+                    } else {
+                        high
+                    };
+
+                    string.push(char::from_u32(code)?);
+                }
+                c => string.push(c),
+            },
+            c => string.push(c),
+        }
+    }
+}
+
+fn parse_code_unit(chars: &mut Peekable<Chars>) -> Option<u32> {
+    let digits = (0..4).map(|_| chars.next()).collect::<Option<String>>()?;
+
+    u32::from_str_radix(&digits, 16).ok()
+}
+
+// Cassandra's mapping of values to JSON: numbers and booleans as themselves,
+// collections and tuples as arrays, maps and user types as objects, and
+// everything else as the string it's written as
+pub fn to_json(value: Option<&Value>, column_type: &ColumnType) -> Json {
+    let Some(value) = value else {
+        return Json::Null;
+    };
+
+    match (value, column_type) {
+        (_, ColumnType::Frozen(inner)) => to_json(Some(value), inner),
+        (Value::Boolean(boolean), _) => Json::Boolean(*boolean),
+        (Value::Float(float), _) if float.is_finite() => Json::Number(text(value)),
+        (Value::Double(double), _) if double.is_finite() => Json::Number(text(value)),
+        (
+            Value::Bigint(_)
+            | Value::Counter(_)
+            | Value::Int(_)
+            | Value::Smallint(_)
+            | Value::Tinyint(_)
+            | Value::Varint(_)
+            | Value::Decimal(_),
+            _,
+        ) => Json::Number(text(value)),
+        (Value::Timestamp(millis), _) => Json::String(format_timestamp(*millis).replace('T', " ")),
+        (Value::List(elements) | Value::Set(elements), _) => {
+            let element_type = match column_type {
+                ColumnType::List(element_type) | ColumnType::Set(element_type) => element_type,
+                _ => return Json::Null,
+            };
+
+            Json::Array(
+                elements
+                    .iter()
+                    .map(|element| to_json(Some(element), element_type))
+                    .collect(),
+            )
+        }
+        (Value::Vector(elements), ColumnType::Vector(element_type, _)) => Json::Array(
+            elements
+                .iter()
+                .map(|element| to_json(Some(element), element_type))
+                .collect(),
+        ),
+        (Value::Map(entries), ColumnType::Map(key_type, value_type)) => Json::Object(
+            entries
+                .iter()
+                .map(|(key, value)| {
+                    let key = match to_json(Some(key), key_type) {
+                        Json::String(key) => key,
+                        key => key.to_string(),
+                    };
+
+                    (key, to_json(Some(value), value_type))
+                })
+                .collect(),
+        ),
+        (Value::Tuple(fields), ColumnType::Tuple(field_types)) => Json::Array(
+            fields
+                .iter()
+                .zip(field_types)
+                .map(|(field, field_type)| to_json(field.as_ref(), field_type))
+                .collect(),
+        ),
+        (Value::Udt(fields), ColumnType::Udt(user_type)) => Json::Object(
+            fields
+                .iter()
+                .zip(&user_type.fields)
+                .map(|(field, (name, field_type))| {
+                    (name.clone(), to_json(field.as_ref(), field_type))
+                })
+                .collect(),
+        ),
+        _ => Json::String(text(value)),
+    }
+}
+
+// The inverse of to_json, though numbers, booleans and map keys of any type
+// may also be given as strings
+pub fn from_json(json: &Json, column_type: &ColumnType) -> Result<Option<Value>, DbError> {
+    let invalid = || {
+        DbError::new(
+            ErrorCode::Invalid,
+            format!("Unable to make {} from JSON value {}", column_type, json),
+        )
+    };
+    let scalar = match json {
+        Json::Null => return Ok(None),
+        Json::Number(scalar) | Json::String(scalar) => scalar.as_str(),
+        _ => "",
+    };
+    let value = match (column_type, json) {
+        (ColumnType::Frozen(inner), _) => return from_json(json, inner),
+        (ColumnType::Boolean, Json::Boolean(boolean)) => Some(Value::Boolean(*boolean)),
+        (ColumnType::Boolean, Json::String(_)) => match scalar.to_lowercase().as_str() {
+            "true" => Some(Value::Boolean(true)),
+            "false" => Some(Value::Boolean(false)),
+            _ => None,
+        },
+        (ColumnType::Ascii, Json::String(string)) if string.is_ascii() => {
+            Some(Value::Ascii(string.as_bytes().to_vec()))
+        }
+        (ColumnType::Varchar, Json::String(string)) => Some(Value::Varchar(string.clone())),
+        (_, Json::Number(_) | Json::String(_)) if column_type.is_numeric() => {
+            number(column_type, scalar)
+        }
+        (ColumnType::Timestamp, Json::Number(_)) => scalar.parse().ok().map(Value::Timestamp),
+        (ColumnType::Timestamp, Json::String(_)) => parse_timestamp(scalar).map(Value::Timestamp),
+        (ColumnType::Date, Json::Number(_)) => parse_date_number(scalar).map(Value::Date),
+        (ColumnType::Date, Json::String(_)) => parse_date(scalar).map(Value::Date),
+        (ColumnType::Time, Json::Number(_)) => parse_time_number(scalar).map(Value::Time),
+        (ColumnType::Time, Json::String(_)) => parse_time(scalar).map(Value::Time),
+        (ColumnType::Uuid, Json::String(_)) => Uuid::parse_str(scalar).ok().map(Value::Uuid),
+        (ColumnType::Timeuuid, Json::String(_)) => Uuid::parse_str(scalar)
+            .ok()
+            .filter(|uuid| uuid.get_version_num() == 1)
+            .map(Value::Timeuuid),
+        (ColumnType::Inet, Json::String(_)) => parse_inet(scalar).map(Value::Inet),
+        (ColumnType::Blob, Json::String(_)) => scalar
+            .strip_prefix("0x")
+            .and_then(parse_hex)
+            .map(Value::Blob),
+        (ColumnType::Duration, Json::String(_)) => {
+            parse_duration(scalar).map(|(months, days, nanos)| Value::Duration(months, days, nanos))
+        }
+        (ColumnType::List(element_type) | ColumnType::Set(element_type), Json::Array(elements)) => {
+            let elements = elements
+                .iter()
+                .map(|element| from_json(element, element_type)?.ok_or_else(invalid))
+                .collect::<Result<Vec<_>, DbError>>()?;
+
+            match column_type {
+                ColumnType::List(_) => Some(Value::List(elements)),
+                _ => Some(Value::set(elements)),
+            }
+        }
+        (ColumnType::Vector(element_type, dimension), Json::Array(elements))
+            if elements.len() == *dimension =>
+        {
+            let elements = elements
+                .iter()
+                .map(|element| from_json(element, element_type)?.ok_or_else(invalid))
+                .collect::<Result<Vec<_>, DbError>>()?;
+
+            Some(Value::Vector(elements))
+        }
+        (ColumnType::Map(key_type, value_type), Json::Object(members)) => {
+            let entries = members
+                .iter()
+                .map(|(key, value)| {
+                    // Keys are strings, holding the JSON of keys of other types
+                    let key = from_json(&Json::String(key.clone()), key_type)
+                        .or_else(|_| from_json(&Json::parse(key)?, key_type))?
+                        .ok_or_else(invalid)?;
+                    let value = from_json(value, value_type)?.ok_or_else(invalid)?;
+
+                    Ok((key, value))
+                })
+                .collect::<Result<Vec<_>, DbError>>()?;
+
+            Some(Value::map(entries))
+        }
+        (ColumnType::Tuple(field_types), Json::Array(fields))
+            if fields.len() == field_types.len() =>
+        {
+            let fields = fields
+                .iter()
+                .zip(field_types)
+                .map(|(field, field_type)| from_json(field, field_type))
+                .collect::<Result<Vec<_>, DbError>>()?;
+
+            Some(Value::Tuple(fields))
+        }
+        (ColumnType::Udt(user_type), Json::Object(members)) => {
+            let mut fields = vec![None; user_type.fields.len()];
+
+            for (name, value) in members {
+                let (position, field_type) = user_type.field(name).ok_or_else(|| {
+                    DbError::new(
+                        ErrorCode::Invalid,
+                        format!(
+                            "JSON object for user type {} contains unknown field {}",
+                            user_type.name, name
+                        ),
+                    )
+                })?;
+
+                fields[position] = from_json(value, field_type)?;
+            }
+
+            Some(Value::Udt(fields))
+        }
+        _ => None,
+    };
+
+    value.map(Some).ok_or_else(invalid)
+}
+
+fn number(column_type: &ColumnType, number: &str) -> Option<Value> {
+    match column_type {
+        ColumnType::Tinyint => number.parse().ok().map(Value::Tinyint),
+        ColumnType::Smallint => number.parse().ok().map(Value::Smallint),
+        ColumnType::Int => number.parse().ok().map(Value::Int),
+        ColumnType::Bigint => number.parse().ok().map(Value::Bigint),
+        ColumnType::Counter => number.parse().ok().map(Value::Counter),
+        ColumnType::Varint => parse_varint(number).map(Value::Varint),
+        ColumnType::Decimal => parse_decimal(number).map(Value::Decimal),
+        ColumnType::Float => parse_float(number).map(|float| Value::Float(float as f32)),
+        ColumnType::Double => parse_float(number).map(Value::Double),
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::db::schema::UserType;
+
+    #[test]
+    fn test_parse_and_format() {
+        let text = r#" {"a" : [1, -2.5e3, true, null], "b": "x\"\u00e9\ud83d\ude00\n", "c": {}} "#;
+        let json = Json::parse(text).unwrap();
+
+        assert_eq!(
+            json,
+            Json::Object(vec![
+                (
+                    "a".to_string(),
+                    Json::Array(vec![
+                        Json::Number("1".to_string()),
+                        Json::Number("-2.5e3".to_string()),
+                        Json::Boolean(true),
+                        Json::Null,
+                    ])
+                ),
+                ("b".to_string(), Json::String("x\"é😀\n".to_string())),
+                ("c".to_string(), Json::Object(vec![])),
+            ])
+        );
+        assert_eq!(
+            json.to_string(),
+            r#"{"a": [1, -2.5e3, true, null], "b": "x\"é😀\n", "c": {}}"#
+        );
+
+        for invalid in ["", "{", "[1,]", "{\"a\" 1}", "tru", "1 2", "\"\\ud800\""] {
+            assert!(Json::parse(invalid).is_err(), "{}", invalid);
+        }
+    }
+
+    #[test]
+    fn test_values_round_trip() {
+        let point = UserType {
+            keyspace: String::new(),
+            name: "point".to_string(),
+            fields: vec![
+                ("x".to_string(), ColumnType::Int),
+                ("y".to_string(), ColumnType::Int),
+            ],
+        };
+        let cases = [
+            (ColumnType::Int, Value::Int(-3), "-3"),
+            (ColumnType::Double, Value::Double(1.5), "1.5"),
+            (ColumnType::Double, Value::Double(f64::NAN), "\"NaN\""),
+            (ColumnType::Boolean, Value::Boolean(true), "true"),
+            (
+                ColumnType::Timestamp,
+                Value::Timestamp(1_000),
+                "\"1970-01-01 00:00:01.000Z\"",
+            ),
+            (
+                ColumnType::Blob,
+                Value::Blob(vec![0xCA, 0xFE]),
+                "\"0xcafe\"",
+            ),
+            (ColumnType::Duration, Value::Duration(0, 1, 0), "\"1d\""),
+            (
+                ColumnType::Set(Box::new(ColumnType::Varchar)),
+                Value::Set(vec![Value::Varchar("a".to_string())]),
+                "[\"a\"]",
+            ),
+            (
+                ColumnType::Map(Box::new(ColumnType::Int), Box::new(ColumnType::Boolean)),
+                Value::Map(vec![(Value::Int(1), Value::Boolean(false))]),
+                "{\"1\": false}",
+            ),
+            (
+                ColumnType::Tuple(vec![ColumnType::Int, ColumnType::Varchar]),
+                Value::Tuple(vec![Some(Value::Int(1)), None]),
+                "[1, null]",
+            ),
+            (
+                ColumnType::Udt(point),
+                Value::Udt(vec![Some(Value::Int(1)), Some(Value::Int(2))]),
+                "{\"x\": 1, \"y\": 2}",
+            ),
+            (
+                ColumnType::Vector(Box::new(ColumnType::Float), 2),
+                Value::Vector(vec![Value::Float(0.5), Value::Float(-1.0)]),
+                "[0.5, -1.0]",
+            ),
+        ];
+
+        for (column_type, value, json) in cases {
+            assert_eq!(to_json(Some(&value), &column_type).to_string(), json);
+
+            let parsed = from_json(&Json::parse(json).unwrap(), &column_type).unwrap();
+
+            match value {
+                Value::Double(double) if double.is_nan() => {
+                    assert!(matches!(parsed, Some(Value::Double(parsed)) if parsed.is_nan()))
+                }
+                value => assert_eq!(parsed, Some(value)),
+            }
+        }
+
+        // Numbers and booleans may also be strings
+        assert_eq!(
+            from_json(&Json::String("12".to_string()), &ColumnType::Bigint).unwrap(),
+            Some(Value::Bigint(12))
+        );
+        assert!(from_json(&Json::String("x".to_string()), &ColumnType::Int).is_err());
+        assert!(from_json(&Json::Number("1".to_string()), &ColumnType::Varchar).is_err());
+    }
+}
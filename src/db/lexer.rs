@@ -0,0 +1,551 @@
+use crate::db::error::{DbError, ErrorCode};
+use std::fmt::{self, Display, Formatter};
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Token {
+    // Keywords and unquoted identifiers alike, told apart by the parser
+    Word(String),
+    QuotedName(String),
+    String(String),
+    Number(String),
+    Hex(String),
+    Duration(String),
+    Uuid(String),
+    LParen,
+    RParen,
+    LBracket,
+    RBracket,
+    LBrace,
+    RBrace,
+    Comma,
+    Period,
+    SemiColon,
+    Colon,
+    Question,
+    Eq,
+    NotEq,
+    Lt,
+    LtEq,
+    Gt,
+    GtEq,
+    Plus,
+    Minus,
+    Star,
+    Slash,
+    Percent,
+    Eof,
+}
+
+// Lines count from 1 and columns from 0, as Cassandra reports them
+#[derive(Debug, Clone, PartialEq)]
+pub struct Located {
+    pub token: Token,
+    pub line: usize,
+    pub column: usize,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct SyntaxError {
+    pub line: usize,
+    pub column: usize,
+    pub message: String,
+}
+
+impl Display for SyntaxError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        write!(f, "line {}:{} {}", self.line, self.column, self.message)
+    }
+}
+
+impl From<SyntaxError> for DbError {
+    fn from(error: SyntaxError) -> DbError {
+        DbError::new(ErrorCode::SyntaxError, error.to_string())
+    }
+}
+
+impl Display for Token {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        match self {
+            Token::Word(word) => write!(f, "{}", word),
+            Token::QuotedName(name) => write!(f, "\"{}\"", name.replace('"', "\"\"")),
+            Token::String(text) => write!(f, "'{}'", text.replace('\'', "''")),
+            Token::Number(number) | Token::Duration(number) | Token::Uuid(number) => {
+                write!(f, "{}", number)
+            }
+            Token::Hex(hex) => write!(f, "0x{}", hex),
+            Token::LParen => write!(f, "("),
+            Token::RParen => write!(f, ")"),
+            Token::LBracket => write!(f, "["),
+            Token::RBracket => write!(f, "]"),
+            Token::LBrace => write!(f, "{{"),
+            Token::RBrace => write!(f, "}}"),
+            Token::Comma => write!(f, ","),
+            Token::Period => write!(f, "."),
+            Token::SemiColon => write!(f, ";"),
+            Token::Colon => write!(f, ":"),
+            Token::Question => write!(f, "?"),
+            Token::Eq => write!(f, "="),
+            Token::NotEq => write!(f, "!="),
+            Token::Lt => write!(f, "<"),
+            Token::LtEq => write!(f, "<="),
+            Token::Gt => write!(f, ">"),
+            Token::GtEq => write!(f, ">="),
+            Token::Plus => write!(f, "+"),
+            Token::Minus => write!(f, "-"),
+            Token::Star => write!(f, "*"),
+            Token::Slash => write!(f, "/"),
+            Token::Percent => write!(f, "%"),
+            Token::Eof => write!(f, "<EOF>"),
+        }
+    }
+}
+
+const DURATION_UNITS: [&str; 11] = ["y", "mo", "w", "d", "h", "m", "s", "ms", "us", "µs", "ns"];
+
+struct Lexer {
+    chars: Vec<char>,
+    position: usize,
+    line: usize,
+    column: usize,
+}
+
+// Splits a statement into tokens, skipping whitespace and `--`, `//` and
+// `/* */` comments. The tokens end with Eof.
+pub fn tokenize(cql: &str) -> Result<Vec<Located>, SyntaxError> {
+    let mut lexer = Lexer {
+        chars: cql.chars().collect(),
+        position: 0,
+        line: 1,
+        column: 0,
+    };
+    let mut tokens = vec![];
+
+    loop {
+        lexer.skip_blanks()?;
+
+        let (line, column) = (lexer.line, lexer.column);
+        let token = lexer.next_token()?;
+        let end = token == Token::Eof;
+
+        tokens.push(Located {
+            token,
+            line,
+            column,
+        });
+
+        if end {
+            return Ok(tokens);
+        }
+    }
+}
+
+impl Lexer {
+    fn peek(&self, offset: usize) -> Option<char> {
+        self.chars.get(self.position + offset).copied()
+    }
+
+    fn advance(&mut self) -> Option<char> {
+        let ch = self.peek(0)?;
+
+        self.position += 1;
+
+        if ch == '\n' {
+            self.line += 1;
+            self.column = 0;
+        } else {
+            self.column += 1;
+        }
+
+        Some(ch)
+    }
+
+    fn take(&mut self, count: usize) -> String {
+        (0..count).filter_map(|_| self.advance()).collect()
+    }
+
+    fn error(&self, line: usize, column: usize, message: String) -> SyntaxError {
+        SyntaxError {
+            line,
+            column,
+            message,
+        }
+    }
+
+    fn skip_blanks(&mut self) -> Result<(), SyntaxError> {
+        loop {
+            match (self.peek(0), self.peek(1)) {
+                (Some(ch), _) if ch.is_whitespace() => {
+                    self.advance();
+                }
+                (Some('-'), Some('-')) | (Some('/'), Some('/')) => {
+                    while self.peek(0).is_some_and(|ch| ch != '\n') {
+                        self.advance();
+                    }
+                }
+                (Some('/'), Some('*')) => {
+                    let (line, column) = (self.line, self.column);
+
+                    self.take(2);
+
+                    while !(self.peek(0) == Some('*') && self.peek(1) == Some('/')) {
+                        if self.advance().is_none() {
+                            return Err(self.error(
+                                line,
+                                column,
+                                "unterminated comment".to_string(),
+                            ));
+                        }
+                    }
+
+                    self.take(2);
+                }
+                _ => return Ok(()),
+            }
+        }
+    }
+
+    fn next_token(&mut self) -> Result<Token, SyntaxError> {
+        let (line, column) = (self.line, self.column);
+        let Some(ch) = self.peek(0) else {
+            return Ok(Token::Eof);
+        };
+
+        if let Some(length) = self.uuid_length() {
+            return Ok(Token::Uuid(self.take(length)));
+        }
+
+        let token = match ch {
+            '\'' => Token::String(self.quoted('\'', "string")?),
+            '"' => Token::QuotedName(self.quoted('"', "quoted identifier")?),
+            '$' if self.peek(1) == Some('$') => Token::String(self.dollar_quoted()?),
+            '0' if matches!(self.peek(1), Some('x' | 'X')) => {
+                self.take(2);
+
+                let digits = self.take_while(|ch| ch.is_ascii_hexdigit());
+
+                Token::Hex(digits)
+            }
+            ch if ch.is_ascii_digit() => self.number()?,
+            'P' | 'p' if self.iso_duration_length().is_some() => {
+                let length = self.iso_duration_length().unwrap_or_default();
+
+                Token::Duration(self.take(length))
+            }
+            ch if ch.is_alphabetic() => Token::Word(self.take_while(is_identifier_part)),
+            _ => {
+                let symbol = match (ch, self.peek(1)) {
+                    ('!', Some('=')) => Some((Token::NotEq, 2)),
+                    ('<', Some('=')) => Some((Token::LtEq, 2)),
+                    ('>', Some('=')) => Some((Token::GtEq, 2)),
+                    ('(', _) => Some((Token::LParen, 1)),
+                    (')', _) => Some((Token::RParen, 1)),
+                    ('[', _) => Some((Token::LBracket, 1)),
+                    (']', _) => Some((Token::RBracket, 1)),
+                    ('{', _) => Some((Token::LBrace, 1)),
+                    ('}', _) => Some((Token::RBrace, 1)),
+                    (',', _) => Some((Token::Comma, 1)),
+                    ('.', _) => Some((Token::Period, 1)),
+                    (';', _) => Some((Token::SemiColon, 1)),
+                    (':', _) => Some((Token::Colon, 1)),
+                    ('?', _) => Some((Token::Question, 1)),
+                    ('=', _) => Some((Token::Eq, 1)),
+                    ('<', _) => Some((Token::Lt, 1)),
+                    ('>', _) => Some((Token::Gt, 1)),
+                    ('+', _) => Some((Token::Plus, 1)),
+                    ('-', _) => Some((Token::Minus, 1)),
+                    ('*', _) => Some((Token::Star, 1)),
+                    ('/', _) => Some((Token::Slash, 1)),
+                    ('%', _) => Some((Token::Percent, 1)),
+                    _ => None,
+                };
+                let Some((token, length)) = symbol else {
+                    return Err(self.error(
+                        line,
+                        column,
+                        format!("token recognition error at: '{}'", ch),
+                    ));
+                };
+
+                self.take(length);
+                token
+            }
+        };
+
+        Ok(token)
+    }
+
+    fn take_while(&mut self, predicate: impl Fn(char) -> bool) -> String {
+        let mut taken = String::new();
+
+        while let Some(ch) = self.peek(0).filter(|ch| predicate(*ch)) {
+            self.advance();
+            taken.push(ch);
+        }
+
+        taken
+    }
+
+    // 'it''s' or "Quoted ""Name""", the quote doubled within
+    fn quoted(&mut self, quote: char, what: &str) -> Result<String, SyntaxError> {
+        let (line, column) = (self.line, self.column);
+        let mut text = String::new();
+
+        self.advance();
+
+        loop {
+            match self.advance() {
+                Some(ch) if ch == quote && self.peek(0) == Some(quote) => {
+                    self.advance();
+                    text.push(quote);
+                }
+                Some(ch) if ch == quote => return Ok(text),
+                Some(ch) => text.push(ch),
+                None => return Err(self.error(line, column, format!("unterminated {}", what))),
+            }
+        }
+    }
+
+    // $$text$$, for function bodies and the like
+    fn dollar_quoted(&mut self) -> Result<String, SyntaxError> {
+        let (line, column) = (self.line, self.column);
+        let mut text = String::new();
+
+        self.take(2);
+
+        while !(self.peek(0) == Some('$') && self.peek(1) == Some('$')) {
+            match self.advance() {
+                Some(ch) => text.push(ch),
+                None => return Err(self.error(line, column, "unterminated string".to_string())),
+            }
+        }
+
+        self.take(2);
+        Ok(text)
+    }
+
+    // Integers, decimals with an optional exponent, or durations such as 1h30m
+    fn number(&mut self) -> Result<Token, SyntaxError> {
+        let (line, column) = (self.line, self.column);
+        let mut number = self.take_while(|ch| ch.is_ascii_digit());
+
+        if self.peek(0) == Some('.') && self.peek(1).is_some_and(|ch| ch.is_ascii_digit()) {
+            number.push_str(&self.take(1));
+            number.push_str(&self.take_while(|ch| ch.is_ascii_digit()));
+        }
+
+        let exponent = match (self.peek(0), self.peek(1), self.peek(2)) {
+            (Some('e' | 'E'), Some(digit), _) if digit.is_ascii_digit() => Some(1),
+            (Some('e' | 'E'), Some('+' | '-'), Some(digit)) if digit.is_ascii_digit() => Some(2),
+            _ => None,
+        };
+
+        if let Some(length) = exponent {
+            number.push_str(&self.take(length));
+            number.push_str(&self.take_while(|ch| ch.is_ascii_digit()));
+        }
+
+        if !self.peek(0).is_some_and(is_identifier_part) {
+            return Ok(Token::Number(number));
+        }
+
+        // Otherwise the number is the first quantity of a duration
+        let rest = self.take_while(|ch| is_identifier_part(ch) || ch == 'µ');
+        let duration = format!("{}{}", number, rest);
+
+        if exponent.is_none() && number.chars().all(|ch| ch.is_ascii_digit()) && is_duration(&rest)
+        {
+            Ok(Token::Duration(duration))
+        } else {
+            Err(self.error(
+                line,
+                column,
+                format!("token recognition error at: '{}'", duration),
+            ))
+        }
+    }
+
+    // 8-4-4-4-12 hex digits, not followed by more of an identifier
+    fn uuid_length(&self) -> Option<usize> {
+        let groups = [8, 4, 4, 4, 12];
+        let mut offset = 0;
+
+        for (i, length) in groups.iter().enumerate() {
+            if i > 0 {
+                (self.peek(offset) == Some('-')).then_some(())?;
+                offset += 1;
+            }
+
+            for _ in 0..*length {
+                self.peek(offset).filter(char::is_ascii_hexdigit)?;
+                offset += 1;
+            }
+        }
+
+        (!self.peek(offset).is_some_and(is_identifier_part)).then_some(offset)
+    }
+
+    // P1Y2M3DT4H5M6S, P3W or P0001-02-03T04:05:06
+    fn iso_duration_length(&self) -> Option<usize> {
+        let text = self.chars[self.position..]
+            .iter()
+            .take_while(|ch| is_identifier_part(**ch) || matches!(ch, '-' | ':'))
+            .collect::<String>()
+            .to_uppercase();
+        let body = &text[1..];
+        let prefix = body.chars().take(19).collect::<Vec<_>>();
+        let alternative = prefix.len() == 19
+            && prefix.iter().enumerate().all(|(i, ch)| match i {
+                4 | 7 => *ch == '-',
+                10 => *ch == 'T',
+                13 | 16 => *ch == ':',
+                _ => ch.is_ascii_digit(),
+            });
+
+        if alternative {
+            return Some(20);
+        }
+
+        let length = 1 + body
+            .chars()
+            .take_while(|ch| ch.is_ascii_alphanumeric())
+            .count();
+        if self.peek(length).is_some_and(is_identifier_part) {
+            return None;
+        }
+
+        let designators = &text[1..length];
+        let (date, time) = match designators.split_once('T') {
+            Some((date, time)) if !time.is_empty() => (date, Some(time)),
+            Some(_) => return None,
+            None => (designators, None),
+        };
+        let valid = |part: &str, units: &[char]| {
+            let mut remaining = units.iter();
+            let mut digits = 0;
+
+            for ch in part.chars() {
+                if ch.is_ascii_digit() {
+                    digits += 1;
+                } else if digits == 0 || !remaining.any(|unit| *unit == ch) {
+                    return false;
+                } else {
+                    digits = 0;
+                }
+            }
+
+            digits == 0
+        };
+        let weeks = time.is_none() && date.ends_with('W') && valid(date, &['W']);
+
+        (!designators.is_empty()
+            && (weeks
+                || (valid(date, &['Y', 'M', 'D'])
+                    && time.is_none_or(|time| valid(time, &['H', 'M', 'S'])))))
+        .then_some(length)
+    }
+}
+
+fn is_identifier_part(ch: char) -> bool {
+    ch.is_alphanumeric() || ch == '_'
+}
+
+// Quantities each followed by a unit, after the leading number, e.g. h30m
+fn is_duration(rest: &str) -> bool {
+    let mut remaining = rest;
+
+    while !remaining.is_empty() {
+        let unit_length = remaining
+            .find(|ch: char| ch.is_ascii_digit())
+            .unwrap_or(remaining.len());
+        let (unit, next) = remaining.split_at(unit_length);
+
+        if !DURATION_UNITS.contains(&unit.to_lowercase().as_str()) {
+            return false;
+        }
+
+        let digits = next
+            .find(|ch: char| !ch.is_ascii_digit())
+            .unwrap_or(next.len());
+
+        if digits == 0 && !next.is_empty() {
+            return false;
+        }
+
+        if digits == next.len() && digits > 0 {
+            return false;
+        }
+
+        remaining = &next[digits..];
+    }
+
+    true
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn tokens(cql: &str) -> Vec<Token> {
+        tokenize(cql)
+            .unwrap()
+            .into_iter()
+            .map(|located| located.token)
+            .collect()
+    }
+
+    #[test]
+    fn test_tokenize() {
+        assert_eq!(
+            tokens("SELECT \"Name\", 'it''s' -- comment\nFROM t // comment\n/* more */ WHERE x >= -1.5e3"),
+            vec![
+                Token::Word("SELECT".to_string()),
+                Token::QuotedName("Name".to_string()),
+                Token::Comma,
+                Token::String("it's".to_string()),
+                Token::Word("FROM".to_string()),
+                Token::Word("t".to_string()),
+                Token::Word("WHERE".to_string()),
+                Token::Word("x".to_string()),
+                Token::GtEq,
+                Token::Minus,
+                Token::Number("1.5e3".to_string()),
+                Token::Eof,
+            ]
+        );
+        assert_eq!(
+            tokens("1h30m P1DT2H P2W P0001-02-03T04:05:06 0xCAFE $$a 'b'$$"),
+            vec![
+                Token::Duration("1h30m".to_string()),
+                Token::Duration("P1DT2H".to_string()),
+                Token::Duration("P2W".to_string()),
+                Token::Duration("P0001-02-03T04:05:06".to_string()),
+                Token::Hex("CAFE".to_string()),
+                Token::String("a 'b'".to_string()),
+                Token::Eof,
+            ]
+        );
+        assert_eq!(
+            tokens("123e4567-e89b-12d3-a456-426614174000 p1 Product"),
+            vec![
+                Token::Uuid("123e4567-e89b-12d3-a456-426614174000".to_string()),
+                Token::Word("p1".to_string()),
+                Token::Word("Product".to_string()),
+                Token::Eof,
+            ]
+        );
+    }
+
+    #[test]
+    fn test_tokenize_errors() {
+        let error = tokenize("SELECT *\nFROM t WHERE a = 'open").unwrap_err();
+
+        assert_eq!((error.line, error.column), (2, 17));
+        assert_eq!(error.to_string(), "line 2:17 unterminated string");
+        assert_eq!(
+            tokenize("SELECT # FROM t").unwrap_err().to_string(),
+            "line 1:7 token recognition error at: '#'"
+        );
+        assert!(tokenize("SELECT 1abc").is_err());
+        assert!(tokenize("/* open").is_err());
+    }
+}
@@ -0,0 +1,566 @@
+use bigdecimal::BigDecimal;
+use num_bigint::BigInt;
+use std::net::IpAddr;
+
+const NANOS_PER_DAY: i64 = 86_400_000_000_000;
+
+// Dates go over the wire as unsigned days with the epoch at 2^31
+const EPOCH_DAY: u32 = 1 << 31;
+
+// Raw date integers are the unsigned wire value, as in Cassandra
+pub fn parse_date_number(literal: &str) -> Option<i32> {
+    literal
+        .parse::<u32>()
+        .ok()
+        .map(|days| days.wrapping_sub(EPOCH_DAY) as i32)
+}
+
+// yyyy-mm-dd, as days since the epoch
+pub fn parse_date(literal: &str) -> Option<i32> {
+    let (negative, literal) = match literal.strip_prefix('-') {
+        Some(literal) => (true, literal),
+        None => (false, literal),
+    };
+    let mut parts = literal.splitn(3, '-');
+    let year = parts.next()?.parse::<i64>().ok()?;
+    let month = parts.next()?.parse::<u32>().ok()?;
+    let day = parts.next()?.parse::<u32>().ok()?;
+    let year = if negative { -year } else { year };
+
+    if !(1..=12).contains(&month) || day < 1 || day > days_in_month(year, month) {
+        return None;
+    }
+
+    i32::try_from(days_from_civil(year, month, day)).ok()
+}
+
+// HH:MM:SS[.fffffffff], as nanoseconds since midnight
+pub fn parse_time(literal: &str) -> Option<i64> {
+    let (time, fraction) = literal.split_once('.').unwrap_or((literal, ""));
+    let mut parts = time.splitn(3, ':');
+    let hours = parse_field(parts.next()?, 0..24)?;
+    let minutes = parse_field(parts.next()?, 0..60)?;
+    let seconds = parse_field(parts.next()?, 0..60)?;
+
+    Some(((hours * 60 + minutes) * 60 + seconds) * 1_000_000_000 + parse_fraction(fraction, 9)?)
+}
+
+pub fn parse_time_number(literal: &str) -> Option<i64> {
+    literal
+        .parse::<i64>()
+        .ok()
+        .filter(|nanos| (0..NANOS_PER_DAY).contains(nanos))
+}
+
+// yyyy-mm-dd[(T| )HH:MM[:SS[.fff]]][Z|(+|-)HH[:]MM], as milliseconds since
+// the epoch. Timestamps without a zone are taken to be UTC.
+pub fn parse_timestamp(literal: &str) -> Option<i64> {
+    let (date, time) = match literal.find(['T', ' ']) {
+        Some(index) => (&literal[..index], literal[index + 1..].trim()),
+        None => (literal, ""),
+    };
+    let days = parse_date(date)? as i64;
+
+    if time.is_empty() {
+        return Some(days * 86_400_000);
+    }
+
+    let (time, offset) = if let Some(time) = time.strip_suffix('Z') {
+        (time, 0)
+    } else {
+        match time.rfind(['+', '-']) {
+            Some(index) => (&time[..index], parse_zone_offset(&time[index..])?),
+            None => (time, 0),
+        }
+    };
+    let (time, fraction) = time.split_once('.').unwrap_or((time, ""));
+    let mut parts = time.splitn(3, ':');
+    let hours = parse_field(parts.next()?, 0..24)?;
+    let minutes = parse_field(parts.next()?, 0..60)?;
+    let seconds = parts
+        .next()
+        .map_or(Some(0), |seconds| parse_field(seconds, 0..60))?;
+    let millis = ((hours * 60 + minutes) * 60 + seconds) * 1000 + parse_fraction(fraction, 3)?;
+
+    (days * 86_400_000 + millis).checked_sub(offset * 60_000)
+}
+
+// +HHMM or +HH:MM, in minutes
+fn parse_zone_offset(zone: &str) -> Option<i64> {
+    let sign = if zone.starts_with('-') { -1 } else { 1 };
+    let digits = zone[1..].replace(':', "");
+
+    if digits.len() != 4 {
+        return None;
+    }
+
+    let hours = parse_field(&digits[..2], 0..24)?;
+    let minutes = parse_field(&digits[2..], 0..60)?;
+
+    Some(sign * (hours * 60 + minutes))
+}
+
+fn parse_field(field: &str, range: std::ops::Range<i64>) -> Option<i64> {
+    if field.is_empty() || !field.bytes().all(|b| b.is_ascii_digit()) {
+        return None;
+    }
+
+    field
+        .parse::<i64>()
+        .ok()
+        .filter(|value| range.contains(value))
+}
+
+// Fractional seconds scaled to the given number of digits, ignoring any beyond
+fn parse_fraction(fraction: &str, digits: usize) -> Option<i64> {
+    if !fraction.bytes().all(|b| b.is_ascii_digit()) || fraction.len() > 9 {
+        return None;
+    }
+
+    let scaled = format!("{:0<width$}", fraction, width = digits);
+
+    scaled[..digits].parse::<i64>().ok()
+}
+
+// Days since the epoch moved by whole months, keeping the day of the month
+// where it exists and clamping it to the month's last day where it doesn't
+pub fn add_months(days: i64, months: i64) -> Option<i64> {
+    let (year, month, day) = civil_from_days(days);
+    let month_index = (year.checked_mul(12)? + month as i64 - 1).checked_add(months)?;
+    let year = month_index.div_euclid(12);
+    let month = (month_index.rem_euclid(12) + 1) as u32;
+
+    Some(days_from_civil(
+        year,
+        month,
+        day.min(days_in_month(year, month)),
+    ))
+}
+
+fn days_in_month(year: i64, month: u32) -> u32 {
+    match month {
+        4 | 6 | 9 | 11 => 30,
+        2 if year % 4 == 0 && (year % 100 != 0 || year % 400 == 0) => 29,
+        2 => 28,
+        _ => 31,
+    }
+}
+
+// Days since 1970-01-01 in the proleptic Gregorian calendar
+fn days_from_civil(year: i64, month: u32, day: u32) -> i64 {
+    let year = if month <= 2 { year - 1 } else { year };
+    let era = year.div_euclid(400);
+    let year_of_era = year - era * 400;
+    let day_of_year = (153 * ((month as i64 + 9) % 12) + 2) / 5 + day as i64 - 1;
+    let day_of_era = year_of_era * 365 + year_of_era / 4 - year_of_era / 100 + day_of_year;
+
+    era * 146_097 + day_of_era - 719_468
+}
+
+// The inverse of days_from_civil
+fn civil_from_days(days: i64) -> (i64, u32, u32) {
+    let days = days + 719_468;
+    let era = days.div_euclid(146_097);
+    let day_of_era = days - era * 146_097;
+    let year_of_era =
+        (day_of_era - day_of_era / 1460 + day_of_era / 36_524 - day_of_era / 146_096) / 365;
+    let day_of_year = day_of_era - (365 * year_of_era + year_of_era / 4 - year_of_era / 100);
+    let month_index = (5 * day_of_year + 2) / 153;
+    let day = (day_of_year - (153 * month_index + 2) / 5 + 1) as u32;
+    let month = if month_index < 10 {
+        month_index + 3
+    } else {
+        month_index - 9
+    } as u32;
+    let year = year_of_era + era * 400 + (month <= 2) as i64;
+
+    (year, month, day)
+}
+
+// Formats the inverse of the parsers above, as Cassandra renders values as text
+pub fn format_date(days: i32) -> String {
+    let (year, month, day) = civil_from_days(days as i64);
+
+    format!("{:04}-{:02}-{:02}", year, month, day)
+}
+
+pub fn format_time(nanos: i64) -> String {
+    let seconds = nanos / 1_000_000_000;
+
+    format!(
+        "{:02}:{:02}:{:02}.{:09}",
+        seconds / 3600,
+        seconds / 60 % 60,
+        seconds % 60,
+        nanos % 1_000_000_000
+    )
+}
+
+pub fn format_timestamp(millis: i64) -> String {
+    let days = millis.div_euclid(86_400_000);
+    let millis = millis.rem_euclid(86_400_000);
+    let (year, month, day) = civil_from_days(days);
+    let seconds = millis / 1000;
+
+    format!(
+        "{:04}-{:02}-{:02}T{:02}:{:02}:{:02}.{:03}Z",
+        year,
+        month,
+        day,
+        seconds / 3600,
+        seconds / 60 % 60,
+        seconds % 60,
+        millis % 1000
+    )
+}
+
+// Largest units first, e.g. 1y2mo3d4h5m6s
+pub fn format_duration(months: i32, days: i32, nanos: i64) -> String {
+    let negative = months < 0 || days < 0 || nanos < 0;
+    let mut text = if negative { "-" } else { "" }.to_string();
+    let mut append = |quantity: u64, unit: &str| {
+        if quantity != 0 {
+            text.push_str(&format!("{}{}", quantity, unit));
+        }
+    };
+    let months = months.unsigned_abs() as u64;
+    let nanos = nanos.unsigned_abs();
+
+    append(months / 12, "y");
+    append(months % 12, "mo");
+    append(days.unsigned_abs() as u64, "d");
+    append(nanos / 3_600_000_000_000, "h");
+    append(nanos / 60_000_000_000 % 60, "m");
+    append(nanos / 1_000_000_000 % 60, "s");
+    append(nanos / 1_000_000 % 1000, "ms");
+    append(nanos / 1000 % 1000, "us");
+    append(nanos % 1000, "ns");
+
+    if text.is_empty() || text == "-" {
+        "0s".to_string()
+    } else {
+        text
+    }
+}
+
+// Either quantities with units, e.g. 1h30m or 2mo3d, or ISO 8601 as
+// P1Y2M3DT4H5M6S, P2W or P0001-02-03T04:05:06, as months, days and nanoseconds
+pub fn parse_duration(literal: &str) -> Option<(i32, i32, i64)> {
+    let (negative, literal) = match literal.strip_prefix('-') {
+        Some(literal) => (true, literal),
+        None => (false, literal),
+    };
+    let (months, days, nanos) = match literal.strip_prefix(['P', 'p']) {
+        Some(designators) => parse_iso_duration(designators)?,
+        None => parse_unit_duration(literal)?,
+    };
+    let months = i32::try_from(months).ok()?;
+    let days = i32::try_from(days).ok()?;
+
+    if negative {
+        Some((-months, -days, -nanos))
+    } else {
+        Some((months, days, nanos))
+    }
+}
+
+fn parse_unit_duration(literal: &str) -> Option<(i64, i64, i64)> {
+    const UNITS: [&str; 11] = ["y", "mo", "w", "d", "h", "m", "s", "ms", "us", "µs", "ns"];
+
+    let mut duration = (0, 0, 0);
+    let mut rest = literal;
+    let mut last_unit = None;
+
+    while !rest.is_empty() {
+        let digits = rest
+            .find(|ch: char| !ch.is_ascii_digit())
+            .unwrap_or(rest.len());
+        let units = rest[digits..]
+            .find(|ch: char| ch.is_ascii_digit())
+            .map_or(rest.len(), |index| digits + index);
+        let quantity = rest[..digits].parse::<i64>().ok()?;
+        let unit = rest[digits..units].to_lowercase();
+        let position = UNITS.iter().position(|candidate| *candidate == unit)?;
+
+        // Each unit at most once, from the largest to the smallest
+        if last_unit.is_some_and(|last| position <= last) {
+            return None;
+        }
+
+        // us and µs are the same unit
+        last_unit = Some(if unit == "us" { position + 1 } else { position });
+        add_quantity(&mut duration, quantity, &unit)?;
+        rest = &rest[units..];
+    }
+
+    last_unit.map(|_| duration)
+}
+
+fn parse_iso_duration(designators: &str) -> Option<(i64, i64, i64)> {
+    if designators.contains('-') {
+        return parse_alternative_duration(designators);
+    }
+
+    if let Some(weeks) = designators.strip_suffix(['W', 'w']) {
+        return Some((0, weeks.parse::<i64>().ok()?.checked_mul(7)?, 0));
+    }
+
+    let (date, time) = match designators.split_once(['T', 't']) {
+        Some((_, "")) => return None,
+        Some((date, time)) => (date, time),
+        None => (designators, ""),
+    };
+    let mut duration = (0, 0, 0);
+
+    if date.is_empty() && time.is_empty() {
+        return None;
+    }
+
+    for (part, units) in [(date, ["y", "mo", "d"]), (time, ["h", "m", "s"])] {
+        let mut rest = part;
+        let mut units = units.iter();
+
+        while !rest.is_empty() {
+            let end = rest.find(|ch: char| !ch.is_ascii_digit())?;
+            let designator = rest[end..end + 1].to_lowercase();
+            let quantity = rest[..end].parse::<i64>().ok()?;
+            // M is months before the T and minutes after it
+            let unit = units.find(|unit| unit.starts_with(designator.as_str()))?;
+
+            add_quantity(&mut duration, quantity, unit)?;
+            rest = &rest[end + 1..];
+        }
+    }
+
+    Some(duration)
+}
+
+// ISO 8601's alternative format, PYYYY-MM-DDThh:mm:ss
+fn parse_alternative_duration(designators: &str) -> Option<(i64, i64, i64)> {
+    let (date, time) = designators.split_once(['T', 't'])?;
+    let date = date.split('-').collect::<Vec<_>>();
+    let time = time.split(':').collect::<Vec<_>>();
+    let (&[years, months, days], &[hours, minutes, seconds]) = (&date[..], &time[..]) else {
+        return None;
+    };
+    let mut duration = (0, 0, 0);
+
+    for (digits, width, unit) in [
+        (years, 4, "y"),
+        (months, 2, "mo"),
+        (days, 2, "d"),
+        (hours, 2, "h"),
+        (minutes, 2, "m"),
+        (seconds, 2, "s"),
+    ] {
+        if digits.len() != width || !digits.bytes().all(|byte| byte.is_ascii_digit()) {
+            return None;
+        }
+
+        add_quantity(&mut duration, digits.parse().ok()?, unit)?;
+    }
+
+    Some(duration)
+}
+
+fn add_quantity(duration: &mut (i64, i64, i64), quantity: i64, unit: &str) -> Option<()> {
+    let (months, days, nanos) = duration;
+    let (total, scale) = match unit {
+        "y" => (months, 12),
+        "mo" => (months, 1),
+        "w" => (days, 7),
+        "d" => (days, 1),
+        "h" => (nanos, 3_600_000_000_000),
+        "m" => (nanos, 60_000_000_000),
+        "s" => (nanos, 1_000_000_000),
+        "ms" => (nanos, 1_000_000),
+        "us" | "µs" => (nanos, 1_000),
+        _ => (nanos, 1),
+    };
+
+    *total = total.checked_add(quantity.checked_mul(scale)?)?;
+    Some(())
+}
+
+pub fn parse_inet(literal: &str) -> Option<Vec<u8>> {
+    match literal.parse::<IpAddr>().ok()? {
+        IpAddr::V4(address) => Some(address.octets().to_vec()),
+        IpAddr::V6(address) => Some(address.octets().to_vec()),
+    }
+}
+
+// The digits following 0x
+pub fn parse_hex(literal: &str) -> Option<Vec<u8>> {
+    if !literal.len().is_multiple_of(2) {
+        return None;
+    }
+
+    (0..literal.len())
+        .step_by(2)
+        .map(|i| u8::from_str_radix(&literal[i..i + 2], 16).ok())
+        .collect()
+}
+
+pub fn parse_float(literal: &str) -> Option<f64> {
+    match literal {
+        "NaN" => Some(f64::NAN),
+        "Infinity" => Some(f64::INFINITY),
+        "-Infinity" => Some(f64::NEG_INFINITY),
+        _ => literal.parse::<f64>().ok(),
+    }
+}
+
+pub fn parse_varint(literal: &str) -> Option<BigInt> {
+    if !is_integer(literal) {
+        return None;
+    }
+
+    literal.parse().ok()
+}
+
+// Keeps the scale the literal was written with, e.g. 1.50 is 150 at scale 2
+// and 1E+3 is 1 at scale -3
+pub fn parse_decimal(literal: &str) -> Option<BigDecimal> {
+    let (mantissa, exponent) = match literal.find(['e', 'E']) {
+        Some(index) => (&literal[..index], literal[index + 1..].parse::<i32>().ok()?),
+        None => (literal, 0),
+    };
+    let (integer, fraction) = mantissa.split_once('.').unwrap_or((mantissa, ""));
+
+    if !is_integer(integer) || !fraction.bytes().all(|b| b.is_ascii_digit()) {
+        return None;
+    }
+
+    let unscaled = format!("{}{}", integer, fraction).parse::<BigInt>().ok()?;
+    let scale = i32::try_from(fraction.len()).ok()?.checked_sub(exponent)?;
+
+    Some(BigDecimal::new(unscaled, scale as i64))
+}
+
+fn is_integer(literal: &str) -> bool {
+    let digits = literal.strip_prefix('-').unwrap_or(literal);
+
+    !digits.is_empty() && digits.bytes().all(|b| b.is_ascii_digit())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_dates_and_times() {
+        assert_eq!(parse_date("1970-01-01"), Some(0));
+        assert_eq!(parse_date("2024-02-29"), Some(19782));
+        assert_eq!(parse_date("1969-12-31"), Some(-1));
+        assert_eq!(parse_date("2023-02-29"), None);
+        assert_eq!(parse_date_number("2147483648"), Some(0));
+        assert_eq!(parse_time("12:30:15.5"), Some(45_015_500_000_000));
+        assert_eq!(parse_time("24:00:00"), None);
+        assert_eq!(parse_time_number("86400000000000"), None);
+        assert_eq!(
+            add_months(parse_date("2024-01-31").unwrap() as i64, 1),
+            parse_date("2024-02-29").map(i64::from)
+        );
+        assert_eq!(
+            add_months(parse_date("2024-03-15").unwrap() as i64, -15),
+            parse_date("2022-12-15").map(i64::from)
+        );
+    }
+
+    #[test]
+    fn test_parse_duration() {
+        assert_eq!(parse_duration("1h30m"), Some((0, 0, 5_400_000_000_000)));
+        assert_eq!(parse_duration("1y2mo3w4d"), Some((14, 25, 0)));
+        assert_eq!(parse_duration("-2d10ms"), Some((0, -2, -10_000_000)));
+        assert_eq!(parse_duration("1s5us7ns"), Some((0, 0, 1_000_005_007)));
+        assert_eq!(parse_duration("P1DT2H"), Some((0, 1, 7_200_000_000_000)));
+        assert_eq!(
+            parse_duration("P1Y2M3DT4M5S"),
+            Some((14, 3, 245_000_000_000))
+        );
+        assert_eq!(parse_duration("P2W"), Some((0, 14, 0)));
+        assert_eq!(
+            parse_duration("P0001-02-03T04:05:06"),
+            Some((14, 3, 14_706_000_000_000))
+        );
+        assert_eq!(
+            parse_duration("-P0000-00-01T00:00:30"),
+            Some((0, -1, -30_000_000_000))
+        );
+        assert_eq!(parse_duration("P0001-02-03"), None);
+        assert_eq!(parse_duration("P1-02-03T04:05:06"), None);
+        assert_eq!(parse_duration("P0001-02-03T04:05"), None);
+        assert_eq!(parse_duration("30m1h"), None);
+        assert_eq!(parse_duration("1h1h"), None);
+        assert_eq!(parse_duration("1x"), None);
+        assert_eq!(parse_duration("P"), None);
+        assert_eq!(parse_duration("P1DT"), None);
+        assert_eq!(parse_duration("P1H"), None);
+    }
+
+    #[test]
+    fn test_parse_timestamp() {
+        assert_eq!(parse_timestamp("1970-01-01"), Some(0));
+        assert_eq!(
+            parse_timestamp("2024-01-01 12:00:00+0000"),
+            Some(1_704_110_400_000)
+        );
+        assert_eq!(
+            parse_timestamp("2024-01-01T13:30:00.250+01:30"),
+            Some(1_704_110_400_250)
+        );
+        assert_eq!(
+            parse_timestamp("2024-01-01 12:00Z"),
+            Some(1_704_110_400_000)
+        );
+        assert_eq!(parse_timestamp("2024-01-01 12:00:00+25"), None);
+        assert_eq!(parse_timestamp("yesterday"), None);
+    }
+
+    #[test]
+    fn test_format_dates_and_times() {
+        assert_eq!(format_date(parse_date("2024-02-29").unwrap()), "2024-02-29");
+        assert_eq!(format_time(45_015_500_000_000), "12:30:15.500000000");
+        assert_eq!(
+            format_timestamp(1_704_110_400_250),
+            "2024-01-01T12:00:00.250Z"
+        );
+        assert_eq!(format_timestamp(-1), "1969-12-31T23:59:59.999Z");
+        assert_eq!(format_duration(14, 3, 5_400_000_000_000), "1y2mo3d1h30m");
+        assert_eq!(format_duration(0, -2, -10_000_000), "-2d10ms");
+        assert_eq!(format_duration(0, 0, 0), "0s");
+    }
+
+    #[test]
+    fn test_parse_numbers() {
+        assert_eq!(
+            parse_varint("-123456789012345678901234567890123456789012"),
+            "-123456789012345678901234567890123456789012".parse().ok()
+        );
+        assert_eq!(parse_varint("1.5"), None);
+        assert_eq!(parse_varint(""), None);
+        assert_eq!(
+            parse_decimal("1.50").map(|d| d.into_bigint_and_exponent()),
+            Some((BigInt::from(150), 2))
+        );
+        assert_eq!(
+            parse_decimal("-1E+3").map(|d| d.into_bigint_and_exponent()),
+            Some((BigInt::from(-1), -3))
+        );
+        assert_eq!(
+            parse_decimal("12345678901234567890123456789.0123456789")
+                .map(|d| d.into_bigint_and_exponent()),
+            Some((
+                "123456789012345678901234567890123456789".parse().unwrap(),
+                10
+            ))
+        );
+        assert_eq!(parse_decimal("1.5.0"), None);
+        assert_eq!(parse_decimal("1e"), None);
+        assert_eq!(parse_hex("CAFE"), Some(vec![0xCA, 0xFE]));
+        assert_eq!(parse_hex("CAF"), None);
+        assert!(parse_float("NaN").unwrap().is_nan());
+        assert_eq!(parse_inet("127.0.0.1"), Some(vec![127, 0, 0, 1]));
+        assert_eq!(parse_inet("::1").map(|octets| octets.len()), Some(16));
+    }
+}
@@ -1,58 +1,287 @@
+mod aggregate;
+mod ast;
 mod builtins;
 pub mod data;
-mod dialect;
+mod describe;
 pub mod error;
 mod execution;
+mod grammar;
+mod json;
+mod lexer;
+mod literal;
+mod options;
 mod parse;
 pub mod schema;
-mod visitor;
+mod storage;
+mod system;
+mod vector;
 
 use crate::cql::request::query::Query;
-use crate::db::data::Value;
-use crate::db::dialect::CassandraDialect;
+use crate::db::data::{ColumnType, Value};
+use crate::db::describe::describe_columns;
 use crate::db::error::DbError;
-use crate::db::execution::{execute_create_table, execute_insert, execute_select};
+use crate::db::execution::{
+    execute_alter_table, execute_alter_type, execute_batch, execute_create_aggregate,
+    execute_create_function, execute_create_index, execute_create_table, execute_create_type,
+    execute_create_view, execute_delete, execute_describe, execute_drop_index, execute_drop_type,
+    execute_drop_view, execute_insert, execute_select, execute_select_page, execute_update,
+};
 use crate::db::parse::parse;
-use crate::db::parse::ParsedStatement::{Create, Insert, Select};
-use crate::db::schema::Tables;
+use crate::db::parse::ParsedStatement::{
+    AlterTable, AlterType, Batch, Create, CreateAggregate, CreateFunction, CreateIndex, CreateType,
+    CreateView, Delete, Describe, DropIndex, DropType, DropView, Insert, InsertJson, Select,
+    Update,
+};
+use crate::db::schema::{Functions, Tables, Types};
 use fjall::Keyspace as FjallKeyspace;
 use std::sync::Arc;
 use tokio::sync::RwLock;
 
-static DIALECT: CassandraDialect = CassandraDialect {};
-
 pub struct Database<'db> {
     pub name: &'db str,
     pub tables: &'db Arc<RwLock<Tables>>,
+    pub types: &'db Arc<RwLock<Types>>,
+    pub functions: &'db Arc<RwLock<Functions>>,
     pub fjall: &'db FjallKeyspace,
 }
 
 pub struct Results {
-    pub result: Box<dyn Iterator<Item = Vec<Option<Value>>>>,
+    pub metadata: Option<ResultMetadata>,
+    // Rows are read as they're iterated, so reading any of them can fail
+    pub result: Box<dyn Iterator<Item = Result<Vec<Option<Value>>, DbError>>>,
+}
+
+pub struct ResultMetadata {
+    pub table: String,
+    pub columns: Vec<(String, ColumnType)>,
+    // Set when a page of results was cut short, to be passed back for the next
+    pub paging_state: Option<Vec<u8>>,
 }
 
 impl<'db> Database<'_> {
     pub async fn query(&self, query: Query) -> Result<Results, DbError> {
-        let parsed_query = parse(query.query, self.tables).await?;
+        let query_options = query.query_options;
+        let mut parsed_query = parse(query.query, self.tables, self.types, self.functions).await?;
+
+        parsed_query.bind(query_options.values.as_ref())?;
 
         match parsed_query {
             Select(query) => {
-                let results = execute_select(&self.fjall, query)?;
+                let table = query.table.name.clone();
+                let columns = if query.json {
+                    vec![("[json]".to_string(), ColumnType::Varchar)]
+                } else {
+                    query
+                        .projection
+                        .iter()
+                        .map(|expr| expr.column_spec())
+                        .collect()
+                };
+                let (results, paging_state) = match query_options.page_size {
+                    Some(page_size) if page_size > 0 => {
+                        let (page, paging_state) = execute_select_page(
+                            self.fjall,
+                            *query,
+                            page_size as usize,
+                            query_options.paging_state.as_deref(),
+                        )?;
+
+                        (
+                            Box::new(page.into_iter().map(Ok)) as Box<dyn Iterator<Item = _>>,
+                            paging_state,
+                        )
+                    }
+                    _ => (execute_select(self.fjall, *query)?, None),
+                };
                 Ok(Results {
-                    result: Box::new(results),
+                    metadata: Some(ResultMetadata {
+                        table,
+                        columns,
+                        paging_state,
+                    }),
+                    result: results,
                 })
             }
             Create(table_metadata) => {
+                let results = execute_create_table(
+                    self.name,
+                    &table_metadata,
+                    &Arc::clone(self.tables),
+                    self.fjall,
+                )
+                .await?;
+                Ok(Results {
+                    metadata: None,
+                    result: Box::new(results.map(Ok)),
+                })
+            }
+            AlterTable { table, options } => {
                 let results =
-                    execute_create_table(&table_metadata, &Arc::clone(self.tables)).await?;
+                    execute_alter_table(self.name, &table, options, self.tables, self.fjall)
+                        .await?;
                 Ok(Results {
-                    result: Box::new(results),
+                    metadata: None,
+                    result: Box::new(results.map(Ok)),
                 })
             }
             Insert(insert) => {
                 let results = execute_insert(&self.fjall, insert)?;
                 Ok(Results {
-                    result: Box::new(results),
+                    metadata: None,
+                    result: Box::new(results.map(Ok)),
+                })
+            }
+            InsertJson(insert) => {
+                let results = execute_insert(self.fjall, insert.to_insert()?)?;
+                Ok(Results {
+                    metadata: None,
+                    result: Box::new(results.map(Ok)),
+                })
+            }
+            Update(update) => {
+                let results = execute_update(self.fjall, update)?;
+                Ok(Results {
+                    metadata: None,
+                    result: Box::new(results.map(Ok)),
+                })
+            }
+            Delete(delete) => {
+                let results = execute_delete(self.fjall, delete)?;
+                Ok(Results {
+                    metadata: None,
+                    result: Box::new(results.map(Ok)),
+                })
+            }
+            CreateType {
+                user_type,
+                if_not_exists,
+            } => {
+                let results = execute_create_type(&user_type, if_not_exists, self.types).await?;
+                Ok(Results {
+                    metadata: None,
+                    result: Box::new(results.map(Ok)),
+                })
+            }
+            AlterType(user_type) => {
+                let results = execute_alter_type(&user_type, self.types, self.tables).await?;
+                Ok(Results {
+                    metadata: None,
+                    result: Box::new(results.map(Ok)),
+                })
+            }
+            DropType { name, if_exists } => {
+                let results = execute_drop_type(&name, if_exists, self.types, self.tables).await?;
+                Ok(Results {
+                    metadata: None,
+                    result: Box::new(results.map(Ok)),
+                })
+            }
+            CreateFunction {
+                function,
+                body,
+                or_replace,
+                if_not_exists,
+            } => {
+                let results = execute_create_function(
+                    &function,
+                    body,
+                    or_replace,
+                    if_not_exists,
+                    self.functions,
+                )
+                .await?;
+                Ok(Results {
+                    metadata: None,
+                    result: Box::new(results.map(Ok)),
+                })
+            }
+            CreateAggregate {
+                aggregate,
+                or_replace,
+                if_not_exists,
+            } => {
+                let results =
+                    execute_create_aggregate(aggregate, or_replace, if_not_exists, self.functions)
+                        .await?;
+                Ok(Results {
+                    metadata: None,
+                    result: Box::new(results.map(Ok)),
+                })
+            }
+            CreateIndex {
+                table,
+                index,
+                default_name,
+                if_not_exists,
+            } => {
+                let results = execute_create_index(
+                    &table,
+                    index,
+                    default_name,
+                    if_not_exists,
+                    self.tables,
+                    self.fjall,
+                )
+                .await?;
+                Ok(Results {
+                    metadata: None,
+                    result: Box::new(results.map(Ok)),
+                })
+            }
+            DropIndex { name, if_exists } => {
+                let results = execute_drop_index(&name, if_exists, self.tables, self.fjall).await?;
+                Ok(Results {
+                    metadata: None,
+                    result: Box::new(results.map(Ok)),
+                })
+            }
+            CreateView {
+                view,
+                if_not_exists,
+            } => {
+                let results =
+                    execute_create_view(view, if_not_exists, self.tables, self.fjall).await?;
+                Ok(Results {
+                    metadata: None,
+                    result: Box::new(results.map(Ok)),
+                })
+            }
+            DropView { name, if_exists } => {
+                let results = execute_drop_view(&name, if_exists, self.tables, self.fjall).await?;
+                Ok(Results {
+                    metadata: None,
+                    result: Box::new(results.map(Ok)),
+                })
+            }
+            Describe {
+                kind,
+                keyspace,
+                name,
+            } => {
+                let results = execute_describe(
+                    self.name,
+                    kind,
+                    keyspace.as_deref(),
+                    name.as_deref(),
+                    self.tables,
+                    self.types,
+                    self.functions,
+                )
+                .await?;
+                Ok(Results {
+                    metadata: Some(ResultMetadata {
+                        table: "describe".to_string(),
+                        columns: describe_columns(kind),
+                        paging_state: None,
+                    }),
+                    result: Box::new(results.map(Ok)),
+                })
+            }
+            Batch(statements) => {
+                let results = execute_batch(self.fjall, statements)?;
+                Ok(Results {
+                    metadata: None,
+                    result: Box::new(results.map(Ok)),
                 })
             }
         }
@@ -62,19 +291,175 @@ impl<'db> Database<'_> {
 #[cfg(test)]
 mod tests {
     use super::*;
-    use crate::cql::request::query::{Consistency, QueryOptions};
+    use crate::cql::request::query::{Consistency, QueryOptions, Values};
+    use crate::db::error::ErrorCode;
+    use crate::serde::reader::Value as BoundValue;
+    use bytes::Bytes;
+    use fjall::compaction::Strategy;
     use fjall::Config;
+    use std::collections::HashMap;
+    use tempfile::TempDir;
+
+    // A database over a temporary directory, removed once the test is done
+    struct Fixture {
+        tables: Arc<RwLock<Tables>>,
+        types: Arc<RwLock<Types>>,
+        functions: Arc<RwLock<Functions>>,
+        fjall: FjallKeyspace,
+        _directory: TempDir,
+    }
+
+    impl Fixture {
+        fn new() -> Fixture {
+            let directory = tempfile::tempdir().unwrap();
+
+            Fixture {
+                tables: Arc::new(RwLock::new(Tables::new())),
+                types: Arc::new(RwLock::new(Types::new())),
+                functions: Arc::new(RwLock::new(Functions::default())),
+                fjall: FjallKeyspace::open(Config::new(directory.path())).unwrap(),
+                _directory: directory,
+            }
+        }
+
+        async fn query(&self, query: Query) -> Result<Results, DbError> {
+            Database {
+                name: "test_db",
+                tables: &self.tables,
+                types: &self.types,
+                functions: &self.functions,
+                fjall: &self.fjall,
+            }
+            .query(query)
+            .await
+        }
+
+        // The rows of a statement, failing if reading any of them does
+        async fn run(&self, statement: &str) -> Result<Vec<Vec<Option<Value>>>, DbError> {
+            self.query(query(statement)).await?.result.collect()
+        }
+
+        async fn execute(&self, statements: &[&str]) {
+            for statement in statements {
+                self.run(statement).await.unwrap();
+            }
+        }
+
+        async fn rows(&self, statement: &str) -> Vec<Vec<Option<Value>>> {
+            self.run(statement).await.unwrap()
+        }
+
+        async fn error(&self, statement: &str) -> DbError {
+            self.run(statement).await.err().unwrap()
+        }
+
+        // Indexes are built in the background from the rows already written
+        async fn build_indexes(&self, table: &str) {
+            while !self.tables.read().await[table].indexes.iter().all(|index| {
+                match index.failure() {
+                    Some(message) => panic!("Failed to build {}: {}", index.name, message),
+                    None => index.is_built(),
+                }
+            }) {
+                tokio::time::sleep(std::time::Duration::from_millis(10)).await;
+            }
+        }
+
+        // Every page of the results, each resuming where the one before ended
+        async fn pages(&self, statement: &str, page_size: i32) -> Vec<Vec<Vec<Option<Value>>>> {
+            let mut pages = vec![];
+            let mut paging_state = None;
+
+            loop {
+                let results = self
+                    .query(paged_query(statement, Some(page_size), paging_state))
+                    .await
+                    .unwrap();
+
+                paging_state = results.metadata.unwrap().paging_state;
+                pages.push(results.result.collect::<Result<_, _>>().unwrap());
+
+                if paging_state.is_none() {
+                    return pages;
+                }
+            }
+        }
+
+        // The names of the columns a statement's results have
+        async fn columns(&self, statement: &str) -> Vec<String> {
+            let results = self.query(query(statement)).await.unwrap();
+
+            results
+                .metadata
+                .unwrap()
+                .columns
+                .into_iter()
+                .map(|(name, _)| name)
+                .collect()
+        }
+
+        async fn assert_error(&self, statement: &str, code: ErrorCode, message: &str) {
+            let error = self.error(statement).await;
+
+            assert_eq!(
+                format!("{:?}", error.code),
+                format!("{:?}", code),
+                "{}",
+                statement
+            );
+            assert_eq!(error.message, message, "{}", statement);
+        }
+
+        async fn assert_invalid(&self, statements: &[&str]) {
+            for statement in statements {
+                assert!(self.run(statement).await.is_err(), "{}", statement);
+            }
+        }
+    }
+
+    fn query(query: &str) -> Query {
+        Query {
+            query: query.to_string(),
+            query_options: QueryOptions {
+                consistency: Consistency::One,
+                values: None,
+                skip_metadata: false,
+                page_size: None,
+                paging_state: None,
+                timestamp: None,
+            },
+        }
+    }
+
+    fn bound_query(statement: &str, values: Option<Values>) -> Query {
+        let mut query = query(statement);
+
+        query.query_options.values = values;
+        query
+    }
+
+    fn bound(bytes: &[u8]) -> BoundValue {
+        BoundValue::Set {
+            bytes: Bytes::copy_from_slice(bytes),
+        }
+    }
+
+    fn paged_query(
+        statement: &str,
+        page_size: Option<i32>,
+        paging_state: Option<Vec<u8>>,
+    ) -> Query {
+        let mut query = query(statement);
+
+        query.query_options.page_size = page_size;
+        query.query_options.paging_state = paging_state.map(Bytes::from);
+        query
+    }
 
     #[tokio::test]
     async fn query_create_insert_select() {
         // Step 1: Set up the database
-        let tables = Arc::new(RwLock::new(Tables::new()));
-        let fjall = FjallKeyspace::open(Config::new("/tmp/test_db")).unwrap();
-        let database = Database {
-            name: "test_db",
-            tables: &tables,
-            fjall: &fjall,
-        };
+        let database = Fixture::new();
 
         // Step 2: Create a table
         let create_table_query = "
@@ -159,13 +544,13 @@ mod tests {
         let mut result_iter = select_result.unwrap().result;
 
         // Step 5: Verify the data
-        let row1 = result_iter.next().unwrap();
+        let row1 = result_iter.next().unwrap().unwrap();
         assert_eq!(row1[0], Some(Value::Int(1)));
         assert_eq!(row1[1], Some(Value::Varchar("Alice".to_string())));
         assert_eq!(row1[2], Some(Value::Int(30)));
         assert_eq!(row1[3], Some(Value::Boolean(true)));
 
-        let row2 = result_iter.next().unwrap();
+        let row2 = result_iter.next().unwrap().unwrap();
         assert_eq!(row2[0], Some(Value::Int(2)));
         assert_eq!(row2[1], Some(Value::Varchar("Bob".to_string())));
         assert_eq!(row2[2], None); // Null value
@@ -173,87 +558,3165 @@ mod tests {
     }
 
     #[tokio::test]
-    async fn query_single_record() {
-        // Step 1: Set up the database
-        let tables = Arc::new(RwLock::new(Tables::new()));
-        let fjall = FjallKeyspace::open(Config::new("/tmp/test_db_single")).unwrap();
-        let database = Database {
-            name: "test_db",
-            tables: &tables,
-            fjall: &fjall,
-        };
+    async fn query_collections() {
+        let database = Fixture::new();
 
-        // Step 2: Create a table
-        let create_table_query = "
-            CREATE TABLE users (
-                id INT PRIMARY KEY,
-                name VARCHAR,
-                age INT,
-                is_active BOOLEAN
+        database
+            .execute(&[
+                "CREATE TABLE users (id INT PRIMARY KEY, emails set<text>, \
+                 phones map<text, text>, logins list<int>)",
+                "INSERT INTO users (id, emails, phones, logins) \
+                 VALUES (1, {'b@x.com', 'a@x.com'}, {'home': '555'}, [3, 1])",
+            ])
+            .await;
+
+        let results = database
+            .query(query(
+                "SELECT emails, phones, logins FROM users WHERE id = 1",
+            ))
+            .await
+            .unwrap();
+        let metadata = results.metadata.unwrap();
+
+        assert_eq!(
+            metadata.columns[1],
+            (
+                "phones".to_string(),
+                ColumnType::Map(Box::new(ColumnType::Varchar), Box::new(ColumnType::Varchar))
             )
-        ";
-        let create_result = database
-            .query(Query {
-                query: create_table_query.to_string(),
-                query_options: QueryOptions {
-                    consistency: Consistency::One,
-                    values: None,
-                    skip_metadata: false,
-                    page_size: None,
-                    paging_state: None,
-                    timestamp: None,
-                },
-            })
+        );
+
+        let row = results
+            .result
+            .collect::<Result<Vec<_>, _>>()
+            .unwrap()
+            .pop()
+            .unwrap();
+
+        assert_eq!(
+            row[0],
+            Some(Value::Set(vec![
+                Value::Varchar("a@x.com".to_string()),
+                Value::Varchar("b@x.com".to_string())
+            ]))
+        );
+        assert_eq!(
+            row[1],
+            Some(Value::Map(vec![(
+                Value::Varchar("home".to_string()),
+                Value::Varchar("555".to_string())
+            )]))
+        );
+        assert_eq!(
+            row[2],
+            Some(Value::List(vec![Value::Int(3), Value::Int(1)]))
+        );
+    }
+
+    #[tokio::test]
+    async fn query_collection_operations() {
+        let database = Fixture::new();
+
+        database
+            .execute(&[
+                "CREATE TABLE users (id INT PRIMARY KEY, emails set<text>, \
+                 phones map<text, text>, logins list<int>)",
+                "INSERT INTO users (id, emails, phones, logins) \
+                 VALUES (1, {'a@x.com', 'b@x.com'}, {'home': '555'}, [2, 3])",
+                "UPDATE users SET logins = logins + [4, 5], logins = [1] + logins WHERE id = 1",
+                "UPDATE users SET logins[1] = 20 WHERE id = 1",
+                "DELETE logins[2] FROM users WHERE id = 1",
+                "UPDATE users SET logins = logins - [5] WHERE id = 1",
+                "UPDATE users SET emails = emails - {'a@x.com'}, emails = emails + {'c@x.com'} \
+                 WHERE id = 1",
+                "UPDATE users SET phones['work'] = '556', phones = phones + {'cell': '557'} \
+                 WHERE id = 1",
+                "DELETE phones['home'] FROM users WHERE id = 1",
+            ])
             .await;
-        assert!(create_result.is_ok());
 
-        // Step 3: Insert data
-        let insert_query = "
-            INSERT INTO users (id, name, age, is_active)
-            VALUES (1, 'Alice', 30, true)
-        ";
-        let insert_result = database
-            .query(Query {
-                query: insert_query.to_string(),
-                query_options: QueryOptions {
-                    consistency: Consistency::One,
-                    values: None,
-                    skip_metadata: false,
-                    page_size: None,
-                    paging_state: None,
-                    timestamp: None,
-                },
-            })
+        assert_eq!(
+            database
+                .rows("SELECT emails, phones, logins FROM users WHERE id = 1")
+                .await,
+            vec![vec![
+                Some(Value::Set(vec![
+                    Value::Varchar("b@x.com".to_string()),
+                    Value::Varchar("c@x.com".to_string())
+                ])),
+                Some(Value::Map(vec![
+                    (
+                        Value::Varchar("cell".to_string()),
+                        Value::Varchar("557".to_string())
+                    ),
+                    (
+                        Value::Varchar("work".to_string()),
+                        Value::Varchar("556".to_string())
+                    )
+                ])),
+                Some(Value::List(vec![
+                    Value::Int(1),
+                    Value::Int(20),
+                    Value::Int(4)
+                ]))
+            ]]
+        );
+        assert!(database
+            .query(query("UPDATE users SET logins[7] = 1 WHERE id = 1"))
+            .await
+            .is_err());
+
+        database.execute(&["DELETE FROM users WHERE id = 1"]).await;
+
+        assert!(database
+            .rows("SELECT emails FROM users WHERE id = 1")
+            .await
+            .is_empty());
+    }
+
+    #[tokio::test]
+    async fn query_user_types() {
+        let database = Fixture::new();
+
+        database
+            .execute(&[
+                "CREATE TYPE ks.address (street text, zip int)",
+                "CREATE TABLE users (id INT PRIMARY KEY, home address, work frozen<ks.address>)",
+                "INSERT INTO users (id, home, work) \
+                 VALUES (1, {street: 'Main St', zip: 1}, {zip: 2})",
+                "UPDATE users SET home.zip = 10 WHERE id = 1",
+                "ALTER TYPE address ADD city text",
+                "ALTER TYPE address RENAME street TO line",
+                "UPDATE users SET home.city = 'Springfield' WHERE id = 1",
+            ])
             .await;
-        assert!(insert_result.is_ok());
 
-        // Step 4: Query a single record
-        let select_query = "SELECT id, name, age, is_active FROM users WHERE id = 1";
-        let select_result = database
-            .query(Query {
-                query: select_query.to_string(),
-                query_options: QueryOptions {
-                    consistency: Consistency::One,
-                    values: None,
-                    skip_metadata: false,
-                    page_size: None,
-                    paging_state: None,
-                    timestamp: None,
-                },
-            })
+        let results = database
+            .query(query("SELECT home.zip, home, work FROM users WHERE id = 1"))
+            .await
+            .unwrap();
+        let metadata = results.metadata.unwrap();
+
+        assert_eq!(
+            metadata.columns[0],
+            ("home.zip".to_string(), ColumnType::Int)
+        );
+
+        let user_type = metadata.columns[1].1.user_type().unwrap();
+
+        assert_eq!(user_type.keyspace, "ks");
+        assert_eq!(
+            user_type.fields,
+            vec![
+                ("line".to_string(), ColumnType::Varchar),
+                ("zip".to_string(), ColumnType::Int),
+                ("city".to_string(), ColumnType::Varchar)
+            ]
+        );
+        assert_eq!(
+            results.result.collect::<Result<Vec<_>, _>>().unwrap(),
+            vec![vec![
+                Some(Value::Int(10)),
+                Some(Value::Udt(vec![
+                    Some(Value::Varchar("Main St".to_string())),
+                    Some(Value::Int(10)),
+                    Some(Value::Varchar("Springfield".to_string()))
+                ])),
+                Some(Value::Udt(vec![None, Some(Value::Int(2))]))
+            ]]
+        );
+
+        // Frozen values are only replaced whole, and types in use stay
+        database
+            .error("UPDATE users SET work.zip = 3 WHERE id = 1")
             .await;
-        assert!(select_result.is_ok());
+        database.error("DROP TYPE address").await;
+    }
 
-        let mut result_iter = select_result.unwrap().result;
+    #[tokio::test]
+    async fn query_tuples_and_frozen_keys() {
+        let database = Fixture::new();
 
-        // Step 5: Verify the data
-        let row = result_iter.next().unwrap();
-        assert_eq!(row[0], Some(Value::Int(1)));
-        assert_eq!(row[1], Some(Value::Varchar("Alice".to_string())));
-        assert_eq!(row[2], Some(Value::Int(30)));
-        assert_eq!(row[3], Some(Value::Boolean(true)));
+        database
+            .execute(&[
+                "CREATE TABLE points (id frozen<list<int>> PRIMARY KEY, point tuple<int, text>)",
+                "INSERT INTO points (id, point) VALUES ([1, 2], (3, 'a'))",
+                "INSERT INTO points (id, point) VALUES ([1], (4, null))",
+            ])
+            .await;
 
-        // Ensure no additional rows are returned
-        assert!(result_iter.next().is_none());
+        let results = database
+            .query(query("SELECT point FROM points WHERE id = [1, 2]"))
+            .await
+            .unwrap();
+
+        assert_eq!(
+            results.metadata.unwrap().columns[0],
+            (
+                "point".to_string(),
+                ColumnType::Tuple(vec![ColumnType::Int, ColumnType::Varchar])
+            )
+        );
+        assert_eq!(
+            results.result.collect::<Result<Vec<_>, _>>().unwrap(),
+            vec![vec![Some(Value::Tuple(vec![
+                Some(Value::Int(3)),
+                Some(Value::Varchar("a".to_string()))
+            ]))]]
+        );
+
+        database
+            .error("CREATE TABLE bad (id list<int> PRIMARY KEY, v int)")
+            .await;
+    }
+
+    #[tokio::test]
+    async fn query_counters() {
+        let database = Fixture::new();
+        let statements = [
+            "CREATE TABLE views (page text PRIMARY KEY, hits counter, visits counter)",
+            "CREATE TABLE pages (page text PRIMARY KEY, title text)",
+            "UPDATE views SET hits = hits + 3 WHERE page = 'home'",
+            "UPDATE views SET hits = hits - 1, visits = 1 + visits WHERE page = 'home'",
+            "BEGIN COUNTER BATCH \
+             UPDATE views SET hits = hits + 10 WHERE page = 'home'; \
+             UPDATE views SET visits = visits + 1 WHERE page = 'about'; \
+             APPLY BATCH",
+            "BEGIN BATCH \
+             INSERT INTO pages (page, title) VALUES ('home', 'Home'); \
+             UPDATE pages SET title = 'About' WHERE page = 'about' \
+             APPLY BATCH",
+        ];
+
+        database.execute(&statements).await;
+
+        let results = database
+            .query(query("SELECT page, hits, visits FROM views"))
+            .await
+            .unwrap();
+        let mut rows = results.result.collect::<Result<Vec<_>, _>>().unwrap();
+
+        rows.sort_by(|a, b| a[0].partial_cmp(&b[0]).unwrap());
+
+        assert_eq!(
+            rows,
+            vec![
+                vec![
+                    Some(Value::Varchar("about".to_string())),
+                    None,
+                    Some(Value::Counter(1))
+                ],
+                vec![
+                    Some(Value::Varchar("home".to_string())),
+                    Some(Value::Counter(12)),
+                    Some(Value::Counter(1))
+                ],
+            ]
+        );
+        assert_eq!(database.rows("SELECT title FROM pages").await.len(), 2);
+
+        let invalid = [
+            "CREATE TABLE mixed (id int PRIMARY KEY, hits counter, name text)",
+            "CREATE TABLE keyed (id counter PRIMARY KEY, hits counter)",
+            "INSERT INTO views (page, hits) VALUES ('home', 1)",
+            "UPDATE views SET hits = 5 WHERE page = 'home'",
+            "BEGIN COUNTER BATCH \
+             UPDATE pages SET title = 'Home' WHERE page = 'home'; \
+             APPLY BATCH",
+            "BEGIN BATCH UPDATE views SET hits = hits + 1 WHERE page = 'home'; APPLY BATCH",
+        ];
+
+        database.assert_invalid(&invalid).await;
+    }
+
+    #[tokio::test]
+    async fn query_counter_deltas() {
+        let database = Fixture::new();
+
+        database
+            .execute(&["CREATE TABLE views (page text PRIMARY KEY, hits counter)"])
+            .await;
+
+        for _ in 0..10 {
+            database
+                .execute(&["UPDATE views SET hits = hits + 1 WHERE page = 'home'"])
+                .await;
+        }
+
+        database
+            .execute(&["BEGIN COUNTER BATCH \
+                 UPDATE views SET hits = hits + 2 WHERE page = 'home'; \
+                 UPDATE views SET hits = hits - 1 WHERE page = 'home'; \
+                 APPLY BATCH"])
+            .await;
+
+        // Earlier deltas are folded into the total rather than piling up
+        let partition = database
+            .fjall
+            .open_partition("views", Default::default())
+            .unwrap();
+
+        assert_eq!(partition.len().unwrap(), 3);
+        assert_eq!(
+            database.rows("SELECT hits FROM views").await,
+            vec![vec![Some(Value::Counter(11))]]
+        );
+    }
+
+    #[test]
+    fn query_concurrent_counters() {
+        let database = Fixture::new();
+        let runtime = || {
+            tokio::runtime::Builder::new_current_thread()
+                .enable_all()
+                .build()
+                .unwrap()
+        };
+
+        runtime().block_on(
+            database.execute(&["CREATE TABLE views (page text PRIMARY KEY, hits counter)"]),
+        );
+
+        std::thread::scope(|scope| {
+            for _ in 0..8 {
+                scope.spawn(|| {
+                    runtime().block_on(async {
+                        for _ in 0..25 {
+                            database
+                                .execute(&["UPDATE views SET hits = hits + 1 WHERE page = 'home'"])
+                                .await;
+                        }
+                    })
+                });
+            }
+        });
+
+        assert_eq!(
+            runtime().block_on(database.rows("SELECT hits FROM views")),
+            vec![vec![Some(Value::Counter(200))]]
+        );
+    }
+
+    #[tokio::test]
+    async fn query_big_numbers() {
+        let database = Fixture::new();
+        let statements = [
+            "CREATE TABLE accounts (id varint PRIMARY KEY, balance decimal)",
+            "INSERT INTO accounts (id, balance) \
+             VALUES (123456789012345678901234567890, 98765432109876543210.0123456789)",
+            "INSERT INTO accounts (id, balance) VALUES (-1, -0.50)",
+            "INSERT INTO accounts (id, balance) VALUES (2, 1.00)",
+        ];
+
+        database.execute(&statements).await;
+
+        let rows = database
+            .rows("SELECT balance FROM accounts WHERE id = 123456789012345678901234567890")
+            .await;
+
+        assert_eq!(
+            rows,
+            vec![vec![Some(Value::Decimal(
+                "98765432109876543210.0123456789".parse().unwrap()
+            ))]]
+        );
+
+        // Decimals compare by value, whatever their scale
+        let rows = database
+            .rows("SELECT id FROM accounts WHERE balance = 1 ALLOW FILTERING")
+            .await;
+
+        assert_eq!(rows, vec![vec![Some(Value::Varint(2.into()))]]);
+    }
+
+    #[tokio::test]
+    async fn query_durations() {
+        let database = Fixture::new();
+        let statements = [
+            "CREATE TABLE events (id int PRIMARY KEY, at timestamp, day date, length duration)",
+            "INSERT INTO events (id, at, day, length) \
+             VALUES (1, '2024-01-01 12:00:00', '2024-01-31', 1h30m)",
+            "INSERT INTO events (id, at, day, length) \
+             VALUES (2, '2024-01-01 09:00:00' + 1h, '2024-01-31' + 1mo, P1DT2H)",
+            "UPDATE events SET length = -10ms WHERE id = 3",
+            "UPDATE events SET length = P0001-02-03T04:05:06 WHERE id = 4",
+        ];
+
+        database.execute(&statements).await;
+
+        assert_eq!(
+            database
+                .rows("SELECT length, day FROM events WHERE id = 2")
+                .await,
+            vec![vec![
+                Some(Value::Duration(0, 1, 7_200_000_000_000)),
+                Some(Value::Date(19_782)),
+            ]]
+        );
+        assert_eq!(
+            database
+                .rows(
+                    "SELECT id FROM events \
+                 WHERE at >= '2024-01-01 12:00:00' - 2h AND at < '2024-01-01 12:00:00' \
+                 ALLOW FILTERING"
+                )
+                .await,
+            vec![vec![Some(Value::Int(2))]]
+        );
+
+        assert_eq!(
+            database
+                .rows("SELECT id FROM events WHERE length = 1h30m ALLOW FILTERING")
+                .await,
+            vec![vec![Some(Value::Int(1))]]
+        );
+        assert_eq!(
+            database
+                .rows("SELECT length FROM events WHERE id = 3")
+                .await,
+            vec![vec![Some(Value::Duration(0, 0, -10_000_000))]]
+        );
+        assert_eq!(
+            database
+                .rows("SELECT length FROM events WHERE id = 4")
+                .await,
+            vec![vec![Some(Value::Duration(14, 3, 14_706_000_000_000))]]
+        );
+
+        let invalid = [
+            "CREATE TABLE keyed (length duration PRIMARY KEY)",
+            "CREATE TABLE sets (id int PRIMARY KEY, lengths set<duration>)",
+            "CREATE TABLE maps (id int PRIMARY KEY, lengths map<frozen<list<duration>>, int>)",
+            "SELECT id FROM events WHERE length > 1h",
+            "INSERT INTO events (id, day) VALUES (4, '2024-01-01' + 1h)",
+            "INSERT INTO events (id, length) VALUES (4, 1h1h)",
+        ];
+
+        database.assert_invalid(&invalid).await;
+    }
+
+    #[tokio::test]
+    async fn query_aggregates() {
+        let database = Fixture::new();
+        let statements = [
+            "CREATE TABLE sales (id int PRIMARY KEY, region text, units int, amount decimal)",
+            "INSERT INTO sales (id, region, units, amount) VALUES (1, 'east', 3, 10.50)",
+            "INSERT INTO sales (id, region, units, amount) VALUES (2, 'west', 4, 0.25)",
+            "INSERT INTO sales (id, region, amount) VALUES (3, 'east', 2.00)",
+        ];
+
+        database.execute(&statements).await;
+
+        let results = database
+            .query(query(
+                "SELECT COUNT(*), count(units), min(units), max(region), sum(amount), \
+                 avg(units) AS mean FROM sales",
+            ))
+            .await
+            .unwrap();
+
+        assert_eq!(
+            results.metadata.unwrap().columns,
+            vec![
+                ("count".to_string(), ColumnType::Bigint),
+                ("system.count(units)".to_string(), ColumnType::Bigint),
+                ("system.min(units)".to_string(), ColumnType::Int),
+                ("system.max(region)".to_string(), ColumnType::Varchar),
+                ("system.sum(amount)".to_string(), ColumnType::Decimal),
+                ("mean".to_string(), ColumnType::Int),
+            ]
+        );
+        assert_eq!(
+            results.result.collect::<Result<Vec<_>, _>>().unwrap(),
+            vec![vec![
+                Some(Value::Bigint(3)),
+                Some(Value::Bigint(2)),
+                Some(Value::Int(3)),
+                Some(Value::Varchar("west".to_string())),
+                Some(Value::Decimal("12.75".parse().unwrap())),
+                Some(Value::Int(3)),
+            ]]
+        );
+
+        // Aggregates over no rows still return one
+        let rows = database
+            .rows("SELECT count(*), max(units), sum(units) FROM sales WHERE region = 'north' ALLOW FILTERING")
+            .await;
+
+        assert_eq!(
+            rows,
+            vec![vec![Some(Value::Bigint(0)), None, Some(Value::Int(0))]]
+        );
+
+        let invalid = [
+            "SELECT sum(region) FROM sales",
+            "SELECT count(*, units) FROM sales",
+            "SELECT median(units) FROM sales",
+            "SELECT max(sum(units)) FROM sales",
+        ];
+
+        database.assert_invalid(&invalid).await;
+    }
+
+    // Sensor readings over two clustering columns, inserted out of order
+    const READINGS: [&str; 5] = [
+        "CREATE TABLE readings (sensor text, day int, hour int, value int, \
+         PRIMARY KEY (sensor, day, hour))",
+        "INSERT INTO readings (sensor, day, hour, value) VALUES ('a', 2, 1, 3)",
+        "INSERT INTO readings (sensor, day, hour, value) VALUES ('a', 1, 2, 2)",
+        "INSERT INTO readings (sensor, day, hour, value) VALUES ('b', 1, 1, 10)",
+        "INSERT INTO readings (sensor, day, hour, value) VALUES ('a', 1, 1, 1)",
+    ];
+
+    #[tokio::test]
+    async fn query_compound_primary_keys() {
+        let database = Fixture::new();
+
+        database.execute(&READINGS).await;
+        database
+            .execute(&["CREATE TABLE events (site int, sensor int, at int, \
+                 PRIMARY KEY ((site, sensor), at))"])
+            .await;
+
+        let tables = database.tables.read().await;
+
+        assert_eq!(tables["readings"].partition_key, vec!["sensor".to_string()]);
+        assert_eq!(
+            tables["readings"].cluster_key,
+            vec!["day".to_string(), "hour".to_string()]
+        );
+        assert_eq!(
+            tables["events"].partition_key,
+            vec!["site".to_string(), "sensor".to_string()]
+        );
+        assert_eq!(tables["events"].cluster_key, vec!["at".to_string()]);
+
+        drop(tables);
+
+        assert_eq!(
+            database.pages("SELECT value FROM readings", 3).await,
+            vec![ids(&[1, 2, 3]), ids(&[10])]
+        );
+
+        let invalid = [
+            "INSERT INTO readings (sensor, day, value) VALUES ('c', 1, 1)",
+            "CREATE TABLE broken (id int, PRIMARY KEY (missing))",
+            "CREATE TABLE broken (id int PRIMARY KEY, other int, PRIMARY KEY (other))",
+        ];
+
+        database.assert_invalid(&invalid).await;
+    }
+
+    #[tokio::test]
+    async fn query_group_by() {
+        let database = Fixture::new();
+
+        database.execute(&READINGS).await;
+
+        assert_eq!(
+            database
+                .rows("SELECT sensor, count(*), sum(value) FROM readings GROUP BY sensor")
+                .await,
+            vec![
+                vec![text("a"), Some(Value::Bigint(3)), Some(Value::Int(6))],
+                vec![text("b"), Some(Value::Bigint(1)), Some(Value::Int(10))],
+            ]
+        );
+        assert_eq!(
+            database
+                .rows("SELECT sensor, day, max(value) FROM readings GROUP BY sensor, day")
+                .await,
+            vec![
+                vec![text("a"), Some(Value::Int(1)), Some(Value::Int(2))],
+                vec![text("a"), Some(Value::Int(2)), Some(Value::Int(3))],
+                vec![text("b"), Some(Value::Int(1)), Some(Value::Int(10))],
+            ]
+        );
+        // A column restricted to a single value can be left out
+        assert_eq!(
+            database
+                .rows("SELECT day, count(*) FROM readings WHERE sensor = 'a' GROUP BY day")
+                .await,
+            vec![
+                vec![Some(Value::Int(1)), Some(Value::Bigint(2))],
+                vec![Some(Value::Int(2)), Some(Value::Bigint(1))],
+            ]
+        );
+        // Without aggregates each group is its first row
+        assert_eq!(
+            database
+                .rows("SELECT sensor, hour FROM readings GROUP BY sensor")
+                .await,
+            vec![
+                vec![text("a"), Some(Value::Int(1))],
+                vec![text("b"), Some(Value::Int(1))],
+            ]
+        );
+        // Pages end between groups, the paging state resuming at the next one
+        assert_eq!(
+            database
+                .pages(
+                    "SELECT sensor, day, count(*) FROM readings GROUP BY sensor, day",
+                    2
+                )
+                .await,
+            vec![
+                vec![
+                    vec![text("a"), Some(Value::Int(1)), Some(Value::Bigint(2))],
+                    vec![text("a"), Some(Value::Int(2)), Some(Value::Bigint(1))],
+                ],
+                vec![vec![text("b"), Some(Value::Int(1)), Some(Value::Bigint(1))]],
+            ]
+        );
+
+        database
+            .execute(&["CREATE TABLE events (site int, sensor int, at int, \
+                 PRIMARY KEY ((site, sensor), at))"])
+            .await;
+
+        let invalid = [
+            "SELECT count(*) FROM readings GROUP BY day",
+            "SELECT count(*) FROM readings GROUP BY value",
+            "SELECT count(*) FROM readings GROUP BY sensor, hour",
+            "SELECT count(*) FROM events GROUP BY site",
+        ];
+
+        database.assert_invalid(&invalid).await;
+    }
+
+    #[tokio::test]
+    async fn query_distinct() {
+        let database = Fixture::new();
+        let statements = [
+            "CREATE TABLE readings (device_id int, day int, at int, value int, \
+             PRIMARY KEY ((device_id, day), at))",
+            "INSERT INTO readings (device_id, day, at, value) VALUES (2, 1, 1, 20)",
+            "INSERT INTO readings (device_id, day, at, value) VALUES (1, 1, 1, 10)",
+            "INSERT INTO readings (device_id, day, at, value) VALUES (1, 1, 2, 11)",
+            "INSERT INTO readings (device_id, day, at, value) VALUES (1, 2, 1, 12)",
+            "INSERT INTO readings (device_id, day, at, value) VALUES (3, 1, 1, 30)",
+            "INSERT INTO readings (device_id, day, at, value) VALUES (3, 1, 2, 31)",
+        ];
+
+        database.execute(&statements).await;
+
+        assert_eq!(
+            database
+                .rows("SELECT DISTINCT device_id, day FROM readings")
+                .await,
+            vec![
+                vec![int(1), int(1)],
+                vec![int(1), int(2)],
+                vec![int(2), int(1)],
+                vec![int(3), int(1)],
+            ]
+        );
+        assert_eq!(
+            database
+                .rows(
+                    "SELECT DISTINCT device_id, day FROM readings WHERE device_id = 3 AND day = 1"
+                )
+                .await,
+            vec![vec![int(3), int(1)]]
+        );
+        assert_eq!(
+            database
+                .rows("SELECT DISTINCT device_id, day, count(*) FROM readings")
+                .await,
+            vec![vec![int(1), int(1), Some(Value::Bigint(4))]]
+        );
+
+        // Each page resumes at the partition after the last one returned
+        let pages = database
+            .pages("SELECT DISTINCT device_id, day FROM readings", 3)
+            .await;
+
+        assert_eq!(
+            pages,
+            vec![
+                vec![
+                    vec![int(1), int(1)],
+                    vec![int(1), int(2)],
+                    vec![int(2), int(1)],
+                ],
+                vec![vec![int(3), int(1)]],
+            ]
+        );
+
+        let invalid = [
+            "SELECT DISTINCT device_id, day, value FROM readings",
+            "SELECT DISTINCT device_id FROM readings",
+            "SELECT DISTINCT device_id, day FROM readings WHERE at = 1",
+            "SELECT DISTINCT device_id, day FROM readings WHERE value = 10",
+        ];
+
+        database.assert_invalid(&invalid).await;
+    }
+
+    // Devices whose model and tags are static, shared by each reading
+    const DEVICE_READINGS: [&str; 6] = [
+        "CREATE TABLE readings (device_id int, at int, value int, model text STATIC, \
+         tags set<text> STATIC, PRIMARY KEY (device_id, at))",
+        "INSERT INTO readings (device_id, at, value) VALUES (1, 1, 10)",
+        "INSERT INTO readings (device_id, at, value) VALUES (1, 2, 11)",
+        "INSERT INTO readings (device_id, model) VALUES (1, 'x1')",
+        "UPDATE readings SET model = 'y', tags = tags + {'new'} WHERE device_id = 2",
+        "INSERT INTO readings (device_id, at, value, model) VALUES (3, 1, 30, 'z')",
+    ];
+
+    #[tokio::test]
+    async fn query_static_columns() {
+        let database = Fixture::new();
+
+        database.execute(&DEVICE_READINGS).await;
+
+        assert_eq!(
+            database.tables.read().await["readings"].columns["model"].kind,
+            schema::Kind::Static
+        );
+
+        // A partition with static columns alone is a row of its own
+        assert_eq!(
+            database
+                .rows("SELECT device_id, at, value, model FROM readings")
+                .await,
+            vec![
+                vec![int(1), int(1), int(10), text("x1")],
+                vec![int(1), int(2), int(11), text("x1")],
+                vec![int(2), None, None, text("y")],
+                vec![int(3), int(1), int(30), text("z")],
+            ]
+        );
+        assert_eq!(
+            database
+                .rows("SELECT value, model FROM readings WHERE device_id = 1 AND at = 2")
+                .await,
+            vec![vec![int(11), text("x1")]]
+        );
+        assert_eq!(
+            database
+                .rows("SELECT value FROM readings WHERE device_id = 1 AND at = 3")
+                .await,
+            Vec::<Vec<Option<Value>>>::new()
+        );
+    }
+
+    #[tokio::test]
+    async fn query_static_column_updates() {
+        let database = Fixture::new();
+
+        database.execute(&DEVICE_READINGS).await;
+
+        // Static columns are shared by every row of the partition
+        database
+            .execute(&["UPDATE readings SET model = 'x2' WHERE device_id = 1 AND at = 1"])
+            .await;
+        assert_eq!(
+            database
+                .rows("SELECT at, model FROM readings WHERE device_id = 1")
+                .await,
+            vec![vec![int(1), text("x2")], vec![int(2), text("x2")]]
+        );
+
+        // Pages resumed within a partition still carry its static columns
+        let pages = database
+            .pages("SELECT device_id, at, model FROM readings", 1)
+            .await
+            .concat();
+
+        assert_eq!(
+            pages,
+            vec![
+                vec![int(1), int(1), text("x2")],
+                vec![int(1), int(2), text("x2")],
+                vec![int(2), None, text("y")],
+                vec![int(3), int(1), text("z")],
+            ]
+        );
+        assert_eq!(
+            database
+                .rows("SELECT DISTINCT device_id, model FROM readings")
+                .await,
+            vec![
+                vec![int(1), text("x2")],
+                vec![int(2), text("y")],
+                vec![int(3), text("z")],
+            ]
+        );
+
+        let statements = [
+            "DELETE model, tags FROM readings WHERE device_id = 2",
+            "DELETE FROM readings WHERE device_id = 1 AND at = 1",
+        ];
+
+        database.execute(&statements).await;
+
+        assert_eq!(
+            database
+                .rows("SELECT device_id, at, model FROM readings")
+                .await,
+            vec![
+                vec![int(1), int(2), text("x2")],
+                vec![int(3), int(1), text("z")],
+            ]
+        );
+    }
+
+    #[tokio::test]
+    async fn query_static_column_errors() {
+        let database = Fixture::new();
+
+        database.execute(&DEVICE_READINGS).await;
+
+        let invalid = [
+            "CREATE TABLE plain (id int PRIMARY KEY, model text STATIC)",
+            "CREATE TABLE keyed (id int, at int STATIC, PRIMARY KEY (id, at))",
+            "INSERT INTO readings (device_id, value) VALUES (1, 5)",
+            "UPDATE readings SET value = 5, model = 'x' WHERE device_id = 1",
+            "SELECT DISTINCT device_id, value FROM readings",
+        ];
+
+        database.assert_invalid(&invalid).await;
+    }
+
+    #[tokio::test]
+    async fn query_functions() {
+        let database = Fixture::new();
+        let statements = [
+            "CREATE TABLE events (id int PRIMARY KEY, at timestamp, created timeuuid, \
+             payload blob, ratio double)",
+            "INSERT INTO events (id, at, created, payload, ratio) \
+             VALUES (1, '2024-01-01 12:00:00+0000', now(), intAsBlob(7), 2.5)",
+            "INSERT INTO events (id, at, created, payload, ratio) \
+             VALUES (2, '2024-01-02 12:00:00+0000', minTimeuuid('2020-01-01'), 0x01, -1.5)",
+            "UPDATE events SET at = toTimestamp(toDate('2024-01-03')) WHERE id = 2",
+        ];
+
+        database.execute(&statements).await;
+
+        let results = database
+            .query(query(
+                "SELECT toDate(at), toUnixTimestamp(at), blobAsInt(payload), \
+                 CAST(ratio AS int), token(id) FROM events WHERE id = 1",
+            ))
+            .await
+            .unwrap();
+
+        assert_eq!(
+            results.metadata.unwrap().columns,
+            vec![
+                ("system.todate(at)".to_string(), ColumnType::Date),
+                ("system.tounixtimestamp(at)".to_string(), ColumnType::Bigint),
+                ("system.blobasint(payload)".to_string(), ColumnType::Int),
+                ("cast(ratio as int)".to_string(), ColumnType::Int),
+                ("system.token(id)".to_string(), ColumnType::Bigint),
+            ]
+        );
+        assert_eq!(
+            results.result.collect::<Result<Vec<_>, _>>().unwrap(),
+            vec![vec![
+                Some(Value::Date(19723)),
+                Some(Value::Bigint(1_704_110_400_000)),
+                Some(Value::Int(7)),
+                Some(Value::Int(2)),
+                Some(Value::Bigint(-4069959284402364209)),
+            ]]
+        );
+
+        assert_eq!(
+            database
+                .rows("SELECT id, at FROM events WHERE created < maxTimeuuid('2021-01-01') ALLOW FILTERING")
+                .await,
+            vec![vec![
+                Some(Value::Int(2)),
+                Some(Value::Timestamp(1_704_240_000_000))
+            ]]
+        );
+        assert_eq!(
+            database
+                .rows("SELECT id FROM events WHERE token(id) = token(1)")
+                .await,
+            vec![vec![Some(Value::Int(1))]]
+        );
+
+        let invalid = [
+            "SELECT blobAsInt(ratio) FROM events",
+            "SELECT unknown(id) FROM events",
+            "SELECT token(ratio) FROM events",
+            "SELECT CAST(payload AS int) FROM events",
+            "INSERT INTO events (id, at) VALUES (3, now())",
+            // The payload of the second row is a single byte
+            "SELECT blobAsBigint(payload) FROM events",
+        ];
+
+        database.assert_invalid(&invalid).await;
+    }
+
+    #[tokio::test]
+    async fn query_arithmetic() {
+        let database = Fixture::new();
+        let statements = [
+            "CREATE TABLE items (id int PRIMARY KEY, quantity int, price double, \
+             total bigint, name text)",
+            "INSERT INTO items (id, quantity, price, name) VALUES (1, 3, 2.5, 'bolt')",
+            "INSERT INTO items (id, quantity, price, name) VALUES (1 + 1, -(2 * 3), 0.5, 'nut')",
+            "UPDATE items SET total = 40 + 2 WHERE id = 1",
+        ];
+
+        database.execute(&statements).await;
+
+        let results = database
+            .query(query(
+                "SELECT quantity * price, quantity / 2, quantity % 2, -quantity, \
+                 total - quantity, name + 's' AS plural FROM items WHERE id = 1",
+            ))
+            .await
+            .unwrap();
+
+        assert_eq!(
+            results.metadata.unwrap().columns,
+            vec![
+                ("quantity * price".to_string(), ColumnType::Double),
+                ("quantity / 2".to_string(), ColumnType::Int),
+                ("quantity % 2".to_string(), ColumnType::Int),
+                ("-quantity".to_string(), ColumnType::Int),
+                ("total - quantity".to_string(), ColumnType::Bigint),
+                ("plural".to_string(), ColumnType::Varchar),
+            ]
+        );
+        assert_eq!(
+            results.result.collect::<Result<Vec<_>, _>>().unwrap(),
+            vec![vec![
+                Some(Value::Double(7.5)),
+                Some(Value::Int(1)),
+                Some(Value::Int(1)),
+                Some(Value::Int(-3)),
+                Some(Value::Bigint(39)),
+                Some(Value::Varchar("bolts".to_string())),
+            ]]
+        );
+
+        assert_eq!(
+            database
+                .rows("SELECT id, quantity FROM items WHERE quantity < 2 - 3 ALLOW FILTERING")
+                .await,
+            vec![vec![Some(Value::Int(2)), Some(Value::Int(-6))]]
+        );
+        assert_eq!(
+            database
+                .rows("SELECT id FROM items WHERE price = quantity - 0.5 ALLOW FILTERING")
+                .await,
+            vec![vec![Some(Value::Int(1))]]
+        );
+        // Nulls propagate through operations
+        assert_eq!(
+            database
+                .rows("SELECT total + 1 FROM items WHERE id = 2")
+                .await,
+            vec![vec![None]]
+        );
+
+        // Rows are read as they're iterated, so one failing only fails then
+        let mut rows = database
+            .query(query("SELECT quantity / 0 FROM items WHERE id = 1"))
+            .await
+            .unwrap()
+            .result;
+
+        assert!(rows.next().unwrap().is_err());
+
+        let invalid = [
+            "SELECT name * 2 FROM items",
+            "SELECT quantity / 0 FROM items",
+            "UPDATE items SET quantity = 1.5 * 2 WHERE id = 1",
+            "UPDATE items SET quantity = quantity + 1 WHERE id = 1",
+        ];
+
+        database.assert_invalid(&invalid).await;
+    }
+
+    // Items to call functions over, with one quantity left null
+    const ITEMS: [&str; 4] = [
+        "CREATE TABLE items (id int PRIMARY KEY, quantity int, name text)",
+        "INSERT INTO items (id, quantity, name) VALUES (1, 6, 'bolt')",
+        "INSERT INTO items (id, quantity, name) VALUES (2, 5, 'nut')",
+        "INSERT INTO items (id, name) VALUES (3, 'washer')",
+    ];
+
+    #[tokio::test]
+    async fn query_user_functions() {
+        let database = Fixture::new();
+
+        database.execute(&ITEMS).await;
+        database
+            .execute(&[
+                "CREATE FUNCTION twice (x int) RETURNS NULL ON NULL INPUT RETURNS int \
+                 LANGUAGE cql AS 'x * 2'",
+                "CREATE FUNCTION label (x int, name text) CALLED ON NULL INPUT RETURNS text \
+                 LANGUAGE cql AS $$ name + ' #' + CAST(x AS text) $$",
+                "INSERT INTO items (id, quantity, name) VALUES (4, twice(3), 'pin')",
+            ])
+            .await;
+
+        let results = database
+            .query(query(
+                "SELECT twice(quantity), label(quantity, name) FROM items WHERE id = 1",
+            ))
+            .await
+            .unwrap();
+
+        assert_eq!(
+            results.metadata.unwrap().columns,
+            vec![
+                ("twice(quantity)".to_string(), ColumnType::Int),
+                ("label(quantity, name)".to_string(), ColumnType::Varchar),
+            ]
+        );
+        assert_eq!(
+            results.result.collect::<Result<Vec<_>, _>>().unwrap(),
+            vec![vec![
+                Some(Value::Int(12)),
+                Some(Value::Varchar("bolt #6".to_string())),
+            ]]
+        );
+        assert_eq!(
+            database
+                .rows("SELECT id FROM items WHERE quantity = twice(3) ALLOW FILTERING")
+                .await,
+            vec![vec![Some(Value::Int(1))], vec![Some(Value::Int(4))]]
+        );
+        // Functions returning null on null input aren't called with one
+        assert_eq!(
+            database
+                .rows("SELECT twice(quantity) FROM items WHERE id = 3")
+                .await,
+            vec![vec![None]]
+        );
+
+        let invalid = [
+            "CREATE FUNCTION f (x int) RETURNS NULL ON NULL INPUT RETURNS int \
+             LANGUAGE java AS 'return x;'",
+            "CREATE FUNCTION f (x int) RETURNS NULL ON NULL INPUT RETURNS text \
+             LANGUAGE cql AS 'x'",
+            "CREATE FUNCTION f (x int) RETURNS NULL ON NULL INPUT RETURNS int \
+             LANGUAGE cql AS 'y + 1'",
+            "CREATE FUNCTION f (x int, x int) RETURNS NULL ON NULL INPUT RETURNS int \
+             LANGUAGE cql AS 'x'",
+            "CREATE FUNCTION intasblob (x int) RETURNS NULL ON NULL INPUT RETURNS int \
+             LANGUAGE cql AS 'x'",
+            "SELECT twice(name) FROM items",
+        ];
+
+        database.assert_invalid(&invalid).await;
+    }
+
+    #[tokio::test]
+    async fn query_replace_user_functions() {
+        let database = Fixture::new();
+        let twice = "CREATE FUNCTION twice (x int) RETURNS NULL ON NULL INPUT RETURNS int \
+                     LANGUAGE cql AS 'x * 2'";
+
+        database.execute(&ITEMS).await;
+        database.execute(&[twice]).await;
+
+        // Existing functions are only replaced when asked to
+        assert!(matches!(
+            database.error(twice).await.code,
+            ErrorCode::AlreadyExists
+        ));
+
+        database
+            .execute(&[
+                "CREATE FUNCTION IF NOT EXISTS twice (x int) RETURNS NULL ON NULL INPUT \
+                 RETURNS int LANGUAGE cql AS 'x + 1'",
+                "CREATE OR REPLACE FUNCTION twice (x int) RETURNS NULL ON NULL INPUT \
+                 RETURNS int LANGUAGE cql AS 'x * 20'",
+            ])
+            .await;
+
+        assert_eq!(
+            database
+                .rows("SELECT twice(quantity) FROM items WHERE id = 2")
+                .await,
+            vec![vec![Some(Value::Int(100))]]
+        );
+
+        database
+            .error(
+                "CREATE OR REPLACE FUNCTION twice (x int) RETURNS NULL ON NULL INPUT \
+                 RETURNS bigint LANGUAGE cql AS '1'",
+            )
+            .await;
+    }
+
+    #[tokio::test]
+    async fn query_user_aggregates() {
+        let database = Fixture::new();
+
+        database.execute(&ITEMS).await;
+        database
+            .execute(&[
+                "CREATE FUNCTION accumulate (acc bigint, x int) RETURNS NULL ON NULL INPUT \
+                 RETURNS bigint LANGUAGE cql AS 'acc + x'",
+                "CREATE FUNCTION halve (acc bigint) RETURNS NULL ON NULL INPUT RETURNS double \
+                 LANGUAGE cql AS 'CAST(acc AS double) / 2'",
+                "CREATE FUNCTION twice (x int) RETURNS NULL ON NULL INPUT RETURNS int \
+                 LANGUAGE cql AS 'x * 2'",
+                "CREATE AGGREGATE total (int) SFUNC accumulate STYPE bigint INITCOND 0",
+                "CREATE AGGREGATE half_total (int) SFUNC accumulate STYPE bigint \
+                 FINALFUNC halve INITCOND 0",
+            ])
+            .await;
+
+        // The state function isn't called for null values
+        assert_eq!(
+            database
+                .rows("SELECT total(quantity), half_total(quantity) FROM items")
+                .await,
+            vec![vec![Some(Value::Bigint(11)), Some(Value::Double(5.5))]]
+        );
+
+        let invalid = [
+            "CREATE AGGREGATE a (text) SFUNC accumulate STYPE bigint",
+            "CREATE AGGREGATE a (int) SFUNC accumulate STYPE bigint FINALFUNC twice",
+            "CREATE AGGREGATE a (int) SFUNC accumulate STYPE bigint INITCOND 'zero'",
+            "CREATE AGGREGATE count (int) SFUNC accumulate STYPE bigint",
+            "SELECT total(name) FROM items",
+        ];
+
+        database.assert_invalid(&invalid).await;
+    }
+
+    #[tokio::test]
+    async fn query_bind_markers() {
+        let database = Fixture::new();
+        let statements = [
+            (
+                "CREATE TABLE users (id int PRIMARY KEY, name text, tags list<int>)",
+                None,
+            ),
+            (
+                "INSERT INTO users (id, name, tags) VALUES (?, ?, ?)",
+                Some(Values::Unnamed(vec![
+                    bound(&[0, 0, 0, 1]),
+                    bound(b"ann"),
+                    bound(&[0, 0, 0, 1, 0, 0, 0, 4, 0, 0, 0, 7]),
+                ])),
+            ),
+            (
+                "UPDATE users SET name = :name WHERE id = :id",
+                Some(Values::Named(HashMap::from([
+                    ("id".to_string(), bound(&[0, 0, 0, 1])),
+                    ("name".to_string(), bound(b"bob")),
+                ]))),
+            ),
+            ("CREATE TABLE hits (id int PRIMARY KEY, c counter)", None),
+            (
+                "UPDATE hits SET c = c + ? WHERE id = ?",
+                Some(Values::Unnamed(vec![
+                    bound(&[0, 0, 0, 0, 0, 0, 0, 5]),
+                    bound(&[0, 0, 0, 1]),
+                ])),
+            ),
+        ];
+
+        for (statement, values) in statements {
+            database
+                .query(bound_query(statement, values))
+                .await
+                .unwrap();
+        }
+
+        let results = database
+            .query(bound_query(
+                "SELECT name, tags FROM users WHERE id = ?",
+                Some(Values::Unnamed(vec![bound(&[0, 0, 0, 1])])),
+            ))
+            .await
+            .unwrap();
+
+        assert_eq!(
+            results.result.collect::<Result<Vec<_>, _>>().unwrap(),
+            vec![vec![
+                Some(Value::Varchar("bob".to_string())),
+                Some(Value::List(vec![Value::Int(7)]))
+            ]]
+        );
+
+        let hits = database.query(query("SELECT c FROM hits")).await.unwrap();
+
+        assert_eq!(
+            hits.result.collect::<Result<Vec<_>, _>>().unwrap(),
+            vec![vec![Some(Value::Counter(5))]]
+        );
+
+        let missing = database
+            .query(query("SELECT name FROM users WHERE id = ?"))
+            .await;
+        let malformed = database
+            .query(bound_query(
+                "SELECT name FROM users WHERE id = ?",
+                Some(Values::Unnamed(vec![bound(&[0, 1])])),
+            ))
+            .await;
+
+        assert!(missing.is_err());
+        assert!(malformed.is_err());
+    }
+
+    #[tokio::test]
+    async fn query_bound_collection_elements() {
+        let database = Fixture::new();
+        let statements = [
+            (
+                "CREATE TABLE users (id int PRIMARY KEY, logins list<int>, emails set<text>, \
+                 phones map<text, int>)",
+                None,
+            ),
+            (
+                "INSERT INTO users (id, logins, emails, phones) VALUES (1, [?, 2], {?}, {?: ?})",
+                Some(Values::Unnamed(vec![
+                    bound(&[0, 0, 0, 1]),
+                    bound(b"a@x"),
+                    bound(b"home"),
+                    bound(&[0, 0, 0, 5]),
+                ])),
+            ),
+            (
+                "UPDATE users SET logins = logins + [?], emails = emails + {?}, \
+                 phones = phones + {?: ?} WHERE id = ?",
+                Some(Values::Unnamed(vec![
+                    bound(&[0, 0, 0, 3]),
+                    bound(b"b@x"),
+                    bound(b"work"),
+                    bound(&[0, 0, 0, 6]),
+                    bound(&[0, 0, 0, 1]),
+                ])),
+            ),
+            (
+                "UPDATE users SET logins = logins - [:login] WHERE id = 1",
+                Some(Values::Named(HashMap::from([(
+                    "login".to_string(),
+                    bound(&[0, 0, 0, 1]),
+                )]))),
+            ),
+        ];
+
+        for (statement, values) in statements {
+            database
+                .query(bound_query(statement, values))
+                .await
+                .unwrap();
+        }
+
+        assert_eq!(
+            database
+                .rows("SELECT logins, emails, phones FROM users WHERE id = 1")
+                .await,
+            vec![vec![
+                Some(Value::List(vec![Value::Int(2), Value::Int(3)])),
+                Some(Value::Set(vec![
+                    Value::Varchar("a@x".to_string()),
+                    Value::Varchar("b@x".to_string())
+                ])),
+                Some(Value::Map(vec![
+                    (Value::Varchar("home".to_string()), Value::Int(5)),
+                    (Value::Varchar("work".to_string()), Value::Int(6))
+                ]))
+            ]]
+        );
+        assert!(database
+            .query(bound_query(
+                "UPDATE users SET logins = logins + [?] WHERE id = 1",
+                Some(Values::Unnamed(vec![BoundValue::Null])),
+            ))
+            .await
+            .is_err());
+    }
+
+    #[tokio::test]
+    async fn query_unset_values() {
+        let database = Fixture::new();
+
+        database
+            .execute(&[
+                "CREATE TABLE t (id int PRIMARY KEY, v int, w int)",
+                "INSERT INTO t (id, v, w) VALUES (1, 1, 9)",
+            ])
+            .await;
+
+        // Unset values leave their columns as they were, where null clears them
+        let statements = [
+            (
+                "UPDATE t SET v = ?, w = ? WHERE id = 1",
+                vec![BoundValue::Null, BoundValue::NotSet],
+            ),
+            (
+                "INSERT INTO t (id, v, w) VALUES (?, ?, ?)",
+                vec![
+                    bound(&[0, 0, 0, 2]),
+                    bound(&[0, 0, 0, 2]),
+                    BoundValue::NotSet,
+                ],
+            ),
+        ];
+
+        for (statement, values) in statements {
+            database
+                .query(bound_query(statement, Some(Values::Unnamed(values))))
+                .await
+                .unwrap();
+        }
+
+        assert_eq!(
+            database.rows("SELECT id, v, w FROM t").await,
+            vec![vec![int(1), None, int(9)], vec![int(2), int(2), None]]
+        );
+        assert!(database
+            .query(bound_query(
+                "SELECT v FROM t WHERE id = ?",
+                Some(Values::Unnamed(vec![BoundValue::NotSet])),
+            ))
+            .await
+            .is_err());
+    }
+
+    #[tokio::test]
+    async fn query_single_record() {
+        // Step 1: Set up the database
+        let database = Fixture::new();
+
+        // Step 2: Create a table
+        let create_table_query = "
+            CREATE TABLE users (
+                id INT PRIMARY KEY,
+                name VARCHAR,
+                age INT,
+                is_active BOOLEAN
+            )
+        ";
+        let create_result = database
+            .query(Query {
+                query: create_table_query.to_string(),
+                query_options: QueryOptions {
+                    consistency: Consistency::One,
+                    values: None,
+                    skip_metadata: false,
+                    page_size: None,
+                    paging_state: None,
+                    timestamp: None,
+                },
+            })
+            .await;
+        assert!(create_result.is_ok());
+
+        // Step 3: Insert data
+        let insert_query = "
+            INSERT INTO users (id, name, age, is_active)
+            VALUES (1, 'Alice', 30, true)
+        ";
+        let insert_result = database
+            .query(Query {
+                query: insert_query.to_string(),
+                query_options: QueryOptions {
+                    consistency: Consistency::One,
+                    values: None,
+                    skip_metadata: false,
+                    page_size: None,
+                    paging_state: None,
+                    timestamp: None,
+                },
+            })
+            .await;
+        assert!(insert_result.is_ok());
+
+        // Step 4: Query a single record
+        let select_query = "SELECT id, name, age, is_active FROM users WHERE id = 1";
+        let select_result = database
+            .query(Query {
+                query: select_query.to_string(),
+                query_options: QueryOptions {
+                    consistency: Consistency::One,
+                    values: None,
+                    skip_metadata: false,
+                    page_size: None,
+                    paging_state: None,
+                    timestamp: None,
+                },
+            })
+            .await;
+        assert!(select_result.is_ok());
+
+        let mut result_iter = select_result.unwrap().result;
+
+        // Step 5: Verify the data
+        let row = result_iter.next().unwrap().unwrap();
+        assert_eq!(row[0], Some(Value::Int(1)));
+        assert_eq!(row[1], Some(Value::Varchar("Alice".to_string())));
+        assert_eq!(row[2], Some(Value::Int(30)));
+        assert_eq!(row[3], Some(Value::Boolean(true)));
+
+        // Ensure no additional rows are returned
+        assert!(result_iter.next().is_none());
+    }
+
+    // Documents with a column of most kinds, the first written as JSON
+    const DOCS: [&str; 3] = [
+        "CREATE TYPE point (x int, y int)",
+        "CREATE TABLE docs (id int PRIMARY KEY, name text, born timestamp, tags set<text>, \
+         scores map<int, double>, home frozen<point>, pair tuple<int, text>, data blob, \
+         ok boolean)",
+        r#"INSERT INTO docs JSON '{"id": 1, "name": "ann", "born": "2020-01-02 03:04:05.000Z",
+           "tags": ["b", "a"], "scores": {"1": 0.5, "2": "NaN"}, "home": {"x": 1},
+           "pair": [1, "one"], "data": "0xcafe", "ok": "true"}'"#,
+    ];
+
+    fn json(text: &str) -> Vec<Option<Value>> {
+        vec![Some(Value::Varchar(text.to_string()))]
+    }
+
+    #[tokio::test]
+    async fn query_insert_json() {
+        let database = Fixture::new();
+
+        database.execute(&DOCS).await;
+        database
+            .execute(&[
+                r#"INSERT INTO docs JSON '{"id": 1, "name": "bob"}' DEFAULT UNSET"#,
+                r#"INSERT INTO docs JSON '{"id": "2", "ok": false}' DEFAULT NULL"#,
+                "UPDATE docs SET tags = fromJson('[\"z\"]') WHERE id = 2",
+            ])
+            .await;
+        database
+            .query(bound_query(
+                "INSERT INTO docs JSON ?",
+                Some(Values::Unnamed(vec![BoundValue::Set {
+                    bytes: Bytes::from_static(br#"{"id": 3, "name": "cy"}"#),
+                }])),
+            ))
+            .await
+            .unwrap();
+
+        assert_eq!(
+            database
+                .rows("SELECT JSON id, name, tags, ok FROM docs")
+                .await,
+            vec![
+                json(r#"{"id": 1, "name": "bob", "tags": ["a", "b"], "ok": true}"#),
+                json(r#"{"id": 2, "name": null, "tags": ["z"], "ok": false}"#),
+                json(r#"{"id": 3, "name": "cy", "tags": null, "ok": null}"#),
+            ]
+        );
+
+        let invalid = [
+            r#"INSERT INTO docs JSON '{"id": 4, "nope": 1}'"#,
+            r#"INSERT INTO docs JSON '{"name": "dan"}'"#,
+            r#"INSERT INTO docs JSON '{"id": "four"}'"#,
+            r#"INSERT INTO docs JSON '{"id": 4, "home": {"z": 1}}'"#,
+            r#"INSERT INTO docs JSON '{"id": 4, "pair": [1]}'"#,
+            "INSERT INTO docs JSON '[4]'",
+            "INSERT INTO docs JSON '{\"id\": 4'",
+        ];
+
+        database.assert_invalid(&invalid).await;
+    }
+
+    #[tokio::test]
+    async fn query_insert_json_defaults() {
+        let database = Fixture::new();
+
+        database.execute(&DOCS).await;
+        database
+            .execute(&[r#"INSERT INTO docs JSON '{"id": 1, "name": "bob"}'"#])
+            .await;
+
+        // Without a DEFAULT clause the columns left out are cleared
+        assert_eq!(
+            database
+                .rows("SELECT JSON id, name, tags, home, ok FROM docs")
+                .await,
+            vec![json(
+                r#"{"id": 1, "name": "bob", "tags": null, "home": null, "ok": null}"#
+            )]
+        );
+    }
+
+    #[tokio::test]
+    async fn query_select_json() {
+        let database = Fixture::new();
+
+        database.execute(&DOCS).await;
+
+        let results = database
+            .query(query(
+                "SELECT JSON id, name, born, tags, scores, home, pair, data, ok \
+                 FROM docs WHERE id = 1",
+            ))
+            .await
+            .unwrap();
+
+        assert_eq!(
+            results.metadata.unwrap().columns,
+            vec![("[json]".to_string(), ColumnType::Varchar)]
+        );
+        assert_eq!(
+            results.result.collect::<Result<Vec<_>, _>>().unwrap(),
+            vec![json(
+                r#"{"id": 1, "name": "ann", "born": "2020-01-02 03:04:05.000Z", "tags": ["a", "b"], "scores": {"1": 0.5, "2": "NaN"}, "home": {"x": 1, "y": null}, "pair": [1, "one"], "data": "0xcafe", "ok": true}"#
+            )]
+        );
+    }
+
+    #[tokio::test]
+    async fn query_json_functions() {
+        let database = Fixture::new();
+
+        database.execute(&DOCS).await;
+
+        let results = database
+            .query(query(
+                "SELECT toJson(home), toJson(born) FROM docs WHERE id = 1",
+            ))
+            .await
+            .unwrap();
+
+        assert_eq!(
+            results.metadata.unwrap().columns,
+            vec![
+                ("system.tojson(home)".to_string(), ColumnType::Varchar),
+                ("system.tojson(born)".to_string(), ColumnType::Varchar),
+            ]
+        );
+        assert_eq!(
+            results.result.collect::<Result<Vec<_>, _>>().unwrap(),
+            vec![vec![
+                Some(Value::Varchar(r#"{"x": 1, "y": null}"#.to_string())),
+                Some(Value::Varchar(r#""2020-01-02 03:04:05.000Z""#.to_string())),
+            ]]
+        );
+
+        let invalid = [
+            "SELECT fromJson(name) FROM docs",
+            "SELECT toJson(?) FROM docs",
+            "UPDATE docs SET ok = fromJson('1') WHERE id = 1",
+        ];
+
+        database.assert_invalid(&invalid).await;
+    }
+
+    // Users indexed by name, tags and the keys and entries of attrs, the rows
+    // written before the indexes built in the background
+    const INDEXED_USERS: [&str; 10] = [
+        "CREATE TABLE users (id int, seq int, team text static, name text, tags set<text>, \
+         attrs map<text, int>, PRIMARY KEY (id, seq))",
+        "INSERT INTO users (id, seq, name, tags, attrs) VALUES (1, 1, 'ann', {'x', 'y'}, {'k': 1})",
+        "INSERT INTO users (id, seq, name, tags) VALUES (1, 2, 'ann', {'y'})",
+        "INSERT INTO users (id, seq, name, tags) VALUES (2, 1, 'bob', {'y'})",
+        "UPDATE users SET team = 'red' WHERE id = 1",
+        "CREATE INDEX ON users (name)",
+        "CREATE INDEX tags_idx ON users (tags)",
+        "CREATE INDEX ON users (keys(attrs))",
+        "CREATE INDEX IF NOT EXISTS attrs_entries ON users (entries(attrs))",
+        "CREATE INDEX IF NOT EXISTS tags_idx ON users (name)",
+    ];
+
+    fn id_seq(id: i32, seq: i32) -> Vec<Option<Value>> {
+        vec![Some(Value::Int(id)), Some(Value::Int(seq))]
+    }
+
+    #[tokio::test]
+    async fn query_secondary_indexes() {
+        let database = Fixture::new();
+
+        database.execute(&INDEXED_USERS).await;
+        database.build_indexes("users").await;
+        database
+            .execute(&[
+                "INSERT INTO users (id, seq, name, tags) VALUES (3, 1, 'ann', {'z'})",
+                "UPDATE users SET name = 'cy' WHERE id = 1 AND seq = 2",
+                "UPDATE users SET tags = tags + {'z'} WHERE id = 2 AND seq = 1",
+            ])
+            .await;
+
+        assert_eq!(
+            database
+                .rows("SELECT id, seq, team FROM users WHERE name = 'ann'")
+                .await,
+            vec![
+                vec![
+                    Some(Value::Int(1)),
+                    Some(Value::Int(1)),
+                    Some(Value::Varchar("red".to_string()))
+                ],
+                vec![Some(Value::Int(3)), Some(Value::Int(1)), None],
+            ]
+        );
+        assert_eq!(
+            database
+                .rows("SELECT id, seq FROM users WHERE tags CONTAINS 'z'")
+                .await,
+            vec![id_seq(2, 1), id_seq(3, 1)]
+        );
+        assert_eq!(
+            database
+                .rows("SELECT id, seq FROM users WHERE attrs CONTAINS KEY 'k'")
+                .await,
+            vec![id_seq(1, 1)]
+        );
+        assert_eq!(
+            database
+                .rows("SELECT id, seq FROM users WHERE attrs['k'] = 1")
+                .await,
+            vec![id_seq(1, 1)]
+        );
+        assert!(database
+            .rows("SELECT id, seq FROM users WHERE attrs['k'] = 2")
+            .await
+            .is_empty());
+        // Index lookups page like any other query
+        assert_eq!(
+            database
+                .pages("SELECT id, seq FROM users WHERE tags CONTAINS 'y'", 1)
+                .await,
+            vec![vec![id_seq(1, 1)], vec![id_seq(1, 2)], vec![id_seq(2, 1)]]
+        );
+    }
+
+    #[tokio::test]
+    async fn query_secondary_index_entries() {
+        let database = Fixture::new();
+        let entries = |index: &str| {
+            database
+                .fjall
+                .open_partition(index, Default::default())
+                .unwrap()
+                .len()
+                .unwrap()
+        };
+
+        database.execute(&INDEXED_USERS).await;
+        database.build_indexes("users").await;
+        database
+            .execute(&[
+                "INSERT INTO users (id, seq, name, tags) VALUES (3, 1, 'ann', {'z'})",
+                "UPDATE users SET name = 'cy' WHERE id = 1 AND seq = 2",
+                "UPDATE users SET tags = tags + {'z'} WHERE id = 2 AND seq = 1",
+            ])
+            .await;
+
+        // Entries go with the values and rows they were made for
+        assert_eq!(entries("users.users_name_idx"), 4);
+        assert_eq!(entries("users.tags_idx"), 6);
+
+        database
+            .execute(&[
+                "DELETE FROM users WHERE id = 2",
+                "UPDATE users SET tags = tags - {'x'} WHERE id = 1 AND seq = 1",
+                "DELETE name FROM users WHERE id = 3 AND seq = 1",
+            ])
+            .await;
+
+        assert_eq!(entries("users.users_name_idx"), 2);
+        assert_eq!(entries("users.tags_idx"), 3);
+        assert_eq!(
+            database
+                .rows("SELECT id, seq FROM users WHERE tags CONTAINS 'z'")
+                .await,
+            vec![id_seq(3, 1)]
+        );
+
+        database.execute(&["DROP INDEX users_name_idx"]).await;
+
+        assert_eq!(
+            database
+                .rows("SELECT id, seq FROM users WHERE name = 'ann' ALLOW FILTERING")
+                .await,
+            vec![id_seq(1, 1)]
+        );
+        database.error("DROP INDEX users_name_idx").await;
+    }
+
+    #[tokio::test]
+    async fn query_secondary_index_errors() {
+        let database = Fixture::new();
+
+        database.execute(&INDEXED_USERS).await;
+
+        let invalid = [
+            "CREATE INDEX ON users (id)",
+            "CREATE INDEX ON users (team)",
+            "CREATE INDEX ON users (nope)",
+            "CREATE INDEX ON users (keys(tags))",
+            "CREATE INDEX ON users (full(tags))",
+            "CREATE INDEX ON users (tags)",
+            "CREATE INDEX tags_idx ON users (attrs)",
+            "DROP INDEX missing_idx",
+            "SELECT id FROM users WHERE name CONTAINS 'a'",
+        ];
+
+        database.assert_invalid(&invalid).await;
+    }
+
+    #[tokio::test]
+    async fn query_secondary_index_names() {
+        let database = Fixture::new();
+
+        database.execute(&INDEXED_USERS).await;
+        // Indexes on other tables' columns of the same name aren't duplicates
+        database
+            .execute(&[
+                "CREATE TABLE admins (id int PRIMARY KEY, name text, level int, \
+                 attrs map<text, int>)",
+                "CREATE INDEX ON admins (name)",
+                "CREATE INDEX ON admins (keys(attrs))",
+                "CREATE INDEX ON admins (entries(attrs))",
+                "CREATE INDEX ON admins (values(attrs))",
+            ])
+            .await;
+
+        assert_eq!(
+            database.tables.read().await["admins"]
+                .indexes
+                .iter()
+                .map(|index| index.name.as_str())
+                .collect::<Vec<_>>(),
+            [
+                "admins_name_idx",
+                "admins_attrs_idx",
+                "admins_attrs_idx_1",
+                "admins_attrs_idx_2"
+            ]
+        );
+        database
+            .assert_error(
+                "CREATE INDEX tags_idx ON admins (level)",
+                ErrorCode::AlreadyExists,
+                "Index tags_idx already exists",
+            )
+            .await;
+        database
+            .assert_error(
+                "CREATE INDEX other_idx ON admins (keys(attrs))",
+                ErrorCode::Invalid,
+                "Index other_idx is a duplicate of existing index admins_attrs_idx",
+            )
+            .await;
+    }
+
+    #[tokio::test]
+    async fn query_failed_secondary_index() {
+        let database = Fixture::new();
+
+        database.execute(&INDEXED_USERS).await;
+        database.build_indexes("users").await;
+        database.tables.read().await["users"].indexes[0].set_failed("Disk full".to_string());
+
+        // Queries the index would answer fail rather than fall back on a scan
+        database
+            .assert_error(
+                "SELECT id FROM users WHERE name = 'ann'",
+                ErrorCode::ReadFailure,
+                "Index users_name_idx failed to build: Disk full",
+            )
+            .await;
+        assert_eq!(
+            database
+                .rows("SELECT id, seq FROM users WHERE tags CONTAINS 'x'")
+                .await,
+            vec![id_seq(1, 1)]
+        );
+    }
+
+    // Products with storage-attached indexes over price, name and tags
+    const PRODUCTS: [&str; 9] = [
+        "CREATE TABLE products (id int, seq int, name text, note text, price int, \
+         tags set<text>, PRIMARY KEY (id, seq))",
+        "INSERT INTO products (id, seq, name, price, tags) VALUES (1, 1, 'Apple', 10, {'Red'})",
+        "INSERT INTO products (id, seq, name, price) VALUES (1, 2, 'apricot', 25)",
+        "INSERT INTO products (id, seq, name, price, tags) VALUES (2, 1, 'Avocado', 40, {'green'})",
+        "INSERT INTO products (id, seq, name, price) VALUES (3, 1, 'banana', 15)",
+        "INSERT INTO products (id, seq, name, price, tags) VALUES (3, 2, 'Café', 30, {'RED'})",
+        "CREATE CUSTOM INDEX ON products (price) USING 'StorageAttachedIndex'",
+        "CREATE CUSTOM INDEX name_idx ON products (name) USING 'StorageAttachedIndex' \
+         WITH OPTIONS = {'case_sensitive': false, 'normalize': 'true', 'ascii': 'true'}",
+        "CREATE CUSTOM INDEX IF NOT EXISTS ON products (tags) USING 'sai' \
+         WITH OPTIONS = {'case_sensitive': 'false'}",
+    ];
+
+    #[tokio::test]
+    async fn query_storage_attached_indexes() {
+        let database = Fixture::new();
+
+        database.execute(&PRODUCTS).await;
+        database.build_indexes("products").await;
+
+        // Slices come back in the order of the values they restrict
+        assert_eq!(
+            database
+                .rows("SELECT id, seq FROM products WHERE price > 10 AND price <= 30")
+                .await,
+            vec![id_seq(3, 1), id_seq(1, 2), id_seq(3, 2)]
+        );
+        assert_eq!(
+            database
+                .rows("SELECT id, seq FROM products WHERE price >= 40")
+                .await,
+            vec![id_seq(2, 1)]
+        );
+        assert_eq!(
+            database
+                .rows("SELECT id, seq FROM products WHERE name LIKE 'a%'")
+                .await,
+            vec![id_seq(1, 1), id_seq(1, 2), id_seq(2, 1)]
+        );
+        assert_eq!(
+            database
+                .rows("SELECT id, seq FROM products WHERE name = 'CAFE'")
+                .await,
+            vec![id_seq(3, 2)]
+        );
+        assert_eq!(
+            database
+                .rows("SELECT id, seq FROM products WHERE tags CONTAINS 'red'")
+                .await,
+            vec![id_seq(1, 1), id_seq(3, 2)]
+        );
+        assert_eq!(
+            database
+                .pages("SELECT id, seq FROM products WHERE price >= 10", 2)
+                .await,
+            vec![
+                vec![id_seq(1, 1), id_seq(3, 1)],
+                vec![id_seq(1, 2), id_seq(3, 2)],
+                vec![id_seq(2, 1)]
+            ]
+        );
+    }
+
+    #[tokio::test]
+    async fn query_storage_attached_index_intersections() {
+        let database = Fixture::new();
+
+        database.execute(&PRODUCTS).await;
+        database.build_indexes("products").await;
+
+        assert_eq!(
+            database
+                .rows("SELECT id, seq FROM products WHERE name LIKE 'A%' AND price < 30")
+                .await,
+            vec![id_seq(1, 1), id_seq(1, 2)]
+        );
+        assert_eq!(
+            database
+                .rows("SELECT id, seq FROM products WHERE price > 10 AND tags CONTAINS 'Red'")
+                .await,
+            vec![id_seq(3, 2)]
+        );
+    }
+
+    #[tokio::test]
+    async fn query_storage_attached_index_updates() {
+        let database = Fixture::new();
+
+        database.execute(&PRODUCTS).await;
+        database.build_indexes("products").await;
+        database
+            .execute(&["UPDATE products SET price = 50, name = 'Banana' WHERE id = 3 AND seq = 1"])
+            .await;
+
+        assert_eq!(
+            database
+                .rows("SELECT id, seq FROM products WHERE price > 45")
+                .await,
+            vec![id_seq(3, 1)]
+        );
+        assert!(database
+            .rows("SELECT id, seq FROM products WHERE price > 10 AND price < 20")
+            .await
+            .is_empty());
+        assert_eq!(
+            database
+                .rows("SELECT id, seq FROM products WHERE name LIKE 'b%'")
+                .await,
+            vec![id_seq(3, 1)]
+        );
+    }
+
+    #[tokio::test]
+    async fn query_storage_attached_index_errors() {
+        let database = Fixture::new();
+
+        database.execute(&PRODUCTS).await;
+        database.build_indexes("products").await;
+
+        let invalid = [
+            "CREATE CUSTOM INDEX ON products (seq) USING 'StorageAttachedIndex'",
+            "CREATE CUSTOM INDEX ON products (note) USING 'NoSuchIndex'",
+            "CREATE INDEX ON products (note) WITH OPTIONS = {'case_sensitive': 'false'}",
+            "CREATE CUSTOM INDEX ON products (note) USING 'sai' WITH OPTIONS = {'stem': 'true'}",
+            "CREATE CUSTOM INDEX ON products (note) USING 'sai' WITH OPTIONS = {'ascii': 'yes'}",
+            "CREATE CUSTOM INDEX ON products (price) USING 'sai' WITH OPTIONS = {'normalize': 'true'}",
+            "SELECT id FROM products WHERE note LIKE 'a%'",
+            "SELECT id FROM products WHERE price LIKE 'a%'",
+            "SELECT id FROM products WHERE name LIKE '%a'",
+        ];
+
+        database.assert_invalid(&invalid).await;
+    }
+
+    // Users with a view keyed by email, created after the first rows are written
+    const USERS_BY_EMAIL: [&str; 5] = [
+        "CREATE TABLE users (org text, id int, email text, name text, tags set<text>, \
+         PRIMARY KEY (org, id))",
+        "INSERT INTO users (org, id, email, name, tags) VALUES ('acme', 1, 'a@x', 'Ann', {'x'})",
+        "INSERT INTO users (org, id, email, name) VALUES ('acme', 2, 'b@x', 'Bob')",
+        // Rows written so far are copied into the view as it's created
+        "CREATE MATERIALIZED VIEW users_by_email AS SELECT name, tags FROM users \
+         WHERE email IS NOT NULL AND org IS NOT NULL AND id IS NOT NULL \
+         PRIMARY KEY (email, org, id)",
+        "CREATE MATERIALIZED VIEW IF NOT EXISTS users_by_email AS SELECT * FROM users \
+         WHERE email IS NOT NULL AND org IS NOT NULL AND id IS NOT NULL \
+         PRIMARY KEY (email, org, id)",
+    ];
+
+    fn int(value: i32) -> Option<Value> {
+        Some(Value::Int(value))
+    }
+
+    fn text(text: &str) -> Option<Value> {
+        Some(Value::Varchar(text.to_string()))
+    }
+
+    #[tokio::test]
+    async fn query_materialized_views() {
+        let database = Fixture::new();
+
+        database.execute(&USERS_BY_EMAIL).await;
+
+        assert_eq!(
+            database
+                .rows("SELECT org, id, name, tags FROM users_by_email WHERE email = 'a@x'")
+                .await,
+            vec![vec![
+                text("acme"),
+                Some(Value::Int(1)),
+                text("Ann"),
+                Some(Value::Set(vec![Value::Varchar("x".to_string())]))
+            ]]
+        );
+    }
+
+    #[tokio::test]
+    async fn query_materialized_view_updates() {
+        let database = Fixture::new();
+        let writes = [
+            // Rows with a null view key column stay out of the view
+            "INSERT INTO users (org, id, name) VALUES ('acme', 3, 'Cy')",
+            "UPDATE users SET email = 'z@x', tags = tags + {'y'} WHERE org = 'acme' AND id = 1",
+            "DELETE name FROM users WHERE org = 'acme' AND id = 2",
+            "UPDATE users SET email = 'd@x' WHERE org = 'acme' AND id = 4",
+        ];
+
+        database.execute(&USERS_BY_EMAIL).await;
+        database.execute(&writes).await;
+
+        assert_eq!(
+            database
+                .rows("SELECT email, id, name, tags FROM users_by_email")
+                .await,
+            vec![
+                vec![text("b@x"), Some(Value::Int(2)), None, None],
+                vec![text("d@x"), Some(Value::Int(4)), None, None],
+                vec![
+                    text("z@x"),
+                    Some(Value::Int(1)),
+                    text("Ann"),
+                    Some(Value::Set(vec![
+                        Value::Varchar("x".to_string()),
+                        Value::Varchar("y".to_string())
+                    ]))
+                ],
+            ]
+        );
+
+        let writes = [
+            "UPDATE users SET email = 'c@x' WHERE org = 'acme' AND id = 3",
+            "UPDATE users SET email = null WHERE org = 'acme' AND id = 1",
+            "DELETE FROM users WHERE org = 'acme' AND id = 2",
+            "BEGIN BATCH \
+             INSERT INTO users (org, id, email, name) VALUES ('init', 1, 'e@x', 'Eve'); \
+             DELETE FROM users WHERE org = 'acme' AND id = 4; \
+             APPLY BATCH",
+        ];
+
+        database.execute(&writes).await;
+
+        assert_eq!(
+            database
+                .rows("SELECT email, org, id, name FROM users_by_email")
+                .await,
+            vec![
+                vec![text("c@x"), text("acme"), Some(Value::Int(3)), text("Cy")],
+                vec![text("e@x"), text("init"), Some(Value::Int(1)), text("Eve")],
+            ]
+        );
+    }
+
+    #[tokio::test]
+    async fn query_materialized_view_errors() {
+        let database = Fixture::new();
+        let invalid = [
+            "INSERT INTO users_by_email (email, org, id) VALUES ('f@x', 'acme', 5)",
+            "UPDATE users_by_email SET name = 'F' WHERE email = 'a@x' AND org = 'acme' AND id = 1",
+            "DELETE FROM users_by_email WHERE email = 'a@x'",
+            "CREATE INDEX ON users_by_email (name)",
+            "CREATE MATERIALIZED VIEW users_by_email AS SELECT * FROM users \
+             WHERE email IS NOT NULL AND org IS NOT NULL AND id IS NOT NULL \
+             PRIMARY KEY (email, org, id)",
+            "CREATE MATERIALIZED VIEW v AS SELECT * FROM users \
+             WHERE email IS NOT NULL AND org IS NOT NULL PRIMARY KEY (email, org, id)",
+            "CREATE MATERIALIZED VIEW v AS SELECT * FROM users \
+             WHERE email IS NOT NULL AND org IS NOT NULL PRIMARY KEY (email, org)",
+            "CREATE MATERIALIZED VIEW v AS SELECT * FROM users \
+             WHERE email IS NOT NULL AND name IS NOT NULL AND org IS NOT NULL AND id IS NOT NULL \
+             PRIMARY KEY (email, name, org, id)",
+            "CREATE MATERIALIZED VIEW v AS SELECT * FROM users \
+             WHERE email = 'a@x' AND org IS NOT NULL AND id IS NOT NULL \
+             PRIMARY KEY (email, org, id)",
+            "CREATE MATERIALIZED VIEW v AS SELECT * FROM users \
+             WHERE tags IS NOT NULL AND org IS NOT NULL AND id IS NOT NULL \
+             PRIMARY KEY (tags, org, id)",
+            "CREATE MATERIALIZED VIEW v AS SELECT email FROM users_by_email \
+             WHERE email IS NOT NULL AND org IS NOT NULL AND id IS NOT NULL \
+             PRIMARY KEY (email, org, id)",
+            "DROP MATERIALIZED VIEW users",
+            "DROP MATERIALIZED VIEW v",
+        ];
+
+        database.execute(&USERS_BY_EMAIL).await;
+        database.assert_invalid(&invalid).await;
+    }
+
+    #[tokio::test]
+    async fn query_drop_materialized_views() {
+        let database = Fixture::new();
+
+        database.execute(&USERS_BY_EMAIL).await;
+        database
+            .execute(&[
+                "DROP MATERIALIZED VIEW users_by_email",
+                "INSERT INTO users (org, id, email) VALUES ('acme', 6, 'g@x')",
+                "DROP MATERIALIZED VIEW IF EXISTS users_by_email",
+            ])
+            .await;
+
+        assert!(database.tables.read().await["users"].views.is_empty());
+        database
+            .assert_invalid(&["SELECT email FROM users_by_email"])
+            .await;
+    }
+
+    // Items with embeddings, some written before and some after the index
+    const EMBEDDINGS: [&str; 8] = [
+        "CREATE TABLE items (id int PRIMARY KEY, category text, embedding vector<float, 3>)",
+        "INSERT INTO items (id, category, embedding) VALUES (1, 'a', [1.0, 0.0, 0.0])",
+        "INSERT INTO items (id, category, embedding) VALUES (2, 'b', [0.9, 0.1, 0.0])",
+        "CREATE CUSTOM INDEX ON items (embedding) USING 'StorageAttachedIndex'",
+        "INSERT INTO items (id, category, embedding) VALUES (3, 'a', [0.0, 1.0, 0.0])",
+        "INSERT INTO items (id, category, embedding) VALUES (4, 'b', [0.0, 0.0, 1.0])",
+        "INSERT INTO items (id, category, embedding) VALUES (5, 'b', [0.5, 0.5, 0.0])",
+        "INSERT INTO items (id, category) VALUES (6, 'a')",
+    ];
+
+    fn ids(ids: &[i32]) -> Vec<Vec<Option<Value>>> {
+        ids.iter().map(|id| vec![Some(Value::Int(*id))]).collect()
+    }
+
+    #[tokio::test]
+    async fn query_vector_search() {
+        let database = Fixture::new();
+
+        database.execute(&EMBEDDINGS).await;
+        database.build_indexes("items").await;
+
+        assert_eq!(
+            database
+                .rows("SELECT embedding FROM items WHERE id = 4")
+                .await,
+            vec![vec![Some(Value::Vector(vec![
+                Value::Float(0.0),
+                Value::Float(0.0),
+                Value::Float(1.0)
+            ]))]]
+        );
+        assert_eq!(
+            database
+                .rows("SELECT id FROM items ORDER BY embedding ANN OF [1.0, 0.0, 0.0] LIMIT 3")
+                .await,
+            ids(&[1, 2, 5])
+        );
+        // Restricted rows are each scored
+        assert_eq!(
+            database
+                .rows(
+                    "SELECT id FROM items WHERE category = 'b' \
+                     ORDER BY embedding ANN OF [0.0, 0.2, 0.0] LIMIT 2 ALLOW FILTERING"
+                )
+                .await,
+            ids(&[5, 2])
+        );
+    }
+
+    #[tokio::test]
+    async fn query_vector_functions() {
+        let database = Fixture::new();
+
+        database.execute(&EMBEDDINGS).await;
+
+        assert_eq!(
+            database
+                .rows(
+                    "SELECT similarity_cosine(embedding, [1.0, 0.0, 0.0]), \
+                     similarity_dot_product([1.0, 0.0, 0.0], embedding), \
+                     similarity_euclidean(embedding, [0.0, 0.0, 0.0]) FROM items WHERE id = 3"
+                )
+                .await,
+            vec![vec![
+                Some(Value::Float(0.5)),
+                Some(Value::Float(0.5)),
+                Some(Value::Float(0.5))
+            ]]
+        );
+    }
+
+    #[tokio::test]
+    async fn query_vector_search_updates() {
+        let database = Fixture::new();
+        let writes = [
+            "UPDATE items SET embedding = [-1.0, 0.0, 0.0] WHERE id = 1",
+            "DELETE FROM items WHERE id = 2",
+        ];
+
+        database.execute(&EMBEDDINGS).await;
+        database.build_indexes("items").await;
+        database.execute(&writes).await;
+
+        // 3 and 4 are as alike, so come in key order
+        assert_eq!(
+            database
+                .pages(
+                    "SELECT id FROM items ORDER BY embedding ANN OF [1.0, 0.0, 0.0] LIMIT 3",
+                    2,
+                )
+                .await,
+            vec![ids(&[5, 3]), ids(&[4])]
+        );
+    }
+
+    #[tokio::test]
+    async fn query_vector_search_by_distance() {
+        let database = Fixture::new();
+        let statements = [
+            "CREATE TABLE points (id int PRIMARY KEY, v vector<float, 2>)",
+            "CREATE CUSTOM INDEX ON points (v) USING 'sai' \
+             WITH OPTIONS = {'similarity_function': 'EUCLIDEAN'}",
+            "INSERT INTO points (id, v) VALUES (1, [10.0, 10.0])",
+            "INSERT INTO points (id, v) VALUES (2, [1.0, 1.0])",
+            "INSERT INTO points (id, v) VALUES (3, [2.0, 2.0])",
+        ];
+
+        database.execute(&statements).await;
+        database.build_indexes("points").await;
+
+        assert_eq!(
+            database
+                .rows("SELECT id FROM points ORDER BY v ANN OF [9.0, 9.0] LIMIT 2")
+                .await,
+            ids(&[1, 3])
+        );
+    }
+
+    #[tokio::test]
+    async fn query_vector_search_errors() {
+        let database = Fixture::new();
+        let invalid = [
+            "INSERT INTO items (id, embedding) VALUES (7, [1.0, 2.0])",
+            "CREATE TABLE empty (id int PRIMARY KEY, v vector<float, 0>)",
+            "CREATE TABLE counts (id int PRIMARY KEY, v vector<counter, 2>)",
+            "SELECT id FROM items ORDER BY embedding ANN OF [1.0, 0.0, 0.0]",
+            "SELECT id FROM items ORDER BY embedding ANN OF [1.0, 0.0, 0.0] LIMIT 1001",
+            "SELECT id FROM items ORDER BY category ANN OF [1.0, 0.0, 0.0] LIMIT 1",
+            "SELECT count(*) FROM items ORDER BY embedding ANN OF [1.0, 0.0, 0.0] LIMIT 1",
+            "SELECT similarity_cosine(embedding, [0.0, 0.0, 0.0]) FROM items WHERE id = 3",
+            "SELECT similarity_cosine(category, 'a') FROM items",
+            "CREATE CUSTOM INDEX ON items (embedding) USING 'sai' \
+             WITH OPTIONS = {'similarity_function': 'manhattan'}",
+        ];
+
+        database.execute(&EMBEDDINGS).await;
+        database.build_indexes("items").await;
+        database.assert_invalid(&invalid).await;
+    }
+
+    // A schema with an object of every kind DESCRIBE covers
+    const SCHEMA: [&str; 12] = [
+        "CREATE TYPE zone (code int)",
+        "CREATE TYPE address (street text, zone frozen<zone>)",
+        "CREATE TABLE users (id int, seq int, name text, home frozen<address>, \
+         tags set<text>, region text static, PRIMARY KEY (id, seq))",
+        "CREATE TABLE notes (id int PRIMARY KEY, body text, embedding vector<float, 2>)",
+        "CREATE INDEX users_name ON users (name)",
+        "CREATE INDEX ON users (tags)",
+        "CREATE CUSTOM INDEX notes_body ON notes (body) USING 'StorageAttachedIndex' \
+         WITH OPTIONS = {'case_sensitive': 'false'}",
+        "CREATE CUSTOM INDEX notes_embedding ON notes (embedding) \
+         USING 'StorageAttachedIndex' WITH OPTIONS = {'similarity_function': 'dot_product'}",
+        "CREATE MATERIALIZED VIEW users_by_name AS SELECT id, seq, name FROM users \
+         WHERE name IS NOT NULL AND id IS NOT NULL AND seq IS NOT NULL \
+         PRIMARY KEY (name, id, seq)",
+        "CREATE FUNCTION twice (x int) RETURNS NULL ON NULL INPUT RETURNS int \
+         LANGUAGE cql AS 'x * 2'",
+        "CREATE FUNCTION accumulate (acc bigint, x int) CALLED ON NULL INPUT \
+         RETURNS bigint LANGUAGE cql AS 'acc + x'",
+        "CREATE AGGREGATE total (int) SFUNC accumulate STYPE bigint INITCOND 0",
+    ];
+
+    // The text columns of each row DESCRIBE returns
+    async fn describe(database: &Fixture, statement: &str) -> Vec<Vec<String>> {
+        database
+            .rows(statement)
+            .await
+            .into_iter()
+            .map(|row| {
+                row.into_iter()
+                    .map(|value| match value {
+                        Some(Value::Varchar(text)) => text,
+                        value => panic!("Unexpected value {:?}", value),
+                    })
+                    .collect()
+            })
+            .collect()
+    }
+
+    fn description(kind: &str, name: &str, statement: &str) -> Vec<String> {
+        vec![
+            "test_db".to_string(),
+            kind.to_string(),
+            name.to_string(),
+            statement.to_string(),
+        ]
+    }
+
+    fn listing(kind: &str, names: &[&str]) -> Vec<Vec<String>> {
+        names
+            .iter()
+            .map(|name| vec!["test_db".to_string(), kind.to_string(), name.to_string()])
+            .collect()
+    }
+
+    #[tokio::test]
+    async fn query_describe_tables() {
+        let database = Fixture::new();
+        let users = [
+            description(
+                "table",
+                "users",
+                "CREATE TABLE test_db.users (\n    id int,\n    seq int,\n    \
+                 home frozen<address>,\n    name text,\n    region text static,\n    \
+                 tags set<text>,\n    PRIMARY KEY (id, seq)\n);",
+            ),
+            description(
+                "index",
+                "users_name",
+                "CREATE INDEX users_name ON test_db.users (name);",
+            ),
+            description(
+                "index",
+                "users_tags_idx",
+                "CREATE INDEX users_tags_idx ON test_db.users (values(tags));",
+            ),
+            description(
+                "materialized_view",
+                "users_by_name",
+                "CREATE MATERIALIZED VIEW test_db.users_by_name AS\n    \
+                 SELECT name, id, seq\n    FROM test_db.users\n    \
+                 WHERE name IS NOT NULL AND id IS NOT NULL AND seq IS NOT NULL\n    \
+                 PRIMARY KEY (name, id, seq);",
+            ),
+        ];
+
+        database.execute(&SCHEMA).await;
+
+        assert_eq!(describe(&database, "DESCRIBE TABLE users").await, users);
+        assert_eq!(describe(&database, "DESC TABLE test_db.users").await, users);
+        assert_eq!(
+            describe(&database, "DESCRIBE TABLE notes").await,
+            vec![
+                description(
+                    "table",
+                    "notes",
+                    "CREATE TABLE test_db.notes (\n    id int PRIMARY KEY,\n    body text,\n    \
+                     embedding vector<float, 2>\n);",
+                ),
+                description(
+                    "index",
+                    "notes_body",
+                    "CREATE CUSTOM INDEX notes_body ON test_db.notes (body) \
+                     USING 'StorageAttachedIndex' WITH OPTIONS = {'case_sensitive': 'false'};",
+                ),
+                description(
+                    "index",
+                    "notes_embedding",
+                    "CREATE CUSTOM INDEX notes_embedding ON test_db.notes (embedding) \
+                     USING 'StorageAttachedIndex' \
+                     WITH OPTIONS = {'similarity_function': 'dot_product'};",
+                ),
+            ]
+        );
+        assert_eq!(
+            describe(&database, "DESCRIBE INDEX users_name").await,
+            vec![users[1].clone()]
+        );
+        assert_eq!(
+            describe(&database, "DESCRIBE MATERIALIZED VIEW users_by_name").await,
+            vec![users[3].clone()]
+        );
+    }
+
+    #[tokio::test]
+    async fn query_describe_types_and_functions() {
+        let database = Fixture::new();
+
+        database.execute(&SCHEMA).await;
+
+        assert_eq!(
+            describe(&database, "DESCRIBE TYPE address").await,
+            vec![description(
+                "type",
+                "address",
+                "CREATE TYPE test_db.address (\n    street text,\n    zone frozen<zone>\n);",
+            )]
+        );
+        assert_eq!(
+            describe(&database, "DESCRIBE FUNCTION twice").await,
+            vec![description(
+                "function",
+                "twice(int)",
+                "CREATE FUNCTION test_db.twice(x int)\n    RETURNS NULL ON NULL INPUT\n    \
+                 RETURNS int\n    LANGUAGE cql\n    AS $$x * 2$$;",
+            )]
+        );
+        assert_eq!(
+            describe(&database, "DESCRIBE AGGREGATE total").await,
+            vec![description(
+                "aggregate",
+                "total(int)",
+                "CREATE AGGREGATE test_db.total(int)\n    SFUNC accumulate\n    \
+                 STYPE bigint\n    INITCOND 0;",
+            )]
+        );
+    }
+
+    #[tokio::test]
+    async fn query_quoted_user_types() {
+        let database = Fixture::new();
+
+        database
+            .execute(&[
+                "CREATE TYPE \"Addr\" (street text)",
+                "CREATE TYPE addr (zip int)",
+                "CREATE TABLE homes (id int PRIMARY KEY, home frozen<\"Addr\">, \
+                 work frozen<Addr>)",
+            ])
+            .await;
+
+        assert_eq!(
+            describe(&database, "DESCRIBE TYPE \"Addr\"").await,
+            vec![description(
+                "type",
+                "Addr",
+                "CREATE TYPE test_db.\"Addr\" (\n    street text\n);",
+            )]
+        );
+        assert_eq!(
+            describe(&database, "DESCRIBE TYPE Addr").await,
+            vec![description(
+                "type",
+                "addr",
+                "CREATE TYPE test_db.addr (\n    zip int\n);",
+            )]
+        );
+        assert_eq!(
+            describe(&database, "DESCRIBE TABLE homes").await,
+            vec![description(
+                "table",
+                "homes",
+                "CREATE TABLE test_db.homes (\n    id int PRIMARY KEY,\n    \
+                 home frozen<\"Addr\">,\n    work frozen<addr>\n);",
+            )]
+        );
+
+        database.error("DROP TYPE \"Addr\"").await;
+        database.error("CREATE TYPE \"Addr\" (line text)").await;
+    }
+
+    #[tokio::test]
+    async fn query_describe_listings() {
+        let database = Fixture::new();
+
+        database.execute(&SCHEMA).await;
+
+        assert_eq!(
+            describe(&database, "DESCRIBE KEYSPACES").await,
+            listing("keyspace", &["test_db"])
+        );
+        assert_eq!(
+            describe(&database, "DESCRIBE TABLES").await,
+            listing("table", &["notes", "users"])
+        );
+        assert_eq!(
+            describe(&database, "DESCRIBE TYPES").await,
+            listing("type", &["zone", "address"])
+        );
+        assert_eq!(
+            describe(&database, "DESCRIBE FUNCTIONS").await,
+            listing("function", &["accumulate(bigint, int)", "twice(int)"])
+        );
+    }
+
+    #[tokio::test]
+    async fn query_describe_schema() {
+        let database = Fixture::new();
+
+        database.execute(&SCHEMA).await;
+
+        // The schema is given in an order it can be created in, each
+        // statement being valid CQL
+        let schema = describe(&database, "DESCRIBE SCHEMA").await;
+        let names = schema
+            .iter()
+            .map(|row| format!("{} {}", row[1], row[2]))
+            .collect::<Vec<_>>();
+
+        assert_eq!(
+            names,
+            [
+                "keyspace test_db",
+                "type zone",
+                "type address",
+                "function accumulate(bigint, int)",
+                "function twice(int)",
+                "aggregate total(int)",
+                "table notes",
+                "index notes_body",
+                "index notes_embedding",
+                "table users",
+                "index users_name",
+                "index users_tags_idx",
+                "materialized_view users_by_name",
+            ]
+        );
+        assert_eq!(describe(&database, "DESCRIBE KEYSPACE").await, schema);
+        assert_eq!(
+            describe(&database, "DESCRIBE KEYSPACE test_db").await,
+            schema
+        );
+
+        for row in &schema[1..] {
+            assert!(grammar::parse_statement(&row[3]).is_ok(), "{}", row[3]);
+        }
+    }
+
+    #[tokio::test]
+    async fn query_describe_errors() {
+        let database = Fixture::new();
+
+        database.execute(&SCHEMA).await;
+
+        for (statement, message) in [
+            (
+                "DESCRIBE TABLE missing",
+                "'missing' not found in keyspace 'test_db'",
+            ),
+            (
+                "DESCRIBE TABLE users_by_name",
+                "'users_by_name' not found in keyspace 'test_db'",
+            ),
+            ("DESCRIBE KEYSPACE other", "'other' not found in keyspaces"),
+            (
+                "DESCRIBE TABLE other.users",
+                "'other' not found in keyspaces",
+            ),
+            (
+                "DESCRIBE TYPE missing",
+                "User defined type 'missing' not found in 'test_db'",
+            ),
+            (
+                "DESCRIBE FUNCTION missing",
+                "User defined function 'missing' not found in 'test_db'",
+            ),
+            (
+                "DESCRIBE INDEX missing",
+                "Index 'missing' not found in 'test_db'",
+            ),
+        ] {
+            let error = database.error(statement).await;
+
+            assert!(matches!(error.code, ErrorCode::Invalid));
+            assert_eq!(error.message, message);
+        }
+    }
+
+    #[tokio::test]
+    async fn query_cql_syntax() {
+        let database = Fixture::new();
+
+        database
+            .execute(&[
+                "CREATE TABLE events (
+                     id int, -- the partition
+                     seq int, // the clustering
+                     /* a free-form
+                        label */ label text,
+                     PRIMARY KEY (id, seq)
+                 );",
+                "INSERT INTO events (id, seq, label) VALUES (1, 2, 'b')",
+                "INSERT INTO events (id, seq, label) VALUES (1, 1, 'a'); -- trailing comment",
+            ])
+            .await;
+
+        // * selects every column in the order of the table's definition
+        assert_eq!(
+            database.columns("SELECT * FROM events WHERE id = 1").await,
+            ["id", "seq", "label"]
+        );
+        assert_eq!(
+            database.rows("SELECT * FROM events WHERE id = 1").await,
+            vec![
+                vec![
+                    Some(Value::Int(1)),
+                    Some(Value::Int(1)),
+                    Some(Value::Varchar("a".to_string()))
+                ],
+                vec![
+                    Some(Value::Int(1)),
+                    Some(Value::Int(2)),
+                    Some(Value::Varchar("b".to_string()))
+                ],
+            ]
+        );
+    }
+
+    #[tokio::test]
+    async fn query_syntax_errors() {
+        let database = Fixture::new();
+
+        database
+            .assert_error(
+                "SELECT *\nFORM events",
+                ErrorCode::SyntaxError,
+                "line 2:0 mismatched input 'FORM' expecting FROM",
+            )
+            .await;
+        database
+            .assert_error(
+                "CREATE TABLE keyless (id int, label text)",
+                ErrorCode::Invalid,
+                "No PRIMARY KEY specified (exactly one required)",
+            )
+            .await;
+        database
+            .assert_error(
+                "CREATE TABLE keyed (id int PRIMARY KEY, seq int, PRIMARY KEY (id, seq))",
+                ErrorCode::Invalid,
+                "Multiple PRIMARY KEYs specified (exactly one required)",
+            )
+            .await;
+    }
+
+    #[tokio::test]
+    async fn query_unsupported_cql() {
+        let database = Fixture::new();
+
+        database
+            .execute(&["CREATE TABLE events (id int, seq int, label text, PRIMARY KEY (id, seq))"])
+            .await;
+
+        // CQL the grammar reads but that isn't supported
+        for (statement, message) in [
+            (
+                "INSERT INTO events (id, seq) VALUES (1, 3) USING TTL 60",
+                "USING TTL is not supported",
+            ),
+            (
+                "INSERT INTO events (id, seq) VALUES (1, 3) IF NOT EXISTS",
+                "IF NOT EXISTS is not supported",
+            ),
+            (
+                "UPDATE events USING TIMESTAMP 1 SET label = 'c' WHERE id = 1 AND seq = 1",
+                "USING TIMESTAMP is not supported",
+            ),
+            (
+                "DELETE FROM events WHERE id = 1 AND seq = 1 IF EXISTS",
+                "IF EXISTS is not supported",
+            ),
+            (
+                "SELECT * FROM events PER PARTITION LIMIT 1",
+                "PER PARTITION LIMIT is not supported",
+            ),
+            (
+                "CREATE TABLE sorted (id int, seq int, PRIMARY KEY (id, seq)) \
+                 WITH default_time_to_live = 60",
+                "default_time_to_live must be 0 as rows never expire (got 60)",
+            ),
+        ] {
+            database
+                .assert_error(statement, ErrorCode::Invalid, message)
+                .await;
+        }
+    }
+
+    const ACCOUNTS: [&str; 3] = [
+        r#"CREATE TABLE Accounts (Id int, "Name" text, "select" int, PRIMARY KEY (ID))"#,
+        r#"INSERT INTO ACCOUNTS (ID, "Name", "select") VALUES (1, 'ann', 2)"#,
+        r#"INSERT INTO accounts JSON '{"ID": 2, "\"Name\"": "bob"}'"#,
+    ];
+
+    #[tokio::test]
+    async fn query_identifiers() {
+        let database = Fixture::new();
+
+        // Unquoted names fold to lowercase, quoted ones keep their case
+        database.execute(&ACCOUNTS).await;
+
+        assert_eq!(
+            database
+                .columns(r#"SELECT iD, "Name", "select" FROM accounts WHERE id = 1"#)
+                .await,
+            ["id", "Name", "select"]
+        );
+        assert_eq!(
+            database
+                .rows(r#"SELECT iD, "Name", "select" FROM accounts WHERE id = 1"#)
+                .await,
+            vec![vec![
+                Some(Value::Int(1)),
+                Some(Value::Varchar("ann".to_string())),
+                Some(Value::Int(2))
+            ]]
+        );
+        assert_eq!(
+            database
+                .rows(r#"SELECT JSON id, "Name" FROM accounts WHERE id = 2"#)
+                .await,
+            vec![vec![Some(Value::Varchar(
+                r#"{"id": 2, "\"Name\"": "bob"}"#.to_string()
+            ))]]
+        );
+    }
+
+    #[tokio::test]
+    async fn query_quoted_identifiers() {
+        let database = Fixture::new();
+
+        database.execute(&ACCOUNTS).await;
+
+        // Names are quoted where they need to be to read back as themselves
+        assert_eq!(
+            database.rows("DESCRIBE TABLE accounts").await[0][3],
+            Some(Value::Varchar(
+                "CREATE TABLE test_db.accounts (\n    id int PRIMARY KEY,\n    \"Name\" text,\n    \
+                 \"select\" int\n);"
+                    .to_string()
+            ))
+        );
+    }
+
+    #[tokio::test]
+    async fn query_identifier_errors() {
+        let database = Fixture::new();
+
+        database.execute(&ACCOUNTS).await;
+
+        let error = database.error("SELECT name FROM accounts").await;
+
+        assert!(matches!(error.code, ErrorCode::Invalid));
+
+        database
+            .assert_error(
+                "CREATE TABLE t (select int PRIMARY KEY)",
+                ErrorCode::SyntaxError,
+                "line 1:16 mismatched input 'select' expecting an identifier",
+            )
+            .await;
+    }
+
+    const SORTED_READINGS: [&str; 4] = [
+        "CREATE TABLE readings (id int, day int, seq int, value text, \
+         PRIMARY KEY (id, day, seq)) WITH CLUSTERING ORDER BY (day ASC, seq DESC) \
+         AND comment = 'sensor readings' AND gc_grace_seconds = 3600 \
+         AND compaction = {'class': 'LeveledCompactionStrategy'} \
+         AND compression = {'enabled': false}",
+        "INSERT INTO readings (id, day, seq, value) VALUES (1, 1, 1, 'a')",
+        "INSERT INTO readings (id, day, seq, value) VALUES (1, 1, 2, 'b')",
+        "INSERT INTO readings (id, day, seq, value) VALUES (1, 2, 1, 'c')",
+    ];
+
+    const READINGS_OPTIONS: &str = "SELECT comment, gc_grace_seconds, compaction \
+                                    FROM system_schema.tables \
+                                    WHERE keyspace_name = 'test_db' AND table_name = 'readings'";
+
+    fn compaction(strategy: &str) -> Option<Value> {
+        let class = format!("org.apache.cassandra.db.compaction.{}", strategy);
+
+        Some(Value::map(
+            [
+                ("class", class.as_str()),
+                ("max_threshold", "32"),
+                ("min_threshold", "4"),
+            ]
+            .into_iter()
+            .map(|(key, value)| {
+                (
+                    Value::Varchar(key.to_string()),
+                    Value::Varchar(value.to_string()),
+                )
+            })
+            .collect(),
+        ))
+    }
+
+    #[tokio::test]
+    async fn query_clustering_order() {
+        let database = Fixture::new();
+
+        database.execute(&SORTED_READINGS).await;
+
+        // Rows come back in each clustering column's order
+        assert_eq!(
+            database
+                .rows("SELECT day, seq FROM readings WHERE id = 1")
+                .await,
+            [(1, 2), (1, 1), (2, 1)]
+                .into_iter()
+                .map(|(day, seq)| vec![Some(Value::Int(day)), Some(Value::Int(seq))])
+                .collect::<Vec<_>>()
+        );
+        assert_eq!(
+            database
+                .rows("SELECT seq FROM readings WHERE id = 1 AND day = 1 AND seq > 1")
+                .await,
+            vec![vec![Some(Value::Int(2))]]
+        );
+    }
+
+    #[tokio::test]
+    async fn query_order_by() {
+        let database = Fixture::new();
+        let day_seq = |rows: &[(i32, i32)]| {
+            rows.iter()
+                .map(|(day, seq)| vec![int(*day), int(*seq)])
+                .collect::<Vec<_>>()
+        };
+
+        database.execute(&SORTED_READINGS).await;
+
+        // Ordering as the table sorts reads forwards, the reverse backwards
+        assert_eq!(
+            database
+                .rows("SELECT day, seq FROM readings WHERE id = 1 ORDER BY day ASC, seq DESC")
+                .await,
+            day_seq(&[(1, 2), (1, 1), (2, 1)])
+        );
+        assert_eq!(
+            database
+                .rows("SELECT day, seq FROM readings WHERE id = 1 ORDER BY day DESC")
+                .await,
+            day_seq(&[(2, 1), (1, 1), (1, 2)])
+        );
+        assert_eq!(
+            database
+                .rows(
+                    "SELECT day, seq FROM readings WHERE id = 1 AND day = 1 \
+                     ORDER BY day DESC, seq ASC"
+                )
+                .await,
+            day_seq(&[(1, 1), (1, 2)])
+        );
+        assert_eq!(
+            database
+                .pages(
+                    "SELECT day, seq FROM readings WHERE id = 1 ORDER BY day DESC",
+                    2
+                )
+                .await,
+            vec![day_seq(&[(2, 1), (1, 1)]), day_seq(&[(1, 2)])]
+        );
+    }
+
+    #[tokio::test]
+    async fn query_order_by_static_columns() {
+        let database = Fixture::new();
+
+        database.execute(&DEVICE_READINGS).await;
+
+        assert_eq!(
+            database
+                .rows("SELECT at, model FROM readings WHERE device_id = 1 ORDER BY at DESC")
+                .await,
+            vec![vec![int(2), text("x1")], vec![int(1), text("x1")]]
+        );
+        assert_eq!(
+            database
+                .pages(
+                    "SELECT at, model FROM readings WHERE device_id = 1 ORDER BY at DESC",
+                    1
+                )
+                .await,
+            vec![
+                vec![vec![int(2), text("x1")]],
+                vec![vec![int(1), text("x1")]]
+            ]
+        );
+    }
+
+    #[tokio::test]
+    async fn query_order_by_errors() {
+        let database = Fixture::new();
+
+        database.execute(&SORTED_READINGS).await;
+
+        for (statement, message) in [
+            (
+                "SELECT * FROM readings WHERE id = 1 ORDER BY value",
+                "Order by is currently only supported on the clustered columns of the PRIMARY KEY, \
+                 got value",
+            ),
+            (
+                "SELECT * FROM readings WHERE id = 1 ORDER BY seq DESC",
+                "Order by currently only supports the ordering of columns following their declared \
+                 order in the PRIMARY KEY",
+            ),
+            (
+                "SELECT * FROM readings WHERE id = 1 ORDER BY day ASC, seq ASC",
+                "Unsupported order by relation",
+            ),
+            (
+                "SELECT * FROM readings ORDER BY day DESC",
+                "ORDER BY is only supported when the partition key is restricted by an EQ or an IN.",
+            ),
+            (
+                "SELECT * FROM readings WHERE id = 1 ORDER BY nope",
+                "Undefined column name nope",
+            ),
+        ] {
+            database
+                .assert_error(statement, ErrorCode::Invalid, message)
+                .await;
+        }
+    }
+
+    #[tokio::test]
+    async fn query_limit() {
+        let database = Fixture::new();
+
+        database.execute(&SORTED_READINGS).await;
+        database
+            .execute(&["INSERT INTO readings (id, day, seq, value) VALUES (2, 1, 1, 'd')"])
+            .await;
+
+        assert_eq!(
+            database
+                .rows("SELECT value FROM readings WHERE id = 1 LIMIT 2")
+                .await,
+            vec![vec![text("b")], vec![text("a")]]
+        );
+        assert_eq!(
+            database
+                .rows("SELECT value FROM readings WHERE id = 1 ORDER BY day DESC LIMIT 1")
+                .await,
+            vec![vec![text("c")]]
+        );
+        assert_eq!(
+            database
+                .rows("SELECT count(*) FROM readings GROUP BY id LIMIT 1")
+                .await,
+            vec![vec![Some(Value::Bigint(3))]]
+        );
+        // The limit holds across pages
+        assert_eq!(
+            database
+                .pages("SELECT value FROM readings LIMIT 3", 2)
+                .await
+                .into_iter()
+                .map(|page| page.len())
+                .collect::<Vec<_>>(),
+            [2, 1]
+        );
+
+        let results = database
+            .query(bound_query(
+                "SELECT value FROM readings WHERE id = 1 LIMIT ?",
+                Some(Values::Unnamed(vec![bound(&[0, 0, 0, 1])])),
+            ))
+            .await
+            .unwrap();
+
+        assert_eq!(
+            results.result.collect::<Result<Vec<_>, _>>().unwrap(),
+            vec![vec![text("b")]]
+        );
+        database
+            .assert_error(
+                "SELECT value FROM readings LIMIT 0",
+                ErrorCode::Invalid,
+                "LIMIT must be strictly positive",
+            )
+            .await;
+    }
+
+    #[tokio::test]
+    async fn query_allow_filtering() {
+        let database = Fixture::new();
+
+        database.execute(&INDEXED_USERS).await;
+        database.build_indexes("users").await;
+
+        // Key and indexed columns are restricted without filtering
+        assert_eq!(
+            database
+                .rows("SELECT id, seq FROM users WHERE id = 1 AND seq > 1")
+                .await,
+            vec![id_seq(1, 2)]
+        );
+        assert_eq!(
+            database
+                .rows("SELECT id, seq FROM users WHERE id = 1 AND name = 'ann'")
+                .await,
+            vec![id_seq(1, 1), id_seq(1, 2)]
+        );
+        database
+            .assert_error(
+                "SELECT id FROM users WHERE team = 'red'",
+                ErrorCode::Invalid,
+                "Cannot execute this query as it might involve data filtering and thus may have \
+                 unpredictable performance. If you want to execute this query despite the \
+                 performance unpredictability, use ALLOW FILTERING",
+            )
+            .await;
+        assert_eq!(
+            database
+                .rows("SELECT id, seq FROM users WHERE team = 'red' ALLOW FILTERING")
+                .await,
+            vec![id_seq(1, 1), id_seq(1, 2)]
+        );
+    }
+
+    #[tokio::test]
+    async fn query_table_options() {
+        let database = Fixture::new();
+
+        database.execute(&SORTED_READINGS).await;
+
+        assert_eq!(
+            database.rows(READINGS_OPTIONS).await,
+            vec![vec![
+                Some(Value::Varchar("sensor readings".to_string())),
+                Some(Value::Int(3600)),
+                compaction("LeveledCompactionStrategy"),
+            ]]
+        );
+    }
+
+    #[tokio::test]
+    async fn query_alter_table_options() {
+        let database = Fixture::new();
+
+        database.execute(&SORTED_READINGS).await;
+        database
+            .execute(&[
+                "ALTER TABLE readings WITH comment = 'hourly readings' AND gc_grace_seconds = 0",
+            ])
+            .await;
+
+        assert_eq!(
+            database.rows(READINGS_OPTIONS).await,
+            vec![vec![
+                Some(Value::Varchar("hourly readings".to_string())),
+                Some(Value::Int(0)),
+                compaction("LeveledCompactionStrategy"),
+            ]]
+        );
+        assert_eq!(
+            database.rows("DESCRIBE TABLE readings").await[0][3],
+            Some(Value::Varchar(
+                "CREATE TABLE test_db.readings (\n    id int,\n    day int,\n    \
+                 seq int,\n    value text,\n    PRIMARY KEY (id, day, seq)\n) \
+                 WITH CLUSTERING ORDER BY (day ASC, seq DESC)\n    \
+                 AND comment = 'hourly readings'\n    \
+                 AND compaction = {'class': \
+                 'org.apache.cassandra.db.compaction.LeveledCompactionStrategy', \
+                 'max_threshold': '32', 'min_threshold': '4'}\n    \
+                 AND compression = {'enabled': 'false'}\n    \
+                 AND gc_grace_seconds = 0;"
+                    .to_string()
+            ))
+        );
+    }
+
+    #[tokio::test]
+    async fn query_alter_table_storage_options() {
+        let database = Fixture::new();
+
+        database.execute(&SORTED_READINGS).await;
+        database
+            .execute(&[
+                "ALTER TABLE readings WITH compaction = {'class': 'SizeTieredCompactionStrategy'} \
+                 AND bloom_filter_fp_chance = 0.1",
+                "INSERT INTO readings (id, day, seq, value) VALUES (2, 1, 1, 'd')",
+                "ALTER TABLE readings WITH comment = 'hourly readings'",
+            ])
+            .await;
+
+        // The cells moved to a partition created with the new options
+        let partition = database
+            .fjall
+            .open_partition("readings#1", Default::default())
+            .unwrap();
+
+        assert!(matches!(
+            partition.config.compaction_strategy,
+            Strategy::SizeTiered(_)
+        ));
+        assert!(!database.fjall.partition_exists("readings"));
+        assert_eq!(
+            database.rows("SELECT value FROM readings").await,
+            vec![
+                vec![text("b")],
+                vec![text("a")],
+                vec![text("c")],
+                vec![text("d")]
+            ]
+        );
+        assert_eq!(
+            database.rows(READINGS_OPTIONS).await,
+            vec![vec![
+                Some(Value::Varchar("hourly readings".to_string())),
+                Some(Value::Int(3600)),
+                compaction("SizeTieredCompactionStrategy"),
+            ]]
+        );
+        assert_eq!(
+            database.rows("DESCRIBE TABLE readings").await[0][3],
+            Some(Value::Varchar(
+                "CREATE TABLE test_db.readings (\n    id int,\n    day int,\n    \
+                 seq int,\n    value text,\n    PRIMARY KEY (id, day, seq)\n) \
+                 WITH CLUSTERING ORDER BY (day ASC, seq DESC)\n    \
+                 AND bloom_filter_fp_chance = 0.1\n    \
+                 AND comment = 'hourly readings'\n    \
+                 AND compression = {'enabled': 'false'}\n    \
+                 AND gc_grace_seconds = 3600;"
+                    .to_string()
+            ))
+        );
+    }
+
+    #[tokio::test]
+    async fn query_default_time_to_live() {
+        let database = Fixture::new();
+
+        database
+            .execute(&[
+                "CREATE TABLE events (id int PRIMARY KEY) WITH default_time_to_live = 0",
+                "ALTER TABLE events WITH default_time_to_live = 0",
+            ])
+            .await;
+
+        assert_eq!(
+            database
+                .rows(
+                    "SELECT default_time_to_live FROM system_schema.tables \
+                     WHERE keyspace_name = 'test_db' AND table_name = 'events'"
+                )
+                .await,
+            vec![vec![int(0)]]
+        );
+        database
+            .assert_error(
+                "ALTER TABLE events WITH default_time_to_live = 3600",
+                ErrorCode::Invalid,
+                "default_time_to_live must be 0 as rows never expire (got 3600)",
+            )
+            .await;
+    }
+
+    #[tokio::test]
+    async fn query_table_option_errors() {
+        let database = Fixture::new();
+
+        database.execute(&SORTED_READINGS).await;
+
+        for (statement, code, message) in [
+            (
+                "CREATE TABLE t (id int PRIMARY KEY) WITH bloom_filter_fp_chance = 2",
+                ErrorCode::ConfigError,
+                "bloom_filter_fp_chance must be larger than 0.0000672 and less than or equal \
+                 to 1.0 (got 2)",
+            ),
+            (
+                "CREATE TABLE t (id int PRIMARY KEY) WITH colour = 'red'",
+                ErrorCode::SyntaxError,
+                "Unknown property 'colour'",
+            ),
+            (
+                "CREATE TABLE t (id int PRIMARY KEY) WITH comment = 'a' AND comment = 'b'",
+                ErrorCode::SyntaxError,
+                "Multiple definitions for property 'comment'",
+            ),
+            (
+                "CREATE TABLE t (id int, seq int, PRIMARY KEY (id, seq)) \
+                 WITH CLUSTERING ORDER BY (id DESC)",
+                ErrorCode::Invalid,
+                "Only clustering key columns can be defined in CLUSTERING ORDER directive",
+            ),
+            (
+                "CREATE TABLE t (id int PRIMARY KEY) \
+                 WITH compression = {'class': 'LZ4Compressor', 'chunk_length_in_kb': 3}",
+                ErrorCode::ConfigError,
+                "chunk_length_in_kb must be a power of 2",
+            ),
+            (
+                "ALTER TABLE readings WITH CLUSTERING ORDER BY (day DESC, seq DESC)",
+                ErrorCode::Invalid,
+                "Only table properties can be altered, not CLUSTERING ORDER BY (day DESC, seq DESC)",
+            ),
+            (
+                "ALTER TABLE readings WITH gc_grace_seconds = -1",
+                ErrorCode::ConfigError,
+                "gc_grace_seconds must be greater than or equal to 0 (got -1)",
+            ),
+        ] {
+            database.assert_error(statement, code, message).await;
+        }
+    }
+
+    #[tokio::test]
+    async fn query_system_schema_errors() {
+        let database = Fixture::new();
+
+        database.execute(&SORTED_READINGS).await;
+
+        for statement in [
+            "INSERT INTO system_schema.tables (keyspace_name, table_name) VALUES ('a', 'b')",
+            "ALTER TABLE system_schema.tables WITH comment = 'mine'",
+        ] {
+            database
+                .assert_error(
+                    statement,
+                    ErrorCode::Unauthorized,
+                    "system_schema keyspace is not user-modifiable.",
+                )
+                .await;
+        }
     }
 }
@@ -0,0 +1,331 @@
+use crate::db::ast::{Expr, Ident, Literal, TableOption};
+use crate::db::error::{DbError, ErrorCode};
+use crate::db::schema::{TableOptions, COMPACTION_PACKAGE, COMPRESSION_PACKAGE};
+use std::collections::BTreeMap;
+
+// About the lowest chance the 20 bits per key fjall allows at most can give
+const MIN_BLOOM_FILTER_FP_CHANCE: f64 = 6.72e-5;
+
+const COMPACTION_STRATEGIES: [&str; 4] = [
+    "SizeTieredCompactionStrategy",
+    "LeveledCompactionStrategy",
+    "TimeWindowCompactionStrategy",
+    "UnifiedCompactionStrategy",
+];
+
+const COMPRESSORS: [&str; 5] = [
+    "LZ4Compressor",
+    "SnappyCompressor",
+    "DeflateCompressor",
+    "ZstdCompressor",
+    "NoopCompressor",
+];
+
+const COMPRESSION_OPTIONS: [&str; 5] = [
+    "class",
+    "chunk_length_in_kb",
+    "enabled",
+    "compression_level",
+    "min_compress_ratio",
+];
+
+// Sets the options of CREATE TABLE or ALTER TABLE ... WITH over a table's
+// current ones. The clustering order and storage are only given on creation.
+pub fn apply_table_options(
+    table_options: &mut TableOptions,
+    options: &[TableOption],
+    cluster_key: &[String],
+    creating: bool,
+) -> Result<(), DbError> {
+    let mut properties = vec![];
+
+    for option in options {
+        match option {
+            TableOption::ClusteringOrder(order) if creating => {
+                table_options.descending = clustering_order(order, cluster_key)?;
+            }
+            TableOption::CompactStorage if creating => {
+                return Err(DbError::new(
+                    ErrorCode::Invalid,
+                    "COMPACT STORAGE tables are not allowed starting with version 4.0".to_string(),
+                ))
+            }
+            TableOption::Property(name, value) => {
+                if properties.contains(&&name.value) {
+                    return Err(syntax_error(format!(
+                        "Multiple definitions for property '{}'",
+                        name.value
+                    )));
+                }
+
+                properties.push(&name.value);
+                apply_property(table_options, &name.value, value)?;
+            }
+            option => {
+                return Err(DbError::new(
+                    ErrorCode::Invalid,
+                    format!("Only table properties can be altered, not {}", option),
+                ))
+            }
+        }
+    }
+
+    Ok(())
+}
+
+// The clustering columns in descending order, every clustering column being
+// given in order
+fn clustering_order(
+    order: &[(Ident, bool)],
+    cluster_key: &[String],
+) -> Result<Vec<String>, DbError> {
+    let invalid = |message: &str| Err(DbError::new(ErrorCode::Invalid, message.to_string()));
+
+    if order
+        .iter()
+        .any(|(column, _)| !cluster_key.contains(&column.value))
+    {
+        return invalid("Only clustering key columns can be defined in CLUSTERING ORDER directive");
+    }
+
+    if !order
+        .iter()
+        .map(|(column, _)| &column.value)
+        .eq(cluster_key.iter())
+    {
+        return invalid(
+            "Clustering key columns must exactly match columns in CLUSTERING ORDER BY directive",
+        );
+    }
+
+    Ok(order
+        .iter()
+        .filter(|(_, descending)| *descending)
+        .map(|(column, _)| column.value.clone())
+        .collect())
+}
+
+fn apply_property(options: &mut TableOptions, name: &str, value: &Expr) -> Result<(), DbError> {
+    match name {
+        "bloom_filter_fp_chance" => {
+            let chance = double(name, value)?;
+
+            if chance <= MIN_BLOOM_FILTER_FP_CHANCE || chance > 1.0 {
+                return Err(config_error(format!(
+                    "{} must be larger than {} and less than or equal to 1.0 (got {})",
+                    name, MIN_BLOOM_FILTER_FP_CHANCE, chance
+                )));
+            }
+
+            options.bloom_filter_fp_chance = chance;
+        }
+        "caching" => options.caching = caching(map(name, value)?)?,
+        "comment" => options.comment = text(name, value)?,
+        "compaction" => options.compaction = compaction(map(name, value)?)?,
+        "compression" => options.compression = compression(map(name, value)?)?,
+        "default_time_to_live" => {
+            // Nothing expires, as with USING TTL, so only 0 can be honoured
+            match non_negative(name, value)? {
+                0 => options.default_time_to_live = 0,
+                seconds => {
+                    return Err(DbError::new(
+                        ErrorCode::Invalid,
+                        format!(
+                            "default_time_to_live must be 0 as rows never expire (got {})",
+                            seconds
+                        ),
+                    ))
+                }
+            }
+        }
+        "gc_grace_seconds" => options.gc_grace_seconds = non_negative(name, value)?,
+        _ => return Err(syntax_error(format!("Unknown property '{}'", name))),
+    }
+
+    Ok(())
+}
+
+fn caching(map: BTreeMap<String, String>) -> Result<BTreeMap<String, String>, DbError> {
+    let mut caching = TableOptions::default().caching;
+
+    for (key, value) in map {
+        let upper = value.to_uppercase();
+        let valid = match key.as_str() {
+            "keys" => upper == "ALL" || upper == "NONE",
+            "rows_per_partition" => {
+                upper == "ALL" || upper == "NONE" || value.parse::<u32>().is_ok_and(|rows| rows > 0)
+            }
+            _ => {
+                return Err(config_error(format!(
+                "Invalid caching sub-option '{}': only 'keys' and 'rows_per_partition' are allowed",
+                key
+            )))
+            }
+        };
+
+        if !valid {
+            return Err(config_error(format!(
+                "Invalid value '{}' for caching sub-option '{}': only {} are supported",
+                value,
+                key,
+                match key.as_str() {
+                    "keys" => "'ALL' and 'NONE'",
+                    _ => "'ALL', 'NONE' or a positive integer",
+                }
+            )));
+        }
+
+        caching.insert(key, upper);
+    }
+
+    Ok(caching)
+}
+
+// The strategy's class is given in full, with the thresholds every strategy has
+fn compaction(mut map: BTreeMap<String, String>) -> Result<BTreeMap<String, String>, DbError> {
+    let Some(class) = map.get("class") else {
+        return Err(config_error(
+            "Missing sub-option 'class' for the 'compaction' option.".to_string(),
+        ));
+    };
+    let strategy = class.trim_start_matches(COMPACTION_PACKAGE);
+
+    if !COMPACTION_STRATEGIES.contains(&strategy) {
+        return Err(config_error(format!(
+            "Unable to find compaction strategy class '{}'",
+            class
+        )));
+    }
+
+    map.insert(
+        "class".to_string(),
+        format!("{}{}", COMPACTION_PACKAGE, strategy),
+    );
+    map.entry("max_threshold".to_string())
+        .or_insert_with(|| "32".to_string());
+    map.entry("min_threshold".to_string())
+        .or_insert_with(|| "4".to_string());
+
+    Ok(map)
+}
+
+// Compression is either disabled or by a compressor class given in full, in
+// chunks of a power of two kilobytes
+fn compression(mut map: BTreeMap<String, String>) -> Result<BTreeMap<String, String>, DbError> {
+    if let Some(key) = map
+        .keys()
+        .find(|key| !COMPRESSION_OPTIONS.contains(&key.as_str()))
+    {
+        return Err(config_error(format!("Unknown compression options {}", key)));
+    }
+
+    match map.remove("enabled").as_deref() {
+        None | Some("true") => {}
+        Some("false") => {
+            return Ok(BTreeMap::from([(
+                "enabled".to_string(),
+                "false".to_string(),
+            )]))
+        }
+        Some(enabled) => {
+            return Err(config_error(format!(
+                "Invalid value '{}' for compression sub-option 'enabled'",
+                enabled
+            )))
+        }
+    }
+
+    let Some(class) = map.get("class") else {
+        return Err(config_error(
+            "Missing sub-option 'class' for the 'compression' option.".to_string(),
+        ));
+    };
+    let compressor = class.trim_start_matches(COMPRESSION_PACKAGE);
+
+    if !COMPRESSORS.contains(&compressor) {
+        return Err(config_error(format!(
+            "Could not create Compression for type {}{}",
+            COMPRESSION_PACKAGE, compressor
+        )));
+    }
+
+    map.insert(
+        "class".to_string(),
+        format!("{}{}", COMPRESSION_PACKAGE, compressor),
+    );
+
+    let chunk_length = map
+        .entry("chunk_length_in_kb".to_string())
+        .or_insert_with(|| "16".to_string());
+
+    if !chunk_length
+        .parse::<u32>()
+        .is_ok_and(|chunk_length| chunk_length.is_power_of_two())
+    {
+        return Err(config_error(
+            "chunk_length_in_kb must be a power of 2".to_string(),
+        ));
+    }
+
+    Ok(map)
+}
+
+fn text(name: &str, value: &Expr) -> Result<String, DbError> {
+    match value {
+        Expr::Literal(Literal::String(text) | Literal::Number(text)) => Ok(text.clone()),
+        Expr::Literal(Literal::Boolean(boolean)) => Ok(boolean.to_string()),
+        Expr::Negate(number) => match number.as_ref() {
+            Expr::Literal(Literal::Number(number)) => Ok(format!("-{}", number)),
+            _ => text(name, number),
+        },
+        _ => Err(syntax_error(format!(
+            "Invalid value for property '{}'. It should be a string",
+            name
+        ))),
+    }
+}
+
+fn map(name: &str, value: &Expr) -> Result<BTreeMap<String, String>, DbError> {
+    let Expr::Map(entries) = value else {
+        return Err(syntax_error(format!(
+            "Invalid value for property '{}'. It should be a map.",
+            name
+        )));
+    };
+
+    entries
+        .iter()
+        .map(|(key, value)| Ok((text(name, key)?, text(name, value)?)))
+        .collect()
+}
+
+fn double(name: &str, value: &Expr) -> Result<f64, DbError> {
+    let text = text(name, value)?;
+
+    text.parse()
+        .map_err(|_| syntax_error(format!("Invalid double value {} for '{}'", text, name)))
+}
+
+fn non_negative(name: &str, value: &Expr) -> Result<i32, DbError> {
+    let text = text(name, value)?;
+    let integer = text
+        .parse::<i32>()
+        .map_err(|_| syntax_error(format!("Invalid integer value {} for '{}'", text, name)))?;
+
+    if integer < 0 {
+        return Err(config_error(format!(
+            "{} must be greater than or equal to 0 (got {})",
+            name, integer
+        )));
+    }
+
+    Ok(integer)
+}
+
+fn syntax_error(message: String) -> DbError {
+    DbError::new(ErrorCode::SyntaxError, message)
+}
+
+fn config_error(message: String) -> DbError {
+    DbError::new(ErrorCode::ConfigError, message)
+}
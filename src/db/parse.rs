@@ -1,387 +1,3633 @@
-use crate::db::builtins::{eq, neq, FilterFunction};
+use crate::cql::request::query::Values;
+use crate::cql::value::deserialize;
+use crate::db::aggregate::{AggregateFunction, UserAggregate};
+use crate::db::ast::{
+    comma_separated, AggregateDefinition, Batch, BatchKind, Conditions, CreateTable, DescribeKind,
+    ElementAssignment, Expr, FieldChange, Function, FunctionDefinition, Ident, IndexDefinition,
+    Insert, InsertValues, Literal, Mutation, Name, Operator, Select, Selection, Statement,
+    TableOption, TypeAlteration, TypeDefinition, UsingOption, ViewDefinition,
+};
+use crate::db::builtins::{
+    analyze_function, contains, contains_key, entry_eq, eq, from_json_function, gt, gte, like, lt,
+    lte, neq, signature, similarity_function, to_json_function, FilterFunction, FunctionCatalog,
+    ScalarFunction, OPERATORS,
+};
 use crate::db::data::{ColumnType, Value};
-use crate::db::dialect::CassandraDialect;
 use crate::db::error::{DbError, ErrorCode};
-use crate::db::schema::{ColumnMetadata, Kind, TableMetadata, Tables};
-use anyhow::anyhow;
-use indexmap::IndexMap;
-use sqlparser::ast::{
-    BinaryOperator, ColumnOption, CreateTable, Expr, Query, Select, SelectItem, SetExpr, Statement,
-    TableFactor,
+use crate::db::grammar::{parse_statement, parse_term};
+use crate::db::json::{from_json, Json};
+use crate::db::options::apply_table_options;
+use crate::db::schema::{
+    ColumnMetadata, IndexKind, IndexMetadata, IndexTarget, Kind, TableMetadata, TableOptions,
+    Tables, TextAnalysis, Types, UserFunction, UserType,
 };
-use sqlparser::parser::Parser;
+use crate::db::system::{system_table, SYSTEM_SCHEMA};
+use crate::db::vector::Similarity;
+use crate::serde::reader::Value as BoundValue;
+use indexmap::IndexMap;
 use std::collections::HashMap;
-use std::ops::Deref;
+use std::ops::Bound;
 use std::sync::Arc;
 use tokio::sync::RwLock;
 
-pub enum ParsedStatement {
-    Select(ParsedQuery),
-    Create(TableMetadata),
-    Insert(ParsedInsert),
-}
+pub enum ParsedStatement {
+    Select(Box<ParsedQuery>),
+    Create(TableMetadata),
+    AlterTable {
+        table: String,
+        options: TableOptions,
+    },
+    Insert(ParsedInsert),
+    InsertJson(Box<ParsedInsertJson>),
+    Update(ParsedUpdate),
+    Delete(ParsedDelete),
+    CreateType {
+        user_type: UserType,
+        if_not_exists: bool,
+    },
+    AlterType(UserType),
+    DropType {
+        name: String,
+        if_exists: bool,
+    },
+    CreateFunction {
+        function: UserFunction,
+        body: ParsedExpr,
+        or_replace: bool,
+        if_not_exists: bool,
+    },
+    CreateAggregate {
+        aggregate: UserAggregate,
+        or_replace: bool,
+        if_not_exists: bool,
+    },
+    CreateIndex {
+        table: String,
+        index: IndexMetadata,
+        // Numbered when taken, where a given name must be free
+        default_name: bool,
+        if_not_exists: bool,
+    },
+    CreateView {
+        view: TableMetadata,
+        if_not_exists: bool,
+    },
+    DropView {
+        name: String,
+        if_exists: bool,
+    },
+    DropIndex {
+        name: String,
+        if_exists: bool,
+    },
+    // The keyspace is set when the object described was qualified by one
+    Describe {
+        kind: DescribeKind,
+        keyspace: Option<String>,
+        name: Option<String>,
+    },
+    Batch(Vec<ParsedStatement>),
+}
+
+#[derive(Debug, Clone)]
+pub struct ParsedQuery {
+    pub partition_key: Vec<ParsedExpr>,
+    pub clustering_key: Vec<ParsedExpr>,
+    pub projection: Vec<ParsedExpr>,
+    pub filters: Vec<ParsedFilter>,
+    // The primary key prefix rows are grouped by, empty when not grouped
+    pub group_by: Vec<String>,
+    // SELECT DISTINCT reads a single row per partition
+    pub distinct: bool,
+    // SELECT JSON returns each row as a single JSON object
+    pub json: bool,
+    // The restrictions indexes answer when the rows are found through them
+    // rather than scanned, the rows found being those all of them hold
+    pub index: Vec<IndexRestriction>,
+    // ORDER BY v ANN OF [...] LIMIT k, for the rows with the vectors most
+    // like the one given
+    pub ann: Option<AnnOrdering>,
+    // LIMIT n, bound to an int. ANN ordering takes it as its own.
+    pub limit: Option<ParsedExpr>,
+    // ORDER BY against the clustering order, reading the partition backwards
+    pub reversed: bool,
+    pub table: TableMetadata,
+    pub column_count: i32,
+}
+
+#[derive(Debug, Clone)]
+pub struct AnnOrdering {
+    pub column: String,
+    pub similarity: Similarity,
+    pub vector: ParsedExpr,
+    pub limit: usize,
+    // The vector index to search, when it's built and nothing else restricts
+    // the rows. Otherwise every row selected is scored.
+    pub index: Option<IndexMetadata>,
+}
+
+// The index entries a restriction such as c = v or c CONTAINS v looks up, the
+// restriction itself still being checked against the rows found
+#[derive(Debug, Clone)]
+pub struct IndexRestriction {
+    pub index: IndexMetadata,
+    pub lookup: IndexLookup,
+}
+
+#[derive(Debug, Clone)]
+pub enum IndexLookup {
+    // The entries of a term, for c = v, c CONTAINS v, m CONTAINS KEY k and m[k] = v
+    Term(Vec<ParsedExpr>),
+    // The entries of the terms between two bounds, for c > v, c <= v and the like
+    Range(Bound<ParsedExpr>, Bound<ParsedExpr>),
+    // The entries of the text terms starting like a pattern, for c LIKE 'p%'
+    Prefix(ParsedExpr),
+}
+
+impl IndexRestriction {
+    fn exprs_mut(&mut self) -> Vec<&mut ParsedExpr> {
+        match &mut self.lookup {
+            IndexLookup::Term(terms) => terms.iter_mut().collect(),
+            IndexLookup::Range(lower, upper) => [lower, upper]
+                .into_iter()
+                .filter_map(|bound| match bound {
+                    Bound::Included(expr) | Bound::Excluded(expr) => Some(expr),
+                    Bound::Unbounded => None,
+                })
+                .collect(),
+            IndexLookup::Prefix(pattern) => vec![pattern],
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct ParsedFilter {
+    pub filter: FilterFunction,
+    pub args: Vec<ParsedExpr>,
+}
+
+#[derive(Debug, Clone)]
+pub struct ParsedInsert {
+    pub table: TableMetadata,
+    pub partition_key: Vec<ParsedExpr>,
+    pub clustering_key: Vec<ParsedExpr>,
+    pub columns: Vec<String>,
+    pub values: Vec<ParsedExpr>,
+}
+
+// The document may be a bind marker, so is only read into columns once bound
+#[derive(Debug, Clone)]
+pub struct ParsedInsertJson {
+    pub table: TableMetadata,
+    pub json: ParsedExpr,
+    pub default_null: bool,
+}
+
+#[derive(Debug, Clone)]
+pub struct ParsedUpdate {
+    pub table: TableMetadata,
+    pub partition_key: Vec<ParsedExpr>,
+    pub clustering_key: Vec<ParsedExpr>,
+    pub operations: Vec<ParsedOperation>,
+}
+
+// A DELETE without operations removes the whole row, or the whole partition
+// when only the partition key is given
+#[derive(Debug, Clone)]
+pub struct ParsedDelete {
+    pub table: TableMetadata,
+    pub partition_key: Vec<ParsedExpr>,
+    pub clustering_key: Vec<ParsedExpr>,
+    pub operations: Vec<ParsedOperation>,
+}
+
+#[derive(Debug, Clone)]
+pub struct ParsedOperation {
+    pub column: ColumnMetadata,
+    pub operation: Operation,
+}
+
+#[derive(Debug, Clone)]
+pub enum Operation {
+    // c = v
+    Set(ParsedExpr),
+    // c = c + v
+    Append(ParsedExpr),
+    // l = v + l
+    Prepend(ParsedExpr),
+    // c = c - v
+    Remove(ParsedExpr),
+    // l[i] = v, m[k] = v, where a null value deletes the element
+    SetElement(ParsedExpr, ParsedExpr),
+    // u.f = v, by the field's position in the user type
+    SetField(usize, ParsedExpr),
+    // c = c + n
+    Increment(ParsedExpr),
+    // c = c - n
+    Decrement(ParsedExpr),
+}
+
+#[derive(Debug, Clone)]
+pub enum ParsedExpr {
+    Column(ProjectedColumn),
+    Field(Box<ParsedExpr>, ProjectedField),
+    Function(ParsedFunction),
+    Literal(Option<Value>),
+    // Evaluated once over all the selected rows rather than for each of them
+    Aggregate(ParsedAggregate),
+    // A `?` or `:name` bind marker, typed by where it appears
+    Marker(String, ColumnType),
+    // A collection literal holding bind markers, built once they're bound. A
+    // map's elements are its keys and values in turn.
+    Collection(ColumnType, Vec<ParsedExpr>),
+}
+
+impl ParsedExpr {
+    pub fn column_spec(&self) -> (String, ColumnType) {
+        match self {
+            ParsedExpr::Column(column) => (
+                column.resolved_name.clone(),
+                column.column_metadata.column_type.clone(),
+            ),
+            ParsedExpr::Field(_, field) => (field.resolved_name.clone(), field.field_type.clone()),
+            ParsedExpr::Function(function) => {
+                (function.resolved_name.clone(), function.return_type.clone())
+            }
+            ParsedExpr::Literal(_) => ("literal".to_string(), ColumnType::Blob),
+            ParsedExpr::Aggregate(aggregate) => (
+                aggregate.resolved_name.clone(),
+                aggregate.return_type.clone(),
+            ),
+            ParsedExpr::Marker(name, column_type) => (name.clone(), column_type.clone()),
+            ParsedExpr::Collection(column_type, _) => ("literal".to_string(), column_type.clone()),
+        }
+    }
+
+    // The columns the expression reads
+    fn columns(&self) -> Vec<&str> {
+        match self {
+            ParsedExpr::Column(column) => vec![column.target_column.as_str()],
+            ParsedExpr::Field(expr, _) => expr.columns(),
+            ParsedExpr::Function(function) => function
+                .arguments
+                .iter()
+                .flat_map(ParsedExpr::columns)
+                .collect(),
+            ParsedExpr::Aggregate(aggregate) => aggregate.argument.columns(),
+            ParsedExpr::Collection(_, elements) => {
+                elements.iter().flat_map(ParsedExpr::columns).collect()
+            }
+            ParsedExpr::Literal(_) | ParsedExpr::Marker(..) => vec![],
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct ProjectedColumn {
+    pub target_column: String,
+    pub resolved_name: String,
+    pub column_metadata: ColumnMetadata,
+}
+
+// A call to a scalar function of the catalog
+#[derive(Debug, Clone)]
+pub struct ParsedFunction {
+    pub function: Arc<ScalarFunction>,
+    pub arguments: Vec<ParsedExpr>,
+    pub resolved_name: String,
+    pub return_type: ColumnType,
+}
+
+#[derive(Debug, Clone)]
+pub struct ParsedAggregate {
+    pub function: AggregateFunction,
+    pub argument: Box<ParsedExpr>,
+    pub resolved_name: String,
+    pub return_type: ColumnType,
+}
+
+#[derive(Debug, Clone)]
+pub struct ProjectedField {
+    pub resolved_name: String,
+    pub position: usize,
+    pub field_type: ColumnType,
+}
+
+// Parse SQL query
+pub async fn parse<'a>(
+    sql: String,
+    table_metadata: &Arc<RwLock<Tables>>,
+    types: &Arc<RwLock<Types>>,
+    functions: &Arc<RwLock<FunctionCatalog>>,
+) -> Result<ParsedStatement, DbError> {
+    let statement = parse_statement(&sql)?;
+    let functions = &*functions.read().await;
+
+    match statement {
+        Statement::CreateTable(create_table) => {
+            parse_create_table(&create_table, &*types.read().await)
+        }
+        Statement::AlterTable(name, options) => {
+            parse_alter_table(table_metadata, name, options).await
+        }
+        Statement::Select(select) => parse_select(table_metadata, &select, functions).await,
+        Statement::Insert(insert) => parse_insert(table_metadata, insert, functions).await,
+        Statement::Update(mutation) => parse_update(table_metadata, mutation, functions).await,
+        Statement::Delete(mutation) => parse_delete(table_metadata, mutation, functions).await,
+        Statement::CreateType(definition) => parse_create_type(definition, &*types.read().await),
+        Statement::AlterType(alteration) => parse_alter_type(alteration, &*types.read().await),
+        Statement::DropType(name, if_exists) => Ok(ParsedStatement::DropType {
+            name: type_name(&name),
+            if_exists,
+        }),
+        Statement::CreateFunction(definition) => {
+            parse_create_function(definition, &*types.read().await, functions)
+        }
+        Statement::CreateAggregate(definition) => {
+            parse_create_aggregate(definition, &*types.read().await, functions)
+        }
+        Statement::CreateIndex(definition) => parse_create_index(table_metadata, definition).await,
+        Statement::DropIndex(name, if_exists) => Ok(ParsedStatement::DropIndex {
+            name: type_name(&name),
+            if_exists,
+        }),
+        Statement::CreateView(definition) => parse_create_view(table_metadata, definition).await,
+        Statement::DropView(name, if_exists) => Ok(ParsedStatement::DropView {
+            name: table_name(&name),
+            if_exists,
+        }),
+        Statement::Describe(kind, name) => Ok(parse_describe(kind, name)),
+        Statement::Batch(batch) => parse_batch(table_metadata, batch, functions).await,
+    }
+}
+
+// Parse SELECT statement
+async fn parse_select(
+    table_metadata: &Arc<RwLock<Tables>>,
+    select: &Select,
+    functions: &FunctionCatalog,
+) -> Result<ParsedStatement, DbError> {
+    if select.per_partition_limit.is_some() {
+        return Err(unsupported("PER PARTITION LIMIT"));
+    }
+
+    let table = derive_table_metadata(table_metadata, select).await?;
+    let projection = derive_projection(select, &table, functions)?;
+    let filters = derive_filters(select.restriction.as_ref(), &table, functions)?;
+    let restrictions = equality_restrictions(select.restriction.as_ref(), &table, functions)?;
+    let partition_key = key_prefix(&table.partition_key, &restrictions);
+    let (partition_key, clustering_key) = if partition_key.len() == table.partition_key.len() {
+        (partition_key, key_prefix(&table.cluster_key, &restrictions))
+    } else {
+        (vec![], vec![])
+    };
+
+    check_filtering(select, &table, &filters)?;
+
+    let reversed = derive_reversed(select, &table, &partition_key)?;
+    let group_by = derive_group_by(&select.group_by, &table, &restrictions)?;
+    let distinct = select.distinct;
+
+    if distinct {
+        check_distinct(&table, &projection, &filters)?;
+    }
+
+    let index = if partition_key.is_empty() && !distinct {
+        index_restrictions(select.restriction.as_ref(), &table, functions)?
+    } else {
+        vec![]
+    };
+    let mut ann = derive_ann(select, &table, functions)?;
+
+    if let Some(ann) = &mut ann {
+        let aggregates = projection
+            .iter()
+            .any(|expr| matches!(expr, ParsedExpr::Aggregate(_)));
+
+        if aggregates || distinct || !group_by.is_empty() {
+            return Err(DbError::new(
+                ErrorCode::Invalid,
+                "ANN ordering is not supported with aggregates, DISTINCT or GROUP BY".to_string(),
+            ));
+        }
+
+        if select.restriction.is_some() {
+            ann.index = None;
+        }
+    }
+
+    let limit = match &select.limit {
+        Some(limit) if ann.is_none() => Some(literal(&ColumnType::Int, limit, functions)?),
+        _ => None,
+    };
+
+    Ok(ParsedStatement::Select(Box::new(ParsedQuery {
+        filters,
+        partition_key,
+        clustering_key,
+        group_by,
+        distinct,
+        json: select.json,
+        index,
+        ann,
+        limit,
+        reversed,
+        projection: projection.clone(),
+        table: table.clone(),
+        column_count: projection.len() as i32,
+    })))
+}
+
+// Restrictions on columns that are neither part of the primary key nor indexed
+// are checked against every row read, so must be asked for
+fn check_filtering(
+    select: &Select,
+    table: &TableMetadata,
+    filters: &[ParsedFilter],
+) -> Result<(), DbError> {
+    let filtered = filters
+        .iter()
+        .flat_map(|filter| &filter.args)
+        .flat_map(ParsedExpr::columns)
+        .any(|column| {
+            !table.columns[column].kind.is_primary_key()
+                && !table.indexes.iter().any(|index| index.column == column)
+        });
+
+    if filtered && !select.allow_filtering {
+        return Err(DbError::new(
+            ErrorCode::Invalid,
+            "Cannot execute this query as it might involve data filtering and thus may have \
+             unpredictable performance. If you want to execute this query despite the \
+             performance unpredictability, use ALLOW FILTERING"
+                .to_string(),
+        ));
+    }
+
+    Ok(())
+}
+
+// ORDER BY the clustering columns in their declared order, either all as the
+// table sorts them or all reversed. Whether the partition is read backwards.
+fn derive_reversed(
+    select: &Select,
+    table: &TableMetadata,
+    partition_key: &[ParsedExpr],
+) -> Result<bool, DbError> {
+    let invalid = |message: String| Err(DbError::new(ErrorCode::Invalid, message));
+    let Some(first) = select.order_by.first() else {
+        return Ok(false);
+    };
+
+    // ANN ordering is left to derive_ann
+    if select
+        .order_by
+        .iter()
+        .any(|ordering| ordering.ann.is_some())
+    {
+        return Ok(false);
+    }
+
+    for (position, ordering) in select.order_by.iter().enumerate() {
+        let column = &ordering.column.value;
+
+        if !table.columns.contains_key(column) {
+            return invalid(format!("Undefined column name {}", column));
+        }
+
+        if !table.cluster_key.contains(column) {
+            return invalid(format!(
+                "Order by is currently only supported on the clustered columns of the PRIMARY KEY, got {}",
+                column
+            ));
+        }
+
+        if table.cluster_key[position] != *column {
+            return invalid(
+                "Order by currently only supports the ordering of columns following their declared order in the PRIMARY KEY"
+                    .to_string(),
+            );
+        }
+    }
+
+    let reversed =
+        |column: &Ident, descending: bool| descending != table.is_descending(&column.value);
+    let first = reversed(&first.column, first.descending);
+
+    if select
+        .order_by
+        .iter()
+        .any(|ordering| reversed(&ordering.column, ordering.descending) != first)
+    {
+        return invalid("Unsupported order by relation".to_string());
+    }
+
+    if partition_key.is_empty() {
+        return invalid(
+            "ORDER BY is only supported when the partition key is restricted by an EQ or an IN."
+                .to_string(),
+        );
+    }
+
+    Ok(first)
+}
+
+// Clauses the grammar reads but tarrasque doesn't act on
+fn unsupported(clause: &str) -> DbError {
+    DbError::new(ErrorCode::Invalid, format!("{} is not supported", clause))
+}
+
+// ORDER BY v ANN OF [...] LIMIT k, on a vector column with a vector index.
+// Other orderings are left to the primary key as ever.
+fn derive_ann(
+    select: &Select,
+    table: &TableMetadata,
+    functions: &FunctionCatalog,
+) -> Result<Option<AnnOrdering>, DbError> {
+    let invalid = |message: &str| Err(DbError::new(ErrorCode::Invalid, message.to_string()));
+    let ann = select
+        .order_by
+        .iter()
+        .find_map(|order| order.ann.as_ref().map(|vector| (order, vector)));
+    let Some((order, right)) = ann else {
+        return Ok(None);
+    };
+
+    if select.order_by.len() > 1 {
+        return invalid("ANN ordering does not support any other ordering");
+    }
+
+    if order.descending {
+        return invalid("Descending ANN ordering is not supported");
+    }
+
+    let column = &order.column;
+    let Some(column_metadata) = table.columns.get(&column.value) else {
+        return Err(DbError::new(
+            ErrorCode::Invalid,
+            format!("Undefined column name {}", column.value),
+        ));
+    };
+    let index = table
+        .indexes
+        .iter()
+        .find(|index| index.column == column.value && matches!(index.kind, IndexKind::Vector(_)));
+    let Some(index) = index else {
+        return invalid(
+            "ANN ordering by vector requires the column to be indexed using 'StorageAttachedIndex'",
+        );
+    };
+    let IndexKind::Vector(similarity) = index.kind else {
+        unreachable!()
+    };
+    let limit = match &select.limit {
+        Some(Expr::Literal(Literal::Number(limit))) => limit.parse::<usize>().ok(),
+        _ => None,
+    };
+    let Some(limit) = limit.filter(|limit| (1..=1000).contains(limit)) else {
+        return invalid(
+            "Use of ANN OF in an ORDER BY clause requires a LIMIT that is not greater than 1000",
+        );
+    };
+
+    Ok(Some(AnnOrdering {
+        column: column.value.clone(),
+        similarity,
+        vector: literal(&column_metadata.column_type, right, functions)?,
+        limit,
+        index: is_built(index)?.then(|| index.clone()),
+    }))
+}
+
+async fn parse_insert(
+    table_metadata: &Arc<RwLock<Tables>>,
+    insert: Insert,
+    functions: &FunctionCatalog,
+) -> Result<ParsedStatement, DbError> {
+    if insert.if_not_exists {
+        return Err(unsupported("IF NOT EXISTS"));
+    }
+
+    check_using(&insert.using)?;
+
+    let table = find_writable_table(table_metadata, &table_name(&insert.table)).await?;
+
+    if table.is_counter_table() {
+        return Err(DbError::new(
+            ErrorCode::Invalid,
+            "INSERT statements are not allowed on counter tables, use UPDATE instead".to_string(),
+        ));
+    }
+
+    let (insert_columns_names, insert_values) = match insert.values {
+        InsertValues::Columns(columns, values) => (columns, values),
+        InsertValues::Json { json, default_null } => {
+            let json = literal(&ColumnType::Varchar, &json, functions)?;
+
+            return Ok(ParsedStatement::InsertJson(Box::new(ParsedInsertJson {
+                table,
+                json,
+                default_null,
+            })));
+        }
+    };
+    let mut columns = vec![];
+    let mut values = vec![];
+
+    for (i, column) in insert_columns_names.iter().enumerate() {
+        let column_name = column.value.clone();
+
+        let column_metadata = table.columns.get(&column_name).ok_or_else(|| {
+            DbError::new(
+                ErrorCode::Invalid,
+                format!("Column {} not found", column_name),
+            )
+        })?;
+
+        let Some(value_expr) = insert_values.get(i) else {
+            return Err(DbError::new(
+                ErrorCode::Invalid,
+                "Value not found".to_string(),
+            ));
+        };
+        let value = literal(&column_metadata.column_type, value_expr, functions)?;
+
+        columns.push(column_name);
+        values.push(value);
+    }
+
+    insert_columns(table, columns, values).map(ParsedStatement::Insert)
+}
+
+// USING TTL and USING TIMESTAMP are read but not applied
+fn check_using(using: &[(UsingOption, Expr)]) -> Result<(), DbError> {
+    match using.first() {
+        Some((UsingOption::Ttl, _)) => Err(unsupported("USING TTL")),
+        Some((UsingOption::Timestamp, _)) => Err(unsupported("USING TIMESTAMP")),
+        None => Ok(()),
+    }
+}
+
+fn insert_columns(
+    table: TableMetadata,
+    columns: Vec<String>,
+    values: Vec<ParsedExpr>,
+) -> Result<ParsedInsert, DbError> {
+    let restrictions: HashMap<String, ParsedExpr> = columns
+        .iter()
+        .cloned()
+        .zip(values.iter().cloned())
+        .collect();
+    let partition_key = key_prefix(&table.partition_key, &restrictions);
+    let clustering_key = key_prefix(&table.cluster_key, &restrictions);
+    let kinds = columns
+        .iter()
+        .map(|column| &table.columns[column].kind)
+        .collect::<Vec<_>>();
+    // Static columns alone can be written without a clustering key
+    let static_only = clustering_key.is_empty()
+        && kinds.contains(&&Kind::Static)
+        && !kinds.contains(&&Kind::Regular);
+
+    check_primary_key(&table, &partition_key, &clustering_key, !static_only)?;
+
+    Ok(ParsedInsert {
+        table,
+        partition_key,
+        clustering_key,
+        columns,
+        values,
+    })
+}
+
+impl ParsedInsertJson {
+    // The insert of the columns the bound document holds
+    pub fn to_insert(&self) -> Result<ParsedInsert, DbError> {
+        let invalid = |message: String| DbError::new(ErrorCode::Invalid, message);
+        let text = match &self.json {
+            ParsedExpr::Literal(Some(Value::Varchar(text))) => text,
+            ParsedExpr::Literal(None) => {
+                return Err(invalid("Got null for INSERT JSON values".to_string()))
+            }
+            _ => return Err(invalid("Unbound INSERT JSON values".to_string())),
+        };
+        let Json::Object(members) = Json::parse(text)? else {
+            return Err(invalid(format!(
+                "Could not decode JSON string as a map: {}",
+                text
+            )));
+        };
+        let mut columns = vec![];
+        let mut values = vec![];
+
+        for (key, value) in &members {
+            // Keys are column names, folded unless double quoted as in CQL
+            let name = match key.strip_prefix('"').and_then(|key| key.strip_suffix('"')) {
+                Some(quoted) => quoted.replace("\"\"", "\""),
+                None => key.to_lowercase(),
+            };
+            let column = self.table.columns.get(&name).ok_or_else(|| {
+                invalid(format!(
+                    "JSON values map contains unrecognized column: {}",
+                    key
+                ))
+            })?;
+
+            columns.push(name);
+            values.push(ParsedExpr::Literal(from_json(value, &column.column_type)?));
+        }
+
+        // Columns left out are unset, or null with DEFAULT NULL
+        if self.default_null {
+            for column in self.table.columns.values() {
+                if !column.kind.is_primary_key() && !columns.contains(&column.name) {
+                    columns.push(column.name.clone());
+                    values.push(ParsedExpr::Literal(None));
+                }
+            }
+        }
+
+        insert_columns(self.table.clone(), columns, values)
+    }
+}
+
+async fn parse_update(
+    table_metadata: &Arc<RwLock<Tables>>,
+    mutation: Mutation,
+    functions: &FunctionCatalog,
+) -> Result<ParsedStatement, DbError> {
+    check_mutation(&mutation)?;
+
+    let table = find_writable_table(table_metadata, &table_name(&mutation.table)).await?;
+    let operations = mutation
+        .assignments
+        .iter()
+        .map(|assignment| derive_operation(&table, assignment, functions))
+        .collect::<Result<Vec<_>, DbError>>()?;
+    let full_key = operations
+        .iter()
+        .any(|operation| operation.column.kind != Kind::Static);
+    let (partition_key, clustering_key) =
+        derive_primary_key(&mutation.selection, &table, full_key, functions)?;
+
+    Ok(ParsedStatement::Update(ParsedUpdate {
+        table,
+        partition_key,
+        clustering_key,
+        operations,
+    }))
+}
+
+async fn parse_delete(
+    table_metadata: &Arc<RwLock<Tables>>,
+    mutation: Mutation,
+    functions: &FunctionCatalog,
+) -> Result<ParsedStatement, DbError> {
+    check_mutation(&mutation)?;
+
+    let table = find_writable_table(table_metadata, &table_name(&mutation.table)).await?;
+    let operations = mutation
+        .assignments
+        .iter()
+        .map(|assignment| derive_operation(&table, assignment, functions))
+        .collect::<Result<Vec<_>, DbError>>()?;
+    let full_key = operations
+        .iter()
+        .any(|operation| operation.column.kind != Kind::Static);
+    let (partition_key, clustering_key) =
+        derive_primary_key(&mutation.selection, &table, full_key, functions)?;
+
+    Ok(ParsedStatement::Delete(ParsedDelete {
+        table,
+        partition_key,
+        clustering_key,
+        operations,
+    }))
+}
+
+// Lightweight transactions and USING clauses aren't supported
+fn check_mutation(mutation: &Mutation) -> Result<(), DbError> {
+    check_using(&mutation.using)?;
+
+    match &mutation.conditions {
+        Some(Conditions::Exists) => Err(unsupported("IF EXISTS")),
+        Some(Conditions::Columns(conditions)) => Err(unsupported(&format!("IF {}", conditions))),
+        None => Ok(()),
+    }
+}
+
+async fn parse_create_index(
+    table_metadata: &Arc<RwLock<Tables>>,
+    definition: IndexDefinition,
+) -> Result<ParsedStatement, DbError> {
+    let table = find_table(table_metadata, &table_name(&definition.table)).await?;
+    let invalid = |message: String| Err(DbError::new(ErrorCode::Invalid, message));
+
+    if table.base_table.is_some() {
+        return invalid("Secondary indexes are not supported on materialized views".to_string());
+    }
+    let name = &definition.column.value;
+    let Some(column) = table.columns.get(name) else {
+        return invalid(format!(
+            "Undefined column name {} in table {}",
+            name, table.name
+        ));
+    };
+    let column_type = &column.column_type;
+    let target_name = definition
+        .target
+        .as_ref()
+        .map(|target| target.value.to_lowercase());
+    let target = match target_name.as_deref() {
+        None if column_type.is_multi_cell() && column_type.is_collection() => IndexTarget::Values,
+        None => IndexTarget::Simple,
+        Some("full") => IndexTarget::Full,
+        Some("keys") => IndexTarget::Keys,
+        Some("values") => IndexTarget::Values,
+        Some("entries") => IndexTarget::Entries,
+        Some(target) => return invalid(format!("Unknown index target {}", target)),
+    };
+    let frozen_collection = !column_type.is_multi_cell() && column_type.unfrozen().is_collection();
+    let error = match (target, column_type.unfrozen()) {
+        _ if column.kind != Kind::Regular => Some(format!(
+            "Cannot create secondary index on {}, only regular columns can be indexed",
+            name
+        )),
+        (_, ColumnType::Counter) => Some(format!(
+            "Cannot create secondary index on counter column {}",
+            name
+        )),
+        (_, ColumnType::Udt(_)) if column_type.is_multi_cell() => Some(format!(
+            "Cannot create secondary index on non-frozen UDT column {}",
+            name
+        )),
+        (IndexTarget::Full, _) if !frozen_collection => {
+            Some("full() indexes can only be created on frozen collections".to_string())
+        }
+        (IndexTarget::Full, _) => None,
+        _ if frozen_collection => Some(format!(
+            "Cannot create {} index on frozen column {}. Frozen collections only support full() indexes",
+            target_name.map_or_else(|| "a".to_string(), |target| format!("{}()", target)),
+            name
+        )),
+        (IndexTarget::Keys | IndexTarget::Entries, inner) if !matches!(inner, ColumnType::Map(..)) => {
+            Some(format!(
+                "Cannot create index on {} of column {} with non-map type",
+                target_name.unwrap_or_default(),
+                name
+            ))
+        }
+        (IndexTarget::Values, inner) if !inner.is_collection() => Some(format!(
+            "Cannot create index on values of column {} with non-collection type",
+            name
+        )),
+        _ => None,
+    };
+
+    if let Some(message) = error {
+        return invalid(message);
+    }
+
+    let kind = match definition
+        .class
+        .as_deref()
+        .map(str::to_lowercase)
+        .as_deref()
+    {
+        None if definition.options.is_some() => {
+            return invalid("Cannot specify options for a non-CUSTOM index".to_string())
+        }
+        None => IndexKind::Secondary,
+        Some(
+            "sai" | "storageattachedindex" | "org.apache.cassandra.index.sai.storageattachedindex",
+        ) if matches!(column_type, ColumnType::Vector(..)) => {
+            IndexKind::Vector(vector_similarity(definition.options.as_ref(), column_type)?)
+        }
+        Some(
+            "sai" | "storageattachedindex" | "org.apache.cassandra.index.sai.storageattachedindex",
+        ) => {
+            let is_text = target.term_types(column_type).iter().any(|term_type| {
+                matches!(
+                    term_type.unfrozen(),
+                    ColumnType::Varchar | ColumnType::Ascii
+                )
+            });
+
+            IndexKind::StorageAttached(text_analysis(definition.options.as_ref(), is_text)?)
+        }
+        Some(_) => {
+            return invalid(format!(
+                "Unable to find custom indexer class '{}'",
+                definition.class.unwrap_or_default()
+            ))
+        }
+    };
+    let default_name = definition.name.is_none();
+    let index_name = definition.name.map_or_else(
+        || format!("{}_{}_idx", table.name, name),
+        |index_name| index_name.value,
+    );
+
+    Ok(ParsedStatement::CreateIndex {
+        table: table.name.clone(),
+        index: IndexMetadata::new(index_name, name.clone(), target, kind),
+        default_name,
+        if_not_exists: definition.if_not_exists,
+    })
+}
+
+// WITH OPTIONS = {'case_sensitive': 'false', 'normalize': 'true', 'ascii': 'true'},
+// the ways a storage-attached index can match text
+fn text_analysis(options: Option<&Expr>, is_text: bool) -> Result<TextAnalysis, DbError> {
+    let invalid = |message: String| DbError::new(ErrorCode::Invalid, message);
+    let mut analysis = TextAnalysis::default();
+    let entries = match options {
+        Some(Expr::Map(entries)) => &entries[..],
+        Some(options) => return Err(invalid(format!("Invalid index options {}", options))),
+        None => &[],
+    };
+
+    for (key, value) in entries {
+        let Expr::Literal(Literal::String(option)) = key else {
+            return Err(invalid(format!("Invalid index option {}", key)));
+        };
+        let enabled = match value {
+            Expr::Literal(Literal::Boolean(enabled)) => *enabled,
+            Expr::Literal(Literal::String(enabled)) if enabled.eq_ignore_ascii_case("true") => true,
+            Expr::Literal(Literal::String(enabled)) if enabled.eq_ignore_ascii_case("false") => {
+                false
+            }
+            value => {
+                return Err(invalid(format!(
+                    "Invalid value {} for index option {}",
+                    value, option
+                )))
+            }
+        };
+
+        match option.to_lowercase().as_str() {
+            "case_sensitive" => analysis.case_insensitive = !enabled,
+            "normalize" => analysis.normalize = enabled,
+            "ascii" => analysis.ascii = enabled,
+            _ => return Err(invalid(format!("Unknown index option {}", option))),
+        }
+    }
+
+    if !analysis.is_identity() && !is_text {
+        return Err(invalid(
+            "Text analysis options can only be set on indexes of text".to_string(),
+        ));
+    }
+
+    Ok(analysis)
+}
+
+// WITH OPTIONS = {'similarity_function': 'dot_product'}, how a vector index
+// compares vectors, cosine similarity unless set
+fn vector_similarity(
+    options: Option<&Expr>,
+    column_type: &ColumnType,
+) -> Result<Similarity, DbError> {
+    let invalid = |message: String| DbError::new(ErrorCode::Invalid, message);
+    let entries = match options {
+        Some(Expr::Map(entries)) => &entries[..],
+        Some(options) => return Err(invalid(format!("Invalid index options {}", options))),
+        None => &[],
+    };
+    let mut similarity = Similarity::Cosine;
+
+    if !matches!(column_type, ColumnType::Vector(element, _) if **element == ColumnType::Float) {
+        return Err(invalid(format!(
+            "Vector indexes are only supported on vector<float, n> columns, not {}",
+            column_type
+        )));
+    }
+
+    for (key, value) in entries {
+        match (key, value) {
+            (Expr::Literal(Literal::String(option)), Expr::Literal(Literal::String(name)))
+                if option.eq_ignore_ascii_case("similarity_function") =>
+            {
+                similarity = Similarity::from_name(name).ok_or_else(|| {
+                    invalid(format!(
+                        "Invalid similarity function {}, expected one of cosine, dot_product or euclidean",
+                        name
+                    ))
+                })?;
+            }
+            (option, _) => return Err(invalid(format!("Unknown index option {}", option))),
+        }
+    }
+
+    Ok(similarity)
+}
+
+// A view selects columns of its base table by name, each column of its
+// primary key being restricted to rows where it's not null. Its key is the
+// base table's along with at most one other column, so that every base row
+// makes at most one view row.
+async fn parse_create_view(
+    table_metadata: &Arc<RwLock<Tables>>,
+    definition: ViewDefinition,
+) -> Result<ParsedStatement, DbError> {
+    let invalid = |message: String| Err(DbError::new(ErrorCode::Invalid, message));
+    let select = &definition.query;
+
+    if let Some(option) = definition.options.first() {
+        return Err(unsupported(&format!("WITH {}", option)));
+    }
+
+    let base = find_table(table_metadata, &table_name(&select.table)).await?;
+
+    if base.base_table.is_some() {
+        return invalid(
+            "Materialized views cannot be created against other materialized views".to_string(),
+        );
+    }
+
+    if base.is_counter_table() {
+        return invalid("Materialized views are not supported on counter tables".to_string());
+    }
+
+    let mut selected = vec![];
+
+    match &select.selection {
+        Selection::Wildcard => selected.extend(
+            base.columns
+                .values()
+                .filter(|column| column.kind != Kind::Static)
+                .map(|column| column.name.clone()),
+        ),
+        Selection::Selectors(selectors) => {
+            for selector in selectors {
+                match &selector.expr {
+                    Expr::Identifier(ident) if selector.alias.is_none() => {
+                        selected.push(ident.value.clone())
+                    }
+                    expr => return invalid(format!(
+                        "Can only select columns by name when defining a materialized view (got {})",
+                        expr
+                    )),
+                }
+            }
+        }
+    }
+
+    let mut not_null = vec![];
+
+    for restriction in select
+        .restriction
+        .as_ref()
+        .map(conjuncts)
+        .unwrap_or_default()
+    {
+        match restriction {
+            Expr::IsNotNull(expr) => match &**expr {
+                Expr::Identifier(ident) => not_null.push(ident.value.clone()),
+                _ => return invalid(format!("Invalid restriction {} in materialized view", restriction)),
+            },
+            _ => {
+                return invalid(format!(
+                    "Unsupported restriction {} in materialized view, only IS NOT NULL restrictions are supported",
+                    restriction
+                ))
+            }
+        }
+    }
+
+    let ident_names = |idents: &[Ident]| {
+        idents
+            .iter()
+            .map(|ident| ident.value.clone())
+            .collect::<Vec<_>>()
+    };
+    let partition_key = ident_names(&definition.primary_key.partition_key);
+    let cluster_key = ident_names(&definition.primary_key.clustering_key);
+    let primary_key = [partition_key.clone(), cluster_key.clone()].concat();
+
+    for column in selected.iter().chain(&primary_key).chain(&not_null) {
+        match base.columns.get(column) {
+            None => {
+                return invalid(format!(
+                    "Undefined column name {} in table {}",
+                    column, base.name
+                ))
+            }
+            Some(column) if column.kind == Kind::Static => {
+                return invalid(format!(
+                    "Cannot include static column '{}' in materialized view",
+                    column.name
+                ))
+            }
+            Some(_) => {}
+        }
+    }
+
+    if let Some(column) = base
+        .partition_key
+        .iter()
+        .chain(&base.cluster_key)
+        .find(|column| !primary_key.contains(column))
+    {
+        return invalid(format!(
+            "Cannot create materialized view without primary key column {} from the base table",
+            column
+        ));
+    }
+
+    let added = primary_key
+        .iter()
+        .filter(|column| !base.is_primary_key(column))
+        .collect::<Vec<_>>();
+
+    if added.len() > 1 {
+        return invalid(format!(
+            "Cannot include more than one non-primary key column in materialized view primary key (got {})",
+            added.iter().map(|column| column.as_str()).collect::<Vec<_>>().join(", ")
+        ));
+    }
+
+    if let Some(column) = primary_key.iter().find(|column| !not_null.contains(column)) {
+        return invalid(format!(
+            "Primary key column '{}' is required to be filtered by 'IS NOT NULL'",
+            column
+        ));
+    }
+
+    let mut columns = IndexMap::new();
+
+    for name in primary_key.iter().chain(&selected) {
+        let column = &base.columns[name];
+        let kind = if partition_key.contains(name) {
+            Kind::PartitionKey
+        } else if primary_key.contains(name) {
+            Kind::Clustering
+        } else {
+            Kind::Regular
+        };
+
+        if kind.is_primary_key()
+            && (column.column_type.is_multi_cell() || column.column_type.references_duration())
+        {
+            return invalid(format!(
+                "Cannot use column {} of type {} in the primary key of a materialized view",
+                name, column.column_type
+            ));
+        }
+
+        columns
+            .entry(name.clone())
+            .or_insert_with(|| ColumnMetadata {
+                name: name.clone(),
+                column_type: column.column_type.clone(),
+                kind,
+            });
+    }
+
+    Ok(ParsedStatement::CreateView {
+        view: TableMetadata {
+            name: table_name(&definition.name),
+            partition_key,
+            cluster_key,
+            columns,
+            indexes: vec![],
+            views: vec![],
+            base_table: Some(base.name.clone()),
+            options: TableOptions::default(),
+        },
+        if_not_exists: definition.if_not_exists,
+    })
+}
+
+// Counter updates can only be batched with each other, in a COUNTER batch
+async fn parse_batch(
+    table_metadata: &Arc<RwLock<Tables>>,
+    batch: Batch,
+    functions: &FunctionCatalog,
+) -> Result<ParsedStatement, DbError> {
+    check_using(&batch.using)?;
+
+    let mut parsed_statements = vec![];
+
+    for statement in batch.statements {
+        let parsed_statement = match statement {
+            Statement::Insert(insert) => parse_insert(table_metadata, insert, functions).await?,
+            Statement::Update(mutation) => {
+                parse_update(table_metadata, mutation, functions).await?
+            }
+            Statement::Delete(mutation) => {
+                parse_delete(table_metadata, mutation, functions).await?
+            }
+            _ => return Err(invalid_batch_statement()),
+        };
+        let is_counter = match &parsed_statement {
+            ParsedStatement::Insert(insert) => insert.table.is_counter_table(),
+            ParsedStatement::InsertJson(insert) => insert.table.is_counter_table(),
+            ParsedStatement::Update(update) => update.table.is_counter_table(),
+            ParsedStatement::Delete(delete) => delete.table.is_counter_table(),
+            _ => false,
+        };
+
+        if batch.kind == BatchKind::Counter && !is_counter {
+            return Err(DbError::new(
+                ErrorCode::Invalid,
+                "Cannot include non-counter statement in a counter batch".to_string(),
+            ));
+        }
+
+        if batch.kind != BatchKind::Counter && is_counter {
+            return Err(DbError::new(
+                ErrorCode::Invalid,
+                "Counter mutations are only allowed in COUNTER batches".to_string(),
+            ));
+        }
+
+        parsed_statements.push(parsed_statement);
+    }
+
+    Ok(ParsedStatement::Batch(parsed_statements))
+}
+
+fn invalid_batch_statement() -> DbError {
+    DbError::new(
+        ErrorCode::Invalid,
+        "Only INSERT, UPDATE and DELETE statements are allowed in a BATCH".to_string(),
+    )
+}
+
+fn parse_create_type(
+    definition: TypeDefinition,
+    types: &Types,
+) -> Result<ParsedStatement, DbError> {
+    let fields = definition
+        .fields
+        .iter()
+        .map(|(name, field_type)| Ok((name.value.clone(), resolve_type(field_type, types)?)))
+        .collect::<Result<Vec<_>, DbError>>()?;
+    let keyspace = definition
+        .name
+        .keyspace
+        .as_ref()
+        .map(|keyspace| keyspace.value.clone())
+        .unwrap_or_default();
+
+    Ok(ParsedStatement::CreateType {
+        user_type: UserType {
+            keyspace,
+            name: type_name(&definition.name),
+            fields,
+        },
+        if_not_exists: definition.if_not_exists,
+    })
+}
+
+fn parse_alter_type(alteration: TypeAlteration, types: &Types) -> Result<ParsedStatement, DbError> {
+    let name = type_name(&alteration.name);
+    let mut user_type = types.get(&name).cloned().ok_or_else(|| {
+        DbError::new(
+            ErrorCode::Invalid,
+            format!("No user type named {} exists.", name),
+        )
+    })?;
+
+    match alteration.change {
+        FieldChange::Add(field, field_type) => {
+            if user_type.field(&field.value).is_some() {
+                return Err(DbError::new(
+                    ErrorCode::Invalid,
+                    format!(
+                        "Cannot add new field {} to type {}: a field of the same name already exists",
+                        field, name
+                    ),
+                ));
+            }
+
+            user_type
+                .fields
+                .push((field.value, resolve_type(&field_type, types)?));
+        }
+        FieldChange::Rename(renames) => {
+            for (from, to) in renames {
+                let (position, _) = user_type.field(&from.value).ok_or_else(|| {
+                    DbError::new(
+                        ErrorCode::Invalid,
+                        format!("Unknown field {} in user type {}", from, name),
+                    )
+                })?;
+
+                user_type.fields[position].0 = to.value;
+            }
+        }
+    }
+
+    Ok(ParsedStatement::AlterType(user_type))
+}
+
+// Function bodies are CQL terms over the function's arguments, which are
+// parsed as the columns of a table of their own
+fn parse_create_function(
+    definition: FunctionDefinition,
+    types: &Types,
+    functions: &FunctionCatalog,
+) -> Result<ParsedStatement, DbError> {
+    let invalid = |message: String| Err(DbError::new(ErrorCode::Invalid, message));
+    let (keyspace, name) = function_name(&definition.name);
+    let language = definition.language.value.to_lowercase();
+
+    if definition.or_replace && definition.if_not_exists {
+        return invalid("Cannot use both 'OR REPLACE' and 'IF NOT EXISTS' directives".to_string());
+    }
+
+    if language != "cql" {
+        return invalid(format!(
+            "Invalid language '{}' for function '{}'",
+            language, definition.name
+        ));
+    }
+
+    let mut columns = IndexMap::new();
+
+    for (argument, argument_type) in &definition.arguments {
+        let column = ColumnMetadata {
+            name: argument.value.clone(),
+            column_type: resolve_type(argument_type, types)?,
+            kind: Kind::Regular,
+        };
+
+        if columns.insert(argument.value.clone(), column).is_some() {
+            return invalid(format!(
+                "Duplicate argument names for given function {}",
+                definition.name
+            ));
+        }
+    }
+
+    let function = UserFunction {
+        keyspace: keyspace.unwrap_or_default(),
+        name,
+        arguments: columns
+            .values()
+            .map(|column| (column.name.clone(), column.column_type.clone()))
+            .collect(),
+        return_type: resolve_type(&definition.return_type, types)?,
+        called_on_null_input: definition.called_on_null_input,
+        language,
+        body: definition.body,
+    };
+    let argument_types = columns
+        .values()
+        .map(|column| column.column_type.clone())
+        .collect::<Vec<_>>();
+
+    if functions
+        .overloads(&function.name)
+        .any(|overload| overload.definition.is_none() && overload.argument_types == argument_types)
+    {
+        return invalid(format!(
+            "Function {} would shadow the native function of the same signature",
+            definition.name
+        ));
+    }
+
+    let table = TableMetadata {
+        name: function.name.clone(),
+        partition_key: vec![],
+        cluster_key: vec![],
+        columns,
+        indexes: vec![],
+        views: vec![],
+        base_table: None,
+        options: TableOptions::default(),
+    };
+    let scope = Scope {
+        table: Some(&table),
+        functions,
+    };
+    let expr = parse_term(&function.body).map_err(|error| {
+        DbError::new(
+            ErrorCode::Invalid,
+            format!("Invalid body for function {}: {}", definition.name, error),
+        )
+    })?;
+    let body = match operand(&expr, scope)? {
+        Some(ParsedExpr::Column(column)) => {
+            let argument_type = &column.column_metadata.column_type;
+
+            if *argument_type != function.return_type {
+                return invalid(format!(
+                    "Type error: cannot return argument {} (type {}) from function {} returning {}",
+                    column.target_column, argument_type, definition.name, function.return_type
+                ));
+            }
+
+            ParsedExpr::Column(column)
+        }
+        _ => term(&function.return_type, &expr, scope)?,
+    };
+
+    Ok(ParsedStatement::CreateFunction {
+        function,
+        body,
+        or_replace: definition.or_replace,
+        if_not_exists: definition.if_not_exists,
+    })
+}
+
+// The state function takes the state and the aggregated value and returns the
+// next state, the final function takes the last state
+fn parse_create_aggregate(
+    definition: AggregateDefinition,
+    types: &Types,
+    functions: &FunctionCatalog,
+) -> Result<ParsedStatement, DbError> {
+    let invalid = |message: String| DbError::new(ErrorCode::Invalid, message);
+    let (keyspace, name) = function_name(&definition.name);
+
+    if definition.or_replace && definition.if_not_exists {
+        return Err(invalid(
+            "Cannot use both 'OR REPLACE' and 'IF NOT EXISTS' directives".to_string(),
+        ));
+    }
+
+    if AggregateFunction::from_name(&name).is_some() {
+        return Err(invalid(format!(
+            "Aggregate {} would shadow the native aggregate of the same name",
+            definition.name
+        )));
+    }
+
+    let argument_types = definition
+        .argument_types
+        .iter()
+        .map(|argument_type| resolve_type(argument_type, types))
+        .collect::<Result<Vec<_>, DbError>>()?;
+    let [argument_type] = argument_types.as_slice() else {
+        return Err(invalid(format!(
+            "Aggregate {} must take exactly one argument",
+            definition.name
+        )));
+    };
+    let state_type = resolve_type(&definition.state_type, types)?;
+    let find_function = |name: &Name, argument_types: &[ColumnType]| {
+        let (keyspace, base_name) = function_name(name);
+        let function = functions
+            .overloads(&base_name)
+            .find(|overload| {
+                keyspace
+                    .as_ref()
+                    .is_none_or(|keyspace| overload.keyspace == *keyspace)
+                    && overload.argument_types == argument_types
+            })
+            .cloned();
+
+        function.ok_or_else(|| {
+            invalid(format!(
+                "Function {} does not exist",
+                signature_of(name, argument_types)
+            ))
+        })
+    };
+    let state_function = find_function(
+        &definition.state_function,
+        &[state_type.clone(), argument_type.clone()],
+    )?;
+
+    if state_function.return_type != state_type {
+        return Err(invalid(format!(
+            "State function {} return type must be the same as the first argument type - check STYPE, argument and return types",
+            state_function.handle()
+        )));
+    }
+
+    let final_function = definition
+        .final_function
+        .as_ref()
+        .map(|final_function| find_function(final_function, std::slice::from_ref(&state_type)))
+        .transpose()?;
+    let initial_condition = match &definition.initial_condition {
+        Some(expr) => match literal(&state_type, expr, functions)? {
+            ParsedExpr::Literal(value) => value,
+            _ => {
+                return Err(invalid(format!(
+                    "INITCOND of aggregate {} must be a literal",
+                    definition.name
+                )))
+            }
+        },
+        None => None,
+    };
+
+    Ok(ParsedStatement::CreateAggregate {
+        aggregate: UserAggregate {
+            keyspace: keyspace.unwrap_or_default(),
+            name,
+            argument_types,
+            state_function,
+            state_type,
+            final_function,
+            initial_condition,
+        },
+        or_replace: definition.or_replace,
+        if_not_exists: definition.if_not_exists,
+    })
+}
+
+fn signature_of(name: &Name, argument_types: &[ColumnType]) -> String {
+    let (keyspace, name) = function_name(name);
+
+    signature(&keyspace.unwrap_or_default(), &name, argument_types)
+}
+
+// Type names fold to lowercase unless quoted, and keyspaces aren't modelled yet
+fn type_name(name: &Name) -> String {
+    name.name.value.clone()
+}
+
+// Tables are looked up by their name alone, keyspaces not being modelled
+// beyond system_schema
+fn table_name(name: &Name) -> String {
+    match is_system(name) {
+        true => format!("{}.{}", SYSTEM_SCHEMA, name.name.value),
+        false => name.name.value.clone(),
+    }
+}
+
+fn is_system(name: &Name) -> bool {
+    name.keyspace
+        .as_ref()
+        .is_some_and(|keyspace| keyspace.value == SYSTEM_SCHEMA)
+}
+
+fn not_modifiable() -> DbError {
+    DbError::new(
+        ErrorCode::Unauthorized,
+        format!("{} keyspace is not user-modifiable.", SYSTEM_SCHEMA),
+    )
+}
+
+fn resolve_type(type_str: &str, types: &Types) -> Result<ColumnType, DbError> {
+    let column_type = ColumnType::resolve(type_str, types)
+        .ok_or_else(|| DbError::new(ErrorCode::Invalid, format!("Unknown type {}", type_str)))?;
+
+    check_durations(&column_type, type_str)?;
+    Ok(column_type)
+}
+
+// Durations have no order, so can't be set elements or map keys
+fn check_durations(column_type: &ColumnType, type_str: &str) -> Result<(), DbError> {
+    let invalid = |message: &str| {
+        Err(DbError::new(
+            ErrorCode::Invalid,
+            format!("{}: {}", message, type_str),
+        ))
+    };
+
+    match column_type {
+        ColumnType::Set(element) if element.references_duration() => {
+            invalid("Durations are not allowed inside sets")
+        }
+        ColumnType::Map(key, _) if key.references_duration() => {
+            invalid("Durations are not allowed as map keys")
+        }
+        ColumnType::List(inner) | ColumnType::Frozen(inner) => check_durations(inner, type_str),
+        ColumnType::Map(_, value) => check_durations(value, type_str),
+        ColumnType::Tuple(element_types) => element_types
+            .iter()
+            .try_for_each(|element_type| check_durations(element_type, type_str)),
+        _ => Ok(()),
+    }
+}
+
+// Bind markers are left for bind to fill in once the statement is parsed
+fn literal(
+    column_type: &ColumnType,
+    expr: &Expr,
+    functions: &FunctionCatalog,
+) -> Result<ParsedExpr, DbError> {
+    term(
+        column_type,
+        expr,
+        Scope {
+            table: None,
+            functions,
+        },
+    )
+}
+
+// A value of the given type, or a function or operation computing one
+fn term(column_type: &ColumnType, expr: &Expr, scope: Scope<'_>) -> Result<ParsedExpr, DbError> {
+    match expr {
+        Expr::Marker(name) => Ok(ParsedExpr::Marker(name.clone(), column_type.clone())),
+        Expr::Function(function) if function_name(&function.name).1 == "fromjson" => {
+            derive_from_json(function, column_type, scope)
+        }
+        Expr::Function(_) | Expr::Cast(..) => function_term(column_type, expr, scope),
+        _ if is_operation(expr, scope.table) => function_term(column_type, expr, scope),
+        _ if has_marker(expr) => collection_term(column_type, expr, scope),
+        _ => Value::from_expr(column_type, expr).map(ParsedExpr::Literal),
+    }
+}
+
+fn has_marker(expr: &Expr) -> bool {
+    match expr {
+        Expr::Marker(_) => true,
+        Expr::List(elements) | Expr::Set(elements) => elements.iter().any(has_marker),
+        Expr::Map(entries) => entries
+            .iter()
+            .any(|(key, value)| has_marker(key) || has_marker(value)),
+        _ => false,
+    }
+}
+
+// A collection literal whose elements are terms of the element type
+fn collection_term(
+    column_type: &ColumnType,
+    expr: &Expr,
+    scope: Scope<'_>,
+) -> Result<ParsedExpr, DbError> {
+    let elements = |element_type: &ColumnType, exprs: &[Expr]| {
+        exprs
+            .iter()
+            .map(|expr| term(element_type, expr, scope))
+            .collect::<Result<Vec<_>, DbError>>()
+    };
+    let elements = match (column_type.unfrozen(), expr) {
+        (ColumnType::List(element_type), Expr::List(exprs))
+        | (ColumnType::Set(element_type), Expr::Set(exprs)) => elements(element_type, exprs)?,
+        (ColumnType::Vector(element_type, dimension), Expr::List(exprs))
+            if exprs.len() == *dimension =>
+        {
+            elements(element_type, exprs)?
+        }
+        (ColumnType::Map(key_type, value_type), Expr::Map(entries)) => entries
+            .iter()
+            .map(|(key, value)| Ok([term(key_type, key, scope)?, term(value_type, value, scope)?]))
+            .collect::<Result<Vec<_>, DbError>>()?
+            .into_iter()
+            .flatten()
+            .collect(),
+        _ => {
+            return Err(DbError::new(
+                ErrorCode::Invalid,
+                format!("Invalid literal {} for type {:?}", expr, column_type),
+            ))
+        }
+    };
+
+    Ok(ParsedExpr::Collection(column_type.clone(), elements))
+}
+
+// A function call or operation standing for a value of the given type
+fn function_term(
+    column_type: &ColumnType,
+    expr: &Expr,
+    scope: Scope<'_>,
+) -> Result<ParsedExpr, DbError> {
+    let function = if is_operation(expr, scope.table) {
+        derive_arithmetic(expr, scope, Some(column_type), None)?
+    } else {
+        operand(expr, scope)?
+            .ok_or_else(|| DbError::new(ErrorCode::Invalid, format!("Invalid value {}", expr)))?
+    };
+    let (name, return_type) = function.column_spec();
+
+    if return_type != *column_type {
+        return Err(DbError::new(
+            ErrorCode::Invalid,
+            format!(
+                "Type error: cannot assign result of function {} (type {}) to a value of type {}",
+                name, return_type, column_type
+            ),
+        ));
+    }
+
+    Ok(function)
+}
+
+impl ParsedStatement {
+    // Replaces bind markers with the values sent along with the query
+    pub fn bind(&mut self, values: Option<&Values>) -> Result<(), DbError> {
+        // An unset value leaves the column it's assigned to as it was
+        match self {
+            ParsedStatement::Insert(insert) => {
+                let (columns, values) = insert
+                    .columns
+                    .drain(..)
+                    .zip(insert.values.drain(..))
+                    .filter(|(_, value)| !is_unset(value, values))
+                    .unzip();
+
+                insert.columns = columns;
+                insert.values = values;
+            }
+            ParsedStatement::Update(update) => update.operations.retain(|operation| {
+                let value = match &operation.operation {
+                    Operation::SetElement(_, value)
+                    | Operation::Set(value)
+                    | Operation::Append(value)
+                    | Operation::Prepend(value)
+                    | Operation::Remove(value)
+                    | Operation::SetField(_, value)
+                    | Operation::Increment(value)
+                    | Operation::Decrement(value) => value,
+                };
+
+                !is_unset(value, values)
+            }),
+            _ => {}
+        }
+
+        let exprs: Vec<&mut ParsedExpr> =
+            match self {
+                ParsedStatement::Select(query) => query
+                    .partition_key
+                    .iter_mut()
+                    .chain(query.clustering_key.iter_mut())
+                    .chain(query.limit.iter_mut())
+                    .chain(query.filters.iter_mut().flat_map(|filter| &mut filter.args))
+                    .chain(query.index.iter_mut().flat_map(IndexRestriction::exprs_mut))
+                    .chain(query.ann.iter_mut().map(|ann| &mut ann.vector))
+                    .collect(),
+                ParsedStatement::Insert(insert) => insert
+                    .partition_key
+                    .iter_mut()
+                    .chain(insert.clustering_key.iter_mut())
+                    .chain(insert.values.iter_mut())
+                    .collect(),
+                ParsedStatement::InsertJson(insert) => vec![&mut insert.json],
+                ParsedStatement::Update(ParsedUpdate {
+                    partition_key,
+                    clustering_key,
+                    operations,
+                    ..
+                })
+                | ParsedStatement::Delete(ParsedDelete {
+                    partition_key,
+                    clustering_key,
+                    operations,
+                    ..
+                }) => partition_key
+                    .iter_mut()
+                    .chain(clustering_key.iter_mut())
+                    .chain(operations.iter_mut().flat_map(
+                        |operation| match &mut operation.operation {
+                            Operation::SetElement(element, value) => vec![element, value],
+                            Operation::Set(value)
+                            | Operation::Append(value)
+                            | Operation::Prepend(value)
+                            | Operation::Remove(value)
+                            | Operation::SetField(_, value)
+                            | Operation::Increment(value)
+                            | Operation::Decrement(value) => vec![value],
+                        },
+                    ))
+                    .collect(),
+                ParsedStatement::Batch(statements) => {
+                    for statement in statements {
+                        statement.bind(values)?;
+                    }
+
+                    vec![]
+                }
+                _ => vec![],
+            };
+
+        for expr in exprs {
+            bind_markers(expr, values)?;
+        }
+
+        Ok(())
+    }
+}
+
+// Markers may also be passed to function calls and collection literals
+fn bind_markers(expr: &mut ParsedExpr, values: Option<&Values>) -> Result<(), DbError> {
+    match expr {
+        ParsedExpr::Marker(name, column_type) => {
+            *expr = ParsedExpr::Literal(bound_value(name, column_type, values)?);
+        }
+        ParsedExpr::Function(function) => {
+            for argument in &mut function.arguments {
+                bind_markers(argument, values)?;
+            }
+        }
+        ParsedExpr::Collection(_, elements) => {
+            for element in elements {
+                bind_markers(element, values)?;
+            }
+        }
+        _ => {}
+    }
+
+    Ok(())
+}
+
+fn is_unset(expr: &ParsedExpr, values: Option<&Values>) -> bool {
+    matches!(expr, ParsedExpr::Marker(name, _)
+        if matches!(marker_value(name, values), Some(BoundValue::NotSet)))
+}
+
+// `?` markers are numbered by the grammar, `:name` markers are bound by name
+fn marker_value<'a>(name: &str, values: Option<&'a Values>) -> Option<&'a BoundValue> {
+    match (values, name.strip_prefix('?'), name.strip_prefix(':')) {
+        (Some(Values::Unnamed(values)), Some(position), _) => position
+            .parse::<usize>()
+            .ok()
+            .and_then(|position| values.get(position.checked_sub(1)?)),
+        (Some(Values::Named(values)), _, Some(name)) => values.get(name),
+        _ => None,
+    }
+}
+
+fn bound_value(
+    name: &str,
+    column_type: &ColumnType,
+    values: Option<&Values>,
+) -> Result<Option<Value>, DbError> {
+    let value = marker_value(name, values).ok_or_else(|| {
+        DbError::new(
+            ErrorCode::Invalid,
+            format!("No value bound for marker {}", name),
+        )
+    })?;
+
+    match value {
+        BoundValue::Set { bytes } => deserialize(column_type, bytes).map(Some).map_err(|error| {
+            DbError::new(
+                ErrorCode::Invalid,
+                format!("Invalid value bound for marker {}: {}", name, error),
+            )
+        }),
+        BoundValue::Null => Ok(None),
+        BoundValue::NotSet => Err(DbError::new(
+            ErrorCode::Invalid,
+            format!("Invalid unset value for marker {}", name),
+        )),
+        BoundValue::Error => Err(DbError::new(
+            ErrorCode::Invalid,
+            format!("Invalid value bound for marker {}", name),
+        )),
+    }
+}
+
+async fn find_table(tables: &Arc<RwLock<Tables>>, name: &str) -> Result<TableMetadata, DbError> {
+    tables
+        .read()
+        .await
+        .get(name)
+        .cloned()
+        .or_else(|| system_table(name))
+        .ok_or_else(|| DbError::new(ErrorCode::Invalid, "Table not found".to_string()))
+}
+
+// Materialized views are only written through their base tables, and system
+// tables as the schema changes
+async fn find_writable_table(
+    tables: &Arc<RwLock<Tables>>,
+    name: &str,
+) -> Result<TableMetadata, DbError> {
+    if system_table(name).is_some() {
+        return Err(not_modifiable());
+    }
+
+    let table = find_table(tables, name).await?;
+
+    if table.base_table.is_some() {
+        return Err(DbError::new(
+            ErrorCode::Invalid,
+            format!("Cannot directly modify a materialized view {}", name),
+        ));
+    }
+
+    Ok(table)
+}
+
+fn derive_operation(
+    table: &TableMetadata,
+    assignment: &ElementAssignment,
+    functions: &FunctionCatalog,
+) -> Result<ParsedOperation, DbError> {
+    let column_name = &assignment.column.value;
+    let column = table.columns.get(column_name).ok_or_else(|| {
+        DbError::new(
+            ErrorCode::Invalid,
+            format!("Undefined column name {}", column_name),
+        )
+    })?;
+
+    if column.kind.is_primary_key() {
+        return Err(DbError::new(
+            ErrorCode::Invalid,
+            format!("PRIMARY KEY part {} found in SET part", column_name),
+        ));
+    }
+
+    let column_type = &column.column_type;
+
+    if let Some(field) = &assignment.field {
+        let ColumnType::Udt(user_type) = column_type else {
+            return Err(DbError::new(
+                ErrorCode::Invalid,
+                format!(
+                    "Invalid operation ({}.{}) for non-UDT or frozen column {}",
+                    column_name, field, column_name
+                ),
+            ));
+        };
+        let (position, field_type) = user_type.field(&field.value).ok_or_else(|| {
+            DbError::new(
+                ErrorCode::Invalid,
+                format!("Unknown field {} in user type {}", field, user_type.name),
+            )
+        })?;
+        let value = match &assignment.value {
+            Some(value) => literal(field_type, value, functions)?,
+            None => ParsedExpr::Literal(None),
+        };
+
+        return Ok(ParsedOperation {
+            column: column.clone(),
+            operation: Operation::SetField(position, value),
+        });
+    }
+
+    let is_column =
+        |expr: &Expr| matches!(expr, Expr::Identifier(ident) if &ident.value == column_name);
+    let invalid = |operation: &str| {
+        Err(DbError::new(
+            ErrorCode::Invalid,
+            format!(
+                "Invalid operation ({}) for {:?} column {}",
+                operation, column_type, column_name
+            ),
+        ))
+    };
+    let operation = match (&assignment.element, &assignment.value) {
+        (Some(element), value) => {
+            let (key_type, value_type) = match column_type {
+                ColumnType::List(value_type) => (&ColumnType::Int, value_type.as_ref()),
+                ColumnType::Map(key_type, value_type) => (key_type.as_ref(), value_type.as_ref()),
+                _ => return invalid(&format!("{}[{}]", column_name, element)),
+            };
+            let value = match value {
+                Some(value) => literal(value_type, value, functions)?,
+                None => ParsedExpr::Literal(None),
+            };
+
+            Operation::SetElement(literal(key_type, element, functions)?, value)
+        }
+        (None, None) => Operation::Set(ParsedExpr::Literal(None)),
+        (None, Some(Expr::BinaryOp { left, op, right }))
+            if column_type == &ColumnType::Counter && (is_column(left) || is_column(right)) =>
+        {
+            match (op, is_column(left)) {
+                (Operator::Plus, true) => {
+                    Operation::Increment(literal(column_type, right, functions)?)
+                }
+                (Operator::Plus, false) => {
+                    Operation::Increment(literal(column_type, left, functions)?)
+                }
+                (Operator::Minus, true) => {
+                    Operation::Decrement(literal(column_type, right, functions)?)
+                }
+                _ => return invalid(&format!("{} = {} {} {}", column_name, left, op, right)),
+            }
+        }
+        (None, Some(_)) if column_type == &ColumnType::Counter => {
+            return Err(DbError::new(
+                ErrorCode::Invalid,
+                format!(
+                    "Cannot set the value of counter column {} \
+                     (counters can only be incremented/decremented, not set)",
+                    column_name
+                ),
+            ))
+        }
+        (None, Some(Expr::BinaryOp { left, op, right })) if is_column(left) || is_column(right) => {
+            if !column_type.is_collection() {
+                return invalid(&format!("{} = {} {} {}", column_name, left, op, right));
+            }
+
+            match (op, is_column(left)) {
+                (Operator::Plus, true) => {
+                    Operation::Append(literal(column_type, right, functions)?)
+                }
+                (Operator::Plus, false) => match column_type {
+                    ColumnType::List(_) => {
+                        Operation::Prepend(literal(column_type, left, functions)?)
+                    }
+                    _ => Operation::Append(literal(column_type, left, functions)?),
+                },
+                // Map entries are removed by a set of their keys
+                (Operator::Minus, true) => match column_type {
+                    ColumnType::Map(key_type, _) => Operation::Remove(literal(
+                        &ColumnType::Set(key_type.clone()),
+                        right,
+                        functions,
+                    )?),
+                    _ => Operation::Remove(literal(column_type, right, functions)?),
+                },
+                _ => return invalid(&format!("{} = {} {} {}", column_name, left, op, right)),
+            }
+        }
+        (None, Some(value)) => Operation::Set(literal(column_type, value, functions)?),
+    };
+
+    Ok(ParsedOperation {
+        column: column.clone(),
+        operation,
+    })
+}
+
+// UPDATE needs the full primary key, a DELETE of whole rows only a prefix of
+// the clustering key
+fn derive_primary_key(
+    selection: &Expr,
+    table: &TableMetadata,
+    full_key: bool,
+    functions: &FunctionCatalog,
+) -> Result<(Vec<ParsedExpr>, Vec<ParsedExpr>), DbError> {
+    for predicate in conjuncts(selection) {
+        let column = match predicate {
+            Expr::BinaryOp {
+                left,
+                op: Operator::Eq,
+                ..
+            } => match left.as_ref() {
+                Expr::Identifier(ident) => table.columns.get(&ident.value),
+                _ => None,
+            },
+            _ => None,
+        };
+
+        match column {
+            Some(column) if !column.kind.is_primary_key() => {
+                return Err(DbError::new(
+                    ErrorCode::Invalid,
+                    format!(
+                        "Non PRIMARY KEY columns found in where clause: {}",
+                        column.name
+                    ),
+                ))
+            }
+            Some(_) => {}
+            None => {
+                return Err(DbError::new(
+                    ErrorCode::Invalid,
+                    format!("Unsupported restriction: {}", predicate),
+                ))
+            }
+        }
+    }
+
+    let restrictions = equality_restrictions(Some(selection), table, functions)?;
+    let partition_key = key_prefix(&table.partition_key, &restrictions);
+    let clustering_key = key_prefix(&table.cluster_key, &restrictions);
+
+    check_primary_key(table, &partition_key, &clustering_key, full_key)?;
+
+    Ok((partition_key, clustering_key))
+}
+
+fn check_primary_key(
+    table: &TableMetadata,
+    partition_key: &[ParsedExpr],
+    clustering_key: &[ParsedExpr],
+    full_key: bool,
+) -> Result<(), DbError> {
+    if partition_key.len() < table.partition_key.len() {
+        return Err(DbError::new(
+            ErrorCode::Invalid,
+            format!(
+                "Some partition key parts are missing: {}",
+                table.partition_key[partition_key.len()..].join(", ")
+            ),
+        ));
+    }
+
+    if full_key && clustering_key.len() < table.cluster_key.len() {
+        return Err(DbError::new(
+            ErrorCode::Invalid,
+            format!(
+                "Some clustering keys are missing: {}",
+                table.cluster_key[clustering_key.len()..].join(", ")
+            ),
+        ));
+    }
+
+    Ok(())
+}
+
+// `key = literal` restrictions among the predicates of a WHERE clause
+fn equality_restrictions(
+    selection: Option<&Expr>,
+    table: &TableMetadata,
+    functions: &FunctionCatalog,
+) -> Result<HashMap<String, ParsedExpr>, DbError> {
+    let mut restrictions = HashMap::new();
+
+    for predicate in selection.map(conjuncts).unwrap_or_default() {
+        if let Expr::BinaryOp {
+            left,
+            op: Operator::Eq,
+            right,
+        } = predicate
+        {
+            let Expr::Identifier(ident) = left.as_ref() else {
+                continue;
+            };
+
+            // Other columns are left to the filters
+            if let Some(column) = table.columns.get(&ident.value) {
+                if !table.is_primary_key(&ident.value) {
+                    continue;
+                }
+
+                restrictions.insert(
+                    ident.value.clone(),
+                    literal(&column.column_type, right, functions)?,
+                );
+            }
+        }
+    }
+
+    Ok(restrictions)
+}
+
+// The values of the longest run of leading key columns that are restricted
+fn key_prefix(columns: &[String], restrictions: &HashMap<String, ParsedExpr>) -> Vec<ParsedExpr> {
+    columns
+        .iter()
+        .map_while(|column| restrictions.get(column).cloned())
+        .collect()
+}
+
+// Rows can only be grouped by a primary key prefix covering at least the
+// partition key, though columns restricted to a single value may be left out
+// DISTINCT selects partitions, so only partition key and static columns may
+// be read
+fn check_distinct(
+    table: &TableMetadata,
+    projection: &[ParsedExpr],
+    filters: &[ParsedFilter],
+) -> Result<(), DbError> {
+    let of_partition = |column: &&str| {
+        matches!(
+            table.columns[*column].kind,
+            Kind::PartitionKey | Kind::Static
+        )
+    };
+    let selected = projection
+        .iter()
+        .flat_map(ParsedExpr::columns)
+        .collect::<Vec<_>>();
+
+    if let Some(column) = selected.iter().find(|column| !of_partition(column)) {
+        return Err(DbError::new(
+            ErrorCode::Invalid,
+            format!(
+                "SELECT DISTINCT queries must only request partition key columns and/or static columns (not {})",
+                column
+            ),
+        ));
+    }
+
+    if let Some(column) = table
+        .partition_key
+        .iter()
+        .find(|key| !selected.contains(&key.as_str()))
+    {
+        return Err(DbError::new(
+            ErrorCode::Invalid,
+            format!(
+                "SELECT DISTINCT queries must request all the partition key columns (missing {})",
+                column
+            ),
+        ));
+    }
+
+    let mut restricted = filters
+        .iter()
+        .flat_map(|filter| filter.args.iter().flat_map(ParsedExpr::columns));
+
+    if !restricted.all(|column| of_partition(&column)) {
+        return Err(DbError::new(
+            ErrorCode::Invalid,
+            "SELECT DISTINCT with WHERE clause only supports restriction by partition key and/or static columns."
+                .to_string(),
+        ));
+    }
+
+    Ok(())
+}
+
+fn derive_group_by(
+    exprs: &[Expr],
+    table: &TableMetadata,
+    restrictions: &HashMap<String, ParsedExpr>,
+) -> Result<Vec<String>, DbError> {
+    if exprs.is_empty() {
+        return Ok(vec![]);
+    }
+
+    let mut columns = vec![];
+
+    for expr in exprs {
+        match expr {
+            Expr::Identifier(ident) if table.is_primary_key(&ident.value) => {
+                columns.push(ident.value.clone())
+            }
+            _ => {
+                return Err(DbError::new(
+                    ErrorCode::Invalid,
+                    format!(
+                        "Group by is currently only supported on the columns of the PRIMARY KEY, got {}",
+                        expr
+                    ),
+                ))
+            }
+        }
+    }
+
+    let mut remaining = columns.iter().peekable();
+    let mut prefix = vec![];
+
+    for column in table.partition_key.iter().chain(&table.cluster_key) {
+        if remaining.next_if(|next| *next == column).is_some()
+            || (remaining.peek().is_some() && restrictions.contains_key(column))
+        {
+            prefix.push(column.clone());
+        } else {
+            break;
+        }
+    }
+
+    if remaining.peek().is_some() {
+        return Err(DbError::new(
+            ErrorCode::Invalid,
+            "Group by currently only support groups of columns following their declared order in the PRIMARY KEY".to_string(),
+        ));
+    }
+
+    if prefix.len() < table.partition_key.len() {
+        return Err(DbError::new(
+            ErrorCode::Invalid,
+            "Group by on a part of the partition key is not supported".to_string(),
+        ));
+    }
+
+    Ok(prefix)
+}
+
+fn conjuncts(expr: &Expr) -> Vec<&Expr> {
+    match expr {
+        Expr::BinaryOp {
+            left,
+            op: Operator::And,
+            right,
+        } => [conjuncts(left), conjuncts(right)].concat(),
+        Expr::Nested(expr) => conjuncts(expr),
+        _ => vec![expr],
+    }
+}
+
+fn parse_create_table(
+    create_table: &CreateTable,
+    types: &Types,
+) -> Result<ParsedStatement, DbError> {
+    let invalid = |message: String| Err(DbError::new(ErrorCode::Invalid, message));
+
+    if is_system(&create_table.name) {
+        return Err(not_modifiable());
+    }
+
+    let mut columns = IndexMap::new();
+    // The key is either given by a column level PRIMARY KEY, the column being
+    // the whole key, or by a table level one
+    let column_keys = create_table
+        .columns
+        .iter()
+        .filter(|column_def| column_def.primary_key)
+        .collect::<Vec<_>>();
+    let (partition_key, cluster_key) = match (&create_table.primary_key, &column_keys[..]) {
+        (None, []) => {
+            return invalid("No PRIMARY KEY specified (exactly one required)".to_string())
+        }
+        (None, [column_def]) => (vec![column_def.name.value.clone()], vec![]),
+        (Some(primary_key), []) => {
+            let names = |idents: &[Ident]| {
+                idents
+                    .iter()
+                    .map(|ident| ident.value.clone())
+                    .collect::<Vec<_>>()
+            };
+
+            (
+                names(&primary_key.partition_key),
+                names(&primary_key.clustering_key),
+            )
+        }
+        _ => return invalid("Multiple PRIMARY KEYs specified (exactly one required)".to_string()),
+    };
+
+    for column in partition_key.iter().chain(&cluster_key) {
+        if !create_table
+            .columns
+            .iter()
+            .any(|column_def| column_def.name.value == *column)
+        {
+            return invalid(format!(
+                "Unknown definition {} referenced in PRIMARY KEY",
+                column
+            ));
+        }
+    }
+
+    for column_def in &create_table.columns {
+        let column_name = column_def.name.value.clone();
+        let is_static = column_def.is_static;
+        let kind = if partition_key.contains(&column_name) {
+            Kind::PartitionKey
+        } else if cluster_key.contains(&column_name) {
+            Kind::Clustering
+        } else if is_static {
+            Kind::Static
+        } else {
+            Kind::Regular
+        };
+
+        if is_static && kind.is_primary_key() {
+            return Err(DbError::new(
+                ErrorCode::Invalid,
+                format!(
+                    "Static column {} cannot be part of the PRIMARY KEY",
+                    column_name
+                ),
+            ));
+        }
+
+        let column_type = resolve_type(&column_def.column_type, types)?;
+
+        if kind.is_primary_key() && column_type.references_duration() {
+            return Err(DbError::new(
+                ErrorCode::Invalid,
+                format!(
+                    "duration type is not supported for PRIMARY KEY column '{}'",
+                    column_name
+                ),
+            ));
+        }
+
+        if kind.is_primary_key() && column_type == ColumnType::Counter {
+            return Err(DbError::new(
+                ErrorCode::Invalid,
+                format!(
+                    "counter type is not supported for PRIMARY KEY column '{}'",
+                    column_name
+                ),
+            ));
+        }
+
+        // Key columns are stored as a single component, so must be frozen
+        if kind.is_primary_key() && column_type.is_multi_cell() {
+            return Err(DbError::new(
+                ErrorCode::Invalid,
+                format!(
+                    "Invalid non-frozen collection type for PRIMARY KEY component {}",
+                    column_name
+                ),
+            ));
+        }
+
+        columns.insert(
+            column_name.clone(),
+            ColumnMetadata {
+                name: column_name,
+                column_type,
+                kind,
+            },
+        );
+    }
+
+    let counters = columns
+        .values()
+        .filter(|column| !column.kind.is_primary_key())
+        .map(|column| column.column_type == ColumnType::Counter)
+        .collect::<Vec<_>>();
+
+    if counters.contains(&true) && counters.contains(&false) {
+        return Err(DbError::new(
+            ErrorCode::Invalid,
+            "Cannot mix counter and non counter columns in the same table".to_string(),
+        ));
+    }
+
+    if cluster_key.is_empty() && columns.values().any(|column| column.kind == Kind::Static) {
+        return Err(DbError::new(
+            ErrorCode::Invalid,
+            "Static columns are only useful (and thus allowed) if the table has at least one clustering column".to_string(),
+        ));
+    }
+
+    let mut options = TableOptions::default();
+
+    apply_table_options(&mut options, &create_table.options, &cluster_key, true)?;
+
+    Ok(ParsedStatement::Create(TableMetadata {
+        name: table_name(&create_table.name),
+        partition_key,
+        cluster_key,
+        columns,
+        indexes: vec![],
+        views: vec![],
+        base_table: None,
+        options,
+    }))
+}
+
+async fn parse_alter_table(
+    table_metadata: &Arc<RwLock<Tables>>,
+    name: Name,
+    options: Vec<TableOption>,
+) -> Result<ParsedStatement, DbError> {
+    let table = find_writable_table(table_metadata, &table_name(&name)).await?;
+    let mut altered = table.options.clone();
+
+    apply_table_options(&mut altered, &options, &table.cluster_key, false)?;
+
+    Ok(ParsedStatement::AlterTable {
+        table: table.name,
+        options: altered,
+    })
+}
+
+fn derive_filters(
+    selection: Option<&Expr>,
+    table: &TableMetadata,
+    functions: &FunctionCatalog,
+) -> Result<Vec<ParsedFilter>, DbError> {
+    let mut filters = vec![];
+    let scope = Scope {
+        table: Some(table),
+        functions,
+    };
+
+    for where_clause in selection.map(conjuncts).unwrap_or_default() {
+        match where_clause {
+            Expr::BinaryOp {
+                left,
+                op: op @ (Operator::Contains | Operator::ContainsKey),
+                right,
+            } => filters.push(contains_filter(left, *op, right, scope)?),
+            Expr::BinaryOp {
+                left,
+                op: Operator::Eq,
+                right,
+            } if matches!(**left, Expr::Index(..)) => {
+                filters.push(entry_filter(left, right, scope)?)
+            }
+            Expr::BinaryOp {
+                left,
+                op: Operator::Like,
+                right,
+            } => filters.push(like_filter(left, right, scope)?),
+            Expr::BinaryOp { left, op, right } => {
+                // A column, or a function of columns such as token(k)
+                let subject = match &**left {
+                    Expr::Identifier(ident) => {
+                        let column_name = ident.value.clone();
+                        let column_metadata = table.columns.get(&column_name).ok_or_else(|| {
+                            DbError::new(
+                                ErrorCode::Invalid,
+                                format!("Column not found: {}", column_name),
+                            )
+                        })?;
+
+                        ParsedExpr::Column(ProjectedColumn {
+                            target_column: column_name.clone(),
+                            resolved_name: column_name,
+                            column_metadata: column_metadata.clone(),
+                        })
+                    }
+                    Expr::Function(_) => operand(left, scope)?.ok_or_else(|| {
+                        DbError::new(
+                            ErrorCode::Invalid,
+                            "Unsupported filter expression".to_string(),
+                        )
+                    })?,
+                    _ => {
+                        return Err(DbError::new(
+                            ErrorCode::Invalid,
+                            "Left side of filter must be a column".to_string(),
+                        ))
+                    }
+                };
+                let (_, column_type) = subject.column_spec();
+                let value = match &**right {
+                    Expr::Identifier(id) if table.columns.contains_key(&id.value) => {
+                        ParsedExpr::Column(ProjectedColumn {
+                            target_column: id.value.clone(),
+                            resolved_name: id.value.clone(),
+                            column_metadata: table.columns[&id.value].clone(),
+                        })
+                    }
+                    Expr::Identifier(id) => {
+                        return Err(DbError::new(
+                            ErrorCode::Invalid,
+                            format!("Column not found: {}", id.value),
+                        ))
+                    }
+                    Expr::IsNotNull(_) | Expr::Wildcard => {
+                        return Err(DbError::new(
+                            ErrorCode::Invalid,
+                            "Unsupported filter expression".to_string(),
+                        ))
+                    }
+                    _ => term(&column_type, right, scope)?,
+                };
+
+                let filter_function = match op {
+                    Operator::Eq => eq,
+                    Operator::NotEq => neq,
+                    _ if column_type.references_duration() => {
+                        return Err(DbError::new(
+                            ErrorCode::Invalid,
+                            "Slice restrictions are not supported on duration columns".to_string(),
+                        ))
+                    }
+                    Operator::Gt => gt,
+                    Operator::GtEq => gte,
+                    Operator::Lt => lt,
+                    Operator::LtEq => lte,
+                    _ => {
+                        return Err(DbError::new(
+                            ErrorCode::Invalid,
+                            "Unsupported operator".to_string(),
+                        ))
+                    }
+                };
+
+                filters.push(ParsedFilter {
+                    filter: filter_function,
+                    args: vec![subject, value],
+                });
+            }
+            _ => {
+                return Err(DbError::new(
+                    ErrorCode::Invalid,
+                    "Unsupported where clause expression".to_string(),
+                ))
+            }
+        }
+    }
+
+    // Text is matched as a storage-attached index on its column analyzes it
+    for filter in &mut filters {
+        let ParsedExpr::Column(column) = &filter.args[0] else {
+            continue;
+        };
+        let Some(analysis) = table
+            .indexes
+            .iter()
+            .filter(|index| index.column == column.target_column)
+            .map(|index| index.kind.analysis())
+            .find(|analysis| !analysis.is_identity())
+        else {
+            continue;
+        };
+
+        filter.args = std::mem::take(&mut filter.args)
+            .into_iter()
+            .map(|arg| {
+                let (name, column_type) = arg.column_spec();
+
+                ParsedExpr::Function(ParsedFunction {
+                    function: Arc::new(analyze_function(analysis, column_type.clone())),
+                    arguments: vec![arg],
+                    resolved_name: format!("system.analyze({})", name),
+                    return_type: column_type,
+                })
+            })
+            .collect();
+    }
+
+    Ok(filters)
+}
+
+// c LIKE 'p%', on text columns a storage-attached index is on
+fn like_filter(expr: &Expr, pattern: &Expr, scope: Scope<'_>) -> Result<ParsedFilter, DbError> {
+    let (subject, column_type) = restricted_column(expr, scope)?;
+
+    if !matches!(
+        column_type.unfrozen(),
+        ColumnType::Varchar | ColumnType::Ascii
+    ) {
+        return Err(DbError::new(
+            ErrorCode::Invalid,
+            "LIKE restrictions are only supported on text columns".to_string(),
+        ));
+    }
+
+    let (name, _) = subject.column_spec();
+    let indexed = scope.table.is_some_and(|table| {
+        table.indexes.iter().any(|index| {
+            index.column == name && matches!(index.kind, IndexKind::StorageAttached(_))
+        })
+    });
+
+    if !indexed {
+        return Err(DbError::new(
+            ErrorCode::Invalid,
+            "LIKE restriction is only supported on properly indexed columns".to_string(),
+        ));
+    }
+
+    let pattern = term(&column_type, pattern, scope)?;
+
+    Ok(ParsedFilter {
+        filter: like,
+        args: vec![subject, pattern],
+    })
+}
+
+// c CONTAINS v or m CONTAINS KEY k
+fn contains_filter(
+    left: &Expr,
+    op: Operator,
+    right: &Expr,
+    scope: Scope<'_>,
+) -> Result<ParsedFilter, DbError> {
+    let (subject, column_type) = restricted_column(left, scope)?;
+    let (filter, operand_type): (FilterFunction, _) = match (op, column_type.unfrozen()) {
+        (Operator::Contains, ColumnType::List(element_type) | ColumnType::Set(element_type)) => {
+            (contains, element_type)
+        }
+        (Operator::Contains, ColumnType::Map(_, value_type)) => (contains, value_type),
+        (Operator::ContainsKey, ColumnType::Map(key_type, _)) => (contains_key, key_type),
+        _ => {
+            return Err(DbError::new(
+                ErrorCode::Invalid,
+                format!("Cannot use {} on non-collection column {}", op, left),
+            ))
+        }
+    };
+    let value = term(operand_type, right, scope)?;
+
+    Ok(ParsedFilter {
+        filter,
+        args: vec![subject, value],
+    })
+}
+
+// m[k] = v
+fn entry_filter(left: &Expr, right: &Expr, scope: Scope<'_>) -> Result<ParsedFilter, DbError> {
+    let Expr::Index(column, key) = left else {
+        return Err(DbError::new(
+            ErrorCode::Invalid,
+            "Unsupported filter expression".to_string(),
+        ));
+    };
+    let (subject, column_type) = restricted_column(column, scope)?;
+    let ColumnType::Map(key_type, value_type) = column_type.unfrozen() else {
+        return Err(DbError::new(
+            ErrorCode::Invalid,
+            format!("Invalid element restriction on column {}", column),
+        ));
+    };
+    let key = term(key_type, key, scope)?;
+    let value = term(value_type, right, scope)?;
+
+    Ok(ParsedFilter {
+        filter: entry_eq,
+        args: vec![subject, key, value],
+    })
+}
+
+fn restricted_column(expr: &Expr, scope: Scope<'_>) -> Result<(ParsedExpr, ColumnType), DbError> {
+    let column = match (expr, scope.table) {
+        (Expr::Identifier(ident), Some(table)) => table.columns.get(&ident.value),
+        _ => None,
+    }
+    .ok_or_else(|| DbError::new(ErrorCode::Invalid, format!("Column not found: {}", expr)))?;
+
+    Ok((
+        ParsedExpr::Column(ProjectedColumn {
+            target_column: column.name.clone(),
+            resolved_name: column.name.clone(),
+            column_metadata: column.clone(),
+        }),
+        column.column_type.clone(),
+    ))
+}
+
+// Whether queries can use the index yet. One that failed to build never will.
+fn is_built(index: &IndexMetadata) -> Result<bool, DbError> {
+    match index.failure() {
+        Some(message) => Err(DbError::new(
+            ErrorCode::ReadFailure,
+            format!("Index {} failed to build: {}", index.name, message),
+        )),
+        None => Ok(index.is_built()),
+    }
+}
+
+// The restrictions indexes that have been built can answer: c = v on the
+// whole value, c CONTAINS v on values, m CONTAINS KEY k on keys and m[k] = v
+// on entries, along with slices of c and c LIKE 'p%' for storage-attached
+// indexes. Rows are read through the first, so term lookups go ahead of the
+// rest.
+fn index_restrictions(
+    selection: Option<&Expr>,
+    table: &TableMetadata,
+    functions: &FunctionCatalog,
+) -> Result<Vec<IndexRestriction>, DbError> {
+    let mut restrictions: Vec<IndexRestriction> = vec![];
+    let whole = [IndexTarget::Simple, IndexTarget::Full];
+
+    for predicate in selection.map(conjuncts).unwrap_or_default() {
+        // The operator of a slice, or LIKE, or None for a term lookup
+        let (column, targets, exprs, operator) = match predicate {
+            Expr::BinaryOp { left, op, right } => match (&**left, op) {
+                (Expr::Identifier(column), Operator::Eq) => (column, whole, vec![&**right], None),
+                (Expr::Identifier(column), Operator::Contains) => {
+                    (column, [IndexTarget::Values; 2], vec![&**right], None)
+                }
+                (Expr::Identifier(column), Operator::ContainsKey) => {
+                    (column, [IndexTarget::Keys; 2], vec![&**right], None)
+                }
+                (
+                    Expr::Identifier(column),
+                    Operator::Gt | Operator::GtEq | Operator::Lt | Operator::LtEq,
+                ) => (column, whole, vec![&**right], Some(*op)),
+                (Expr::Identifier(column), Operator::Like) => (
+                    column,
+                    [IndexTarget::Simple; 2],
+                    vec![&**right],
+                    Some(Operator::Like),
+                ),
+                (Expr::Index(column, key), Operator::Eq) => match &**column {
+                    Expr::Identifier(column) => (
+                        column,
+                        [IndexTarget::Entries; 2],
+                        vec![&**key, &**right],
+                        None,
+                    ),
+                    _ => continue,
+                },
+                _ => continue,
+            },
+            _ => continue,
+        };
+        let index = table.indexes.iter().find(|index| {
+            index.column == column.value
+                && targets.contains(&index.target)
+                && (operator.is_none() || matches!(index.kind, IndexKind::StorageAttached(_)))
+        });
+        let Some(index) = index else {
+            continue;
+        };
+
+        if !is_built(index)? {
+            continue;
+        }
+
+        let column_type = &table.columns[&index.column].column_type;
+        let mut terms = exprs
+            .into_iter()
+            .zip(index.target.term_types(column_type))
+            .map(|(expr, term_type)| literal(&term_type, expr, functions))
+            .collect::<Result<Vec<_>, DbError>>()?;
+        let (lower, upper) = match (operator, terms.pop()) {
+            (None, Some(last)) => {
+                terms.push(last);
+                restrictions.push(IndexRestriction {
+                    index: index.clone(),
+                    lookup: IndexLookup::Term(terms),
+                });
+                continue;
+            }
+            (Some(Operator::Gt), Some(bound)) => (Bound::Excluded(bound), Bound::Unbounded),
+            (Some(Operator::GtEq), Some(bound)) => (Bound::Included(bound), Bound::Unbounded),
+            (Some(Operator::Lt), Some(bound)) => (Bound::Unbounded, Bound::Excluded(bound)),
+            (Some(Operator::LtEq), Some(bound)) => (Bound::Unbounded, Bound::Included(bound)),
+            (Some(_), Some(pattern)) => {
+                restrictions.push(IndexRestriction {
+                    index: index.clone(),
+                    lookup: IndexLookup::Prefix(pattern),
+                });
+                continue;
+            }
+            (_, None) => continue,
+        };
+        // The two bounds of a slice make a single range
+        let range = restrictions
+            .iter_mut()
+            .filter(|restriction| restriction.index.name == index.name)
+            .find_map(|restriction| match &mut restriction.lookup {
+                IndexLookup::Range(lower, upper) => Some((lower, upper)),
+                _ => None,
+            });
+
+        match range {
+            Some((existing, _))
+                if matches!(existing, Bound::Unbounded) && !matches!(lower, Bound::Unbounded) =>
+            {
+                *existing = lower
+            }
+            Some((_, existing))
+                if matches!(existing, Bound::Unbounded) && !matches!(upper, Bound::Unbounded) =>
+            {
+                *existing = upper
+            }
+            _ => restrictions.push(IndexRestriction {
+                index: index.clone(),
+                lookup: IndexLookup::Range(lower, upper),
+            }),
+        }
+    }
+
+    restrictions.sort_by_key(|restriction| !matches!(restriction.lookup, IndexLookup::Term(_)));
+
+    Ok(restrictions)
+}
+
+fn derive_projection(
+    select: &Select,
+    table: &TableMetadata,
+    functions: &FunctionCatalog,
+) -> Result<Vec<ParsedExpr>, DbError> {
+    let selectors = match &select.selection {
+        // Every column, in the order of the table's definition
+        Selection::Wildcard => {
+            return Ok(table
+                .columns
+                .values()
+                .map(|column| {
+                    ParsedExpr::Column(ProjectedColumn {
+                        target_column: column.name.clone(),
+                        resolved_name: column.name.clone(),
+                        column_metadata: column.clone(),
+                    })
+                })
+                .collect())
+        }
+        Selection::Selectors(selectors) => selectors,
+    };
+
+    selectors
+        .iter()
+        .map(|selector| derive_selector(&selector.expr, table, functions, selector.alias.as_ref()))
+        .collect::<Result<Vec<ParsedExpr>, DbError>>()
+}
+
+fn derive_selector(
+    expr: &Expr,
+    table: &TableMetadata,
+    functions: &FunctionCatalog,
+    alias: Option<&Ident>,
+) -> Result<ParsedExpr, DbError> {
+    let scope = Scope {
+        table: Some(table),
+        functions,
+    };
+
+    match expr {
+        Expr::Identifier(ident) => {
+            let column_name = ident.value.clone();
+            let column_metadata = table.columns.get(&column_name);
+
+            match column_metadata {
+                Some(metadata) => Ok(ParsedExpr::Column(ProjectedColumn {
+                    target_column: column_name.clone(),
+                    resolved_name: alias.map_or(column_name, |alias| alias.value.clone()),
+                    column_metadata: metadata.clone(),
+                })),
+                None => Err(DbError::new(
+                    ErrorCode::Invalid,
+                    format!("Undefined column name {}", column_name),
+                )),
+            }
+        }
+        Expr::Field(expr, field) => derive_field(expr, field, table, alias),
+        Expr::Function(function) if is_aggregate(function, functions) => {
+            derive_aggregate(function, table, functions, alias)
+        }
+        Expr::Function(function) => derive_function(function, scope, alias),
+        Expr::Cast(expr, data_type) => derive_cast(expr, data_type, scope, alias),
+        Expr::BinaryOp { .. } | Expr::Negate(_) => derive_arithmetic(expr, scope, None, alias),
+        Expr::Nested(expr) => derive_selector(expr, table, functions, alias),
+        _ => Err(DbError::new(
+            ErrorCode::Invalid,
+            format!("Unsupported selector {}", expr),
+        )),
+    }
+}
+
+// A scalar function call, resolved to the overload whose signature matches
+// the arguments. Columns can only be passed given the table they're from.
+fn derive_function(
+    function: &Function,
+    scope: Scope<'_>,
+    alias: Option<&Ident>,
+) -> Result<ParsedExpr, DbError> {
+    let (keyspace, name) = function_name(&function.name);
+    let exprs = function_arguments(function, &name)?;
+
+    if name == "token" {
+        let resolved_name = alias.map_or_else(
+            || format!("system.token({})", comma_separated(&function.args)),
+            |alias| alias.value.clone(),
+        );
+
+        return derive_token(&exprs, scope, resolved_name);
+    }
+
+    if name == "tojson" {
+        let resolved_name = alias.map_or_else(
+            || format!("system.tojson({})", comma_separated(&function.args)),
+            |alias| alias.value.clone(),
+        );
+
+        return derive_to_json(&exprs, scope, resolved_name);
+    }
+
+    if let Some(similarity) = name
+        .strip_prefix("similarity_")
+        .and_then(Similarity::from_name)
+    {
+        let resolved_name = alias.map_or_else(
+            || format!("system.{}({})", name, comma_separated(&function.args)),
+            |alias| alias.value.clone(),
+        );
+
+        return derive_similarity(&exprs, scope, similarity, resolved_name);
+    }
+
+    // fromJson is typed by what it's assigned to, see term
+    if name == "fromjson" {
+        return Err(DbError::new(
+            ErrorCode::Invalid,
+            "fromJson() cannot be used in the selection clause of a SELECT statement".to_string(),
+        ));
+    }
+
+    // Literals and markers take the type of the parameter they're passed to
+    let operands = exprs
+        .iter()
+        .map(|expr| operand(expr, scope))
+        .collect::<Result<Vec<_>, DbError>>()?;
+    let mut overloads = scope
+        .functions
+        .overloads(&name)
+        .filter(|overload| {
+            keyspace
+                .as_ref()
+                .is_none_or(|keyspace| overload.keyspace == *keyspace)
+        })
+        .peekable();
+
+    if overloads.peek().is_none() {
+        return Err(DbError::new(
+            ErrorCode::Invalid,
+            format!("Unknown function '{}'", function.name),
+        ));
+    }
 
-#[derive(Debug, Clone)]
-pub struct ParsedQuery {
-    pub partition_key: Vec<String>,
-    pub clustering_key: Vec<String>,
-    pub projection: Vec<ParsedExpr>,
-    pub filters: HashMap<String, ParsedFilter>,
-    pub table: TableMetadata,
-    pub column_count: i32,
-}
+    for overload in overloads {
+        if overload.argument_types.len() != operands.len() {
+            continue;
+        }
 
-#[derive(Debug, Clone)]
-pub struct ParsedFilter {
-    pub filter: FilterFunction,
-    pub args: Vec<ParsedExpr>,
-}
+        let arguments = exprs
+            .iter()
+            .zip(&operands)
+            .zip(&overload.argument_types)
+            .map(|((expr, operand), argument_type)| match operand {
+                Some(operand) if operand.column_spec().1 == *argument_type => Ok(operand.clone()),
+                Some(_) => Err(DbError::new(ErrorCode::Invalid, String::new())),
+                None => literal(argument_type, expr, scope.functions),
+            })
+            .collect::<Result<Vec<_>, DbError>>();
+
+        if let Ok(arguments) = arguments {
+            let resolved_name = match (alias, overload.keyspace.as_str()) {
+                (Some(alias), _) => alias.value.clone(),
+                (None, "") => format!("{}({})", name, comma_separated(&function.args)),
+                (None, keyspace) => {
+                    format!("{}.{}({})", keyspace, name, comma_separated(&function.args))
+                }
+            };
+
+            return Ok(ParsedExpr::Function(ParsedFunction {
+                function: Arc::clone(overload),
+                arguments,
+                resolved_name,
+                return_type: overload.return_type.clone(),
+            }));
+        }
+    }
 
-#[derive(Debug, Clone)]
-pub struct ParsedInsert {
-    pub table: TableMetadata,
-    pub partition_key: Vec<String>,
-    pub clustering_key: Vec<String>,
-    pub values: Vec<ParsedExpr>,
+    Err(DbError::new(
+        ErrorCode::Invalid,
+        format!(
+            "Invalid call to function {}, none of its type signatures match",
+            name
+        ),
+    ))
 }
 
-#[derive(Debug, Clone)]
-pub enum ParsedExpr {
-    Column(ProjectedColumn),
-    Function(FunctionHandle, Vec<ParsedExpr>),
-    Literal(Option<Value>),
-}
+fn parse_describe(kind: DescribeKind, name: Option<Name>) -> ParsedStatement {
+    let (keyspace, name) = match (kind, name) {
+        (_, None) => (None, None),
+        (DescribeKind::Keyspace, Some(name)) => (Some(table_name(&name)), None),
+        (DescribeKind::Type, Some(name)) => (qualifier(&name), Some(type_name(&name))),
+        (DescribeKind::Function | DescribeKind::Aggregate, Some(name)) => {
+            let (keyspace, name) = function_name(&name);
 
-pub type FunctionHandle = String;
+            (keyspace, Some(name))
+        }
+        (_, Some(name)) => (qualifier(&name), Some(name.name.value.clone())),
+    };
 
-#[derive(Debug, Clone)]
-pub struct ProjectedColumn {
-    pub target_column: String,
-    pub resolved_name: String,
-    pub column_metadata: ColumnMetadata,
+    ParsedStatement::Describe {
+        kind,
+        keyspace,
+        name,
+    }
 }
 
-// Parse SQL query
-pub async fn parse<'a>(
-    sql: String,
-    table_metadata: &Arc<RwLock<Tables>>,
-) -> Result<ParsedStatement, DbError> {
-    let dialect = CassandraDialect {};
-    let statements = Parser::parse_sql(&dialect, &sql)
-        .map_err(|error| DbError::new(ErrorCode::Invalid, error.to_string()))?;
+fn qualifier(name: &Name) -> Option<String> {
+    name.keyspace
+        .as_ref()
+        .map(|keyspace| keyspace.value.clone())
+}
 
-    if statements.len() != 1 {
-        return Err(DbError::new(
-            ErrorCode::Invalid,
-            "Only one statement is supported".to_string(),
-        ));
-    }
+// Function names are case insensitive, optionally qualified by a keyspace
+fn function_name(name: &Name) -> (Option<String>, String) {
+    (
+        name.keyspace
+            .as_ref()
+            .map(|keyspace| keyspace.value.to_lowercase()),
+        name.name.value.to_lowercase(),
+    )
+}
+
+fn function_arguments<'a>(function: &'a Function, name: &str) -> Result<Vec<&'a Expr>, DbError> {
+    function
+        .args
+        .iter()
+        .map(|argument| match argument {
+            Expr::Wildcard => Err(DbError::new(
+                ErrorCode::Invalid,
+                format!("Invalid argument {} in call to function {}", argument, name),
+            )),
+            expr => Ok(expr),
+        })
+        .collect()
+}
 
-    let statement = &statements[0];
+// What the names in an expression may refer to: the functions of the
+// catalog and, in statements on a table, its columns
+#[derive(Clone, Copy)]
+struct Scope<'a> {
+    table: Option<&'a TableMetadata>,
+    functions: &'a FunctionCatalog,
+}
 
-    match statement {
-        Statement::CreateTable(create_table) => parse_create_table(&create_table),
-        Statement::Query(query) => parse_select(table_metadata, &query).await,
-        Statement::Insert(insert) => parse_insert(table_metadata, &insert).await,
-        _ => {
-            unimplemented!()
+// Function arguments with a type of their own, or None for literals and
+// markers
+fn operand(expr: &Expr, scope: Scope<'_>) -> Result<Option<ParsedExpr>, DbError> {
+    match (expr, scope.table) {
+        (Expr::Identifier(ident), Some(table)) if table.columns.contains_key(&ident.value) => {
+            Ok(Some(ParsedExpr::Column(ProjectedColumn {
+                target_column: ident.value.clone(),
+                resolved_name: ident.value.clone(),
+                column_metadata: table.columns[&ident.value].clone(),
+            })))
+        }
+        (Expr::Field(expr, field), Some(table)) => derive_field(expr, field, table, None).map(Some),
+        (Expr::Function(function), _) => derive_function(function, scope, None).map(Some),
+        (Expr::Cast(expr, data_type), _) => derive_cast(expr, data_type, scope, None).map(Some),
+        _ if is_operation(expr, scope.table) => {
+            derive_arithmetic(expr, scope, None, None).map(Some)
         }
+        (Expr::Nested(expr), _) => operand(expr, scope),
+        _ => Ok(None),
     }
 }
 
-// Parse SELECT statement
-async fn parse_select(
-    table_metadata: &Arc<RwLock<Tables>>,
-    query: &Box<Query>,
-) -> Result<ParsedStatement, DbError> {
-    if let SetExpr::Select(select) = &query.body.deref() {
-        let table = derive_table_metadata(&table_metadata, &select)
-            .await
-            .map_err(|error| DbError::new(ErrorCode::Invalid, "".to_string()))?;
-        let projection: Vec<ParsedExpr> = derive_projection(&select, &table)
-            .map_err(|error| DbError::new(ErrorCode::Invalid, "".to_string()))?;
-        let (filters, partition_key) = derive_filters(&select, &table)
-            .map_err(|error| DbError::new(ErrorCode::Invalid, "".to_string()))?;
-
-        Ok(ParsedStatement::Select(ParsedQuery {
-            filters,
-            partition_key,
-            clustering_key: vec![],
-            projection: projection.clone(),
-            table: table.clone(),
-            column_count: projection.len() as i32,
-        }))
-    } else {
-        unimplemented!()
+// Arithmetic on anything but a negative literal, such as -1 or -Infinity
+fn is_operation(expr: &Expr, table: Option<&TableMetadata>) -> bool {
+    match expr {
+        Expr::BinaryOp { op, .. } => operator_function(op).is_some(),
+        Expr::Negate(expr) => match &**expr {
+            Expr::Literal(_) => false,
+            Expr::Identifier(ident) => {
+                table.is_some_and(|table| table.columns.contains_key(&ident.value))
+            }
+            _ => true,
+        },
+        Expr::Nested(expr) => is_operation(expr, table),
+        _ => false,
     }
 }
 
-async fn parse_insert(
-    table_metadata: &Arc<RwLock<Tables>>,
-    insert: &sqlparser::ast::Insert,
-) -> Result<ParsedStatement, DbError> {
-    let table_name = insert.table_name.to_string();
-    let table = table_metadata
-        .read()
-        .await
-        .get(&table_name)
-        .ok_or_else(|| DbError::new(ErrorCode::Invalid, "Table not found".to_string()))?
-        .clone();
+fn operator_function(op: &Operator) -> Option<&'static str> {
+    let symbol = op.to_string();
 
-    let mut partition_key = vec![];
-    let mut clustering_key = vec![];
-    let mut values = vec![];
+    OPERATORS
+        .iter()
+        .find(|(_, operator)| *operator == symbol)
+        .map(|(name, _)| *name)
+}
 
-    for (i, column) in insert.columns.iter().enumerate() {
-        let column_name = column.value.clone();
+// An operation calls the operator's overload for the types of its operands.
+// Literals and markers take the type of the other operand, or else of the
+// value expected, if they can be read as it.
+fn derive_arithmetic(
+    expr: &Expr,
+    scope: Scope<'_>,
+    expected: Option<&ColumnType>,
+    alias: Option<&Ident>,
+) -> Result<ParsedExpr, DbError> {
+    let (name, symbol, exprs) = match expr {
+        Expr::Nested(expr) => return derive_arithmetic(expr, scope, expected, alias),
+        Expr::BinaryOp { left, op, right } => (
+            operator_function(op),
+            op.to_string(),
+            vec![&**left, &**right],
+        ),
+        Expr::Negate(expr) => (Some("_negate"), "-".to_string(), vec![&**expr]),
+        _ => (None, String::new(), vec![]),
+    };
+    let name = name.ok_or_else(|| {
+        DbError::new(
+            ErrorCode::Invalid,
+            format!("Unsupported operation {}", expr),
+        )
+    })?;
+    let operands = exprs
+        .iter()
+        .map(|expr| operand(expr, scope))
+        .collect::<Result<Vec<_>, DbError>>()?;
+    let hint = operands
+        .iter()
+        .flatten()
+        .next()
+        .map(|operand| operand.column_spec().1)
+        .or_else(|| expected.cloned());
+    let (arguments, argument_types): (Vec<_>, Vec<_>) = exprs
+        .iter()
+        .zip(operands)
+        .map(|(expr, operand)| match operand {
+            Some(operand) => {
+                let (_, operand_type) = operand.column_spec();
+
+                Ok((operand, operand_type))
+            }
+            None => operation_literal(expr, hint.as_ref(), scope.functions),
+        })
+        .collect::<Result<Vec<_>, DbError>>()?
+        .into_iter()
+        .unzip();
+    let overload = scope
+        .functions
+        .overloads(name)
+        .find(|overload| overload.argument_types == argument_types)
+        .ok_or_else(|| {
+            let operands = argument_types
+                .iter()
+                .map(ToString::to_string)
+                .collect::<Vec<_>>();
 
-        let column_metadata = table.columns.get(&column_name).ok_or_else(|| {
             DbError::new(
                 ErrorCode::Invalid,
-                format!("Column {} not found", column_name),
+                format!(
+                    "the '{}' operation is not supported between {}",
+                    symbol,
+                    operands.join(" and ")
+                ),
             )
         })?;
 
-        // let value_expr = &insert.source.clone().unwrap().body.as_ref().unwrap().values().unwrap()[0][i];
-        let body = *insert.source.clone().unwrap().body;
-        let value_expr = match body {
-            SetExpr::Values(ref values) => {
-                if i < values.rows[0].len() {
-                    &values.rows[0][i]
-                } else {
-                    return Err(DbError::new(
-                        ErrorCode::Invalid,
-                        "Value not found".to_string(),
-                    ));
-                }
-            }
-            _ => {
-                return Err(DbError::new(
-                    ErrorCode::Invalid,
-                    "Unsupported insert source".to_string(),
-                ))
-            }
-        };
-        let value = match value_expr {
-            Expr::Value(val) => {
-                if column_metadata.kind == Kind::PartitionKey {
-                    partition_key.push(format!("{}", val));
-                } else if column_metadata.kind == Kind::Clustering {
-                    clustering_key.push(format!("{}", val));
-                }
-                ParsedExpr::Literal(Value::from_sql_value(column_metadata.column_type, val))
-            }
-            _ => {
-                return Err(DbError::new(
-                    ErrorCode::Invalid,
-                    "Unsupported value expression".to_string(),
-                ))
-            }
-        };
+    Ok(ParsedExpr::Function(ParsedFunction {
+        function: Arc::clone(overload),
+        arguments,
+        resolved_name: alias.map_or_else(|| expr.to_string(), |alias| alias.value.clone()),
+        return_type: overload.return_type.clone(),
+    }))
+}
 
-        values.push(value);
+// Read as the hinted type if it can be, or else as the type its form prefers
+fn operation_literal(
+    expr: &Expr,
+    hint: Option<&ColumnType>,
+    functions: &FunctionCatalog,
+) -> Result<(ParsedExpr, ColumnType), DbError> {
+    if let Some((Ok(literal), hint)) = hint.map(|hint| (literal(hint, expr, functions), hint)) {
+        return Ok((literal, hint.clone()));
+    }
 
-        match column_metadata.kind {
-            Kind::PartitionKey => partition_key.push(column_name),
-            Kind::Clustering => clustering_key.push(column_name),
-            _ => {}
+    let preferred = preferred_type(expr).ok_or_else(|| {
+        DbError::new(
+            ErrorCode::Invalid,
+            format!("Cannot infer the type of {}, use a type cast", expr),
+        )
+    })?;
+
+    Ok((literal(&preferred, expr, functions)?, preferred))
+}
+
+// Integers are read as the narrowest of int, bigint and varint holding them,
+// other numbers as doubles, strings as text and duration literals as durations
+fn preferred_type(expr: &Expr) -> Option<ColumnType> {
+    match expr {
+        Expr::Literal(Literal::Number(number))
+            if number.contains(['.', 'e', 'E']) || number == "NaN" || number == "Infinity" =>
+        {
+            Some(ColumnType::Double)
         }
+        Expr::Literal(Literal::Number(number)) if number.parse::<i32>().is_ok() => {
+            Some(ColumnType::Int)
+        }
+        Expr::Literal(Literal::Number(number)) if number.parse::<i64>().is_ok() => {
+            Some(ColumnType::Bigint)
+        }
+        Expr::Literal(Literal::Number(_)) => Some(ColumnType::Varint),
+        Expr::Literal(Literal::String(_)) => Some(ColumnType::Varchar),
+        Expr::Literal(Literal::Duration(_)) => Some(ColumnType::Duration),
+        Expr::Negate(expr) | Expr::Nested(expr) => preferred_type(expr),
+        _ => None,
     }
+}
 
-    Ok(ParsedStatement::Insert(ParsedInsert {
-        table,
-        partition_key,
-        clustering_key,
-        values,
+// CAST(x AS type) calls the castAsType overload for the type of x
+fn derive_cast(
+    expr: &Expr,
+    data_type: &str,
+    scope: Scope<'_>,
+    alias: Option<&Ident>,
+) -> Result<ParsedExpr, DbError> {
+    let target = ColumnType::from_cql_type(data_type.to_string())
+        .ok_or_else(|| DbError::new(ErrorCode::Invalid, format!("Unknown type {}", data_type)))?;
+    let argument = operand(expr, scope)?.ok_or_else(|| {
+        DbError::new(
+            ErrorCode::Invalid,
+            format!("Only selectors can be cast, got {}", expr),
+        )
+    })?;
+    let (_, source) = argument.column_spec();
+    let name = format!("castas{}", target);
+    let overload = scope
+        .functions
+        .overloads(&name)
+        .find(|overload| overload.argument_types == [source.clone()])
+        .ok_or_else(|| {
+            DbError::new(
+                ErrorCode::Invalid,
+                format!("{} cannot be cast to {}", expr, target),
+            )
+        })?;
+
+    Ok(ParsedExpr::Function(ParsedFunction {
+        function: Arc::clone(overload),
+        arguments: vec![argument],
+        resolved_name: alias.map_or_else(
+            || format!("cast({} as {})", expr, target),
+            |alias| alias.value.clone(),
+        ),
+        return_type: target,
     }))
 }
 
-fn parse_create_table(create_table: &CreateTable) -> Result<ParsedStatement, DbError> {
-    let mut columns = IndexMap::new();
+// token() takes the partition key columns in order, or literals of their types
+fn derive_token(
+    exprs: &[&Expr],
+    scope: Scope<'_>,
+    resolved_name: String,
+) -> Result<ParsedExpr, DbError> {
+    let invalid = |message: String| DbError::new(ErrorCode::Invalid, message);
+    let table = scope
+        .table
+        .ok_or_else(|| invalid("Invalid call to function token".to_string()))?;
+
+    if exprs.len() != table.partition_key.len() {
+        return Err(invalid(format!(
+            "Invalid number of arguments in call to function token: {} required but {} provided",
+            table.partition_key.len(),
+            exprs.len()
+        )));
+    }
 
-    for column_def in &create_table.columns {
-        let column_name = column_def.name.value.clone();
-        let partition = column_def
-            .options
-            .iter()
-            .any(move |option| match option.option {
-                ColumnOption::Unique { is_primary, .. } => is_primary,
-                _ => false,
-            });
+    let arguments = exprs
+        .iter()
+        .zip(&table.partition_key)
+        .map(|(expr, key)| match operand(expr, scope)? {
+            Some(ParsedExpr::Column(column)) if column.target_column == *key => {
+                Ok(ParsedExpr::Column(column))
+            }
+            Some(_) => Err(invalid(format!(
+                "The token function arguments must be the partition key columns in order: {}",
+                table.partition_key.join(", ")
+            ))),
+            None => literal(&table.columns[key].column_type, expr, scope.functions),
+        })
+        .collect::<Result<Vec<_>, DbError>>()?;
+    let token = scope
+        .functions
+        .overloads("token")
+        .next()
+        .ok_or_else(|| invalid("Unknown function 'token'".to_string()))?;
+
+    Ok(ParsedExpr::Function(ParsedFunction {
+        function: Arc::clone(token),
+        arguments,
+        resolved_name,
+        return_type: token.return_type.clone(),
+    }))
+}
 
-        let kind = if partition {
-            Kind::PartitionKey
-        } else {
-            Kind::Regular
-        };
+fn derive_to_json(
+    exprs: &[&Expr],
+    scope: Scope<'_>,
+    resolved_name: String,
+) -> Result<ParsedExpr, DbError> {
+    let invalid = |message: String| DbError::new(ErrorCode::Invalid, message);
+    let [expr] = exprs else {
+        return Err(invalid(format!(
+            "toJson() only accepts one argument ({} given)",
+            exprs.len()
+        )));
+    };
+    let argument = operand(expr, scope)?.ok_or_else(|| {
+        invalid(format!(
+            "Ambiguous call to function toJson, the type of {} can't be inferred",
+            expr
+        ))
+    })?;
+    let function = to_json_function(argument.column_spec().1);
+
+    Ok(ParsedExpr::Function(ParsedFunction {
+        return_type: function.return_type.clone(),
+        function: Arc::new(function),
+        arguments: vec![argument],
+        resolved_name,
+    }))
+}
 
-        let column_type = column_def.data_type.to_string().to_lowercase();
+// The similarity functions take two vectors of floats of any one dimension,
+// a literal taking the type of the other argument
+fn derive_similarity(
+    exprs: &[&Expr],
+    scope: Scope<'_>,
+    similarity: Similarity,
+    resolved_name: String,
+) -> Result<ParsedExpr, DbError> {
+    let invalid = |message: String| DbError::new(ErrorCode::Invalid, message);
+    let name = format!("similarity_{}", similarity.name());
+    let [left, right] = exprs else {
+        return Err(invalid(format!(
+            "Invalid number of arguments for function system.{}, expected 2 but got {}",
+            name,
+            exprs.len()
+        )));
+    };
+    let operands = [operand(left, scope)?, operand(right, scope)?];
+    let vector_type = operands
+        .iter()
+        .flatten()
+        .map(|operand| operand.column_spec().1)
+        .next()
+        .ok_or_else(|| {
+            invalid(format!(
+                "Ambiguous call to function {}, the type of its arguments can't be inferred",
+                name
+            ))
+        })?;
 
-        columns.insert(
-            column_name.clone(),
-            ColumnMetadata {
-                name: column_name,
-                column_type: ColumnType::from_cql_type(column_type).unwrap(),
-                kind,
-            },
-        );
+    if !matches!(&vector_type, ColumnType::Vector(element, _) if **element == ColumnType::Float) {
+        return Err(invalid(format!(
+            "Function system.{} requires vector<float, n> arguments, not {}",
+            name, vector_type
+        )));
     }
 
-    let table_name = create_table.name.to_string();
-    let partition_keys = columns
-        .iter()
-        .filter(|(_, col)| col.kind == Kind::PartitionKey)
-        .map(|(name, _)| name.clone())
-        .collect();
+    let arguments = [left, right]
+        .into_iter()
+        .zip(operands)
+        .map(|(expr, operand)| match operand {
+            Some(operand) if operand.column_spec().1 == vector_type => Ok(operand),
+            Some(operand) => Err(invalid(format!(
+                "Function system.{} requires arguments of the same type, got {} and {}",
+                name,
+                vector_type,
+                operand.column_spec().1
+            ))),
+            None => literal(&vector_type, expr, scope.functions),
+        })
+        .collect::<Result<Vec<_>, DbError>>()?;
+    let function = similarity_function(similarity, vector_type);
+
+    Ok(ParsedExpr::Function(ParsedFunction {
+        return_type: function.return_type.clone(),
+        function: Arc::new(function),
+        arguments,
+        resolved_name,
+    }))
+}
 
-    Ok(ParsedStatement::Create(TableMetadata {
-        name: table_name,
-        columns,
-        partition_key: partition_keys,
-        cluster_key: vec![],
+fn derive_from_json(
+    function: &Function,
+    column_type: &ColumnType,
+    scope: Scope<'_>,
+) -> Result<ParsedExpr, DbError> {
+    let exprs = function_arguments(function, "fromjson")?;
+    let [expr] = exprs.as_slice() else {
+        return Err(DbError::new(
+            ErrorCode::Invalid,
+            format!(
+                "fromJson() only accepts one argument ({} given)",
+                exprs.len()
+            ),
+        ));
+    };
+
+    Ok(ParsedExpr::Function(ParsedFunction {
+        function: Arc::new(from_json_function(column_type.clone())),
+        arguments: vec![term(&ColumnType::Varchar, expr, scope)?],
+        resolved_name: format!("system.fromjson({})", comma_separated(&function.args)),
+        return_type: column_type.clone(),
     }))
 }
 
-fn derive_filters(
-    select: &Box<Select>,
+// count(*), count(1) and count, min, max, sum or avg of a column fold every
+// selected row into one, as do user-defined aggregates
+fn derive_aggregate(
+    function: &Function,
     table: &TableMetadata,
-) -> anyhow::Result<(HashMap<String, ParsedFilter>, Vec<String>)> {
-    let mut filters = HashMap::new();
-    let mut partition_key: Vec<String> = vec![];
-
-    if let Some(where_clause) = &select.selection {
-        match where_clause {
-            Expr::BinaryOp { left, op, right } => {
-                if let Expr::Identifier(ident) = &**left {
-                    let column_name = ident.value.clone();
-                    let is_partition_key = table.partition_key.contains(&column_name);
-                    let column_metadata = table
-                        .columns
-                        .get(&column_name)
-                        .ok_or_else(|| anyhow!("Column not found: {}", column_name))?;
-
-                    let value = match &**right {
-                        Expr::Value(val) => {
-                            if is_partition_key {
-                                partition_key.push(format!("{}", val));
-                            }
-
-                            ParsedExpr::Literal(Value::from_sql_value(
-                                column_metadata.column_type,
-                                val,
-                            ))
-                        }
-                        Expr::Identifier(id) => ParsedExpr::Column(ProjectedColumn {
-                            target_column: id.value.clone(),
-                            resolved_name: id.value.clone(),
-                            column_metadata: table
-                                .columns
-                                .get(&id.value)
-                                .ok_or_else(|| anyhow!("Column not found: {}", id.value))?
-                                .clone(),
-                        }),
-                        _ => return Err(anyhow!("Unsupported filter expression")),
-                    };
-
-                    let filter_function = match op {
-                        BinaryOperator::Eq => eq,
-                        BinaryOperator::NotEq => neq,
-                        BinaryOperator::Gt => unimplemented!("gt"),
-                        BinaryOperator::GtEq => unimplemented!(),
-                        BinaryOperator::Lt => unimplemented!(),
-                        BinaryOperator::LtEq => unimplemented!(),
-                        _ => return Err(anyhow!("Unsupported operator")),
-                    };
-
-                    filters.insert(
-                        column_name.clone(),
-                        ParsedFilter {
-                            filter: filter_function,
-                            args: vec![
-                                ParsedExpr::Column(ProjectedColumn {
-                                    target_column: column_name.clone(),
-                                    resolved_name: column_name,
-                                    column_metadata: column_metadata.clone(),
-                                }),
-                                value,
-                            ],
-                        },
-                    );
-                    Ok((filters, partition_key))
-                } else {
-                    Err(anyhow!("Left side of filter must be a column"))
-                }
-            }
-            _ => Err(anyhow!("Unsupported where clause expression")),
+    functions: &FunctionCatalog,
+    alias: Option<&Ident>,
+) -> Result<ParsedExpr, DbError> {
+    let (_, name) = function_name(&function.name);
+    let native = AggregateFunction::from_name(&name);
+    // count(*) is named count, other calls by their argument
+    let (argument, argument_name) = match &function.args[..] {
+        [Expr::Wildcard] | [Expr::Literal(Literal::Number(_))]
+            if matches!(native, Some(AggregateFunction::Count)) =>
+        {
+            (ParsedExpr::Literal(Some(Value::Int(1))), None)
         }
-    } else {
-        Ok((filters, partition_key))
+        [Expr::Wildcard] => {
+            return Err(DbError::new(
+                ErrorCode::Invalid,
+                format!("Invalid argument * in call to function {}", name),
+            ))
+        }
+        [expr] => (
+            derive_selector(expr, table, functions, None)?,
+            Some(expr.to_string()),
+        ),
+        _ => {
+            return Err(DbError::new(
+                ErrorCode::Invalid,
+                format!("Invalid number of arguments in call to function {}", name),
+            ))
+        }
+    };
+
+    if matches!(argument, ParsedExpr::Aggregate(_)) {
+        return Err(DbError::new(
+            ErrorCode::Invalid,
+            "Aggregate functions cannot be used as arguments of aggregates".to_string(),
+        ));
     }
+
+    let (_, argument_type) = argument.column_spec();
+    let no_match = || {
+        DbError::new(
+            ErrorCode::Invalid,
+            format!(
+                "Invalid call to function {}, none of its type signatures match",
+                name
+            ),
+        )
+    };
+    let (aggregate, return_type, resolved_name) = match native {
+        Some(aggregate) => {
+            let return_type = aggregate.return_type(&argument_type).ok_or_else(no_match)?;
+            let resolved_name = match argument_name {
+                Some(argument_name) => format!("system.{}({})", name, argument_name),
+                None => name.clone(),
+            };
+
+            (aggregate, return_type, resolved_name)
+        }
+        None => {
+            let aggregate = functions
+                .aggregate_overloads(&name)
+                .find(|aggregate| aggregate.argument_types == [argument_type.clone()])
+                .ok_or_else(no_match)?;
+
+            let resolved_name = match aggregate.keyspace.as_str() {
+                "" => format!("{}({})", name, argument_name.unwrap_or_default()),
+                keyspace => format!(
+                    "{}.{}({})",
+                    keyspace,
+                    name,
+                    argument_name.unwrap_or_default()
+                ),
+            };
+
+            (
+                AggregateFunction::User(Arc::clone(aggregate)),
+                aggregate.return_type(),
+                resolved_name,
+            )
+        }
+    };
+
+    Ok(ParsedExpr::Aggregate(ParsedAggregate {
+        function: aggregate,
+        argument: Box::new(argument),
+        resolved_name: alias.map_or(resolved_name, |alias| alias.value.clone()),
+        return_type,
+    }))
 }
 
-fn derive_projection(
-    select: &Box<Select>,
-    table: &TableMetadata,
-) -> anyhow::Result<Vec<ParsedExpr>> {
-    select
-        .projection
-        .iter()
-        .map(|item| match item {
-            SelectItem::UnnamedExpr(expr) => match expr {
-                Expr::Identifier(ident) => {
-                    let column_name = ident.value.clone();
-                    let column_metadata = table.columns.get(&column_name);
-
-                    match column_metadata {
-                        Some(metadata) => Ok(ParsedExpr::Column(ProjectedColumn {
-                            target_column: column_name.clone(),
-                            resolved_name: column_name.clone(),
-                            column_metadata: metadata.clone(),
-                        })),
-                        None => Err(anyhow!("Error")),
-                    }
-                }
-                _ => unimplemented!(),
-            },
-            SelectItem::ExprWithAlias { expr, alias } => match expr {
-                Expr::Identifier(ident) => {
-                    let column_name = ident.value.clone();
-                    let column_metadata = table.columns.get(&column_name);
+// Native aggregates, or user-defined ones of the catalog
+fn is_aggregate(function: &Function, functions: &FunctionCatalog) -> bool {
+    let (_, name) = function_name(&function.name);
 
-                    match column_metadata {
-                        Some(metadata) => Ok(ParsedExpr::Column(ProjectedColumn {
-                            target_column: column_name.clone(),
-                            resolved_name: alias.value.clone(),
-                            column_metadata: metadata.clone(),
-                        })),
-                        None => Err(anyhow!("Error")),
-                    }
-                }
-                _ => unimplemented!(),
-            },
-            _ => unimplemented!(),
-        })
-        .collect::<anyhow::Result<Vec<ParsedExpr>>>()
+    AggregateFunction::from_name(&name).is_some()
+        || functions.aggregate_overloads(&name).next().is_some()
+}
+
+// `column.field` selects a field of a user type column
+fn derive_field(
+    expr: &Expr,
+    field_name: &Ident,
+    table: &TableMetadata,
+    alias: Option<&Ident>,
+) -> Result<ParsedExpr, DbError> {
+    let Expr::Identifier(column_name) = expr else {
+        return Err(DbError::new(
+            ErrorCode::Invalid,
+            "Unsupported selector".to_string(),
+        ));
+    };
+    let column_metadata = table.columns.get(&column_name.value).ok_or_else(|| {
+        DbError::new(
+            ErrorCode::Invalid,
+            format!("Undefined column name {}", column_name),
+        )
+    })?;
+    let user_type = column_metadata.column_type.user_type().ok_or_else(|| {
+        DbError::new(
+            ErrorCode::Invalid,
+            format!("{} is not a user type column", column_name),
+        )
+    })?;
+    let (position, field_type) = user_type.field(&field_name.value).ok_or_else(|| {
+        DbError::new(
+            ErrorCode::Invalid,
+            format!(
+                "Unknown field {} in user type {}",
+                field_name, user_type.name
+            ),
+        )
+    })?;
+
+    Ok(ParsedExpr::Field(
+        Box::new(ParsedExpr::Column(ProjectedColumn {
+            target_column: column_name.value.clone(),
+            resolved_name: column_name.value.clone(),
+            column_metadata: column_metadata.clone(),
+        })),
+        ProjectedField {
+            resolved_name: alias.map_or_else(
+                || format!("{}.{}", column_name, field_name),
+                |alias| alias.value.clone(),
+            ),
+            position,
+            field_type: field_type.clone(),
+        },
+    ))
 }
 
 async fn derive_table_metadata(
     tables: &Arc<RwLock<Tables>>,
-    select: &Box<Select>,
-) -> anyhow::Result<TableMetadata> {
-    let table = match &select.from.first().unwrap().relation {
-        TableFactor::Table { name, .. } => {
-            let table_name = name.to_string();
+    select: &Select,
+) -> Result<TableMetadata, DbError> {
+    let table_name = table_name(&select.table);
+    let table = tables
+        .read()
+        .await
+        .get(&table_name)
+        .cloned()
+        .or_else(|| system_table(&table_name))
+        .ok_or_else(|| DbError::new(ErrorCode::Invalid, "Table not found".to_string()))?;
 
-            tables
-                .read()
-                .await
-                .get(&table_name)
-                .ok_or_else(|| anyhow::anyhow!("Table not found"))?
-                .clone()
-        }
-        _ => unimplemented!(),
-    };
-    Ok(table.clone())
+    Ok(table)
 }
 
 // Function to check if the WHERE clause uses a partition key
@@ -394,7 +3640,7 @@ fn analyze_where_clause(
         Expr::BinaryOp { left, op, right } => {
             if let Expr::Identifier(ident) = &**left {
                 if ident.value == partition_key {
-                    if matches!(op, BinaryOperator::Eq) {
+                    if matches!(op, Operator::Eq) {
                         return (true, false); // Partition key is fully specified
                     }
                 } else if let Some(cluster_key) = clustering_key {
@@ -442,19 +3688,20 @@ mod tests {
             columns,
             partition_key: vec!["id".to_string()],
             cluster_key: vec![],
+            indexes: vec![],
+            views: vec![],
+            base_table: None,
+            options: TableOptions::default(),
         };
 
         let tables = Arc::new(RwLock::new(HashMap::new()));
         tables.write().await.insert("users".to_string(), table);
 
         let sql = "INSERT INTO users (id, name) VALUES (1, 'John Doe')".to_string();
-        let insert = Parser::parse_sql(&CassandraDialect {}, &sql)
-            .unwrap()
-            .pop()
-            .unwrap();
+        let insert = parse_statement(&sql).unwrap();
 
         if let Statement::Insert(insert) = insert {
-            let result = parse_insert(&tables, &Box::new(insert)).await;
+            let result = parse_insert(&tables, insert, &FunctionCatalog::default()).await;
             assert!(result.is_ok());
 
             if let ParsedStatement::Insert(parsed_insert) = result.unwrap() {
@@ -471,9 +3718,11 @@ mod tests {
     #[test]
     fn test_parse_create_table() {
         let tables = Arc::new(RwLock::new(HashMap::new()));
+        let types = Arc::new(RwLock::new(HashMap::new()));
+        let functions = Arc::new(RwLock::new(FunctionCatalog::default()));
         let sql = "CREATE TABLE users (id smallint PRIMARY KEY, name varchar)".to_string();
 
-        let result = tokio_test::block_on(parse(sql, &tables));
+        let result = tokio_test::block_on(parse(sql, &tables, &types, &functions));
 
         assert!(result.is_ok());
 
@@ -498,6 +3747,107 @@ mod tests {
         }
     }
 
+    #[test]
+    fn test_parse_create_table_with_collections() {
+        let tables = Arc::new(RwLock::new(HashMap::new()));
+        let types = Arc::new(RwLock::new(HashMap::new()));
+        let functions = Arc::new(RwLock::new(FunctionCatalog::default()));
+        let sql = "CREATE TABLE users (id int PRIMARY KEY, emails set<text>, \
+                   scores map<text, list<int>>, tags list<text>)"
+            .to_string();
+
+        let result = tokio_test::block_on(parse(sql, &tables, &types, &functions));
+
+        if let Ok(ParsedStatement::Create(table)) = result {
+            assert_eq!(table.partition_key, vec!["id"]);
+            assert_eq!(
+                table.columns.get("emails").unwrap().column_type,
+                ColumnType::Set(Box::new(ColumnType::Varchar))
+            );
+            assert_eq!(
+                table.columns.get("tags").unwrap().column_type,
+                ColumnType::List(Box::new(ColumnType::Varchar))
+            );
+            assert_eq!(
+                table.columns.get("scores").unwrap().column_type,
+                ColumnType::Map(
+                    Box::new(ColumnType::Varchar),
+                    Box::new(ColumnType::List(Box::new(ColumnType::Int)))
+                )
+            );
+        } else {
+            panic!("Expected ParsedStatement::Create");
+        }
+    }
+
+    #[test]
+    fn test_parse_collection_operations() {
+        let tables = Arc::new(RwLock::new(HashMap::new()));
+        let types = Arc::new(RwLock::new(HashMap::new()));
+        let functions = Arc::new(RwLock::new(FunctionCatalog::default()));
+        let create = "CREATE TABLE users (id int PRIMARY KEY, emails set<text>, \
+                      phones map<text, text>, logins list<int>)"
+            .to_string();
+
+        if let Ok(ParsedStatement::Create(table)) =
+            tokio_test::block_on(parse(create, &tables, &types, &functions))
+        {
+            tokio_test::block_on(tables.write()).insert("users".to_string(), table);
+        }
+
+        let sql = "UPDATE users SET logins = logins + [1], logins = [0] + logins, \
+                   emails = emails - {'a'}, phones = phones - {'home'}, phones['work'] = '555' \
+                   WHERE id = 1"
+            .to_string();
+
+        if let Ok(ParsedStatement::Update(update)) =
+            tokio_test::block_on(parse(sql, &tables, &types, &functions))
+        {
+            let operations = update
+                .operations
+                .iter()
+                .map(|operation| &operation.operation)
+                .collect::<Vec<_>>();
+
+            assert_eq!(update.partition_key.len(), 1);
+            assert!(matches!(
+                operations[..],
+                [
+                    Operation::Append(ParsedExpr::Literal(Some(Value::List(_)))),
+                    Operation::Prepend(ParsedExpr::Literal(Some(Value::List(_)))),
+                    Operation::Remove(ParsedExpr::Literal(Some(Value::Set(_)))),
+                    Operation::Remove(ParsedExpr::Literal(Some(Value::Set(_)))),
+                    Operation::SetElement(
+                        ParsedExpr::Literal(Some(Value::Varchar(_))),
+                        ParsedExpr::Literal(Some(Value::Varchar(_)))
+                    ),
+                ]
+            ));
+        } else {
+            panic!("Expected ParsedStatement::Update");
+        }
+
+        let sql = "DELETE logins[0] FROM users WHERE id = 1".to_string();
+
+        if let Ok(ParsedStatement::Delete(delete)) =
+            tokio_test::block_on(parse(sql, &tables, &types, &functions))
+        {
+            assert!(matches!(
+                delete.operations[0].operation,
+                Operation::SetElement(
+                    ParsedExpr::Literal(Some(Value::Int(0))),
+                    ParsedExpr::Literal(None)
+                )
+            ));
+        } else {
+            panic!("Expected ParsedStatement::Delete");
+        }
+
+        let missing_key = "UPDATE users SET logins = [1] WHERE logins = [1]".to_string();
+
+        assert!(tokio_test::block_on(parse(missing_key, &tables, &types, &functions)).is_err());
+    }
+
     #[test]
     fn test_parse() {
         let mut columns = IndexMap::new();
@@ -524,15 +3874,21 @@ mod tests {
             columns,
             partition_key: vec!["id".to_string()],
             cluster_key: vec![],
+            indexes: vec![],
+            views: vec![],
+            base_table: None,
+            options: TableOptions::default(),
         };
         let tables = Arc::new(RwLock::new(HashMap::new()));
+        let types = Arc::new(RwLock::new(HashMap::new()));
+        let functions = Arc::new(RwLock::new(FunctionCatalog::default()));
 
         tokio_test::block_on(tables.write()).insert("users".to_string(), table);
 
         let sql = "SELECT id, name FROM users".to_string();
 
         // Call the parse function
-        let result = tokio_test::block_on(parse(sql, &tables));
+        let result = tokio_test::block_on(parse(sql, &tables, &types, &functions));
 
         // Check the result
         assert!(result.is_ok());
@@ -1,9 +1,19 @@
-use crate::db::data::ColumnType;
+use crate::db::builtins::FunctionCatalog;
+use crate::db::data::{ColumnType, Value};
+use crate::db::vector::{Similarity, VectorGraph};
 use indexmap::IndexMap;
-use std::collections::HashMap;
+use std::collections::{BTreeMap, HashMap};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex, MutexGuard, PoisonError};
+use unicode_normalization::char::is_combining_mark;
+use unicode_normalization::UnicodeNormalization;
 
 pub type Tables = HashMap<String, TableMetadata>;
 
+pub type Types = HashMap<String, UserType>;
+
+pub type Functions = FunctionCatalog;
+
 #[derive(Debug)]
 pub struct Keyspace<'a> {
     pub name: String,
@@ -22,6 +32,12 @@ pub struct TableMetadata {
     pub partition_key: Vec<String>,
     pub cluster_key: Vec<String>,
     pub columns: IndexMap<String, ColumnMetadata>,
+    pub indexes: Vec<IndexMetadata>,
+    // The materialized views kept in step with writes to the table
+    pub views: Vec<TableMetadata>,
+    // Set for a materialized view, which can only be written through its base table
+    pub base_table: Option<String>,
+    pub options: TableOptions,
 }
 
 impl TableMetadata {
@@ -31,6 +47,255 @@ impl TableMetadata {
             .map(|name| name.to_string())
             .collect::<Vec<String>>()
     }
+
+    pub fn is_counter_table(&self) -> bool {
+        self.columns
+            .values()
+            .any(|column| column.column_type == ColumnType::Counter)
+    }
+
+    pub fn is_primary_key(&self, column: &str) -> bool {
+        self.partition_key
+            .iter()
+            .chain(&self.cluster_key)
+            .any(|key| key == column)
+    }
+
+    pub fn is_descending(&self, column: &str) -> bool {
+        self.options.descending.iter().any(|name| name == column)
+    }
+}
+
+// The options of CREATE TABLE ... WITH, as system_schema.tables reports them.
+// Maps hold their sub-options with Cassandra's defaults filled in.
+#[derive(Debug, Clone, PartialEq)]
+pub struct TableOptions {
+    pub bloom_filter_fp_chance: f64,
+    pub caching: BTreeMap<String, String>,
+    pub comment: String,
+    pub compaction: BTreeMap<String, String>,
+    pub compression: BTreeMap<String, String>,
+    pub default_time_to_live: i32,
+    pub gc_grace_seconds: i32,
+    // The clustering columns of CLUSTERING ORDER BY (c DESC)
+    pub descending: Vec<String>,
+    // Counts the changes to the options the table's partition is created with
+    pub generation: u32,
+}
+
+pub const COMPACTION_PACKAGE: &str = "org.apache.cassandra.db.compaction.";
+pub const COMPRESSION_PACKAGE: &str = "org.apache.cassandra.io.compress.";
+
+impl Default for TableOptions {
+    fn default() -> Self {
+        let map = |entries: &[(&str, String)]| {
+            entries
+                .iter()
+                .map(|(key, value)| (key.to_string(), value.clone()))
+                .collect()
+        };
+
+        TableOptions {
+            bloom_filter_fp_chance: 0.01,
+            caching: map(&[
+                ("keys", "ALL".to_string()),
+                ("rows_per_partition", "NONE".to_string()),
+            ]),
+            comment: String::new(),
+            compaction: map(&[
+                (
+                    "class",
+                    format!("{}SizeTieredCompactionStrategy", COMPACTION_PACKAGE),
+                ),
+                ("max_threshold", "32".to_string()),
+                ("min_threshold", "4".to_string()),
+            ]),
+            compression: map(&[
+                ("chunk_length_in_kb", "16".to_string()),
+                ("class", format!("{}LZ4Compressor", COMPRESSION_PACKAGE)),
+            ]),
+            default_time_to_live: 0,
+            gc_grace_seconds: 864000,
+            descending: vec![],
+            generation: 0,
+        }
+    }
+}
+
+// A secondary index, mapping the terms of a regular column's values to the
+// keys of the rows holding them
+#[derive(Debug, Clone)]
+pub struct IndexMetadata {
+    pub name: String,
+    pub column: String,
+    pub target: IndexTarget,
+    pub kind: IndexKind,
+    // Set once the rows written before the index was created are indexed,
+    // until when queries can't rely on it
+    built: Arc<AtomicBool>,
+    // Why indexing those rows failed, if it did
+    failure: Arc<Mutex<Option<String>>>,
+    // The vectors of a vector index, kept in memory alongside its entries
+    graph: Option<Arc<Mutex<VectorGraph>>>,
+}
+
+impl IndexMetadata {
+    pub fn new(name: String, column: String, target: IndexTarget, kind: IndexKind) -> Self {
+        let graph = match kind {
+            IndexKind::Vector(similarity) => {
+                Some(Arc::new(Mutex::new(VectorGraph::new(similarity))))
+            }
+            _ => None,
+        };
+
+        IndexMetadata {
+            name,
+            column,
+            target,
+            kind,
+            built: Arc::new(AtomicBool::new(false)),
+            failure: Arc::new(Mutex::new(None)),
+            graph,
+        }
+    }
+
+    pub fn graph(&self) -> Option<MutexGuard<'_, VectorGraph>> {
+        self.graph
+            .as_ref()
+            .map(|graph| graph.lock().unwrap_or_else(PoisonError::into_inner))
+    }
+
+    pub fn is_built(&self) -> bool {
+        self.built.load(Ordering::Acquire)
+    }
+
+    pub fn set_built(&self) {
+        self.built.store(true, Ordering::Release)
+    }
+
+    pub fn failure(&self) -> Option<String> {
+        self.failure
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+            .clone()
+    }
+
+    pub fn set_failed(&self, message: String) {
+        *self.failure.lock().unwrap_or_else(PoisonError::into_inner) = Some(message);
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum IndexKind {
+    // CREATE INDEX, answering equality restrictions
+    Secondary,
+    // CREATE CUSTOM INDEX ... USING 'StorageAttachedIndex', also answering
+    // range and LIKE 'prefix%' restrictions, and matching text as analyzed
+    StorageAttached(TextAnalysis),
+    // A storage-attached index of vectors, answering ORDER BY v ANN OF [...]
+    Vector(Similarity),
+}
+
+impl IndexKind {
+    pub fn analysis(&self) -> TextAnalysis {
+        match self {
+            IndexKind::StorageAttached(analysis) => *analysis,
+            _ => TextAnalysis::default(),
+        }
+    }
+}
+
+// How a storage-attached index matches text, as set by its options
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub struct TextAnalysis {
+    pub case_insensitive: bool,
+    pub normalize: bool,
+    pub ascii: bool,
+}
+
+impl TextAnalysis {
+    pub fn is_identity(&self) -> bool {
+        *self == TextAnalysis::default()
+    }
+
+    // The value with its text, down to that of collection elements, analyzed
+    pub fn analyze(&self, value: &Value) -> Value {
+        match value {
+            Value::Varchar(text) => Value::Varchar(self.analyze_text(text)),
+            Value::Ascii(bytes) if self.case_insensitive => {
+                Value::Ascii(bytes.to_ascii_lowercase())
+            }
+            Value::List(elements) => Value::List(
+                elements
+                    .iter()
+                    .map(|element| self.analyze(element))
+                    .collect(),
+            ),
+            Value::Set(elements) => Value::set(
+                elements
+                    .iter()
+                    .map(|element| self.analyze(element))
+                    .collect(),
+            ),
+            Value::Map(entries) => Value::map(
+                entries
+                    .iter()
+                    .map(|(key, value)| (self.analyze(key), self.analyze(value)))
+                    .collect(),
+            ),
+            value => value.clone(),
+        }
+    }
+
+    fn analyze_text(&self, text: &str) -> String {
+        let mut text = text.to_string();
+
+        if self.normalize {
+            text = text.nfc().collect();
+        }
+
+        // Accents and the like are folded into the letters they mark
+        if self.ascii {
+            text = text.nfkd().filter(|c| !is_combining_mark(*c)).collect();
+        }
+
+        if self.case_insensitive {
+            text = text.to_lowercase();
+        }
+
+        text
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum IndexTarget {
+    // The whole value, of a regular column or a frozen collection, as full(c)
+    Simple,
+    Full,
+    // The keys, values or entries of a non-frozen collection
+    Keys,
+    Values,
+    Entries,
+}
+
+impl IndexTarget {
+    // The types of the values a term of the index is made of
+    pub fn term_types(&self, column_type: &ColumnType) -> Vec<ColumnType> {
+        match (self, column_type) {
+            (IndexTarget::Keys, ColumnType::Map(key_type, _)) => vec![*key_type.clone()],
+            (
+                IndexTarget::Values,
+                ColumnType::List(element_type) | ColumnType::Set(element_type),
+            ) => {
+                vec![*element_type.clone()]
+            }
+            (IndexTarget::Values, ColumnType::Map(_, value_type)) => vec![*value_type.clone()],
+            (IndexTarget::Entries, ColumnType::Map(key_type, value_type)) => {
+                vec![*key_type.clone(), *value_type.clone()]
+            }
+            _ => vec![column_type.clone()],
+        }
+    }
 }
 
 #[derive(Debug, Clone)]
@@ -47,3 +312,37 @@ pub enum Kind {
     Regular,
     Static,
 }
+
+impl Kind {
+    pub fn is_primary_key(&self) -> bool {
+        matches!(self, Kind::PartitionKey | Kind::Clustering)
+    }
+}
+
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct UserType {
+    pub keyspace: String,
+    pub name: String,
+    pub fields: Vec<(String, ColumnType)>,
+}
+
+impl UserType {
+    pub fn field(&self, name: &str) -> Option<(usize, &ColumnType)> {
+        self.fields
+            .iter()
+            .position(|(field, _)| field == name)
+            .map(|position| (position, &self.fields[position].1))
+    }
+}
+
+// A user-defined function as it was created, its body being CQL source
+#[derive(Debug, Clone, PartialEq)]
+pub struct UserFunction {
+    pub keyspace: String,
+    pub name: String,
+    pub arguments: Vec<(String, ColumnType)>,
+    pub return_type: ColumnType,
+    pub called_on_null_input: bool,
+    pub language: String,
+    pub body: String,
+}
@@ -0,0 +1,1218 @@
+use crate::db::data::{timeuuid_from_key, timeuuid_key, ColumnType, Value};
+use crate::db::error::{DbError, ErrorCode};
+use crate::db::schema::{IndexMetadata, IndexTarget, TableMetadata, TableOptions};
+use crate::db::schema::{COMPACTION_PACKAGE, COMPRESSION_PACKAGE};
+use bigdecimal::BigDecimal;
+use fjall::compaction::{Leveled, SizeTiered, Strategy};
+use fjall::{CompressionType, KvPair, PartitionCreateOptions, Slice};
+use num_bigint::{BigInt, Sign};
+use std::collections::{BTreeMap, HashMap};
+use std::f64::consts::LN_2;
+use std::iter::from_fn;
+use std::sync::atomic::{AtomicI64, Ordering};
+use std::time::{SystemTime, UNIX_EPOCH};
+use uuid::Uuid;
+
+// Every column of a row is stored as its own cell, keyed by
+//
+//   [partition key components][clustering key components][column name][path]
+//
+// where each component is encoded so that byte order matches value order and
+// the path addresses a single element of a non-frozen collection. The cell
+// with an empty column name is the row marker written by INSERT.
+//
+// In tables with clustering columns, the clustering key is preceded by a byte
+// telling rows from the partition's static row, which takes the place of the
+// clustering key and sorts ahead of every other row of the partition. The
+// components of clustering columns in descending order have their bytes
+// inverted, which reverses their order.
+
+const ESCAPE: u8 = 0x00;
+const ESCAPED_ZERO: u8 = 0xFF;
+const TERMINATOR: u8 = 0x01;
+
+const ELEMENT: u8 = 0x01;
+const END_OF_ELEMENTS: u8 = 0x00;
+const ABSENT: u8 = 0x00;
+const PRESENT: u8 = 0x01;
+
+const NEGATIVE: u8 = 0x00;
+const ZERO: u8 = 0x01;
+const POSITIVE: u8 = 0x02;
+const END_OF_DIGITS: u8 = 0x00;
+
+const STATIC_ROW: u8 = 0x00;
+const CLUSTERING_ROW: u8 = 0x01;
+
+pub const ROW_MARKER: &str = "";
+
+pub fn row_prefix(
+    table: &TableMetadata,
+    partition_key: &[Value],
+    clustering_key: &[Value],
+) -> Vec<u8> {
+    let mut prefix = vec![];
+
+    for value in partition_key {
+        encode_component(value, &mut prefix);
+    }
+
+    if !clustering_key.is_empty() {
+        prefix.push(CLUSTERING_ROW);
+    }
+
+    for (value, column) in clustering_key.iter().zip(&table.cluster_key) {
+        let start = prefix.len();
+
+        encode_component(value, &mut prefix);
+
+        if table.is_descending(column) {
+            invert(&mut prefix[start..]);
+        }
+    }
+
+    prefix
+}
+
+fn invert(bytes: &mut [u8]) {
+    for byte in bytes {
+        *byte = !*byte;
+    }
+}
+
+// The partition options a table's WITH options map onto. Compression is LZ4
+// whichever compressor is named, as it's the one built in.
+pub fn partition_options(options: &TableOptions) -> PartitionCreateOptions {
+    let class = |map: &BTreeMap<String, String>, package: &str| {
+        map.get("class")
+            .map(|class| class.trim_start_matches(package).to_string())
+            .unwrap_or_default()
+    };
+    let mut partition_options = PartitionCreateOptions::default();
+
+    partition_options = match class(&options.compression, COMPRESSION_PACKAGE).as_str() {
+        _ if options
+            .compression
+            .get("enabled")
+            .is_some_and(|enabled| enabled == "false") =>
+        {
+            partition_options.compression(CompressionType::None)
+        }
+        "NoopCompressor" => partition_options.compression(CompressionType::None),
+        _ => {
+            let chunk_length = options
+                .compression
+                .get("chunk_length_in_kb")
+                .and_then(|chunk_length| chunk_length.parse::<u32>().ok())
+                .unwrap_or(16);
+
+            partition_options
+                .compression(CompressionType::Lz4)
+                .block_size(chunk_length.clamp(1, 512) * 1024)
+        }
+    };
+    partition_options = match class(&options.compaction, COMPACTION_PACKAGE).as_str() {
+        "LeveledCompactionStrategy" | "UnifiedCompactionStrategy" => {
+            partition_options.compaction_strategy(Strategy::Leveled(Leveled::default()))
+        }
+        _ => partition_options.compaction_strategy(Strategy::SizeTiered(SizeTiered::default())),
+    };
+
+    // A chance of 1 needs no filter at all
+    partition_options.bloom_filter_bits(bloom_filter_bits(options.bloom_filter_fp_chance))
+}
+
+// The bits per key giving at most the chance of false positives
+fn bloom_filter_bits(fp_chance: f64) -> Option<u8> {
+    let bits = (-fp_chance.ln() / (LN_2 * LN_2)).ceil();
+
+    (bits > 0.0).then_some(bits as u8)
+}
+
+// The row of the partition's static columns
+pub fn static_prefix(partition_prefix: &[u8]) -> Vec<u8> {
+    [partition_prefix, &[STATIC_ROW]].concat()
+}
+
+// The part of a key made of the partition key components
+pub fn partition_prefix<'a>(table: &TableMetadata, key: &'a [u8]) -> &'a [u8] {
+    let (_, rest) = decode_key(table, &table.partition_key, key);
+
+    &key[..key.len() - rest.len()]
+}
+
+// The smallest key after every key starting with the prefix, if there is one
+pub fn prefix_end(prefix: &[u8]) -> Option<Vec<u8>> {
+    let mut end = prefix.to_vec();
+
+    while let Some(last) = end.pop() {
+        if last < u8::MAX {
+            end.push(last + 1);
+            return Some(end);
+        }
+    }
+
+    None
+}
+
+pub fn cell_key(row_prefix: &[u8], column: &str, path: &[u8]) -> Vec<u8> {
+    let mut key = column_prefix(row_prefix, column);
+
+    key.extend_from_slice(path);
+    key
+}
+
+pub fn column_prefix(row_prefix: &[u8], column: &str) -> Vec<u8> {
+    let mut key = row_prefix.to_vec();
+
+    encode_bytes(column.as_bytes(), &mut key);
+    key
+}
+
+pub fn encode_component(value: &Value, out: &mut Vec<u8>) {
+    match value {
+        Value::Ascii(bytes) | Value::Blob(bytes) | Value::Inet(bytes) => encode_bytes(bytes, out),
+        Value::Decimal(decimal) => encode_decimal(decimal, out),
+        Value::Varint(varint) => encode_varint(varint, out),
+        Value::Varchar(string) => encode_bytes(string.as_bytes(), out),
+        Value::Boolean(boolean) => out.push(*boolean as u8),
+        Value::Tinyint(i) => out.push(*i as u8 ^ 0x80),
+        Value::Smallint(i) => out.extend((*i as u16 ^ 0x8000).to_be_bytes()),
+        Value::Int(i) | Value::Date(i) => out.extend((*i as u32 ^ 0x8000_0000).to_be_bytes()),
+        Value::Bigint(i) | Value::Counter(i) | Value::Timestamp(i) | Value::Time(i) => {
+            out.extend((*i as u64 ^ 0x8000_0000_0000_0000).to_be_bytes())
+        }
+        Value::Float(f) => {
+            let bits = f.to_bits();
+            let bits = if bits & 0x8000_0000 != 0 {
+                !bits
+            } else {
+                bits | 0x8000_0000
+            };
+
+            out.extend(bits.to_be_bytes())
+        }
+        Value::Double(f) => {
+            let bits = f.to_bits();
+            let bits = if bits & 0x8000_0000_0000_0000 != 0 {
+                !bits
+            } else {
+                bits | 0x8000_0000_0000_0000
+            };
+
+            out.extend(bits.to_be_bytes())
+        }
+        Value::Uuid(uuid) => out.extend(uuid.as_bytes()),
+        Value::Timeuuid(uuid) => out.extend(timeuuid_key(uuid)),
+        Value::Duration(months, days, nanos) => {
+            out.extend((*months as u32 ^ 0x8000_0000).to_be_bytes());
+            out.extend((*days as u32 ^ 0x8000_0000).to_be_bytes());
+            out.extend((*nanos as u64 ^ 0x8000_0000_0000_0000).to_be_bytes());
+        }
+        Value::List(values) | Value::Set(values) => {
+            for value in values {
+                out.push(ELEMENT);
+                encode_component(value, out);
+            }
+
+            out.push(END_OF_ELEMENTS);
+        }
+        Value::Map(entries) => {
+            for (key, value) in entries {
+                out.push(ELEMENT);
+                encode_component(key, out);
+                encode_component(value, out);
+            }
+
+            out.push(END_OF_ELEMENTS);
+        }
+        Value::Udt(fields) | Value::Tuple(fields) => {
+            for field in fields {
+                out.push(ELEMENT);
+
+                match field {
+                    Some(value) => {
+                        out.push(PRESENT);
+                        encode_component(value, out);
+                    }
+                    None => out.push(ABSENT),
+                }
+            }
+
+            out.push(END_OF_ELEMENTS);
+        }
+        // A vector's type fixes how many elements it has
+        Value::Vector(elements) => {
+            for element in elements {
+                encode_component(element, out);
+            }
+        }
+    }
+}
+
+pub fn decode_component<'a>(column_type: &ColumnType, bytes: &'a [u8]) -> (Value, &'a [u8]) {
+    match column_type {
+        ColumnType::Ascii => map_bytes(bytes, Value::Ascii),
+        ColumnType::Blob => map_bytes(bytes, Value::Blob),
+        ColumnType::Decimal => {
+            let (decimal, rest) = decode_decimal(bytes);
+
+            (Value::Decimal(decimal), rest)
+        }
+        ColumnType::Varint => {
+            let (varint, rest) = decode_varint(bytes);
+
+            (Value::Varint(varint), rest)
+        }
+        ColumnType::Inet => map_bytes(bytes, Value::Inet),
+        ColumnType::Varchar => map_bytes(bytes, |bytes| {
+            Value::Varchar(String::from_utf8(bytes).unwrap())
+        }),
+        ColumnType::Boolean => (Value::Boolean(bytes[0] != 0), &bytes[1..]),
+        ColumnType::Tinyint => (Value::Tinyint((bytes[0] ^ 0x80) as i8), &bytes[1..]),
+        ColumnType::Smallint => {
+            let (value, rest) = bytes.split_at(2);
+            let value = u16::from_be_bytes(value.try_into().unwrap()) ^ 0x8000;
+
+            (Value::Smallint(value as i16), rest)
+        }
+        ColumnType::Int | ColumnType::Date => {
+            let (value, rest) = bytes.split_at(4);
+            let value = (u32::from_be_bytes(value.try_into().unwrap()) ^ 0x8000_0000) as i32;
+
+            match column_type {
+                ColumnType::Int => (Value::Int(value), rest),
+                _ => (Value::Date(value), rest),
+            }
+        }
+        ColumnType::Bigint | ColumnType::Counter | ColumnType::Timestamp | ColumnType::Time => {
+            let (value, rest) = bytes.split_at(8);
+            let value =
+                (u64::from_be_bytes(value.try_into().unwrap()) ^ 0x8000_0000_0000_0000) as i64;
+
+            match column_type {
+                ColumnType::Bigint => (Value::Bigint(value), rest),
+                ColumnType::Counter => (Value::Counter(value), rest),
+                ColumnType::Timestamp => (Value::Timestamp(value), rest),
+                _ => (Value::Time(value), rest),
+            }
+        }
+        ColumnType::Float => {
+            let (value, rest) = bytes.split_at(4);
+            let bits = u32::from_be_bytes(value.try_into().unwrap());
+            let bits = if bits & 0x8000_0000 != 0 {
+                bits & !0x8000_0000
+            } else {
+                !bits
+            };
+
+            (Value::Float(f32::from_bits(bits)), rest)
+        }
+        ColumnType::Double => {
+            let (value, rest) = bytes.split_at(8);
+            let bits = u64::from_be_bytes(value.try_into().unwrap());
+            let bits = if bits & 0x8000_0000_0000_0000 != 0 {
+                bits & !0x8000_0000_0000_0000
+            } else {
+                !bits
+            };
+
+            (Value::Double(f64::from_bits(bits)), rest)
+        }
+        ColumnType::Uuid => {
+            let (value, rest) = bytes.split_at(16);
+
+            (Value::Uuid(Uuid::from_slice(value).unwrap()), rest)
+        }
+        ColumnType::Timeuuid => {
+            let (value, rest) = bytes.split_at(16);
+
+            (
+                Value::Timeuuid(timeuuid_from_key(value.try_into().unwrap())),
+                rest,
+            )
+        }
+        ColumnType::Duration => {
+            let (months, rest) = bytes.split_at(4);
+            let (days, rest) = rest.split_at(4);
+            let (nanos, rest) = rest.split_at(8);
+            let months = u32::from_be_bytes(months.try_into().unwrap()) ^ 0x8000_0000;
+            let days = u32::from_be_bytes(days.try_into().unwrap()) ^ 0x8000_0000;
+            let nanos = u64::from_be_bytes(nanos.try_into().unwrap()) ^ 0x8000_0000_0000_0000;
+
+            (
+                Value::Duration(months as i32, days as i32, nanos as i64),
+                rest,
+            )
+        }
+        ColumnType::List(element_type) | ColumnType::Set(element_type) => {
+            let mut values = vec![];
+            let mut rest = bytes;
+
+            while rest[0] == ELEMENT {
+                let (value, remaining) = decode_component(element_type, &rest[1..]);
+
+                values.push(value);
+                rest = remaining;
+            }
+
+            match column_type {
+                ColumnType::List(_) => (Value::List(values), &rest[1..]),
+                _ => (Value::Set(values), &rest[1..]),
+            }
+        }
+        ColumnType::Map(key_type, value_type) => {
+            let mut entries = vec![];
+            let mut rest = bytes;
+
+            while rest[0] == ELEMENT {
+                let (key, remaining) = decode_component(key_type, &rest[1..]);
+                let (value, remaining) = decode_component(value_type, remaining);
+
+                entries.push((key, value));
+                rest = remaining;
+            }
+
+            (Value::Map(entries), &rest[1..])
+        }
+        ColumnType::Vector(element_type, dimension) => {
+            let mut elements = vec![];
+            let mut rest = bytes;
+
+            for _ in 0..*dimension {
+                let (element, remaining) = decode_component(element_type, rest);
+
+                elements.push(element);
+                rest = remaining;
+            }
+
+            (Value::Vector(elements), rest)
+        }
+        ColumnType::Udt(user_type) => {
+            let field_types = user_type.fields.iter().map(|(_, field_type)| field_type);
+            let (fields, rest) = decode_fields(field_types, bytes);
+
+            (Value::Udt(fields), rest)
+        }
+        ColumnType::Tuple(element_types) => {
+            let (elements, rest) = decode_fields(element_types.iter(), bytes);
+
+            (Value::Tuple(elements), rest)
+        }
+        ColumnType::Frozen(inner) => decode_component(inner, bytes),
+    }
+}
+
+fn decode_fields<'a, 'b>(
+    mut field_types: impl Iterator<Item = &'a ColumnType>,
+    bytes: &'b [u8],
+) -> (Vec<Option<Value>>, &'b [u8]) {
+    let mut fields = vec![];
+    let mut rest = bytes;
+
+    while rest[0] == ELEMENT {
+        let field_type = field_types.next().unwrap_or(&ColumnType::Blob);
+
+        if rest[1] == ABSENT {
+            fields.push(None);
+            rest = &rest[2..];
+        } else {
+            let (value, remaining) = decode_component(field_type, &rest[2..]);
+
+            fields.push(Some(value));
+            rest = remaining;
+        }
+    }
+
+    (fields, &rest[1..])
+}
+
+// Varints are their sign followed by the length and bytes of their magnitude,
+// inverted for negative numbers so that larger magnitudes sort first
+fn encode_varint(varint: &BigInt, out: &mut Vec<u8>) {
+    let (sign, magnitude) = varint.to_bytes_be();
+    let flip = |byte: u8| if sign == Sign::Minus { !byte } else { byte };
+
+    match sign {
+        Sign::NoSign => out.push(ZERO),
+        Sign::Plus | Sign::Minus => {
+            out.push(if sign == Sign::Minus {
+                NEGATIVE
+            } else {
+                POSITIVE
+            });
+            out.extend((magnitude.len() as u32).to_be_bytes().map(flip));
+            out.extend(magnitude.into_iter().map(flip));
+        }
+    }
+}
+
+fn decode_varint(bytes: &[u8]) -> (BigInt, &[u8]) {
+    let (sign, rest) = (bytes[0], &bytes[1..]);
+    let flip = |byte: &u8| if sign == NEGATIVE { !byte } else { *byte };
+
+    if sign == ZERO {
+        return (BigInt::from(0), rest);
+    }
+
+    let (length, rest) = rest.split_at(4);
+    let length = u32::from_be_bytes(
+        length
+            .iter()
+            .map(flip)
+            .collect::<Vec<_>>()
+            .try_into()
+            .unwrap(),
+    );
+    let (magnitude, rest) = rest.split_at(length as usize);
+    let magnitude = magnitude.iter().map(flip).collect::<Vec<_>>();
+    let sign = if sign == NEGATIVE {
+        Sign::Minus
+    } else {
+        Sign::Plus
+    };
+
+    (BigInt::from_bytes_be(sign, &magnitude), rest)
+}
+
+// Decimals are their sign, then the exponent and significant digits of their
+// value as 0.ddd x 10^exponent, inverted for negative numbers, and finally
+// their scale so that 1.0 and 1.00 keep the scale they were written with
+fn encode_decimal(decimal: &BigDecimal, out: &mut Vec<u8>) {
+    let (unscaled, scale) = decimal.as_bigint_and_exponent();
+    let (sign, magnitude) = unscaled.into_parts();
+    let flip = |byte: u8| if sign == Sign::Minus { !byte } else { byte };
+
+    match sign {
+        Sign::NoSign => out.push(ZERO),
+        Sign::Plus | Sign::Minus => {
+            let digits = magnitude.to_string();
+            let exponent = digits.len() as i64 - scale;
+
+            out.push(if sign == Sign::Minus {
+                NEGATIVE
+            } else {
+                POSITIVE
+            });
+            out.extend(
+                (exponent as u64 ^ 0x8000_0000_0000_0000)
+                    .to_be_bytes()
+                    .map(flip),
+            );
+            out.extend(digits.trim_end_matches('0').bytes().map(flip));
+            out.push(flip(END_OF_DIGITS));
+        }
+    }
+
+    out.extend((scale as i32 as u32 ^ 0x8000_0000).to_be_bytes());
+}
+
+fn decode_decimal(bytes: &[u8]) -> (BigDecimal, &[u8]) {
+    let (sign, mut rest) = (bytes[0], &bytes[1..]);
+    let flip = |byte: &u8| if sign == NEGATIVE { !byte } else { *byte };
+    let mut digits = String::new();
+    let mut exponent = 0;
+
+    if sign != ZERO {
+        let (exponent_bytes, remaining) = rest.split_at(8);
+        let exponent_bytes = exponent_bytes.iter().map(flip).collect::<Vec<_>>();
+        let end = remaining
+            .iter()
+            .position(|byte| flip(byte) == END_OF_DIGITS)
+            .unwrap();
+
+        exponent =
+            (u64::from_be_bytes(exponent_bytes.try_into().unwrap()) ^ 0x8000_0000_0000_0000) as i64;
+        digits = remaining[..end]
+            .iter()
+            .map(|byte| flip(byte) as char)
+            .collect();
+        rest = &remaining[end + 1..];
+    }
+
+    let (scale, rest) = rest.split_at(4);
+    let scale = (u32::from_be_bytes(scale.try_into().unwrap()) ^ 0x8000_0000) as i32 as i64;
+
+    // Put back the trailing zeros of the unscaled value
+    let zeros = (exponent - digits.len() as i64 + scale).max(0) as usize;
+    let magnitude = format!("0{}{}", digits, "0".repeat(zeros))
+        .parse::<BigInt>()
+        .unwrap();
+    let unscaled = if sign == NEGATIVE {
+        -magnitude
+    } else {
+        magnitude
+    };
+
+    (BigDecimal::new(unscaled, scale), rest)
+}
+
+// Variable length components escape their zero bytes so that a terminator
+// can follow them without breaking the ordering of shorter values
+fn encode_bytes(bytes: &[u8], out: &mut Vec<u8>) {
+    for byte in bytes {
+        if *byte == ESCAPE {
+            out.extend([ESCAPE, ESCAPED_ZERO]);
+        } else {
+            out.push(*byte);
+        }
+    }
+
+    out.extend([ESCAPE, TERMINATOR]);
+}
+
+fn decode_bytes(bytes: &[u8]) -> (Vec<u8>, &[u8]) {
+    let mut decoded = vec![];
+    let mut i = 0;
+
+    loop {
+        match (bytes[i], bytes.get(i + 1)) {
+            (ESCAPE, Some(&ESCAPED_ZERO)) => {
+                decoded.push(ESCAPE);
+                i += 2;
+            }
+            (ESCAPE, Some(&TERMINATOR)) => return (decoded, &bytes[i + 2..]),
+            (byte, _) => {
+                decoded.push(byte);
+                i += 1;
+            }
+        }
+    }
+}
+
+fn map_bytes(bytes: &[u8], to_value: impl Fn(Vec<u8>) -> Value) -> (Value, &[u8]) {
+    let (decoded, rest) = decode_bytes(bytes);
+
+    (to_value(decoded), rest)
+}
+
+// Set elements and map keys are addressed by their own encoding
+pub fn element_path(element: &Value) -> Vec<u8> {
+    let mut path = vec![];
+
+    encode_component(element, &mut path);
+    path
+}
+
+// User type fields are addressed by position so that renames keep their cells
+pub fn field_path(position: usize) -> Vec<u8> {
+    element_path(&Value::Smallint(position as i16))
+}
+
+// List elements are keyed by a clock so appends never need to read the list.
+// Prepended elements take negated ticks so they sort ahead of everything else.
+static LIST_CLOCK: AtomicI64 = AtomicI64::new(0);
+
+pub fn list_element_path(prepend: bool) -> Vec<u8> {
+    let now = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap()
+        .as_nanos() as i64;
+    let tick = LIST_CLOCK
+        .fetch_update(Ordering::SeqCst, Ordering::SeqCst, |last| {
+            Some(now.max(last + 1))
+        })
+        .map_or(now, |last| now.max(last + 1));
+
+    element_path(&Value::Bigint(if prepend { -tick } else { tick }))
+}
+
+// Secondary indexes live in a partition of their own, keyed by
+//
+//   [term][row key]
+//
+// where the term is the encoding of the indexed value, element, map key or
+// map entry, so the rows holding a term are found by a prefix scan
+// A table's cells are in a partition of its name, followed by the generation
+// of its storage options once they've been altered
+pub fn table_partition(table: &TableMetadata) -> String {
+    match table.options.generation {
+        0 => table.name.clone(),
+        generation => format!("{}#{}", table.name, generation),
+    }
+}
+
+pub fn index_partition(table: &TableMetadata, index: &IndexMetadata) -> String {
+    format!("{}.{}", table.name, index.name)
+}
+
+pub fn index_term(values: &[Value]) -> Vec<u8> {
+    let mut term = vec![];
+
+    for value in values {
+        encode_component(value, &mut term);
+    }
+
+    term
+}
+
+// The terms an index keeps for a column's value, made of its text as the
+// index analyzes it
+pub fn index_terms(index: &IndexMetadata, value: &Value) -> Vec<Vec<u8>> {
+    let value = index.kind.analysis().analyze(value);
+
+    match (index.target, &value) {
+        (IndexTarget::Values, Value::List(elements) | Value::Set(elements)) => elements
+            .iter()
+            .map(|element| index_term(std::slice::from_ref(element)))
+            .collect(),
+        (IndexTarget::Values, Value::Map(entries)) => entries
+            .iter()
+            .map(|(_, value)| index_term(std::slice::from_ref(value)))
+            .collect(),
+        (IndexTarget::Keys, Value::Map(entries)) => entries
+            .iter()
+            .map(|(key, _)| index_term(std::slice::from_ref(key)))
+            .collect(),
+        (IndexTarget::Entries, Value::Map(entries)) => entries
+            .iter()
+            .map(|(key, value)| index_term(&[key.clone(), value.clone()]))
+            .collect(),
+        _ => vec![index_term(std::slice::from_ref(&value))],
+    }
+}
+
+// The start shared by the terms of every text beginning with the given one,
+// being its encoding short of the terminator
+pub fn index_term_prefix(text: &Value) -> Vec<u8> {
+    let mut term = index_term(std::slice::from_ref(text));
+
+    term.truncate(term.len() - 2);
+    term
+}
+
+// The key of the row an index entry is for, past its term
+pub fn index_row_key<'a>(term_types: &[ColumnType], entry: &'a [u8]) -> &'a [u8] {
+    term_types
+        .iter()
+        .fold(entry, |rest, term_type| decode_component(term_type, rest).1)
+}
+
+pub struct Cell {
+    pub row_prefix: Vec<u8>,
+    pub partition_key: Vec<Value>,
+    pub clustering_key: Vec<Value>,
+    pub is_static: bool,
+    pub column: String,
+    pub path: Vec<u8>,
+    pub value: Slice,
+}
+
+pub fn decode_cell(table: &TableMetadata, key: &[u8], value: Slice) -> Cell {
+    let (partition_key, rest) = decode_key(table, &table.partition_key, key);
+    let (is_static, rest) = match rest.split_first() {
+        Some((&row, rest)) if !table.cluster_key.is_empty() => (row == STATIC_ROW, rest),
+        _ => (false, rest),
+    };
+    let (clustering_key, rest) = if is_static {
+        (vec![], rest)
+    } else {
+        decode_key(table, &table.cluster_key, rest)
+    };
+    let row_prefix = key[..key.len() - rest.len()].to_vec();
+    let (column, path) = decode_bytes(rest);
+
+    Cell {
+        row_prefix,
+        partition_key,
+        clustering_key,
+        is_static,
+        column: String::from_utf8(column).unwrap(),
+        path: path.to_vec(),
+        value,
+    }
+}
+
+fn decode_key<'a>(
+    table: &TableMetadata,
+    names: &[String],
+    mut bytes: &'a [u8],
+) -> (Vec<Value>, &'a [u8]) {
+    let values = names
+        .iter()
+        .map(|name| {
+            let column_type = &table.columns[name].column_type;
+
+            if !table.is_descending(name) {
+                let (value, rest) = decode_component(column_type, bytes);
+
+                bytes = rest;
+                return value;
+            }
+
+            let mut inverted = bytes.to_vec();
+
+            invert(&mut inverted);
+
+            let (value, rest) = decode_component(column_type, &inverted);
+
+            bytes = &bytes[bytes.len() - rest.len()..];
+            value
+        })
+        .collect();
+
+    (values, bytes)
+}
+
+type Row = HashMap<String, Value>;
+
+// The rows of a range of cells from the last to the first, leaving out the
+// static row. The cells of each row stay in order, so fold into rows as ever.
+pub fn reversed_rows<I>(
+    table: TableMetadata,
+    cells: I,
+) -> impl Iterator<Item = fjall::Result<KvPair>>
+where
+    I: DoubleEndedIterator<Item = fjall::Result<KvPair>>,
+{
+    let mut cells = cells.rev().peekable();
+
+    from_fn(move || loop {
+        let (key, value) = match cells.next()? {
+            Ok(cell) => cell,
+            Err(error) => return Some(vec![Err(error)]),
+        };
+        let cell = decode_cell(&table, &key, value.clone());
+        let mut row = vec![Ok((key, value))];
+
+        while let Some(next) = cells.next_if(|next| {
+            next.as_ref()
+                .is_ok_and(|(key, _)| key.starts_with(&cell.row_prefix))
+        }) {
+            row.push(next);
+        }
+
+        if !cell.is_static {
+            row.reverse();
+            return Some(row);
+        }
+    })
+    .flatten()
+}
+
+// A row as stored, along with its key and whether it's the static row
+type StoredRow = (Vec<u8>, bool, Row);
+
+// Folds consecutive cells back into rows keyed by column name. Each row of a
+// partition carries its static columns, which make up a row of their own in
+// a partition without any others.
+pub struct Rows<I: Iterator<Item = fjall::Result<KvPair>>> {
+    table: TableMetadata,
+    cells: I,
+    current: Option<StoredRow>,
+    // The partition's static row, and whether it was returned with a row
+    statics: Option<(Vec<u8>, Row, bool)>,
+    pending: Option<Row>,
+    resumed: Option<Vec<u8>>,
+}
+
+impl<I: Iterator<Item = fjall::Result<KvPair>>> Rows<I> {
+    pub fn new(table: TableMetadata, cells: I) -> Self {
+        Rows {
+            table,
+            cells,
+            current: None,
+            statics: None,
+            pending: None,
+            resumed: None,
+        }
+    }
+
+    // Resuming after the given row, whose partition's static row was returned
+    // with it
+    pub fn resume(mut self, last_key: Option<&[u8]>) -> Self {
+        self.resumed = last_key.map(|key| partition_prefix(&self.table, key).to_vec());
+        self
+    }
+
+    // The next row as stored, along with its key and whether it's the static row
+    fn next_stored(&mut self) -> Option<Result<StoredRow, DbError>> {
+        for item in self.cells.by_ref() {
+            let (key, value) = match item {
+                Ok(item) => item,
+                Err(err) => {
+                    return Some(Err(DbError::new(ErrorCode::ReadFailure, err.to_string())))
+                }
+            };
+            let cell = decode_cell(&self.table, &key, value);
+
+            match self.current.take() {
+                Some((prefix, is_static, mut row)) if prefix == cell.row_prefix => {
+                    if let Err(error) = Self::apply(&self.table, &mut row, cell) {
+                        return Some(Err(error));
+                    }
+
+                    self.current = Some((prefix, is_static, row));
+                }
+                finished => {
+                    let mut row = Self::start_row(&self.table, &cell);
+                    let prefix = cell.row_prefix.clone();
+                    let is_static = cell.is_static;
+
+                    if let Err(error) = Self::apply(&self.table, &mut row, cell) {
+                        return Some(Err(error));
+                    }
+
+                    self.current = Some((prefix, is_static, row));
+
+                    if finished.is_some() {
+                        return finished.map(Ok);
+                    }
+                }
+            }
+        }
+
+        self.current.take().map(Ok)
+    }
+
+    // A static row that no row was returned with
+    fn unreturned_statics(&mut self) -> Option<Row> {
+        match self.statics.take() {
+            Some((_, statics, false)) => Some(statics),
+            _ => None,
+        }
+    }
+
+    fn start_row(table: &TableMetadata, cell: &Cell) -> HashMap<String, Value> {
+        let key_columns = table.partition_key.iter().zip(&cell.partition_key);
+        let clustering_columns = table.cluster_key.iter().zip(&cell.clustering_key);
+
+        key_columns
+            .chain(clustering_columns)
+            .map(|(name, value)| (name.clone(), value.clone()))
+            .collect()
+    }
+
+    fn apply(
+        table: &TableMetadata,
+        row: &mut HashMap<String, Value>,
+        cell: Cell,
+    ) -> Result<(), DbError> {
+        let Some(column) = table.columns.get(&cell.column) else {
+            return Ok(());
+        };
+
+        match &column.column_type {
+            ColumnType::List(_) => {
+                if let Value::List(values) = row
+                    .entry(cell.column)
+                    .or_insert_with(|| Value::List(vec![]))
+                {
+                    values.push(Value::try_from(cell.value)?);
+                }
+            }
+            ColumnType::Set(element_type) => {
+                if let Value::Set(values) =
+                    row.entry(cell.column).or_insert_with(|| Value::Set(vec![]))
+                {
+                    values.push(decode_component(element_type, &cell.path).0);
+                }
+            }
+            ColumnType::Map(key_type, _) => {
+                if let Value::Map(entries) =
+                    row.entry(cell.column).or_insert_with(|| Value::Map(vec![]))
+                {
+                    entries.push((
+                        decode_component(key_type, &cell.path).0,
+                        Value::try_from(cell.value)?,
+                    ));
+                }
+            }
+            ColumnType::Udt(user_type) => {
+                if let (Value::Udt(fields), (Value::Smallint(position), _)) = (
+                    row.entry(cell.column)
+                        .or_insert_with(|| Value::Udt(vec![None; user_type.fields.len()])),
+                    decode_component(&ColumnType::Smallint, &cell.path),
+                ) {
+                    if let Some(field) = fields.get_mut(position as usize) {
+                        *field = Some(Value::try_from(cell.value)?);
+                    }
+                }
+            }
+            ColumnType::Counter => {
+                if let (Value::Counter(total), Value::Counter(delta)) = (
+                    row.entry(cell.column).or_insert(Value::Counter(0)),
+                    Value::try_from(cell.value)?,
+                ) {
+                    *total = total.wrapping_add(delta);
+                }
+            }
+            _ => {
+                row.insert(cell.column, Value::try_from(cell.value)?);
+            }
+        }
+
+        Ok(())
+    }
+}
+
+impl<I: Iterator<Item = fjall::Result<KvPair>>> Iterator for Rows<I> {
+    type Item = Result<Row, DbError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if let Some(row) = self.pending.take() {
+            return Some(Ok(row));
+        }
+
+        loop {
+            let (prefix, is_static, mut row) = match self.next_stored() {
+                Some(Ok(stored)) => stored,
+                Some(Err(error)) => return Some(Err(error)),
+                None => return self.unreturned_statics().map(Ok),
+            };
+            let partition = partition_prefix(&self.table, &prefix).to_vec();
+
+            if is_static {
+                let returned = self.resumed.as_ref() == Some(&partition);
+                let previous = self.statics.replace((partition, row, returned));
+
+                if let Some((_, statics, false)) = previous {
+                    return Some(Ok(statics));
+                }
+
+                continue;
+            }
+
+            match &mut self.statics {
+                Some((static_partition, statics, returned)) if *static_partition == partition => {
+                    row.extend(statics.clone());
+                    *returned = true;
+
+                    return Some(Ok(row));
+                }
+                _ => {
+                    return match self.unreturned_statics() {
+                        Some(statics) => {
+                            self.pending = Some(row);
+                            Some(Ok(statics))
+                        }
+                        None => Some(Ok(row)),
+                    }
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::db::schema::{ColumnMetadata, Kind};
+
+    fn encode(value: &Value) -> Vec<u8> {
+        let mut bytes = vec![];
+
+        encode_component(value, &mut bytes);
+        bytes
+    }
+
+    #[test]
+    fn test_component_order_matches_value_order() {
+        let values = [
+            vec![
+                Value::Int(-10),
+                Value::Int(-1),
+                Value::Int(0),
+                Value::Int(1),
+                Value::Int(10),
+            ],
+            vec![
+                Value::Double(-2.5),
+                Value::Double(-0.5),
+                Value::Double(0.0),
+                Value::Double(3.25),
+            ],
+            vec![
+                Value::Varchar("".to_string()),
+                Value::Varchar("a".to_string()),
+                Value::Varchar("a\0".to_string()),
+                Value::Varchar("ab".to_string()),
+                Value::Varchar("b".to_string()),
+            ],
+            vec![
+                Value::Varint("-100000000000000000000".parse().unwrap()),
+                Value::Varint(BigInt::from(-256)),
+                Value::Varint(BigInt::from(-1)),
+                Value::Varint(BigInt::from(0)),
+                Value::Varint(BigInt::from(255)),
+                Value::Varint("100000000000000000000".parse().unwrap()),
+            ],
+            vec![
+                Value::Decimal("-10.5".parse().unwrap()),
+                Value::Decimal("-9.75".parse().unwrap()),
+                Value::Decimal("-9.7".parse().unwrap()),
+                Value::Decimal("-0.001".parse().unwrap()),
+                Value::Decimal("0".parse().unwrap()),
+                Value::Decimal("0.001".parse().unwrap()),
+                Value::Decimal("9.7".parse().unwrap()),
+                Value::Decimal("9.75".parse().unwrap()),
+                Value::Decimal("10".parse().unwrap()),
+                Value::Decimal("1E+30".parse().unwrap()),
+            ],
+            // By time, then by clock sequence and node as signed bytes
+            [
+                "ffffffff-0000-1000-8000-000000000000",
+                "00000000-0001-1000-8000-000000000000",
+                "00000000-0001-1000-7f00-000000000000",
+            ]
+            .map(|uuid| Value::Timeuuid(uuid.parse().unwrap()))
+            .to_vec(),
+            vec![
+                Value::List(vec![]),
+                Value::List(vec![Value::Int(1)]),
+                Value::List(vec![Value::Int(1), Value::Int(2)]),
+                Value::List(vec![Value::Int(2)]),
+            ],
+        ];
+
+        for ordered in values {
+            for pair in ordered.windows(2) {
+                assert!(pair[0] < pair[1], "{:?}", pair);
+                assert!(encode(&pair[0]) < encode(&pair[1]), "{:?}", pair);
+            }
+        }
+    }
+
+    #[test]
+    fn test_component_round_trip() {
+        let values = [
+            (ColumnType::Smallint, Value::Smallint(-3)),
+            (ColumnType::Bigint, Value::Bigint(i64::MIN)),
+            (ColumnType::Float, Value::Float(-1.5)),
+            (ColumnType::Blob, Value::Blob(vec![0, 1, 0, 0xFF])),
+            (ColumnType::Duration, Value::Duration(-1, -2, -3)),
+            (ColumnType::Uuid, Value::Uuid(Uuid::new_v4())),
+            (
+                ColumnType::Timeuuid,
+                Value::Timeuuid("5a7ad5f0-a8b1-11ef-9c3e-0242ac120002".parse().unwrap()),
+            ),
+            (ColumnType::Varint, Value::Varint(BigInt::from(-65536))),
+            (
+                ColumnType::Varint,
+                Value::Varint("123456789012345678901234567890".parse().unwrap()),
+            ),
+            (
+                ColumnType::Decimal,
+                Value::Decimal("-1.2300".parse().unwrap()),
+            ),
+            (ColumnType::Decimal, Value::Decimal("0.00".parse().unwrap())),
+            (ColumnType::Decimal, Value::Decimal("1E+3".parse().unwrap())),
+            (
+                ColumnType::Map(Box::new(ColumnType::Varchar), Box::new(ColumnType::Int)),
+                Value::Map(vec![(Value::Varchar("k".to_string()), Value::Int(1))]),
+            ),
+        ];
+
+        for (column_type, value) in values {
+            let mut bytes = encode(&value);
+
+            bytes.push(0x42);
+
+            let (decoded, rest) = decode_component(&column_type, &bytes);
+
+            // Decimals compare by value, so check the scale survived too
+            assert_eq!(decoded, value);
+            assert_eq!(encode(&decoded), encode(&value));
+            assert_eq!(rest, &[0x42]);
+        }
+    }
+
+    #[test]
+    fn test_list_element_paths_are_ordered() {
+        let first = list_element_path(false);
+        let second = list_element_path(false);
+        let prepended = list_element_path(true);
+        let prepended_again = list_element_path(true);
+
+        assert!(first < second);
+        assert!(prepended < first);
+        assert!(prepended_again < prepended);
+    }
+
+    fn readings() -> TableMetadata {
+        let column = |name: &str, kind: Kind| {
+            (
+                name.to_string(),
+                ColumnMetadata {
+                    name: name.to_string(),
+                    column_type: ColumnType::Int,
+                    kind,
+                },
+            )
+        };
+
+        TableMetadata {
+            name: "readings".to_string(),
+            partition_key: vec!["id".to_string()],
+            cluster_key: vec!["at".to_string()],
+            columns: [
+                column("id", Kind::PartitionKey),
+                column("at", Kind::Clustering),
+                column("total", Kind::Static),
+            ]
+            .into_iter()
+            .collect(),
+            indexes: vec![],
+            views: vec![],
+            base_table: None,
+            options: TableOptions::default(),
+        }
+    }
+
+    #[test]
+    fn test_prefix_end() {
+        let table = readings();
+        let prefix = row_prefix(&table, &[Value::Int(1)], &[]);
+        let end = prefix_end(&prefix).unwrap();
+
+        assert!(cell_key(&prefix, "column", &[0xFF]) < end);
+        assert!(row_prefix(&table, &[Value::Int(2)], &[]) >= end);
+        assert_eq!(prefix_end(&[0x01, 0xFF]), Some(vec![0x02]));
+        assert_eq!(prefix_end(&[0xFF, 0xFF]), None);
+    }
+
+    #[test]
+    fn test_static_row_sorts_first() {
+        let table = readings();
+        let partition = row_prefix(&table, &[Value::Int(1)], &[]);
+        let statics = cell_key(&static_prefix(&partition), "total", &[]);
+        let row = cell_key(
+            &row_prefix(&table, &[Value::Int(1)], &[Value::Int(i32::MIN)]),
+            "",
+            &[],
+        );
+
+        assert!(statics > partition && statics < row);
+        assert!(decode_cell(&table, &statics, Slice::from(vec![])).is_static);
+        assert!(!decode_cell(&table, &row, Slice::from(vec![])).is_static);
+        assert_eq!(partition_prefix(&table, &row), &partition[..]);
+    }
+
+    #[test]
+    fn test_descending_clustering_order() {
+        let mut table = readings();
+
+        table.options.descending = vec!["at".to_string()];
+
+        let row = |at: i32| {
+            cell_key(
+                &row_prefix(&table, &[Value::Int(1)], &[Value::Int(at)]),
+                "",
+                &[],
+            )
+        };
+        let statics = cell_key(
+            &static_prefix(&row_prefix(&table, &[Value::Int(1)], &[])),
+            "total",
+            &[],
+        );
+
+        assert!(row(2) < row(1) && row(1) < row(-1));
+        assert!(statics < row(i32::MAX));
+
+        let cell = decode_cell(&table, &row(7), Slice::from(vec![]));
+
+        assert_eq!(cell.clustering_key, vec![Value::Int(7)]);
+        assert_eq!(cell.column, ROW_MARKER);
+    }
+}
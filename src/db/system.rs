@@ -0,0 +1,86 @@
+use crate::db::data::{ColumnType, Value};
+use crate::db::schema::{ColumnMetadata, Kind, TableMetadata, TableOptions};
+use std::collections::BTreeMap;
+
+pub const SYSTEM_SCHEMA: &str = "system_schema";
+
+// A row per table holding its options, written as tables are created and altered
+pub const SYSTEM_TABLES: &str = "system_schema.tables";
+
+pub fn system_table(name: &str) -> Option<TableMetadata> {
+    if name != SYSTEM_TABLES {
+        return None;
+    }
+
+    let options = ColumnType::Frozen(Box::new(ColumnType::Map(
+        Box::new(ColumnType::Varchar),
+        Box::new(ColumnType::Varchar),
+    )));
+    let columns = [
+        ("keyspace_name", ColumnType::Varchar, Kind::PartitionKey),
+        ("table_name", ColumnType::Varchar, Kind::Clustering),
+        ("bloom_filter_fp_chance", ColumnType::Double, Kind::Regular),
+        ("caching", options.clone(), Kind::Regular),
+        ("comment", ColumnType::Varchar, Kind::Regular),
+        ("compaction", options.clone(), Kind::Regular),
+        ("compression", options, Kind::Regular),
+        ("default_time_to_live", ColumnType::Int, Kind::Regular),
+        ("gc_grace_seconds", ColumnType::Int, Kind::Regular),
+    ];
+
+    Some(TableMetadata {
+        name: name.to_string(),
+        partition_key: vec!["keyspace_name".to_string()],
+        cluster_key: vec!["table_name".to_string()],
+        columns: columns
+            .into_iter()
+            .map(|(name, column_type, kind)| {
+                (
+                    name.to_string(),
+                    ColumnMetadata {
+                        name: name.to_string(),
+                        column_type,
+                        kind,
+                    },
+                )
+            })
+            .collect(),
+        indexes: vec![],
+        views: vec![],
+        base_table: None,
+        options: TableOptions::default(),
+    })
+}
+
+// The values of a table's row in system_schema.tables, by column
+pub fn tables_row(keyspace: &str, table: &TableMetadata) -> Vec<(String, Value)> {
+    let options = &table.options;
+    let map = |map: &BTreeMap<String, String>| {
+        Value::map(
+            map.iter()
+                .map(|(key, value)| (Value::Varchar(key.clone()), Value::Varchar(value.clone())))
+                .collect(),
+        )
+    };
+
+    [
+        ("keyspace_name", Value::Varchar(keyspace.to_string())),
+        ("table_name", Value::Varchar(table.name.clone())),
+        (
+            "bloom_filter_fp_chance",
+            Value::Double(options.bloom_filter_fp_chance),
+        ),
+        ("caching", map(&options.caching)),
+        ("comment", Value::Varchar(options.comment.clone())),
+        ("compaction", map(&options.compaction)),
+        ("compression", map(&options.compression)),
+        (
+            "default_time_to_live",
+            Value::Int(options.default_time_to_live),
+        ),
+        ("gc_grace_seconds", Value::Int(options.gc_grace_seconds)),
+    ]
+    .into_iter()
+    .map(|(column, value)| (column.to_string(), value))
+    .collect()
+}
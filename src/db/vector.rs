@@ -0,0 +1,450 @@
+use crate::db::data::Value;
+use std::cmp::{Ordering, Reverse};
+use std::collections::hash_map::DefaultHasher;
+use std::collections::{BinaryHeap, HashMap, HashSet};
+use std::fmt::{self, Debug, Formatter};
+use std::hash::{Hash, Hasher};
+
+// How alike two vectors are, scored as Cassandra scores them so that higher
+// is more alike: cosine and dot product scaled into [0, 1], and euclidean as
+// 1 / (1 + the squared distance)
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Similarity {
+    Cosine,
+    DotProduct,
+    Euclidean,
+}
+
+impl Similarity {
+    pub fn from_name(name: &str) -> Option<Similarity> {
+        match name.to_lowercase().as_str() {
+            "cosine" => Some(Similarity::Cosine),
+            "dot_product" => Some(Similarity::DotProduct),
+            "euclidean" => Some(Similarity::Euclidean),
+            _ => None,
+        }
+    }
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            Similarity::Cosine => "cosine",
+            Similarity::DotProduct => "dot_product",
+            Similarity::Euclidean => "euclidean",
+        }
+    }
+
+    // None for vectors of different lengths, and for cosine when either
+    // vector is all zeros and so has no direction
+    pub fn score(&self, a: &[f32], b: &[f32]) -> Option<f32> {
+        if a.len() != b.len() {
+            return None;
+        }
+
+        let dot = || a.iter().zip(b).map(|(x, y)| x * y).sum::<f32>();
+
+        match self {
+            Similarity::Cosine => {
+                let norms = norm(a) * norm(b);
+
+                (norms > 0.0).then(|| (1.0 + dot() / norms) / 2.0)
+            }
+            Similarity::DotProduct => Some((1.0 + dot()) / 2.0),
+            Similarity::Euclidean => {
+                let distance = a.iter().zip(b).map(|(x, y)| (x - y).powi(2)).sum::<f32>();
+
+                Some(1.0 / (1.0 + distance))
+            }
+        }
+    }
+}
+
+fn norm(vector: &[f32]) -> f32 {
+    vector.iter().map(|x| x * x).sum::<f32>().sqrt()
+}
+
+// The elements of a vector of floats
+pub fn floats(value: &Value) -> Option<Vec<f32>> {
+    match value {
+        Value::Vector(elements) => elements
+            .iter()
+            .map(|element| match element {
+                Value::Float(float) => Some(*float),
+                _ => None,
+            })
+            .collect(),
+        _ => None,
+    }
+}
+
+// The most neighbours a node keeps on the layers above the bottom one, which
+// keeps twice as many
+const NEIGHBOURS: usize = 16;
+// How many candidates an insert weighs up when picking a node's neighbours
+const CONSTRUCTION_EF: usize = 64;
+
+// A hierarchical navigable small world graph of the vectors of an index,
+// keyed by the rows holding them. Searches start from the sparse top layer
+// and greedily walk down towards the query, every node being on the bottom
+// layer and each layer above holding a fraction of the one below.
+//
+// Removed nodes stay in the graph to be walked through, but aren't found,
+// until there are as many as there are live ones and the graph is rebuilt.
+pub struct VectorGraph {
+    similarity: Similarity,
+    nodes: Vec<Node>,
+    rows: HashMap<Vec<u8>, usize>,
+    entry: Option<usize>,
+}
+
+struct Node {
+    key: Vec<u8>,
+    vector: Vec<f32>,
+    // The neighbours on each layer the node is on, from the bottom one up
+    neighbours: Vec<Vec<usize>>,
+    removed: bool,
+}
+
+// A node along with its distance to whatever is being searched for
+#[derive(Clone, Copy, PartialEq)]
+struct Candidate {
+    distance: f32,
+    node: usize,
+}
+
+impl Eq for Candidate {}
+
+impl Ord for Candidate {
+    fn cmp(&self, other: &Self) -> Ordering {
+        self.distance
+            .total_cmp(&other.distance)
+            .then(self.node.cmp(&other.node))
+    }
+}
+
+impl PartialOrd for Candidate {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl VectorGraph {
+    pub fn new(similarity: Similarity) -> Self {
+        VectorGraph {
+            similarity,
+            nodes: vec![],
+            rows: HashMap::new(),
+            entry: None,
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.rows.len()
+    }
+
+    // Replaces any vector the row had. Vectors that can't be scored, such as
+    // all zero ones for cosine similarity, are left out.
+    pub fn insert(&mut self, key: Vec<u8>, vector: Vec<f32>) {
+        self.remove(&key);
+
+        if self.similarity.score(&vector, &vector).is_none() {
+            return;
+        }
+
+        let id = self.nodes.len();
+        let level = level(&key);
+
+        self.nodes.push(Node {
+            key: key.clone(),
+            vector,
+            neighbours: vec![vec![]; level + 1],
+            removed: false,
+        });
+        self.rows.insert(key, id);
+
+        let Some(entry) = self.entry else {
+            self.entry = Some(id);
+            return;
+        };
+        let top = self.nodes[entry].neighbours.len() - 1;
+        let query = self.nodes[id].vector.clone();
+        let mut nearest = vec![self.candidate(&query, entry)];
+
+        for layer in (level + 1..=top).rev() {
+            nearest = self.search_layer(&query, nearest, 1, layer, false);
+        }
+
+        for layer in (0..=level.min(top)).rev() {
+            nearest = self.search_layer(&query, nearest, CONSTRUCTION_EF, layer, false);
+
+            let limit = max_neighbours(layer);
+            let neighbours = nearest
+                .iter()
+                .take(limit)
+                .map(|candidate| candidate.node)
+                .collect::<Vec<_>>();
+
+            for &neighbour in &neighbours {
+                self.nodes[neighbour].neighbours[layer].push(id);
+                self.prune(neighbour, layer);
+            }
+
+            self.nodes[id].neighbours[layer] = neighbours;
+        }
+
+        if level > top {
+            self.entry = Some(id);
+        }
+    }
+
+    pub fn remove(&mut self, key: &[u8]) {
+        let Some(id) = self.rows.remove(key) else {
+            return;
+        };
+
+        self.nodes[id].removed = true;
+
+        if self.nodes.len() > 2 * self.rows.len() {
+            self.rebuild();
+        }
+    }
+
+    // The rows of the vectors most like the query, most alike first, along
+    // with their scores. The more candidates weighed up, the likelier the
+    // nearest are found.
+    pub fn search(&self, query: &[f32], limit: usize, candidates: usize) -> Vec<(Vec<u8>, f32)> {
+        let Some(entry) = self.entry else {
+            return vec![];
+        };
+
+        if self
+            .similarity
+            .score(query, &self.nodes[entry].vector)
+            .is_none()
+        {
+            return vec![];
+        }
+
+        let top = self.nodes[entry].neighbours.len() - 1;
+        let mut nearest = vec![self.candidate(query, entry)];
+
+        for layer in (1..=top).rev() {
+            nearest = self.search_layer(query, nearest, 1, layer, false);
+        }
+
+        self.search_layer(query, nearest, candidates.max(limit), 0, true)
+            .into_iter()
+            .take(limit)
+            .map(|candidate| {
+                let node = &self.nodes[candidate.node];
+
+                (node.key.clone(), -candidate.distance)
+            })
+            .collect()
+    }
+
+    // Distances are negated scores, so the nearest nodes are the most alike.
+    // Every node in the graph can be scored against the query.
+    fn candidate(&self, query: &[f32], node: usize) -> Candidate {
+        let score = self
+            .similarity
+            .score(query, &self.nodes[node].vector)
+            .unwrap_or(f32::MIN);
+
+        Candidate {
+            distance: -score,
+            node,
+        }
+    }
+
+    // The nearest nodes of a layer to the query found by walking out from
+    // the given ones, nearest first. Removed nodes are walked through but,
+    // when looking for rows, not counted among the nearest.
+    fn search_layer(
+        &self,
+        query: &[f32],
+        entries: Vec<Candidate>,
+        ef: usize,
+        layer: usize,
+        live: bool,
+    ) -> Vec<Candidate> {
+        let counted = |candidate: &Candidate| !live || !self.nodes[candidate.node].removed;
+        let mut visited = entries
+            .iter()
+            .map(|candidate| candidate.node)
+            .collect::<HashSet<_>>();
+        let mut frontier = entries
+            .iter()
+            .copied()
+            .map(Reverse)
+            .collect::<BinaryHeap<_>>();
+        let mut nearest = entries
+            .into_iter()
+            .filter(counted)
+            .collect::<BinaryHeap<_>>();
+
+        while nearest.len() > ef {
+            nearest.pop();
+        }
+
+        while let Some(Reverse(closest)) = frontier.pop() {
+            let furthest = nearest
+                .peek()
+                .map_or(f32::MAX, |furthest| furthest.distance);
+
+            if closest.distance > furthest && nearest.len() >= ef {
+                break;
+            }
+
+            for &neighbour in &self.nodes[closest.node].neighbours[layer] {
+                if !visited.insert(neighbour) {
+                    continue;
+                }
+
+                let candidate = self.candidate(query, neighbour);
+                let furthest = nearest
+                    .peek()
+                    .map_or(f32::MAX, |furthest| furthest.distance);
+
+                if nearest.len() < ef || candidate.distance < furthest {
+                    frontier.push(Reverse(candidate));
+
+                    if counted(&candidate) {
+                        nearest.push(candidate);
+                    }
+
+                    if nearest.len() > ef {
+                        nearest.pop();
+                    }
+                }
+            }
+        }
+
+        nearest.into_sorted_vec()
+    }
+
+    // Keeps a node's nearest neighbours on a layer once it has too many
+    fn prune(&mut self, id: usize, layer: usize) {
+        let limit = max_neighbours(layer);
+
+        if self.nodes[id].neighbours[layer].len() <= limit {
+            return;
+        }
+
+        let vector = self.nodes[id].vector.clone();
+        let mut neighbours = self.nodes[id].neighbours[layer]
+            .iter()
+            .map(|&neighbour| self.candidate(&vector, neighbour))
+            .collect::<Vec<_>>();
+
+        neighbours.sort();
+        self.nodes[id].neighbours[layer] = neighbours
+            .into_iter()
+            .take(limit)
+            .map(|candidate| candidate.node)
+            .collect();
+    }
+
+    fn rebuild(&mut self) {
+        let nodes = std::mem::take(&mut self.nodes);
+
+        self.rows.clear();
+        self.entry = None;
+
+        for node in nodes.into_iter().filter(|node| !node.removed) {
+            self.insert(node.key, node.vector);
+        }
+    }
+}
+
+impl Debug for VectorGraph {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "VectorGraph({}, {} rows)",
+            self.similarity.name(),
+            self.len()
+        )
+    }
+}
+
+fn max_neighbours(layer: usize) -> usize {
+    if layer == 0 {
+        2 * NEIGHBOURS
+    } else {
+        NEIGHBOURS
+    }
+}
+
+// The top layer of a row's node, drawn from a geometric distribution so that
+// each layer holds about 1 / NEIGHBOURS of the one below. The draw is a hash
+// of the row's key, so rebuilding a graph gives the same one.
+fn level(key: &[u8]) -> usize {
+    let mut hasher = DefaultHasher::new();
+
+    key.hash(&mut hasher);
+
+    let uniform = ((hasher.finish() >> 11) as f64 + 1.0) / (1u64 << 53) as f64;
+
+    (-uniform.ln() / (NEIGHBOURS as f64).ln()) as usize
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_similarity_scores() {
+        let a = [1.0, 0.0];
+        let b = [0.0, 1.0];
+
+        assert_eq!(Similarity::Cosine.score(&a, &a), Some(1.0));
+        assert_eq!(Similarity::Cosine.score(&a, &b), Some(0.5));
+        assert_eq!(Similarity::Cosine.score(&a, &[-2.0, 0.0]), Some(0.0));
+        assert_eq!(Similarity::Cosine.score(&a, &[0.0, 0.0]), None);
+        assert_eq!(Similarity::DotProduct.score(&a, &b), Some(0.5));
+        assert_eq!(Similarity::Euclidean.score(&a, &b), Some(1.0 / 3.0));
+        assert_eq!(Similarity::Euclidean.score(&a, &[1.0]), None);
+    }
+
+    #[test]
+    fn test_graph_finds_nearest() {
+        let mut graph = VectorGraph::new(Similarity::Euclidean);
+        let point = |i: usize| vec![(i % 20) as f32, (i / 20) as f32];
+
+        for i in 0..400usize {
+            graph.insert(i.to_be_bytes().to_vec(), point(i));
+        }
+
+        // (7, 3) is the 67th point, its neighbours a step away
+        let found = graph.search(&[6.9, 3.0], 5, 32);
+
+        assert_eq!(found.len(), 5);
+        assert_eq!(found[0].0, 67usize.to_be_bytes());
+
+        let mut keys = found[1..]
+            .iter()
+            .map(|(key, _)| usize::from_be_bytes(key[..].try_into().unwrap()))
+            .collect::<Vec<_>>();
+
+        keys.sort();
+        assert_eq!(keys, [47, 66, 68, 87]);
+
+        // Moving a row moves its vector, removing it leaves it out
+        graph.insert(67usize.to_be_bytes().to_vec(), point(399));
+        graph.remove(&68usize.to_be_bytes());
+
+        let found = graph.search(&[6.9, 3.0], 1, 32);
+
+        assert_eq!(found[0].0, 66usize.to_be_bytes());
+        assert_eq!(graph.len(), 399);
+
+        for i in 0..300usize {
+            graph.remove(&i.to_be_bytes());
+        }
+
+        let found = graph.search(&[0.0, 0.0], 1, 32);
+
+        assert_eq!(found[0].0, 300usize.to_be_bytes());
+        assert!(graph.nodes.len() <= 2 * graph.len());
+    }
+}
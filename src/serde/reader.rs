@@ -92,9 +92,9 @@ pub(crate) use string_map;
 
 macro_rules! bytes {
     ($bytes:expr) => {{
-        let length = int!($bytes) as usize;
+        let length = int!($bytes);
 
-        if (length < 0) {
+        if length < 0 {
             None
         } else {
             Some($bytes.split_to(length as usize).freeze())
@@ -5,6 +5,7 @@ macro_rules! int {
     }};
 }
 
+#[cfg(test)]
 macro_rules! long {
     ($bytes:expr,$value:expr) => {{
         $bytes.reserve(8 as usize);
@@ -26,6 +27,7 @@ macro_rules! short {
     }};
 }
 
+#[cfg(test)]
 macro_rules! tinyint {
     ($bytes:expr,$value:expr) => {{
         $bytes.reserve(1 as usize);
@@ -34,6 +36,7 @@ macro_rules! tinyint {
 }
 
 pub(crate) use int;
+pub(crate) use unsigned_short;
 
 macro_rules! float {
     ($bytes:expr, $value:expr) => {{
@@ -49,13 +52,11 @@ macro_rules! double {
     }};
 }
 
-
 macro_rules! bytes {
     ($bytes:expr,$value:expr) => {{
-        let byte_length = $value.len() as u16;
-        let length = 2u16 + $value.len() as u16;
-        $bytes.reserve(length as usize);
-        $bytes.extend_from_slice(u16::to_be_bytes(byte_length).as_slice());
+        let byte_length = $value.len() as i32;
+        $bytes.reserve(4 + $value.len());
+        $bytes.extend_from_slice(i32::to_be_bytes(byte_length).as_slice());
         $bytes.extend_from_slice($value);
     }};
 }
@@ -88,6 +89,7 @@ macro_rules! string_map {
 
 pub(crate) use string_map;
 
+#[cfg(test)]
 macro_rules! bool {
     ($bytes:expr, $value:expr) => {{
         $bytes.reserve(1 as usize);
@@ -95,8 +97,6 @@ macro_rules! bool {
     }};
 }
 
-pub use bool;
-
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -136,9 +136,9 @@ mod tests {
         let value = b"hello";
         bytes!(bytes, value);
 
-        let (size, content) = bytes.split_at(2);
+        let (size, content) = bytes.split_at(4);
 
-        assert_eq!(size, &5u16.to_be_bytes()[..]);
+        assert_eq!(size, &5i32.to_be_bytes()[..]);
         assert_eq!(content.to_vec(), value.to_vec());
     }
 
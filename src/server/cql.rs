@@ -6,7 +6,7 @@ use tokio::net::{TcpListener, TcpStream};
 use crate::cql::codec::CqlFrameCodec;
 use crate::cql::operation::Operation;
 use crate::cql::response::error::Error as CqlError;
-use crate::cql::response::result::{Flags, Metadata, Result as CqlResult};
+use crate::cql::response::result::{ColumnSpec, Metadata, Result as CqlResult};
 use crate::db::data::Row;
 use crate::db::error::DbError;
 use crate::db::Database;
@@ -31,6 +31,8 @@ pub async fn main() -> Result<(), Box<dyn Error>> {
         name: "default",
         fjall: &Keyspace::open(Config::new("/tmp/x")).unwrap(),
         tables: &Arc::new(RwLock::new(HashMap::new())),
+        types: &Arc::new(RwLock::new(HashMap::new())),
+        functions: &Arc::new(RwLock::new(Default::default())),
     };
 
     match conn.accept().await {
@@ -70,25 +72,42 @@ async fn exchange(
                         ])))
                         .await?;
                 }
-                Operation::Query(query) => match db.query(query).await {
-                    Ok(result) => {
-                        let iterator = result.result;
-                        let items = iterator
-                            .map(|row| Row { columns: row })
-                            .collect::<Vec<Row>>();
+                Operation::Query(query) => {
+                    // Rows are read as they're collected, so reading one can still fail
+                    let result = db
+                        .query(query)
+                        .await
+                        .and_then(|result| match result.metadata {
+                            Some(metadata) => {
+                                let items = result
+                                    .result
+                                    .map(|row| row.map(|columns| Row { columns }))
+                                    .collect::<Result<Vec<Row>, DbError>>()?;
+                                let column_specs = metadata
+                                    .columns
+                                    .into_iter()
+                                    .map(|(name, column_type)| ColumnSpec::new(name, column_type))
+                                    .collect();
 
-                        let result = CqlResult::Rows {
-                            rows: items.clone(),
-                            metadata: Metadata::new(Flags::empty(), 2),
-                            row_count: items.len() as i32,
-                        };
+                                Ok(CqlResult::Rows {
+                                    row_count: items.len() as i32,
+                                    rows: items,
+                                    metadata: Metadata::for_table(
+                                        db.name.to_string(),
+                                        metadata.table,
+                                        column_specs,
+                                    )
+                                    .with_paging_state(metadata.paging_state),
+                                })
+                            }
+                            None => Ok(CqlResult::Void),
+                        });
 
-                        server.send(Operation::Result(result)).await?;
+                    match result {
+                        Ok(result) => server.send(Operation::Result(result)).await?,
+                        Err(error) => server.send(Operation::Error(error.into())).await?,
                     }
-                    Err(error) => {
-                        server.send(Operation::Error(error.into())).await?;
-                    }
-                },
+                }
                 Operation::Prepare => {}
                 Operation::Execute => {}
                 Operation::Register => {}